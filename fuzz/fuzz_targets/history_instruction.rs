@@ -0,0 +1,8 @@
+#![no_main]
+// Token-2022 instruction data of arbitrary transactions, decoded by the history scanner
+use keypair_utils::history::decode_history_instruction;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_history_instruction(data);
+});
@@ -0,0 +1,37 @@
+#![no_main]
+// Mint account data, as parsed for the UI amount, the ScaledUiAmount and Pausable extensions,
+// and the mint extensions the transfer flow reads
+use keypair_utils::{
+    mint::get_mint_extension_data,
+    pausable::get_pausable_config,
+    ui_amount::{get_scaled_ui_amount_config, UiAmount},
+};
+use libfuzzer_sys::fuzz_target;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::ConfidentialTransferMint, transfer_fee::TransferFeeConfig,
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+    state::Mint,
+};
+
+fuzz_target!(|data: &[u8]| {
+    // The first two bytes pick the extension type to look up, the rest is the account data
+    if data.len() < 2 {
+        return;
+    }
+    let extension_type = u16::from_le_bytes([data[0], data[1]]);
+    let mint_data = &data[2..];
+
+    let _ = get_mint_extension_data(mint_data, extension_type);
+    let _ = get_pausable_config(mint_data);
+    let _ = get_scaled_ui_amount_config(mint_data);
+    if let Ok(ui_amount) = UiAmount::from_mint_data(mint_data, i64::MAX) {
+        let _ = ui_amount.format(u64::MAX);
+    }
+
+    if let Ok(mint) = StateWithExtensions::<Mint>::unpack(mint_data) {
+        let _ = mint.get_extension::<ConfidentialTransferMint>();
+        let _ = mint.get_extension::<TransferFeeConfig>();
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+// Accounts of other programs: ElGamal registry accounts, and proof context state accounts scanned by `resume`
+use keypair_utils::registry::ElGamalRegistry;
+use libfuzzer_sys::fuzz_target;
+use spl_token_2022::solana_zk_token_sdk::{
+    zk_token_proof_instruction::{ProofType, WithdrawProofContext},
+    zk_token_proof_state::{ProofContextState, ProofContextStateMeta},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ElGamalRegistry::unpack(data);
+
+    if let Ok(meta) = ProofContextStateMeta::try_from_bytes(data) {
+        if let Ok(ProofType::Withdraw) = ProofType::try_from(meta.proof_type) {
+            let _ = ProofContextState::<WithdrawProofContext>::try_from_bytes(data);
+        }
+    }
+});
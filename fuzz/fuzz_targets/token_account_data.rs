@@ -0,0 +1,45 @@
+#![no_main]
+// Token account data, unpacked to the `ConfidentialTransferAccount` extension the flows decrypt balances from
+use libfuzzer_sys::fuzz_target;
+use solana_sdk::signer::SeedDerivable;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::{
+            account_info::{
+                ApplyPendingBalanceAccountInfo, TransferAccountInfo, WithdrawAccountInfo,
+            },
+            ConfidentialTransferAccount,
+        },
+        BaseStateWithExtensions, StateWithExtensionsOwned,
+    },
+    solana_zk_token_sdk::encryption::{
+        auth_encryption::{AeCiphertext, AeKey},
+        elgamal::{ElGamalCiphertext, ElGamalPubkey},
+    },
+    state::Account,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(account) = StateWithExtensionsOwned::<Account>::unpack(data.to_vec()) else {
+        return;
+    };
+    let Ok(extension) = account.get_extension::<ConfidentialTransferAccount>() else {
+        return;
+    };
+
+    // Everything up to decrypting the ElGamal balances, whose discrete log is too slow to fuzz
+    let _ = ElGamalPubkey::try_from(extension.elgamal_pubkey);
+    let _ = ElGamalCiphertext::try_from(extension.available_balance);
+    let _ = ElGamalCiphertext::try_from(extension.pending_balance_lo);
+    let _ = ElGamalCiphertext::try_from(extension.pending_balance_hi);
+    let _ = u64::from(extension.pending_balance_credit_counter);
+
+    // AES decryption is cheap, so the decryptable balance goes all the way through
+    let aes_key = AeKey::from_seed(&[1; 32]).unwrap();
+    if let Ok(ciphertext) = AeCiphertext::try_from(extension.decryptable_available_balance) {
+        let _ = ciphertext.decrypt(&aes_key);
+    }
+    let _ = ApplyPendingBalanceAccountInfo::new(extension).pending_balance_credit_counter();
+    let _ = TransferAccountInfo::new(extension).new_decryptable_available_balance(1, &aes_key);
+    let _ = WithdrawAccountInfo::new(extension).new_decryptable_available_balance(1, &aes_key);
+});
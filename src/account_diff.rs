@@ -0,0 +1,389 @@
+use crate::{mint::get_mint_extensions, mint_expectation::extension_name};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::{json, Value};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig},
+};
+use solana_sdk::{
+    account::Account as SolanaAccount, commitment_config::CommitmentConfig, message::Message,
+    program_option::COption, program_pack::Pack, pubkey::Pubkey, transaction::Transaction,
+};
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::{ConfidentialTransferAccount, ConfidentialTransferMint},
+        AccountType, ExtensionType,
+    },
+    state::{Account, Mint},
+};
+use std::{collections::BTreeMap, error::Error, fmt};
+
+// Decides whether a transaction is sent, given how it changes the accounts it writes
+pub type TransactionReview = Box<dyn Fn(&TransactionDiff) -> bool + Send + Sync>;
+
+// Returned by a flow whose transaction was declined after reviewing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionDeclined {
+    pub label: String,
+}
+
+impl fmt::Display for TransactionDeclined {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Declined to send {}", self.label)
+    }
+}
+
+impl Error for TransactionDeclined {}
+
+// One decoded field of an account, `None` where the account or the field doesn't exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+// The fields of an account a transaction changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub address: Pubkey,
+    // "mint", "token account", "account", or "closed" when the transaction closes it
+    pub kind: &'static str,
+    pub changes: Vec<FieldChange>,
+}
+
+// What a transaction would do to the accounts it writes, simulated against the current state of the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionDiff {
+    pub label: String,
+    // Why the simulation failed, the accounts are unchanged then
+    pub error: Option<String>,
+    pub units_consumed: Option<u64>,
+    pub accounts: Vec<AccountDiff>,
+}
+
+impl TransactionDiff {
+    // Simulate the unsigned message and diff the writable accounts before and after.
+    // Both are read at processed commitment, so transactions a flow just sent are taken into account.
+    pub fn simulate(
+        client: &RpcClient,
+        label: &str,
+        message: &Message,
+    ) -> Result<Self, Box<dyn Error>> {
+        let commitment = CommitmentConfig::processed();
+        let addresses: Vec<Pubkey> = message
+            .account_keys
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| message.is_writable(*index))
+            .map(|(_, address)| *address)
+            .collect();
+        let before = client
+            .get_multiple_accounts_with_commitment(&addresses, commitment)?
+            .value;
+
+        // Simulation doesn't check signatures, so the transaction is left unsigned
+        let transaction = Transaction::new_unsigned(message.clone());
+        let simulation = client
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(commitment),
+                    accounts: Some(RpcSimulateTransactionAccountsConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        addresses: addresses.iter().map(Pubkey::to_string).collect(),
+                    }),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )?
+            .value;
+
+        let mut accounts = Vec::new();
+        if simulation.err.is_none() {
+            let after = simulation.accounts.unwrap_or_default();
+            for (index, address) in addresses.iter().enumerate() {
+                let before = before.get(index).cloned().flatten();
+                let after = after
+                    .get(index)
+                    .cloned()
+                    .flatten()
+                    .and_then(|account| account.decode::<SolanaAccount>());
+                let diff = diff_account(address, before.as_ref(), after.as_ref());
+                if !diff.changes.is_empty() {
+                    accounts.push(diff);
+                }
+            }
+        }
+        Ok(Self {
+            label: label.to_string(),
+            error: simulation.err.map(|error| error.to_string()),
+            units_consumed: simulation.units_consumed,
+            accounts,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "label": self.label,
+            "error": self.error,
+            "units_consumed": self.units_consumed,
+            "accounts": self.accounts.iter().map(|account| json!({
+                "address": account.address.to_string(),
+                "kind": account.kind,
+                "changes": account.changes.iter().map(|change| json!({
+                    "field": change.field,
+                    "before": change.before,
+                    "after": change.after,
+                })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for TransactionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label)?;
+        if let Some(units) = self.units_consumed {
+            write!(f, " ({} compute units)", units)?;
+        }
+        if let Some(error) = &self.error {
+            return write!(f, "\n  Simulation failed: {}", error);
+        }
+        if self.accounts.is_empty() {
+            return write!(f, "\n  No account changes");
+        }
+        for account in &self.accounts {
+            write!(f, "\n  {} {}", account.kind, account.address)?;
+            for change in &account.changes {
+                let value =
+                    |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+                write!(
+                    f,
+                    "\n    {:<48} {} -> {}",
+                    change.field,
+                    value(&change.before),
+                    value(&change.after)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The fields that differ between the two states of an account
+pub fn diff_account(
+    address: &Pubkey,
+    before: Option<&SolanaAccount>,
+    after: Option<&SolanaAccount>,
+) -> AccountDiff {
+    let kind = match (before, after) {
+        (_, Some(account)) => account_kind(account),
+        (Some(_), None) => "closed",
+        (None, None) => "account",
+    };
+    let before = before.map(decode_account).unwrap_or_default();
+    let after = after.map(decode_account).unwrap_or_default();
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+    let changes = fields
+        .into_iter()
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| FieldChange {
+            field: field.clone(),
+            before: before.get(field).cloned(),
+            after: after.get(field).cloned(),
+        })
+        .collect();
+    AccountDiff {
+        address: *address,
+        kind,
+        changes,
+    }
+}
+
+// Whether the account is a Token-2022 mint or token account, by its size and account type byte
+fn account_kind(account: &SolanaAccount) -> &'static str {
+    if account.owner != spl_token_2022::id() {
+        return "account";
+    }
+    match account.data.len() {
+        Mint::LEN => "mint",
+        Account::LEN => "token account",
+        len if len > Account::LEN => match account.data[Account::LEN] {
+            byte if byte == AccountType::Mint as u8 => "mint",
+            byte if byte == AccountType::Account as u8 => "token account",
+            _ => "account",
+        },
+        _ => "account",
+    }
+}
+
+// The fields of an account by name: lamports, owner and size for every account, plus the base state and the
+// extensions of mints and token accounts. Confidential transfer extensions are decoded field by field, other
+// extensions are shown as their base64 data.
+pub fn decode_account(account: &SolanaAccount) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    fields.insert("lamports".to_string(), account.lamports.to_string());
+    fields.insert("owner".to_string(), account.owner.to_string());
+    fields.insert("data_len".to_string(), account.data.len().to_string());
+
+    let data = &account.data;
+    let coption = |value: COption<Pubkey>| match value {
+        COption::Some(address) => address.to_string(),
+        COption::None => "none".to_string(),
+    };
+    match account_kind(account) {
+        "mint" => {
+            let Ok(mint) = Mint::unpack_unchecked(&data[..Mint::LEN]) else {
+                return fields;
+            };
+            fields.insert("mint_authority".to_string(), coption(mint.mint_authority));
+            fields.insert("supply".to_string(), mint.supply.to_string());
+            fields.insert("decimals".to_string(), mint.decimals.to_string());
+            fields.insert(
+                "is_initialized".to_string(),
+                mint.is_initialized.to_string(),
+            );
+            fields.insert(
+                "freeze_authority".to_string(),
+                coption(mint.freeze_authority),
+            );
+        }
+        "token account" => {
+            let Ok(token_account) = Account::unpack_unchecked(&data[..Account::LEN]) else {
+                return fields;
+            };
+            fields.insert("mint".to_string(), token_account.mint.to_string());
+            fields.insert("token_owner".to_string(), token_account.owner.to_string());
+            fields.insert("amount".to_string(), token_account.amount.to_string());
+            fields.insert("delegate".to_string(), coption(token_account.delegate));
+            fields.insert("state".to_string(), format!("{:?}", token_account.state));
+            fields.insert(
+                "is_native".to_string(),
+                Option::<u64>::from(token_account.is_native)
+                    .map_or("none".to_string(), |rent| rent.to_string()),
+            );
+            fields.insert(
+                "delegated_amount".to_string(),
+                token_account.delegated_amount.to_string(),
+            );
+            fields.insert(
+                "close_authority".to_string(),
+                coption(token_account.close_authority),
+            );
+        }
+        _ => return fields,
+    }
+
+    // A TLV walk rather than unpacking, which fails on extensions newer than this crate
+    let Ok(extensions) = get_mint_extensions(data) else {
+        return fields;
+    };
+    for (extension_type, value) in extensions {
+        if extension_type == ExtensionType::ConfidentialTransferAccount as u16 {
+            if let Ok(extension) = bytemuck::try_from_bytes::<ConfidentialTransferAccount>(value) {
+                decode_confidential_account(extension, &mut fields);
+                continue;
+            }
+        }
+        if extension_type == ExtensionType::ConfidentialTransferMint as u16 {
+            if let Ok(extension) = bytemuck::try_from_bytes::<ConfidentialTransferMint>(value) {
+                decode_confidential_mint(extension, &mut fields);
+                continue;
+            }
+        }
+        fields.insert(
+            format!("extension.{}", extension_name(extension_type)),
+            BASE64_STANDARD.encode(value),
+        );
+    }
+    fields
+}
+
+fn decode_confidential_account(
+    extension: &ConfidentialTransferAccount,
+    fields: &mut BTreeMap<String, String>,
+) {
+    let mut insert = |field: &str, value: String| {
+        fields.insert(format!("confidential_transfer.{}", field), value);
+    };
+    // Ciphertexts and keys are shown as base64, a changed ciphertext is all there is to see of a balance
+    let encoded = |bytes: &[u8]| BASE64_STANDARD.encode(bytes);
+    insert("approved", bool::from(extension.approved).to_string());
+    insert(
+        "elgamal_pubkey",
+        encoded(bytemuck::bytes_of(&extension.elgamal_pubkey)),
+    );
+    insert(
+        "pending_balance_lo",
+        encoded(bytemuck::bytes_of(&extension.pending_balance_lo)),
+    );
+    insert(
+        "pending_balance_hi",
+        encoded(bytemuck::bytes_of(&extension.pending_balance_hi)),
+    );
+    insert(
+        "available_balance",
+        encoded(bytemuck::bytes_of(&extension.available_balance)),
+    );
+    insert(
+        "decryptable_available_balance",
+        encoded(bytemuck::bytes_of(&extension.decryptable_available_balance)),
+    );
+    insert(
+        "allow_confidential_credits",
+        bool::from(extension.allow_confidential_credits).to_string(),
+    );
+    insert(
+        "allow_non_confidential_credits",
+        bool::from(extension.allow_non_confidential_credits).to_string(),
+    );
+    insert(
+        "pending_balance_credit_counter",
+        u64::from(extension.pending_balance_credit_counter).to_string(),
+    );
+    insert(
+        "maximum_pending_balance_credit_counter",
+        u64::from(extension.maximum_pending_balance_credit_counter).to_string(),
+    );
+    insert(
+        "expected_pending_balance_credit_counter",
+        u64::from(extension.expected_pending_balance_credit_counter).to_string(),
+    );
+    insert(
+        "actual_pending_balance_credit_counter",
+        u64::from(extension.actual_pending_balance_credit_counter).to_string(),
+    );
+}
+
+fn decode_confidential_mint(
+    extension: &ConfidentialTransferMint,
+    fields: &mut BTreeMap<String, String>,
+) {
+    let mut insert = |field: &str, value: String| {
+        fields.insert(format!("confidential_transfer.{}", field), value);
+    };
+    insert(
+        "authority",
+        Option::<Pubkey>::from(extension.authority)
+            .map_or("none".to_string(), |authority| authority.to_string()),
+    );
+    insert(
+        "auto_approve_new_accounts",
+        bool::from(extension.auto_approve_new_accounts).to_string(),
+    );
+    let auditor = bytemuck::bytes_of(&extension.auditor_elgamal_pubkey);
+    insert(
+        "auditor_elgamal_pubkey",
+        if auditor.iter().all(|byte| *byte == 0) {
+            "none".to_string()
+        } else {
+            BASE64_STANDARD.encode(auditor)
+        },
+    );
+}
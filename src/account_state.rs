@@ -0,0 +1,175 @@
+use crate::{audit::BalanceAudit, ui_amount::UiAmount};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::ConfidentialTransferAccount, BaseStateWithExtensions,
+        StateWithExtensionsOwned,
+    },
+    solana_zk_token_sdk::{
+        encryption::{
+            auth_encryption::{AeCiphertext, AeKey},
+            elgamal::{ElGamalCiphertext, ElGamalKeypair},
+        },
+        zk_token_elgamal::pod::ElGamalPubkey,
+    },
+    state::Account,
+};
+use std::error::Error;
+
+// The confidential transfer state of a token account, with the counters and flags of its
+// ConfidentialTransferAccount extension decoded from their on-chain (Pod) types.
+//
+// The balances stay encrypted, the decryption helpers take the keys derived from the owner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfidentialAccountState {
+    pub token_account: Pubkey,
+    // Non-confidential balance of the token account
+    pub public_balance: u64,
+    pub elgamal_pubkey: ElGamalPubkey,
+    pub approved: bool,
+    pub allow_confidential_credits: bool,
+    pub allow_non_confidential_credits: bool,
+    // Deposits and incoming transfers since the last ApplyPendingBalance
+    pub pending_balance_credit_counter: u64,
+    // Credits accepted before the pending balance must be applied
+    pub maximum_pending_balance_credit_counter: u64,
+    // Credits the last ApplyPendingBalance expected, and those the program had actually seen
+    pub expected_pending_balance_credit_counter: u64,
+    pub actual_pending_balance_credit_counter: u64,
+    extension: ConfidentialTransferAccount,
+}
+
+impl ConfidentialAccountState {
+    pub fn new(
+        token_account: Pubkey,
+        public_balance: u64,
+        extension: ConfidentialTransferAccount,
+    ) -> Self {
+        Self {
+            token_account,
+            public_balance,
+            elgamal_pubkey: extension.elgamal_pubkey,
+            approved: extension.approved.into(),
+            allow_confidential_credits: extension.allow_confidential_credits.into(),
+            allow_non_confidential_credits: extension.allow_non_confidential_credits.into(),
+            pending_balance_credit_counter: extension.pending_balance_credit_counter.into(),
+            maximum_pending_balance_credit_counter: extension
+                .maximum_pending_balance_credit_counter
+                .into(),
+            expected_pending_balance_credit_counter: extension
+                .expected_pending_balance_credit_counter
+                .into(),
+            actual_pending_balance_credit_counter: extension
+                .actual_pending_balance_credit_counter
+                .into(),
+            extension,
+        }
+    }
+
+    // The state in a token account's data, `None` if it isn't configured for confidential transfers
+    pub fn unpack(token_account: &Pubkey, data: Vec<u8>) -> Result<Option<Self>, Box<dyn Error>> {
+        let account = StateWithExtensionsOwned::<Account>::unpack(data)?;
+        let Ok(extension) = account.get_extension::<ConfidentialTransferAccount>() else {
+            return Ok(None);
+        };
+        Ok(Some(Self::new(
+            *token_account,
+            account.base.amount,
+            *extension,
+        )))
+    }
+
+    // Fetch a token account that must be configured for confidential transfers
+    pub fn fetch(client: &RpcClient, token_account: &Pubkey) -> Result<Self, Box<dyn Error>> {
+        Self::unpack(token_account, client.get_account_data(token_account)?)?.ok_or_else(|| {
+            format!(
+                "Token account {} isn't configured for confidential transfers",
+                token_account
+            )
+            .into()
+        })
+    }
+
+    // The raw extension, for building instructions and proofs
+    pub fn extension(&self) -> &ConfidentialTransferAccount {
+        &self.extension
+    }
+
+    // Credits raced the last ApplyPendingBalance, so the decryptable available balance is behind
+    // the ElGamal available balance until the next one
+    pub fn is_stale(&self) -> bool {
+        self.expected_pending_balance_credit_counter != self.actual_pending_balance_credit_counter
+    }
+
+    // Whether an ApplyPendingBalance would change anything: credits are pending, or the decryptable
+    // available balance must catch up
+    pub fn needs_apply(&self) -> bool {
+        self.pending_balance_credit_counter > 0 || self.is_stale()
+    }
+
+    // Pending balance (lo + hi), `None` if it is too large to decrypt
+    pub fn decrypt_pending_balance(
+        &self,
+        elgamal_keypair: &ElGamalKeypair,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        let decrypt = |ciphertext| -> Result<Option<u64>, Box<dyn Error>> {
+            Ok(ElGamalCiphertext::try_from(ciphertext)?.decrypt_u32(elgamal_keypair.secret()))
+        };
+        Ok(
+            match (
+                decrypt(self.extension.pending_balance_lo)?,
+                decrypt(self.extension.pending_balance_hi)?,
+            ) {
+                // The high part holds the bits above the 16 low bits of the pending amount
+                (Some(lo), Some(hi)) => Some(lo + (hi << 16)),
+                _ => None,
+            },
+        )
+    }
+
+    // Available balance as the program tracks it, `None` if it is too large to decrypt
+    pub fn decrypt_available_balance(
+        &self,
+        elgamal_keypair: &ElGamalKeypair,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(
+            ElGamalCiphertext::try_from(self.extension.available_balance)?
+                .decrypt_u32(elgamal_keypair.secret()),
+        )
+    }
+
+    // Available balance as the client last wrote it, `None` if it doesn't decrypt with the key
+    pub fn decrypt_decryptable_available_balance(
+        &self,
+        aes_key: &AeKey,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(AeCiphertext::try_from(self.extension.decryptable_available_balance)?.decrypt(aes_key))
+    }
+
+    // Whether `amount` can be withdrawn or transferred out now. Proofs are generated from the decryptable
+    // available balance, so a stale one must be caught up by applying the pending balance first.
+    pub fn can_withdraw(&self, amount: u64, aes_key: &AeKey) -> Result<bool, Box<dyn Error>> {
+        if self.is_stale() {
+            return Ok(false);
+        }
+        Ok(self
+            .decrypt_decryptable_available_balance(aes_key)?
+            .is_some_and(|available| available >= amount))
+    }
+
+    pub fn audit(
+        &self,
+        elgamal_keypair: &ElGamalKeypair,
+        aes_key: &AeKey,
+        ui_amount: UiAmount,
+    ) -> Result<BalanceAudit, Box<dyn Error>> {
+        BalanceAudit::new(
+            self.token_account,
+            &self.extension,
+            elgamal_keypair,
+            aes_key,
+            ui_amount,
+        )
+    }
+}
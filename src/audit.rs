@@ -0,0 +1,95 @@
+use crate::{account_state::ConfidentialAccountState, ui_amount::UiAmount};
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::{
+    extension::confidential_transfer::ConfidentialTransferAccount,
+    solana_zk_token_sdk::encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
+};
+use std::{error::Error, fmt};
+
+// The two copies of a confidential token account's available balance, decrypted side by side.
+//
+// The program only tracks the ElGamal `available_balance` ciphertext. The AES `decryptable_available_balance`
+// is written by the client with each instruction, so a client bug can make the two diverge without any on-chain error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceAudit {
+    pub token_account: Pubkey,
+    // Available balance decrypted from the ElGamal ciphertext, `None` if it is too large to decrypt
+    pub available_balance: Option<u64>,
+    // Available balance decrypted from the AES ciphertext, `None` if it doesn't decrypt with the owner's key
+    pub decryptable_available_balance: Option<u64>,
+    // Pending balance (lo + hi), informational since it isn't part of the available balance yet
+    pub pending_balance: Option<u64>,
+    // Decimals and multiplier of the mint, to display the balances
+    pub ui_amount: UiAmount,
+}
+
+impl BalanceAudit {
+    pub fn new(
+        token_account: Pubkey,
+        extension: &ConfidentialTransferAccount,
+        elgamal_keypair: &ElGamalKeypair,
+        aes_key: &AeKey,
+        ui_amount: UiAmount,
+    ) -> Result<Self, Box<dyn Error>> {
+        // The public balance isn't audited
+        let state = ConfidentialAccountState::new(token_account, 0, *extension);
+        Ok(Self {
+            token_account,
+            available_balance: state.decrypt_available_balance(elgamal_keypair)?,
+            decryptable_available_balance: state.decrypt_decryptable_available_balance(aes_key)?,
+            pending_balance: state.decrypt_pending_balance(elgamal_keypair)?,
+            ui_amount,
+        })
+    }
+
+    // Difference between the AES and ElGamal available balance, `None` if either failed to decrypt
+    pub fn drift(&self) -> Option<i128> {
+        Some(self.decryptable_available_balance? as i128 - self.available_balance? as i128)
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.drift() == Some(0)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "token_account": self.token_account.to_string(),
+            "available_balance": self.available_balance,
+            "decryptable_available_balance": self.decryptable_available_balance,
+            "pending_balance": self.pending_balance,
+            "available_balance_ui": self.available_balance.map(|amount| self.ui_amount.format(amount)),
+            "decryptable_available_balance_ui": self.decryptable_available_balance.map(|amount| self.ui_amount.format(amount)),
+            "pending_balance_ui": self.pending_balance.map(|amount| self.ui_amount.format(amount)),
+            "drift": self.drift().map(|drift| drift.to_string()),
+            "consistent": self.is_consistent(),
+        })
+    }
+}
+
+impl fmt::Display for BalanceAudit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Token Account: {}", self.token_account)?;
+        writeln!(
+            f,
+            "  Available Balance (ElGamal):   {}",
+            self.ui_amount.format_decrypted(self.available_balance)
+        )?;
+        writeln!(
+            f,
+            "  Decryptable Balance (AES):     {}",
+            self.ui_amount
+                .format_decrypted(self.decryptable_available_balance)
+        )?;
+        writeln!(
+            f,
+            "  Pending Balance:               {}",
+            self.ui_amount.format_decrypted(self.pending_balance)
+        )?;
+        match self.drift() {
+            Some(0) => write!(f, "  ✔ Consistent"),
+            Some(drift) => write!(f, "  ✘ Drift: {} base units", drift),
+            None => write!(f, "  ✘ Could not decrypt both balances"),
+        }
+    }
+}
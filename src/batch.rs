@@ -0,0 +1,129 @@
+use solana_sdk::signature::{read_keypair_file, Keypair};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+// Wallets run at once by default, low enough to stay under the rate limits of public RPC nodes
+pub const DEFAULT_PARALLELISM: usize = 8;
+
+// A wallet loaded from a keypair file of a batch
+pub struct BatchWallet {
+    pub path: PathBuf,
+    pub keypair: Keypair,
+}
+
+// Load the keypair files matching a pattern such as `wallets/*.json`, sorted by path.
+// Wildcards (`*` and `?`) are supported in the file name only, not in directories.
+pub fn load_wallet_glob(pattern: &str) -> Result<Vec<BatchWallet>, Box<dyn Error>> {
+    let pattern_path = Path::new(pattern);
+    let file_pattern = pattern_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(format!("Invalid wallet glob {}", pattern))?;
+    let directory = match pattern_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if directory.to_string_lossy().contains(['*', '?']) {
+        return Err(format!(
+            "Wildcards are only supported in file names, not directories: {}",
+            pattern
+        )
+        .into());
+    }
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)
+        .map_err(|error| format!("Could not read {}: {}", directory.display(), error))?
+    {
+        let path = entry?.path();
+        let matches = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| wildcard_match(file_pattern, name));
+        if matches && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("No keypair files match {}", pattern).into());
+    }
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let keypair = read_keypair_file(&path)
+                .map_err(|error| format!("Invalid keypair file {}: {}", path.display(), error))?;
+            Ok(BatchWallet { path, keypair })
+        })
+        .collect()
+}
+
+// Shell-style match of `*` (any run of characters) and `?` (one character)
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it was tried at, to backtrack to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Run `task` over every item on at most `parallelism` threads, returning the results in the order of the items
+pub fn run_bounded<T, R, F>(items: &[T], parallelism: usize, task: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = task(item);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is run once"))
+        .collect()
+}
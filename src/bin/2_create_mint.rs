@@ -1,33 +1,56 @@
 // cargo run --bin 2_create_mint
-use keypair_utils::get_or_create_keypair;
+use clap::Parser;
+use keypair_utils::{
+    cli::CreateMintArgs,
+    config::Config,
+    exit_code,
+    flows::FlowContext,
+    get_or_create_keypair,
+    journal::Journal,
+    pausable::initialize_pausable,
+    rent::{MintLayout, RentCalculator},
+    seed::KeypairSource,
+    ui_amount::initialize_scaled_ui_amount,
+};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig, signature::Signer, system_instruction::create_account,
-    transaction::Transaction,
-};
-use spl_token_2022::{
-    extension::ExtensionType, instruction::initialize_mint,
-    solana_zk_token_sdk::encryption::elgamal::ElGamalKeypair, state::Mint,
 };
+use spl_token_2022::{extension::ExtensionType, instruction::initialize_mint};
 use spl_token_client::token::ExtensionInitializationParams;
-use std::error::Error;
+use std::{error::Error, process::ExitCode};
+
+// Create a mint account with the `ConfidentialTransferMint` extension, and optionally the `ScaledUiAmount`, `Pausable`
+// and `PermanentDelegate` extensions
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = CreateMintArgs::parse();
 
-// Create a mint account with the `ConfidentialTransferMint` extension
-fn main() -> Result<(), Box<dyn Error>> {
     let wallet_1 = get_or_create_keypair("wallet_1")?;
 
-    // Generate a keypair for the mint account saved to .env file
-    let mint = get_or_create_keypair("mint")?;
-    let decimals = 2;
+    // Generate a keypair for the mint account saved to .env file, `mint:<label>` when selected with `--mint <label>`
+    let mint = args.flow.mint.keypair()?;
+    let decimals = args.decimals;
 
-    let client = RpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "create-mint";
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.keypairs = KeypairSource::new(config.seed.clone());
 
     // An option ElGamal keypair for an "auditor" to encrypt/decrypt amounts
     // In this example, the keypair is not stored anywhere so we won't be using it to decrypt balances
-    let auditor_elgamal_keypair = ElGamalKeypair::new_rand();
+    let auditor_elgamal_keypair = ctx.keypairs.elgamal_keypair("auditor")?;
     let confidential_transfer_mint_extension =
         ExtensionInitializationParams::ConfidentialTransferMint {
             authority: Some(wallet_1.pubkey()),
@@ -35,18 +58,32 @@ fn main() -> Result<(), Box<dyn Error>> {
             auditor_elgamal_pubkey: Some((*auditor_elgamal_keypair.pubkey()).into()),
         };
 
-    // Calculate the space and lamports required for the mint account with the ConfidentialTransferMint extension
-    let space = ExtensionType::try_calculate_account_len::<Mint>(&[
-        ExtensionType::ConfidentialTransferMint,
-    ])?;
-    let rent = client.get_minimum_balance_for_rent_exemption(space)?;
+    // The permanent delegate can transfer or burn from any token account of the mint, e.g. to sweep dust
+    let permanent_delegate = args
+        .permanent_delegate
+        .as_deref()
+        .map(get_or_create_keypair)
+        .transpose()?;
+
+    // Calculate the space and lamports required for the mint account with the ConfidentialTransferMint extension,
+    // plus the ScaledUiAmount, Pausable and PermanentDelegate extensions if selected
+    let mut extensions = vec![ExtensionType::ConfidentialTransferMint];
+    if permanent_delegate.is_some() {
+        extensions.push(ExtensionType::PermanentDelegate);
+    }
+    let rent = RentCalculator::fetch(&client)?.mint(&MintLayout {
+        extensions,
+        scaled_ui_amount: args.scaled_ui_multiplier.is_some(),
+        pausable: args.pausable,
+    })?;
+    ctx.report.record_rent(rent.lamports);
 
     // Instruction to create the mint account
     let create_account_instruction = create_account(
         &wallet_1.pubkey(),
         &mint.pubkey(),
-        rent,
-        space as u64,
+        rent.lamports,
+        rent.space as u64,
         &spl_token_2022::id(),
     );
 
@@ -63,25 +100,45 @@ fn main() -> Result<(), Box<dyn Error>> {
         decimals,
     )?;
 
-    let instructions = vec![
-        create_account_instruction,
-        extension_instruction,
-        initialize_mint_instruction,
-    ];
+    let mut instructions = vec![create_account_instruction, extension_instruction];
+
+    // Instruction to initialize the ScaledUiAmount extension, with wallet_1 as the authority to update the multiplier
+    if let Some(multiplier) = args.scaled_ui_multiplier {
+        instructions.push(initialize_scaled_ui_amount(
+            &mint.pubkey(),
+            Some(&wallet_1.pubkey()),
+            multiplier,
+        ));
+    }
+    // Pausable extension instruction, the wallet_1 can pause and resume the mint
+    if args.pausable {
+        instructions.push(initialize_pausable(&mint.pubkey(), &wallet_1.pubkey()));
+    }
+    // Instruction to initialize the PermanentDelegate extension
+    if let Some(delegate) = &permanent_delegate {
+        instructions.push(
+            ExtensionInitializationParams::PermanentDelegate {
+                delegate: delegate.pubkey(),
+            }
+            .instruction(&spl_token_2022::id(), &mint.pubkey())?,
+        );
+    }
+    instructions.push(initialize_mint_instruction);
 
     // Sign and send transaction
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
+    let transaction_signature = ctx.send(
+        "Create Mint Account",
         &instructions,
-        Some(&wallet_1.pubkey()),
+        &wallet_1.pubkey(),
         &[&wallet_1, &mint],
-        recent_blockhash,
-    );
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
+    )?;
 
     println!(
-        "\nCreate Mint Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
+        "\nCreate Mint Account: {}",
+        config.explorer.tx_url(&transaction_signature)
     );
+
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
     Ok(())
 }
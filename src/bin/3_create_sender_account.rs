@@ -1,9 +1,11 @@
 // cargo run --bin 3_create_sender_account
-use keypair_utils::get_or_create_keypair;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::{
-    commitment_config::CommitmentConfig, signature::Signer, transaction::Transaction,
+use clap::Parser;
+use keypair_utils::{
+    cli::FlowArgs, config::Config, exit_code, flows::FlowContext, get_or_create_keypair,
+    journal::Journal,
 };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signer};
 use spl_associated_token_account::{
     get_associated_token_address_with_program_id, instruction::create_associated_token_account,
 };
@@ -11,35 +13,49 @@ use spl_token_2022::{
     extension::{confidential_transfer::instruction::configure_account, ExtensionType},
     instruction::reallocate,
     proof::ProofLocation,
-    solana_zk_token_sdk::{
-        encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
-        zk_token_proof_instruction::PubkeyValidityData,
-    },
+    solana_zk_token_sdk::zk_token_proof_instruction::PubkeyValidityData,
 };
-use std::error::Error;
+use std::{error::Error, process::ExitCode};
 
 // Create a sender associated token account with the `ConfidentialTransferAccount` extension
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = FlowArgs::parse();
+
     let wallet_1 = get_or_create_keypair("wallet_1")?;
-    let mint = get_or_create_keypair("mint")?;
+    let mint = args.mint.pubkey()?;
 
-    let client = RpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.check_mint(&client, &mint)?;
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "create-sender-account";
+    ctx.wait = args.wait;
+    ctx.payers = args.payers()?;
+    ctx.send_strategy = args.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
 
     // Associated token address of the sender
     let sender_associated_token_address = get_associated_token_address_with_program_id(
         &wallet_1.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &mint,              // Mint
         &spl_token_2022::id(),
     );
 
+    // Funds the rent of the token account, the wallet unless --rent-funder is given
+    let rent_funder = ctx.payers.rent_funder(&wallet_1.pubkey());
+
     // Instruction to create associated token account
     let create_associated_token_account_instruction = create_associated_token_account(
-        &wallet_1.pubkey(), // Funding account
+        &rent_funder,       // Funding account
         &wallet_1.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &mint,              // Mint
         &spl_token_2022::id(),
     );
 
@@ -47,18 +63,19 @@ fn main() -> Result<(), Box<dyn Error>> {
     let reallocate_instruction = reallocate(
         &spl_token_2022::id(),
         &sender_associated_token_address, // Token account
-        &wallet_1.pubkey(),               // Payer
+        &rent_funder,                     // Payer
         &wallet_1.pubkey(),               // Token account owner
         &[&wallet_1.pubkey()],            // Signers
         &[ExtensionType::ConfidentialTransferAccount], // Extension to reallocate space for
     )?;
 
     // Derive the ElGamal keypair and AES key for the sender token account
-    let elgamal_keypair =
-        ElGamalKeypair::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes())
-            .unwrap();
-    let aes_key =
-        AeKey::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes()).unwrap();
+    let elgamal_keypair = config
+        .key_derivation
+        .elgamal_keypair(&wallet_1, &sender_associated_token_address)?;
+    let aes_key = config
+        .key_derivation
+        .aes_key(&wallet_1, &sender_associated_token_address)?;
 
     // ElGamal decryption procedure becomes more and more inefficient as the encrypted amount grows.
     // The encrypted number in the pending balance could grow so large that decryption becomes infeasible.
@@ -84,7 +101,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let configure_account_instructions = configure_account(
         &spl_token_2022::id(),                  // Program ID
         &sender_associated_token_address,       // Token account
-        &mint.pubkey(),                         // Mint
+        &mint,                                  // Mint
         decryptable_balance,                    // Initial balance
         maximum_pending_balance_credit_counter, // Maximum pending balance credit counter
         &wallet_1.pubkey(),                     // Token Account Owner
@@ -100,19 +117,21 @@ fn main() -> Result<(), Box<dyn Error>> {
     ];
     instructions.extend(configure_account_instructions);
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
+    let transaction_signature = ctx.send(
+        "Create Sender Token Account",
         &instructions,
-        Some(&wallet_1.pubkey()),
+        &wallet_1.pubkey(),
         &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
+    )?;
 
     println!(
-        "\nCreate Sender Token Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
+        "\nCreate Sender Token Account: {}",
+        config.explorer.tx_url(&transaction_signature)
     );
+
+    // Rent for the token account is funded by the rent funder through the associated token account program
+    ctx.report
+        .record_rent(client.get_balance(&sender_associated_token_address)?);
+    ctx.report.print(args.json, args.fiat_price().as_ref());
     Ok(())
 }
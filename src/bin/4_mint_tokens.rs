@@ -1,23 +1,41 @@
 // cargo run --bin 4_mint_tokens
-use keypair_utils::get_or_create_keypair;
+use clap::Parser;
+use keypair_utils::{
+    cli::FlowArgs, config::Config, exit_code, flows::FlowContext, get_or_create_keypair,
+    journal::Journal, ui_amount::UiAmount, verify::BalanceChange,
+};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig, instruction::Instruction, signature::Signer,
-    transaction::Transaction,
 };
 use spl_associated_token_account::get_associated_token_address_with_program_id;
-use spl_token_2022::instruction::mint_to;
-use std::error::Error;
+use spl_token_2022::{extension::StateWithExtensionsOwned, instruction::mint_to, state::Account};
+use std::{error::Error, process::ExitCode};
 
 // Mint tokens to the sender associated token account, standard mint_to instruction
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = FlowArgs::parse();
+
     let wallet_1 = get_or_create_keypair("wallet_1")?;
-    let mint = get_or_create_keypair("mint")?;
+    let mint = args.mint.pubkey()?;
 
-    let client = RpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.check_mint(&client, &mint)?;
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "mint-tokens";
+    ctx.verify = args.verify;
+    ctx.wait = args.wait;
+    ctx.payers = args.payers()?;
+    ctx.send_strategy = args.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
 
     // Mint 100,000.00 tokens
     let amount = 100_000_00;
@@ -25,33 +43,55 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Associated token address of the sender
     let sender_associated_token_address = get_associated_token_address_with_program_id(
         &wallet_1.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &mint,              // Mint
         &spl_token_2022::id(),
     );
 
+    let balances = ctx.snapshot_balances(&sender_associated_token_address, &wallet_1)?;
+
     // Instruction to mint tokens
     let mint_to_instruction: Instruction = mint_to(
         &spl_token_2022::id(),
-        &mint.pubkey(),                   // Mint
+        &mint,                            // Mint
         &sender_associated_token_address, // Token account to mint to
         &wallet_1.pubkey(),               // Token account owner
         &[&wallet_1.pubkey()],            // Additional signers (mint authority)
         amount,                           // Amount to mint
     )?;
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
+    let transaction_signature = ctx.send(
+        "Mint Tokens",
         &[mint_to_instruction],
-        Some(&wallet_1.pubkey()),
+        &wallet_1.pubkey(),
         &[&wallet_1],
-        recent_blockhash,
+    )?;
+
+    println!(
+        "\nMint Tokens: {}",
+        config.explorer.tx_url(&transaction_signature)
     );
 
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
+    ctx.verify_balances(
+        balances,
+        &wallet_1,
+        BalanceChange {
+            public: amount as i128,
+            ..BalanceChange::default()
+        },
+    )?;
+    if args.verify {
+        println!("✔ Balances of {} verified", sender_associated_token_address);
+    }
 
+    // Public (non-confidential) balance of the token account, with the mint decimals and ScaledUiAmount multiplier
+    let token_account = StateWithExtensionsOwned::<Account>::unpack(
+        client.get_account_data(&sender_associated_token_address)?,
+    )?;
     println!(
-        "\nMint Tokens: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
+        "Public Balance: {}",
+        UiAmount::fetch(&client, &mint)?.format(token_account.base.amount)
     );
+
+    ctx.report.print(args.json, args.fiat_price().as_ref());
     Ok(())
 }
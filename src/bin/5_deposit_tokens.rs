@@ -1,60 +1,106 @@
 // cargo run --bin 5_deposit_tokens
-use keypair_utils::get_or_create_keypair;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::{
-    commitment_config::CommitmentConfig, signature::Signer, transaction::Transaction,
+use clap::Parser;
+use keypair_utils::{
+    cli::FlowArgs, config::Config, exit_code, flows::FlowContext, get_or_create_keypair,
+    journal::Journal, ui_amount::UiAmount, verify::BalanceChange,
 };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signer};
 use spl_associated_token_account::get_associated_token_address_with_program_id;
-use spl_token_2022::extension::confidential_transfer::instruction::deposit;
-use std::error::Error;
+use spl_token_2022::{
+    extension::{confidential_transfer::instruction::deposit, StateWithExtensionsOwned},
+    state::Account,
+};
+use std::{error::Error, process::ExitCode};
 
 // Token accounts with Confidential extension enabled have separate "pending" and "available" balances
 // Token account owner must first "deposit" tokens from non-confidential balance to "pending" confidential balance
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = FlowArgs::parse();
+
     let wallet_1 = get_or_create_keypair("wallet_1")?;
-    let mint = get_or_create_keypair("mint")?;
+    let mint = args.mint.pubkey()?;
 
-    let client = RpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.check_mint(&client, &mint)?;
+
+    // Amounts are checked by the token program, and shown, with the decimals of the mint
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+    let decimals = ui_amount.decimals;
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "deposit-tokens";
+    ctx.verify = args.verify;
+    ctx.wait = args.wait;
+    ctx.payers = args.payers()?;
+    ctx.send_strategy = args.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.memo = args.memo.clone();
 
     // Amount to deposit, 100,000.00 tokens
     let deposit_amount = 100_000_00;
-    // Mint decimals
-    let decimals = 2;
 
     // Associated token address of the sender
     let sender_associated_token_address = get_associated_token_address_with_program_id(
         &wallet_1.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &mint,              // Mint
         &spl_token_2022::id(),
     );
 
+    let balances = ctx.snapshot_balances(&sender_associated_token_address, &wallet_1)?;
+
     // Instruction to deposit from non-confidential balance to "pending" balance
     let deposit_instruction = deposit(
         &spl_token_2022::id(),
         &sender_associated_token_address, // Token account
-        &mint.pubkey(),                   // Mint
+        &mint,                            // Mint
         deposit_amount,                   // Amount to deposit
         decimals,                         // Mint decimals
         &wallet_1.pubkey(),               // Token account owner
         &[&wallet_1.pubkey()],            // Signers
     )?;
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[deposit_instruction],
-        Some(&wallet_1.pubkey()),
+    let transaction_signature = ctx.send(
+        "Deposit Tokens",
+        &ctx.attach_memo(&[deposit_instruction]),
+        &wallet_1.pubkey(),
         &[&wallet_1],
-        recent_blockhash,
+    )?;
+
+    println!(
+        "\nDeposit Tokens: {}",
+        config.explorer.tx_url(&transaction_signature)
     );
 
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
+    ctx.verify_balances(
+        balances,
+        &wallet_1,
+        BalanceChange {
+            public: -(deposit_amount as i128),
+            pending: deposit_amount as i128,
+            ..BalanceChange::default()
+        },
+    )?;
+    if args.verify {
+        println!("✔ Balances of {} verified", sender_associated_token_address);
+    }
 
+    // Public (non-confidential) balance of the token account, with the mint decimals and ScaledUiAmount multiplier
+    let token_account = StateWithExtensionsOwned::<Account>::unpack(
+        client.get_account_data(&sender_associated_token_address)?,
+    )?;
     println!(
-        "\nDeposit Tokens: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
+        "Public Balance: {}",
+        ui_amount.format(token_account.base.amount)
     );
+
+    ctx.report.print(args.json, args.fiat_price().as_ref());
     Ok(())
 }
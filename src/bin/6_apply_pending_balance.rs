@@ -1,116 +1,85 @@
 // cargo run --bin 6_apply_pending_balance
-use keypair_utils::get_or_create_keypair;
-use solana_client::{
-    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
-};
-use solana_sdk::{
-    commitment_config::CommitmentConfig, signature::Signer, transaction::Transaction,
+use clap::Parser;
+use keypair_utils::{
+    cli::FlowArgs,
+    config::Config,
+    exit_code,
+    flows::{self, FlowContext},
+    get_or_create_keypair,
+    journal::Journal,
+    verify::BalanceChange,
 };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signer};
 use spl_associated_token_account::get_associated_token_address_with_program_id;
-use spl_token_2022::{
-    extension::{
-        confidential_transfer::{
-            account_info::ApplyPendingBalanceAccountInfo, instruction::apply_pending_balance,
-            ConfidentialTransferAccount,
-        },
-        BaseStateWithExtensions,
-    },
-    solana_zk_token_sdk::encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
-};
-use spl_token_client::{
-    client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
-    token::Token,
-};
-use std::{error::Error, sync::Arc};
+use std::{error::Error, process::ExitCode};
 
 // The "pending" confidential balance must be applied to "available" balance before it can be used in confidential transfers
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+// See `flows::apply_pending` for how credits landing during the apply are handled
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = FlowArgs::parse();
+
     let wallet_1 = get_or_create_keypair("wallet_1")?;
-    let mint = get_or_create_keypair("mint")?;
-    let decimals = 2;
+    let mint = args.mint.pubkey()?;
 
     // Associated token address of the sender
     let sender_associated_token_address = get_associated_token_address_with_program_id(
         &wallet_1.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &mint,              // Mint
         &spl_token_2022::id(),
     );
 
-    let client = RpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
-
-    // A "non-blocking" RPC client (for async calls)
-    let rpc_client = NonBlockingRpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
+    let config = Config::load()?;
 
-    let program_client =
-        ProgramRpcClient::new(Arc::new(rpc_client), ProgramRpcClientSendTransaction);
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.check_mint(&client, &mint)?;
 
-    // Create a "token" client, to use various helper functions for Token Extensions
-    let token = Token::new(
-        Arc::new(program_client),
-        &spl_token_2022::id(),
-        &mint.pubkey(),
-        Some(decimals),
-        Arc::new(wallet_1.insecure_clone()),
-    );
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.verify = args.verify;
+    ctx.wait = args.wait;
+    ctx.payers = args.payers()?;
+    ctx.send_strategy = args.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
 
-    // Get sender token account data
-    let token_account_info = token
-        .get_account_info(&sender_associated_token_address)
-        .await?;
+    let balances = ctx.snapshot_balances(&sender_associated_token_address, &wallet_1)?;
 
-    // Unpack the ConfidentialTransferAccount extension portion of the token account data
-    let confidential_transfer_account =
-        token_account_info.get_extension::<ConfidentialTransferAccount>()?;
-
-    // ConfidentialTransferAccount extension data needed to construct an `ApplyPendingBalance` instruction.
-    let apply_pending_balance_account_info =
-        ApplyPendingBalanceAccountInfo::new(confidential_transfer_account);
-
-    // Return the number of times the pending balance has been credited (deposit or transfer to the account)
-    let expected_pending_balance_credit_counter =
-        apply_pending_balance_account_info.pending_balance_credit_counter();
-
-    // Derive the ElGamal keypair and AES key for the sender token account
-    let elgamal_keypair =
-        ElGamalKeypair::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes())
-            .unwrap();
-    let aes_key =
-        AeKey::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes()).unwrap();
-
-    // Update the decryptable available balance (add pending balance to available balance)
-    let new_decryptable_available_balance = apply_pending_balance_account_info
-        .new_decryptable_available_balance(&elgamal_keypair.secret(), &aes_key)?;
-
-    // Create a `ApplyPendingBalance` instruction
-    let apply_pending_balance_instruction = apply_pending_balance(
-        &spl_token_2022::id(),
-        &sender_associated_token_address,        // Token account
-        expected_pending_balance_credit_counter, // Expected number of times the pending balance has been credited
-        new_decryptable_available_balance, // Cipher text of the new decryptable available balance
-        &wallet_1.pubkey(),                // Token account owner
-        &[&wallet_1.pubkey()],             // Additional signers
+    let outcome = flows::apply_pending::apply_pending_balance(
+        &mut ctx,
+        &sender_associated_token_address,
+        &wallet_1,
+        flows::apply_pending::DEFAULT_MAX_ROUNDS,
+        flows::apply_pending::DEFAULT_ROUND_PAUSE,
     )?;
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[apply_pending_balance_instruction],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nApply Pending Balance: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
+    if outcome.signatures.is_empty() {
+        println!(
+            "\nNo pending balance to apply for {}",
+            sender_associated_token_address
+        );
+    }
+    for transaction_signature in &outcome.signatures {
+        println!(
+            "\nApply Pending Balance: {}",
+            config.explorer.tx_url(transaction_signature)
+        );
+    }
+
+    // The whole pending balance moves to the available balance
+    let expected = balances
+        .as_ref()
+        .map(BalanceChange::apply_pending)
+        .unwrap_or_default();
+    ctx.verify_balances(balances, &wallet_1, expected)?;
+    if args.verify {
+        println!("✔ Balances of {} verified", sender_associated_token_address);
+    }
+
+    ctx.report.print(args.json, args.fiat_price().as_ref());
     Ok(())
 }
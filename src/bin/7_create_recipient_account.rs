@@ -1,9 +1,11 @@
 // cargo run --bin 7_create_recipient_account
-use keypair_utils::get_or_create_keypair;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::{
-    commitment_config::CommitmentConfig, signature::Signer, transaction::Transaction,
+use clap::Parser;
+use keypair_utils::{
+    cli::FlowArgs, config::Config, exit_code, flows::FlowContext, get_or_create_keypair,
+    journal::Journal,
 };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signer};
 use spl_associated_token_account::{
     get_associated_token_address_with_program_id, instruction::create_associated_token_account,
 };
@@ -12,36 +14,50 @@ use spl_token_2022::{
     extension::{confidential_transfer::instruction::configure_account, ExtensionType},
     instruction::reallocate,
     proof::ProofLocation,
-    solana_zk_token_sdk::{
-        encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
-        zk_token_proof_instruction::PubkeyValidityData,
-    },
+    solana_zk_token_sdk::zk_token_proof_instruction::PubkeyValidityData,
 };
-use std::error::Error;
+use std::{error::Error, process::ExitCode};
 
 // Create a recipient associated token account with the `ConfidentialTransferAccount` extension
 // Same process as creating a sender associated token account
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = FlowArgs::parse();
+
     let wallet_2 = get_or_create_keypair("wallet_2")?;
-    let mint = get_or_create_keypair("mint")?;
+    let mint = args.mint.pubkey()?;
 
-    let client = RpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.check_mint(&client, &mint)?;
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "create-recipient-account";
+    ctx.wait = args.wait;
+    ctx.payers = args.payers()?;
+    ctx.send_strategy = args.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
 
     // Associated token address of the recipient
     let recipient_associated_token_address = get_associated_token_address_with_program_id(
         &wallet_2.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &mint,              // Mint
         &spl_token_2022::id(),
     );
 
+    // Funds the rent of the token account, the wallet unless --rent-funder is given
+    let rent_funder = ctx.payers.rent_funder(&wallet_2.pubkey());
+
     // Instruction to create associated token account
     let create_associated_token_account_instruction = create_associated_token_account(
-        &wallet_2.pubkey(), // Funding account
+        &rent_funder,       // Funding account
         &wallet_2.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &mint,              // Mint
         &spl_token_2022::id(),
     );
 
@@ -49,18 +65,19 @@ fn main() -> Result<(), Box<dyn Error>> {
     let reallocate_instruction = reallocate(
         &spl_token_2022::id(),
         &recipient_associated_token_address,
-        &wallet_2.pubkey(),    // payer
+        &rent_funder,          // payer
         &wallet_2.pubkey(),    // owner
         &[&wallet_2.pubkey()], // signers
         &[ExtensionType::ConfidentialTransferAccount],
     )?;
 
     // Derive the ElGamal keypair and AES key for the recipient token account
-    let elgamal_keypair =
-        ElGamalKeypair::new_from_signer(&wallet_2, &recipient_associated_token_address.to_bytes())
-            .unwrap();
-    let aes_key =
-        AeKey::new_from_signer(&wallet_2, &recipient_associated_token_address.to_bytes()).unwrap();
+    let elgamal_keypair = config
+        .key_derivation
+        .elgamal_keypair(&wallet_2, &recipient_associated_token_address)?;
+    let aes_key = config
+        .key_derivation
+        .aes_key(&wallet_2, &recipient_associated_token_address)?;
 
     let maximum_pending_balance_credit_counter = 65536; // Default value or custom
     let decryptable_balance = aes_key.encrypt(0);
@@ -79,7 +96,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let configure_account_instruction = configure_account(
         &spl_token_2022::id(),
         &recipient_associated_token_address,
-        &mint.pubkey(),
+        &mint,
         decryptable_balance,
         maximum_pending_balance_credit_counter,
         &wallet_2.pubkey(),
@@ -94,19 +111,21 @@ fn main() -> Result<(), Box<dyn Error>> {
     ];
     instructions.extend(configure_account_instruction);
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
+    let transaction_signature = ctx.send(
+        "Create Recipient Token Account",
         &instructions,
-        Some(&wallet_2.pubkey()),
+        &wallet_2.pubkey(),
         &[&wallet_2],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
+    )?;
 
     println!(
-        "\nCreate Recipient Token Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
+        "\nCreate Recipient Token Account: {}",
+        config.explorer.tx_url(&transaction_signature)
     );
+
+    // Rent for the token account is funded by the rent funder through the associated token account program
+    ctx.report
+        .record_rent(client.get_balance(&recipient_associated_token_address)?);
+    ctx.report.print(args.json, args.fiat_price().as_ref());
     Ok(())
 }
@@ -0,0 +1,118 @@
+// cargo run --bin audit-balance -- --wallet wallet_1 --wallet wallet_2
+use clap::Parser;
+use keypair_utils::{
+    account_state::ConfidentialAccountState, audit::BalanceAudit, cli::FlowArgs, config::Config,
+    exit_code, get_or_create_keypair, key_cache::KeyCache, snapshot::read_passphrase,
+    ui_amount::UiAmount,
+};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signer};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::{error::Error, path::PathBuf, process::ExitCode};
+
+// Compare the ElGamal and AES copies of the available balance of confidential token accounts.
+// With --keys the balances are decrypted with keys exported by `snapshot export-keys`, without the wallet keypairs.
+#[derive(Parser, Debug)]
+struct AuditArgs {
+    /// Name of an .env keypair whose token account to audit, can be repeated
+    #[arg(long = "wallet", default_values = ["wallet_1", "wallet_2"])]
+    wallets: Vec<String>,
+
+    /// Key cache written by `snapshot export-keys`, used instead of the wallet keypairs
+    #[arg(long, value_name = "PATH")]
+    keys: Option<PathBuf>,
+
+    /// Passphrase of the key cache
+    #[arg(long, requires = "keys")]
+    passphrase: Option<String>,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = AuditArgs::parse();
+
+    let cache = match &args.keys {
+        Some(path) => Some(KeyCache::load(
+            path,
+            &read_passphrase(args.passphrase.clone())?,
+        )?),
+        None => None,
+    };
+    // The cache knows the mint address, its keypair may not be on this machine either
+    let mint = match &cache {
+        Some(cache) => cache.find(&args.wallets[0], &args.flow.mint)?.mint,
+        None => args.flow.mint.pubkey()?,
+    };
+
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.flow.check_mint(&client, &mint)?;
+
+    // Balances are shown with the mint decimals and ScaledUiAmount multiplier
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+
+    let mut audits = Vec::new();
+    for wallet in &args.wallets {
+        if let Some(cache) = &cache {
+            let cached = cache.find(wallet, &args.flow.mint)?;
+            let state = ConfidentialAccountState::fetch(&client, &cached.keys.token_account)?;
+            cached.check(&state)?;
+            audits.push(state.audit(
+                &cached.keys.elgamal_keypair,
+                &cached.keys.aes_key,
+                ui_amount,
+            )?);
+            continue;
+        }
+
+        let owner = get_or_create_keypair(wallet)?;
+
+        // Associated token address of the owner
+        let token_account = get_associated_token_address_with_program_id(
+            &owner.pubkey(), // Token account owner
+            &mint,           // Mint
+            &spl_token_2022::id(),
+        );
+
+        let state = ConfidentialAccountState::fetch(&client, &token_account)?;
+
+        // Derive the ElGamal keypair and AES key for the token account, under the scheme it was configured with
+        let derivation =
+            config
+                .key_derivation
+                .resolve(&owner, &token_account, &state.elgamal_pubkey)?;
+        let elgamal_keypair = derivation.elgamal_keypair(&owner, &token_account)?;
+        let aes_key = derivation.aes_key(&owner, &token_account)?;
+
+        audits.push(state.audit(&elgamal_keypair, &aes_key, ui_amount)?);
+    }
+
+    if args.flow.json {
+        let audits: Vec<Value> = audits.iter().map(BalanceAudit::to_json).collect();
+        println!("{}", json!({ "audits": audits }));
+    } else {
+        for audit in &audits {
+            println!("{}\n", audit);
+        }
+    }
+
+    let drifted = audits.iter().filter(|audit| !audit.is_consistent()).count();
+    if drifted > 0 {
+        return Err(format!(
+            "{} of {} token accounts have inconsistent balances",
+            drifted,
+            audits.len()
+        )
+        .into());
+    }
+    Ok(())
+}
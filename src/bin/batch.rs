@@ -0,0 +1,201 @@
+// cargo run --bin batch -- apply-pending-balance --wallet-glob 'wallets/*.json'
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    batch::{load_wallet_glob, run_bounded, BatchWallet, DEFAULT_PARALLELISM},
+    cli::FlowArgs,
+    config::Config,
+    exit_code,
+    flows::{apply_pending, configure_account, FlowContext},
+    journal::Journal,
+    report::CostReport,
+    ui_amount::UiAmount,
+    verify::BalanceSnapshot,
+};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signer};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::{error::Error, process::ExitCode};
+
+// Run a command for every wallet in a directory of keypair files, a bounded number of wallets at a time.
+// Each wallet pays its own fees, so they must be funded first.
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create and configure the confidential token account of each wallet, skipping configured ones
+    ConfigureAccount(BatchArgs),
+    /// Apply the pending balance of each wallet's token account
+    ApplyPendingBalance(BatchArgs),
+    /// Show the public and decrypted confidential balances of each wallet's token account
+    Balance(BatchArgs),
+}
+
+#[derive(Args, Debug)]
+struct BatchArgs {
+    /// Keypair files to run for, e.g. 'wallets/*.json' (quoted, so the shell doesn't expand it)
+    #[arg(long, value_name = "PATTERN")]
+    wallet_glob: String,
+
+    /// Number of wallets to run at once
+    #[arg(long, default_value_t = DEFAULT_PARALLELISM)]
+    parallelism: usize,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+// What a command did for one wallet
+struct WalletResult {
+    token_account: Pubkey,
+    outcome: Result<Value, String>,
+    report: CostReport,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let (command, args) = match &cli.command {
+        Command::ConfigureAccount(args) => ("configure-account", args),
+        Command::ApplyPendingBalance(args) => ("apply-pending-balance", args),
+        Command::Balance(args) => ("balance", args),
+    };
+    let mint = args.flow.mint.pubkey()?;
+    args.flow.check_mint(&client, &mint)?;
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+    let wallets = load_wallet_glob(&args.wallet_glob)?;
+    eprintln!(
+        "Running {} for {} wallets, {} at a time",
+        command,
+        wallets.len(),
+        args.parallelism
+    );
+
+    let results = run_bounded(&wallets, args.parallelism, |wallet: &BatchWallet| {
+        let token_account = get_associated_token_address_with_program_id(
+            &wallet.keypair.pubkey(), // Token account owner
+            &mint,                    // Mint
+            &spl_token_2022::id(),
+        );
+
+        // A context per wallet, as contexts (and their journal connection) aren't shared across threads
+        let mut ctx = FlowContext::new(&client, 0);
+        match Journal::open(&config.journal_path) {
+            Ok(journal) => ctx.journal = Some(journal),
+            Err(error) => eprintln!("\nCould not open the transaction journal: {}", error),
+        }
+        ctx.wait = args.flow.wait;
+
+        // Read for each wallet, as keypairs can't be shared across threads either
+        let outcome = args.flow.payers().and_then(|payers| {
+            ctx.payers = payers;
+            ctx.send_strategy = args.flow.send_strategy(&config)?;
+            ctx.confirmation_timeout = config.confirmation_timeout;
+            ctx.key_derivation = config.key_derivation.clone();
+            match &cli.command {
+                Command::ConfigureAccount(_) => {
+                    configure_account::create_confidential_account(&mut ctx, &wallet.keypair, &mint)
+                        .map(|signature| match signature {
+                            Some(signature) => json!({ "signature": signature.to_string() }),
+                            None => json!({ "skipped": "already configured" }),
+                        })
+                }
+                Command::ApplyPendingBalance(_) => apply_pending::apply_pending_balance(
+                    &mut ctx,
+                    &token_account,
+                    &wallet.keypair,
+                    apply_pending::DEFAULT_MAX_ROUNDS,
+                    apply_pending::DEFAULT_ROUND_PAUSE,
+                )
+                .map(|outcome| {
+                    json!({
+                        "applied": ui_amount.format(outcome.applied),
+                        "signatures": outcome
+                            .signatures
+                            .iter()
+                            .map(|signature| signature.to_string())
+                            .collect::<Vec<_>>(),
+                    })
+                }),
+                Command::Balance(_) => BalanceSnapshot::fetch(
+                    &client,
+                    &token_account,
+                    &wallet.keypair,
+                    &config.key_derivation,
+                )
+                .map(|balances| {
+                    json!({
+                        "public": ui_amount.format(balances.public),
+                        "pending": ui_amount.format_decrypted(balances.pending),
+                        "available": ui_amount.format_decrypted(balances.available),
+                    })
+                }),
+            }
+        });
+
+        WalletResult {
+            token_account,
+            outcome: outcome.map_err(|error| error.to_string()),
+            report: ctx.report,
+        }
+    });
+
+    let mut report = CostReport::new();
+    let mut failed = 0;
+    let mut rows = Vec::new();
+    for (wallet, result) in wallets.iter().zip(results) {
+        report.merge(result.report);
+        if result.outcome.is_err() {
+            failed += 1;
+        }
+        let mut row = json!({
+            "wallet": wallet.path.display().to_string(),
+            "owner": wallet.keypair.pubkey().to_string(),
+            "token_account": result.token_account.to_string(),
+        });
+        match result.outcome {
+            Ok(value) => row["result"] = value,
+            Err(error) => row["error"] = json!(error),
+        }
+        rows.push(row);
+    }
+
+    if args.flow.json {
+        println!("{}", json!({ "command": command, "wallets": rows }));
+    } else {
+        for row in &rows {
+            println!(
+                "{} ({})",
+                row["wallet"].as_str().unwrap_or_default(),
+                row["owner"].as_str().unwrap_or_default()
+            );
+            match row.get("error") {
+                Some(error) => println!("  Error: {}", error.as_str().unwrap_or_default()),
+                None => println!("  {}", row["result"]),
+            }
+        }
+        println!(
+            "\n{} of {} wallets succeeded",
+            rows.len() - failed,
+            rows.len()
+        );
+    }
+    report.print(args.flow.json, args.flow.fiat_price().as_ref());
+
+    if failed > 0 {
+        return Err(format!("{} of {} wallets failed", failed, rows.len()).into());
+    }
+    Ok(())
+}
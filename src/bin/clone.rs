@@ -0,0 +1,88 @@
+// cargo run --bin clone -- <MINT> --largest 5 --mint-authority wallet_1
+use clap::Parser;
+use keypair_utils::{
+    clone::{AccountClone, MAINNET_RPC_URL},
+    exit_code, get_or_create_keypair,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signer};
+use std::{error::Error, path::PathBuf, process::Command, process::ExitCode};
+
+// Copy a mint and its token accounts from mainnet into the local test validator, to rehearse flows against
+// a real extension configuration. Writes account dumps and prints the `solana-test-validator` command loading them.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Mint to clone
+    #[arg(value_name = "MINT")]
+    mint: Pubkey,
+
+    /// Token account of the mint to clone too, can be repeated
+    #[arg(long = "token-account", value_name = "PUBKEY")]
+    token_accounts: Vec<Pubkey>,
+
+    /// Also clone the largest token accounts of the mint, up to this many
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    largest: usize,
+
+    /// RPC endpoint to fetch the accounts from
+    #[arg(long, value_name = "URL", default_value = MAINNET_RPC_URL)]
+    source_url: String,
+
+    /// Directory the account dumps are written to
+    #[arg(long, value_name = "DIR", default_value = "clone")]
+    out_dir: PathBuf,
+
+    /// Name of the .env keypair to hand the mint authority to, so tokens can be minted locally
+    #[arg(long, value_name = "NAME")]
+    mint_authority: Option<String>,
+
+    /// Start `solana-test-validator --reset` with the accounts instead of printing the command
+    #[arg(long)]
+    start_validator: bool,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let source =
+        RpcClient::new_with_commitment(args.source_url.clone(), CommitmentConfig::confirmed());
+
+    let mut clone = AccountClone::fetch(&source, &args.mint, &args.token_accounts, args.largest)?;
+    if let Some(name) = &args.mint_authority {
+        let authority = get_or_create_keypair(name)?.pubkey();
+        clone.set_mint_authority(&authority)?;
+        println!("Mint authority handed to {} ({})", name, authority);
+    }
+
+    for path in clone.write_dumps(&args.out_dir)? {
+        println!("Wrote {}", path.display());
+    }
+    let validator_args = clone.account_args(&args.out_dir);
+
+    if args.start_validator {
+        let status = Command::new("solana-test-validator")
+            .arg("--reset")
+            .args(&validator_args)
+            .status()
+            .map_err(|error| format!("Could not start solana-test-validator: {}", error))?;
+        if !status.success() {
+            return Err(format!("solana-test-validator exited with {}", status).into());
+        }
+        return Ok(());
+    }
+
+    println!(
+        "\nStart the test validator with the cloned accounts (--reset, they are only loaded into a new ledger):\n\nsolana-test-validator --reset {}",
+        validator_args.join(" ")
+    );
+    if let Some(clone_args) = clone.clone_args() {
+        println!(
+            "\nOr let it fetch them itself:\n\nsolana-test-validator --reset {}",
+            clone_args.join(" ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+// cargo run --bin contacts -- add alice <ADDRESS>
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    config::Config,
+    contacts::{parse_elgamal_pubkey, AddressBook, Contact},
+    exit_code,
+    registry::fetch_registry,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use spl_token_2022::solana_zk_token_sdk::zk_token_elgamal::pod::ElGamalPubkey;
+use std::{error::Error, process::ExitCode};
+
+// Manage the address book of named recipients, used by `8_transfer_with_split_proofs --to <NAME>`
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Add a contact, or replace the contact with the same name
+    Add(AddArgs),
+    /// Remove a contact
+    Remove(NameArgs),
+    /// Show a contact
+    Show(NameArgs),
+    /// List all contacts
+    List,
+}
+
+#[derive(Args, Debug)]
+struct AddArgs {
+    /// Name to send to with --to
+    name: String,
+
+    /// Wallet address of the contact, owning their token accounts
+    address: Pubkey,
+
+    /// ElGamal pubkey (base64) transfers to the contact must be encrypted under
+    #[arg(long, value_name = "BASE64", value_parser = parse_elgamal_pubkey, conflicts_with = "from_registry")]
+    elgamal_pubkey: Option<ElGamalPubkey>,
+
+    /// Take the ElGamal pubkey the contact published in the ElGamal registry
+    #[arg(long)]
+    from_registry: bool,
+}
+
+#[derive(Args, Debug)]
+struct NameArgs {
+    name: String,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+    let address_book = AddressBook::open(&config.contacts_path)?;
+
+    match cli.command {
+        Command::Add(args) => {
+            let elgamal_pubkey = if args.from_registry {
+                let client = RpcClient::new_with_commitment(
+                    config.rpc_url.clone(),
+                    CommitmentConfig::confirmed(),
+                );
+                let registry = fetch_registry(&client, &args.address)?.ok_or(format!(
+                    "{} hasn't published an ElGamal pubkey in the registry",
+                    args.address
+                ))?;
+                Some(registry.elgamal_pubkey)
+            } else {
+                args.elgamal_pubkey
+            };
+
+            let contact = Contact {
+                name: args.name,
+                address: args.address,
+                elgamal_pubkey,
+            };
+            address_book.save(&contact)?;
+            println!("Saved {}", contact);
+        }
+        Command::Remove(args) => {
+            if address_book.remove(&args.name)? {
+                println!("Removed {}", args.name);
+            } else {
+                return Err(format!("No contact named {}", args.name).into());
+            }
+        }
+        Command::Show(args) => match address_book.get(&args.name)? {
+            Some(contact) => println!("{}", contact),
+            None => return Err(format!("No contact named {}", args.name).into()),
+        },
+        Command::List => {
+            let contacts = address_book.list()?;
+            if contacts.is_empty() {
+                println!("No contacts yet, add one with `cargo run --bin contacts -- add <NAME> <ADDRESS>`");
+            }
+            for contact in contacts {
+                println!("{}", contact);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,344 @@
+// cargo run --bin cosign -- deposit --multisig <MULTISIG> --amount 100 --out deposit.json
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    account_state::ConfidentialAccountState,
+    config::Config,
+    cosign::{BlobLocation, PartialTransaction},
+    exit_code,
+    flows::apply_pending::balances_to_apply,
+    get_or_create_keypair,
+    journal::{self, Journal, JournalEntry, TxStatus},
+    mint::MintSelector,
+    ui_amount::UiAmount,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, instruction::Instruction, program_pack::Pack,
+    pubkey::Pubkey, signature::Signer,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::confidential_transfer::instruction::{apply_pending_balance, deposit},
+    state::Multisig,
+};
+use std::{error::Error, process::ExitCode};
+
+// Collect the signatures of a transaction for a confidential token account owned by an SPL Token multisig,
+// from co-signers on other machines.
+//
+// The coordinator creates the transaction and writes it as a blob to a file or relay URL, each co-signer signs
+// their copy with their .env keypair, and anyone sends it once every signature is in. Blobs written to the same
+// location are merged, so co-signers can share one relay URL, or send their files back to be merged by `send`.
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a transaction depositing the public balance of the multisig's token account to its pending balance
+    Deposit(DepositArgs),
+    /// Create a transaction applying the pending balance of the multisig's token account
+    ApplyPending(ApplyPendingArgs),
+    /// Sign a blob with a .env keypair
+    Sign(SignArgs),
+    /// Show which signatures a blob still needs
+    Status(StatusArgs),
+    /// Merge blobs and send the transaction once it carries every signature
+    Send(SendArgs),
+}
+
+#[derive(Args, Debug)]
+struct CreateArgs {
+    /// SPL Token multisig owning the confidential token account
+    #[arg(long, value_name = "PUBKEY")]
+    multisig: Pubkey,
+
+    /// Multisig signer expected to sign, can be repeated. Defaults to the first signers the multisig requires
+    #[arg(long = "cosigner", value_name = "PUBKEY")]
+    cosigners: Vec<Pubkey>,
+
+    /// Name of the .env keypair paying the fee, which signs when creating the transaction
+    #[arg(long, value_name = "NAME", default_value = "wallet_1")]
+    payer: String,
+
+    /// Durable nonce account with the payer as authority, so the transaction doesn't expire while signatures are
+    /// collected. Without one the transaction must be sent within about a minute
+    #[arg(long, value_name = "PUBKEY")]
+    nonce_account: Option<Pubkey>,
+
+    /// File or relay URL to write the blob to
+    #[arg(long, value_name = "PATH|URL")]
+    out: BlobLocation,
+
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+}
+
+#[derive(Args, Debug)]
+struct DepositArgs {
+    /// Amount to deposit, in base units
+    #[arg(long)]
+    amount: u64,
+
+    #[command(flatten)]
+    create: CreateArgs,
+}
+
+#[derive(Args, Debug)]
+struct ApplyPendingArgs {
+    /// Name of the .env keypair the token account's ElGamal keypair and AES key are derived from,
+    /// the one that configured the account for the multisig
+    #[arg(long, value_name = "NAME")]
+    key_holder: String,
+
+    #[command(flatten)]
+    create: CreateArgs,
+}
+
+#[derive(Args, Debug)]
+struct SignArgs {
+    /// File or relay URL of the blob
+    #[arg(value_name = "PATH|URL")]
+    blob: BlobLocation,
+
+    /// Name of the .env keypair signing
+    #[arg(long, value_name = "NAME", default_value = "wallet_1")]
+    wallet: String,
+
+    /// Write the signed blob here instead of back to where it was read from
+    #[arg(long, value_name = "PATH|URL")]
+    out: Option<BlobLocation>,
+}
+
+#[derive(Args, Debug)]
+struct StatusArgs {
+    /// File or relay URL of the blob
+    #[arg(value_name = "PATH|URL")]
+    blob: BlobLocation,
+}
+
+#[derive(Args, Debug)]
+struct SendArgs {
+    /// Files or relay URLs of copies of the blob, merged before sending
+    #[arg(value_name = "PATH|URL", required = true)]
+    blobs: Vec<BlobLocation>,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    match cli.command {
+        Command::Deposit(args) => {
+            let target = Target::fetch(&client, &args.create)?;
+            let ui_amount = UiAmount::fetch(&client, &target.mint)?;
+            let instruction = deposit(
+                &spl_token_2022::id(),
+                &target.token_account,   // Token account
+                &target.mint,            // Mint
+                args.amount,             // Amount to deposit
+                ui_amount.decimals,      // Mint decimals
+                &args.create.multisig,   // Token account owner
+                &target.cosigner_refs(), // Multisig signers
+            )?;
+            create(
+                &client,
+                &args.create,
+                &format!("Deposit {}", ui_amount.format(args.amount)),
+                &[instruction],
+            )?;
+        }
+        Command::ApplyPending(args) => {
+            let target = Target::fetch(&client, &args.create)?;
+            let key_holder = get_or_create_keypair(&args.key_holder)?;
+
+            let state = ConfidentialAccountState::fetch(&client, &target.token_account)?;
+            let derivation = config
+                .key_derivation
+                .resolve(&key_holder, &target.token_account, &state.elgamal_pubkey)
+                .map_err(|_| {
+                    format!(
+                        "Token account {} isn't encrypted under the keys of {}",
+                        target.token_account, args.key_holder
+                    )
+                })?;
+            let elgamal_keypair = derivation.elgamal_keypair(&key_holder, &target.token_account)?;
+            let aes_key = derivation.aes_key(&key_holder, &target.token_account)?;
+            let (available, pending) = balances_to_apply(
+                &target.token_account,
+                state.extension(),
+                &elgamal_keypair,
+                &aes_key,
+            )?;
+            // Credits arriving after this are left pending, the counter tells the program which ones are included
+            let instruction = apply_pending_balance(
+                &spl_token_2022::id(),
+                &target.token_account,
+                state.pending_balance_credit_counter,
+                aes_key.encrypt(available + pending),
+                &args.create.multisig,
+                &target.cosigner_refs(),
+            )?;
+            create(
+                &client,
+                &args.create,
+                "Apply Pending Balance",
+                &[instruction],
+            )?;
+        }
+        Command::Sign(args) => {
+            let mut partial = args
+                .blob
+                .read()?
+                .ok_or(format!("No blob at {:?}", args.blob))?;
+            let wallet = get_or_create_keypair(&args.wallet)?;
+            partial.sign(&wallet)?;
+            let partial = args.out.as_ref().unwrap_or(&args.blob).write(&partial)?;
+            println!("Signed {} as {}", partial.label, wallet.pubkey());
+            print_status(&partial);
+        }
+        Command::Status(args) => {
+            let partial = args
+                .blob
+                .read()?
+                .ok_or(format!("No blob at {:?}", args.blob))?;
+            print_status(&partial);
+        }
+        Command::Send(args) => {
+            let mut merged: Option<PartialTransaction> = None;
+            for blob in &args.blobs {
+                let partial = blob.read()?.ok_or(format!("No blob at {:?}", blob))?;
+                match &mut merged {
+                    Some(merged) => merged.merge(&partial)?,
+                    None => merged = Some(partial),
+                }
+            }
+            let partial = merged.ok_or("No blobs given")?;
+            if !partial.is_complete() {
+                print_status(&partial);
+                return Err("The transaction still needs signatures".into());
+            }
+
+            let result = client.send_and_confirm_transaction(&partial.transaction);
+            // Journaled like the flows' transactions, so `tx` and `resume` see it
+            let journal = Journal::open(&config.journal_path)?;
+            journal.record(&JournalEntry {
+                recorded_at: journal::now(),
+                signature: partial.transaction.signatures.first().copied(),
+                flow: "cosign".to_string(),
+                label: partial.label.clone(),
+                slot: None,
+                status: if result.is_ok() {
+                    TxStatus::Confirmed
+                } else {
+                    TxStatus::Failed
+                },
+                error: result.as_ref().err().map(ToString::to_string),
+                fee: None,
+                accounts: partial.transaction.message.account_keys.clone(),
+                // Sent and confirmed at once, or valid until its nonce advances
+                last_valid_block_height: None,
+            })?;
+            let signature = result?;
+            println!("{}: {}", partial.label, config.explorer.tx_url(&signature));
+        }
+    }
+    Ok(())
+}
+
+// The multisig's token account and the signers chosen to sign for it
+struct Target {
+    mint: Pubkey,
+    token_account: Pubkey,
+    cosigners: Vec<Pubkey>,
+}
+
+impl Target {
+    fn fetch(client: &RpcClient, args: &CreateArgs) -> Result<Self, Box<dyn Error>> {
+        let mint = args.mint.pubkey()?;
+        let multisig = Multisig::unpack(&client.get_account_data(&args.multisig)?)
+            .map_err(|_| format!("{} is not an SPL Token multisig", args.multisig))?;
+        let signers = &multisig.signers[..multisig.n as usize];
+
+        let cosigners = if args.cosigners.is_empty() {
+            signers[..multisig.m as usize].to_vec()
+        } else {
+            args.cosigners.clone()
+        };
+        if let Some(cosigner) = cosigners
+            .iter()
+            .find(|cosigner| !signers.contains(cosigner))
+        {
+            return Err(
+                format!("{} is not a signer of multisig {}", cosigner, args.multisig).into(),
+            );
+        }
+        if cosigners.len() < multisig.m as usize {
+            return Err(format!(
+                "Multisig {} needs {} signers, only {} given",
+                args.multisig,
+                multisig.m,
+                cosigners.len()
+            )
+            .into());
+        }
+
+        Ok(Self {
+            mint,
+            // Associated token address of the multisig
+            token_account: get_associated_token_address_with_program_id(
+                &args.multisig,
+                &mint,
+                &spl_token_2022::id(),
+            ),
+            cosigners,
+        })
+    }
+
+    fn cosigner_refs(&self) -> Vec<&Pubkey> {
+        self.cosigners.iter().collect()
+    }
+}
+
+// Build the transaction, sign it as the payer and write the blob for the co-signers
+fn create(
+    client: &RpcClient,
+    args: &CreateArgs,
+    label: &str,
+    instructions: &[Instruction],
+) -> Result<(), Box<dyn Error>> {
+    let payer = get_or_create_keypair(&args.payer)?;
+    let nonce = args
+        .nonce_account
+        .map(|nonce_account| (nonce_account, payer.pubkey()));
+    let mut partial = PartialTransaction::new(client, label, instructions, &payer.pubkey(), nonce)?;
+    partial.sign(&payer)?;
+
+    let partial = args.out.write(&partial)?;
+    println!("Wrote {} to {:?}", partial.label, args.out);
+    if args.nonce_account.is_none() {
+        println!("Without --nonce-account the blockhash expires in about a minute");
+    }
+    print_status(&partial);
+    Ok(())
+}
+
+fn print_status(partial: &PartialTransaction) {
+    println!("Transaction: {}", partial.id());
+    for signer in partial.required_signers() {
+        let signed = !partial.missing_signers().contains(signer);
+        println!("  {} {}", if signed { "✔" } else { "…" }, signer);
+    }
+    if partial.is_complete() {
+        println!("Every signature is in, ready to send");
+    }
+}
@@ -0,0 +1,337 @@
+// cargo run --bin dashboard -- --wallet wallet_1 --wallet wallet_2
+use clap::Parser;
+use console::{style, Key, Term};
+use keypair_utils::{
+    account_state::ConfidentialAccountState,
+    audit::BalanceAudit,
+    clock::ClusterClock,
+    config::Config,
+    exit_code, get_or_create_keypair,
+    journal::{Journal, JournalEntry, JournalQuery, TxStatus},
+    key_derivation::KeyDerivation,
+    mint::MintSelector,
+    snapshot::{env_keypairs, EncryptionKeys},
+    ui_amount::UiAmount,
+};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    pubsub_client::PubsubClient,
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcSignatureSubscribeConfig},
+    rpc_response::RpcSignatureResult,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    process::ExitCode,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+// How often the dashboard redraws without updates, refreshing the slot and the journal
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Pause before subscribing again after the websocket connection dropped
+const RESUBSCRIBE_PAUSE: Duration = Duration::from_secs(2);
+
+// Full screen view of confidential token accounts, redrawn as they change: public and decrypted balances,
+// pending credits, the latest journaled transactions and their confirmation status.
+//
+// Accounts and unconfirmed transactions are followed over websocket subscriptions, so changes show up as
+// they land. Press q to quit.
+//
+// The dashboard draws with the `console` crate the progress bars already depend on, rather than a TUI framework.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of a .env keypair whose token account to show, can be repeated. Defaults to every wallet_* keypair
+    #[arg(long = "wallet", value_name = "NAME")]
+    wallets: Vec<String>,
+
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+
+    /// Number of recent transactions to show
+    #[arg(long, value_name = "COUNT", default_value_t = 10)]
+    transactions: usize,
+}
+
+enum Update {
+    Account(Pubkey),
+    Signature(Signature, TxStatus),
+    Quit,
+}
+
+struct WalletRow {
+    name: String,
+    owner: Keypair,
+    token_account: Pubkey,
+    keys: Option<EncryptionKeys>,
+    state: Option<ConfidentialAccountState>,
+    audit: Option<BalanceAudit>,
+    error: Option<String>,
+}
+
+impl WalletRow {
+    // Fetch the account, decrypting again only if its confidential state changed
+    fn refresh(&mut self, client: &RpcClient, ui_amount: UiAmount, derivation: &KeyDerivation) {
+        let result = (|| -> Result<(), Box<dyn Error>> {
+            let state = ConfidentialAccountState::fetch(client, &self.token_account)?;
+            if self.state.as_ref() == Some(&state) {
+                return Ok(());
+            }
+            if self.keys.is_none() {
+                let derivation =
+                    derivation.resolve(&self.owner, &self.token_account, &state.elgamal_pubkey)?;
+                self.keys = Some(EncryptionKeys::derive(
+                    &self.owner,
+                    &self.token_account,
+                    &derivation,
+                )?);
+            }
+            let keys = self.keys.as_ref().unwrap();
+            self.audit = Some(state.audit(&keys.elgamal_keypair, &keys.aes_key, ui_amount)?);
+            self.state = Some(state);
+            Ok(())
+        })();
+        self.error = result.err().map(|error| error.to_string());
+    }
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let config = Config::load()?;
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    let mint = args.mint.pubkey()?;
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+    let journal = Journal::open(&config.journal_path)?;
+    let clock = ClusterClock::subscribe(&client, &config.ws_url)?;
+
+    let names = if args.wallets.is_empty() {
+        env_keypairs(".env")?
+            .into_iter()
+            .map(|keypair| keypair.name)
+            .filter(|name| name.starts_with("wallet"))
+            .collect()
+    } else {
+        args.wallets.clone()
+    };
+    let (sender, receiver) = mpsc::channel();
+    let mut rows = Vec::new();
+    for name in names {
+        let owner = get_or_create_keypair(&name)?;
+        let token_account = get_associated_token_address_with_program_id(
+            &owner.pubkey(),
+            &mint,
+            &spl_token_2022::id(),
+        );
+        subscribe_account(&config.ws_url, token_account, client.commitment(), &sender);
+        let mut row = WalletRow {
+            name,
+            owner,
+            token_account,
+            keys: None,
+            state: None,
+            audit: None,
+            error: None,
+        };
+        row.refresh(&client, ui_amount, &config.key_derivation);
+        rows.push(row);
+    }
+
+    // Keys are read on their own thread, the terminal blocks until one is pressed
+    let term = Term::stdout();
+    let key_sender = sender.clone();
+    let key_term = term.clone();
+    thread::spawn(move || loop {
+        match key_term.read_key() {
+            Ok(Key::Char('q')) | Ok(Key::Escape) | Err(_) => {
+                let _ = key_sender.send(Update::Quit);
+                return;
+            }
+            Ok(_) => {}
+        }
+    });
+
+    // Statuses seen over signature subscriptions, newer than the journal's
+    let mut live: HashMap<Signature, TxStatus> = HashMap::new();
+    let mut subscribed = HashSet::new();
+    term.hide_cursor()?;
+    loop {
+        let entries = journal.entries(&JournalQuery {
+            limit: Some(args.transactions),
+            ..JournalQuery::default()
+        })?;
+        for entry in &entries {
+            if let (Some(signature), TxStatus::Processed | TxStatus::Pending) =
+                (entry.signature, entry.status)
+            {
+                if subscribed.insert(signature) {
+                    subscribe_signature(&config.ws_url, signature, &sender);
+                }
+            }
+        }
+
+        let now = clock.now();
+        let mut lines = vec![
+            format!(
+                "{}  {}  slot {}  epoch {} ({} slots left)",
+                style("Confidential accounts").bold(),
+                config.rpc_url,
+                now.slot,
+                now.epoch,
+                now.slots_until_epoch_end()
+            ),
+            format!("Mint {}", mint),
+            String::new(),
+        ];
+        for row in &rows {
+            lines.extend(render_row(row, ui_amount));
+        }
+        lines.push(String::new());
+        lines.push(style("Recent transactions").bold().to_string());
+        for entry in &entries {
+            lines.push(render_entry(entry, &live));
+        }
+        lines.push(String::new());
+        lines.push(style("q to quit").dim().to_string());
+
+        term.clear_screen()?;
+        term.write_line(&lines.join("\n"))?;
+
+        match receiver.recv_timeout(TICK_INTERVAL) {
+            Ok(Update::Account(token_account)) => {
+                for row in rows
+                    .iter_mut()
+                    .filter(|row| row.token_account == token_account)
+                {
+                    row.refresh(&client, ui_amount, &config.key_derivation);
+                }
+            }
+            Ok(Update::Signature(signature, status)) => {
+                live.insert(signature, status);
+            }
+            Ok(Update::Quit) => break,
+            Err(_) => {}
+        }
+    }
+    term.show_cursor()?;
+    Ok(())
+}
+
+fn render_row(row: &WalletRow, ui_amount: UiAmount) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{} {}  {}",
+        style(&row.name).cyan().bold(),
+        row.owner.pubkey(),
+        style(row.token_account).dim()
+    )];
+    if let Some(error) = &row.error {
+        lines.push(format!("  {}", style(error).red()));
+    }
+    if let (Some(state), Some(audit)) = (&row.state, &row.audit) {
+        lines.push(format!(
+            "  Public {}   Available {}   Pending {} ({} credits of {})",
+            ui_amount.format(state.public_balance),
+            ui_amount.format_decrypted(audit.available_balance),
+            ui_amount.format_decrypted(audit.pending_balance),
+            state.pending_balance_credit_counter,
+            state.maximum_pending_balance_credit_counter
+        ));
+        if !audit.is_consistent() {
+            lines.push(format!(
+                "  {}",
+                style("Decryptable balance out of sync with the available balance").yellow()
+            ));
+        }
+    }
+    lines
+}
+
+fn render_entry(entry: &JournalEntry, live: &HashMap<Signature, TxStatus>) -> String {
+    let status = entry
+        .signature
+        .and_then(|signature| live.get(&signature).copied())
+        .unwrap_or(entry.status);
+    let status = match status {
+        TxStatus::Processed | TxStatus::Pending => style(status.as_str()).yellow(),
+        TxStatus::Confirmed | TxStatus::Finalized => style(status.as_str()).green(),
+        TxStatus::Failed | TxStatus::Expired => style(status.as_str()).red(),
+    };
+    format!(
+        "  {:<10} {:<10} {:<50} {}",
+        status,
+        entry.flow,
+        entry.label,
+        entry
+            .signature
+            .map(|signature| signature.to_string())
+            .unwrap_or_default()
+    )
+}
+
+// Send an update on each change of the token account, subscribing again if the connection drops
+fn subscribe_account(
+    ws_url: &str,
+    token_account: Pubkey,
+    commitment: CommitmentConfig,
+    sender: &Sender<Update>,
+) {
+    let ws_url = ws_url.to_string();
+    let sender = sender.clone();
+    thread::spawn(move || loop {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(commitment),
+            ..RpcAccountInfoConfig::default()
+        };
+        if let Ok((_subscription, receiver)) =
+            PubsubClient::account_subscribe(&ws_url, &token_account, Some(config))
+        {
+            while receiver.recv().is_ok() {
+                if sender.send(Update::Account(token_account)).is_err() {
+                    return;
+                }
+            }
+        }
+        thread::sleep(RESUBSCRIBE_PAUSE);
+    });
+}
+
+// Send the status of a processed or pending transaction once it is confirmed or fails
+fn subscribe_signature(ws_url: &str, signature: Signature, sender: &Sender<Update>) {
+    let ws_url = ws_url.to_string();
+    let sender = sender.clone();
+    thread::spawn(move || {
+        let config = RpcSignatureSubscribeConfig {
+            commitment: Some(CommitmentConfig::confirmed()),
+            enable_received_notification: Some(false),
+        };
+        let Ok((_subscription, receiver)) =
+            PubsubClient::signature_subscribe(&ws_url, &signature, Some(config))
+        else {
+            return;
+        };
+        if let Ok(response) = receiver.recv() {
+            if let RpcSignatureResult::ProcessedSignature(result) = response.value {
+                let status = match result.err {
+                    None => TxStatus::Confirmed,
+                    Some(_) => TxStatus::Failed,
+                };
+                let _ = sender.send(Update::Signature(signature, status));
+            }
+        }
+    });
+}
@@ -0,0 +1,223 @@
+// cargo run --bin doctor
+use keypair_utils::{
+    config::Config,
+    exit_code,
+    proof_program::ProofSupport,
+    read_keypair,
+    rent::{MintLayout, RentCalculator},
+    send::quote_fee,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    native_token::lamports_to_sol,
+    pubkey::Pubkey,
+    signature::{keypair_from_seed, Keypair, Signer},
+    system_instruction,
+};
+use spl_token_2022::{
+    extension::ExtensionType,
+    solana_zk_token_sdk::{
+        instruction::ciphertext_commitment_equality::CiphertextCommitmentEqualityProofContext,
+        zk_token_proof_instruction::{
+            BatchedGroupedCiphertext2HandlesValidityProofContext, BatchedRangeProofContext,
+            WithdrawProofContext,
+        },
+    },
+};
+use std::{error::Error, process::ExitCode};
+
+// Signatures paid by each wallet over a full run of the numbered binaries (or `main`)
+// wallet_1: create mint (2), sender account, mint, deposit, apply pending balance,
+// transfer proofs (2 + 1 + 2 + 2), transfer (closing the proofs), withdraw proof (2 + 1), withdraw
+const WALLET_1_SIGNATURES: u64 = 18;
+// wallet_2: recipient account
+const WALLET_2_SIGNATURES: u64 = 1;
+
+// Check everything the flows need before running them, stopping at the first problem with a hint on how to fix it
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    println!("Cluster: {} ({})", config.cluster.name(), config.rpc_url);
+
+    // Stored keypairs -----------------------------------------------------------
+
+    let wallet_1 = check_keypair("wallet_1")?;
+    let wallet_2 = check_keypair("wallet_2")?;
+    check_keypair("mint")?;
+
+    // RPC health and version -----------------------------------------------------
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let version = client.get_version().map_err(|error| {
+        format!(
+            "Could not reach the RPC node at {}: {}\n  Start a local validator with `solana-test-validator`, or set RPC_URL in .env",
+            config.rpc_url, error
+        )
+    })?;
+    client.get_health().map_err(|error| {
+        format!(
+            "The RPC node at {} is unhealthy: {}\n  Wait for the node to catch up, or point RPC_URL at another node",
+            config.rpc_url, error
+        )
+    })?;
+    println!("✔ RPC node healthy, solana-core {}", version.solana_core);
+
+    // Programs -------------------------------------------------------------------
+
+    check_program(
+        &client,
+        "SPL Token-2022",
+        &spl_token_2022::id(),
+        "Use a cluster with Token-2022 deployed, or load it into the local validator with `--bpf-program`",
+    )?;
+    let proof_support = ProofSupport::detect(&client).map_err(|error| {
+        format!(
+            "{}\n  The cluster must have the zk token proof program feature activated, `solana-test-validator` 1.17 activates it by default",
+            error
+        )
+    })?;
+    println!("✔ Proof program: {}", proof_support);
+
+    // Wallet balances ------------------------------------------------------------
+
+    let rent_calculator = RentCalculator::fetch(&client)?;
+    let mint_rent = rent_calculator
+        .mint(&MintLayout {
+            extensions: vec![ExtensionType::ConfidentialTransferMint],
+            ..MintLayout::default()
+        })?
+        .lamports;
+    let token_account_rent = rent_calculator
+        .token_account(&[ExtensionType::ConfidentialTransferAccount])?
+        .lamports;
+    // Transfer proof accounts are closed again at the end of the transfer, but their rent must be available up front
+    let transfer_proofs_rent = rent_calculator
+        .proof_account::<BatchedRangeProofContext>()
+        .lamports
+        + rent_calculator
+            .proof_account::<CiphertextCommitmentEqualityProofContext>()
+            .lamports
+        + rent_calculator
+            .proof_account::<BatchedGroupedCiphertext2HandlesValidityProofContext>()
+            .lamports;
+    let withdraw_proof_rent = rent_calculator
+        .proof_account::<WithdrawProofContext>()
+        .lamports;
+
+    // Every transaction of a run pays the cluster's fee per signature, quoted on a one-signature transaction
+    let payer = Pubkey::new_unique();
+    let lamports_per_signature = quote_fee(
+        &client,
+        &[system_instruction::transfer(&payer, &payer, 0)],
+        &payer,
+    )?;
+
+    if let Some(wallet_1) = &wallet_1 {
+        check_balance(
+            &client,
+            "wallet_1",
+            wallet_1,
+            mint_rent
+                + token_account_rent
+                + transfer_proofs_rent
+                + withdraw_proof_rent
+                + WALLET_1_SIGNATURES * lamports_per_signature,
+        )?;
+    }
+    if let Some(wallet_2) = &wallet_2 {
+        check_balance(
+            &client,
+            "wallet_2",
+            wallet_2,
+            token_account_rent + WALLET_2_SIGNATURES * lamports_per_signature,
+        )?;
+    }
+
+    println!("\nAll checks passed");
+    Ok(())
+}
+
+// A stored keypair must be valid if present; a missing one is created by the first binary that needs it
+fn check_keypair(name: &str) -> Result<Option<Keypair>, Box<dyn Error>> {
+    let keypair = read_keypair(name).map_err(|error| {
+        format!(
+            "The {} keypair in .env is invalid: {}\n  It must be a JSON array of the 64 secret key bytes, remove the line to generate a new keypair",
+            name, error
+        )
+    })?;
+
+    match keypair {
+        Some(keypair) => {
+            // The public half must match the one derived from the secret half
+            let derived = keypair_from_seed(&keypair.secret().to_bytes())?;
+            if derived.pubkey() != keypair.pubkey() {
+                return Err(format!(
+                    "The {} keypair in .env has a public key that doesn't match its secret key\n  Remove the line to generate a new keypair",
+                    name
+                )
+                .into());
+            }
+            println!("✔ {} keypair: {}", name, keypair.pubkey());
+            Ok(Some(keypair))
+        }
+        None => {
+            println!(
+                "- {} keypair not created yet, it will be added to .env on first use",
+                name
+            );
+            Ok(None)
+        }
+    }
+}
+
+fn check_program(
+    client: &RpcClient,
+    name: &str,
+    program_id: &Pubkey,
+    hint: &str,
+) -> Result<(), Box<dyn Error>> {
+    match client.get_account(program_id) {
+        Ok(account) if account.executable => {
+            println!("✔ {} program: {}", name, program_id);
+            Ok(())
+        }
+        Ok(_) => Err(format!("{} is not an executable program\n  {}", program_id, hint).into()),
+        Err(error) => Err(format!(
+            "The {} program {} was not found on the cluster: {}\n  {}",
+            name, program_id, error, hint
+        )
+        .into()),
+    }
+}
+
+fn check_balance(
+    client: &RpcClient,
+    name: &str,
+    wallet: &Keypair,
+    estimated_cost: u64,
+) -> Result<(), Box<dyn Error>> {
+    let balance = client.get_balance(&wallet.pubkey())?;
+    if balance < estimated_cost {
+        return Err(format!(
+            "{} has {} SOL, but a full run is estimated to need {} SOL\n  Fund {} (e.g. `cargo run --bin 1_airdrop` on a test cluster)",
+            name,
+            lamports_to_sol(balance),
+            lamports_to_sol(estimated_cost),
+            wallet.pubkey()
+        )
+        .into());
+    }
+    println!(
+        "✔ {} balance: {} SOL (estimated cost {} SOL)",
+        name,
+        lamports_to_sol(balance),
+        lamports_to_sol(estimated_cost)
+    );
+    Ok(())
+}
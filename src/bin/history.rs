@@ -0,0 +1,151 @@
+// cargo run --bin history -- --wallet wallet_1 --format csv > history.csv
+use clap::Parser;
+use keypair_utils::{
+    account_state::ConfidentialAccountState,
+    config::Config,
+    exit_code, get_or_create_keypair,
+    history::{scan_history, HistoryEntry, HistoryFormat, CSV_HEADER},
+    key_cache::KeyCache,
+    mint::MintSelector,
+    snapshot::read_passphrase,
+    ui_amount::UiAmount,
+};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, native_token::lamports_to_sol, signature::Signer,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::{error::Error, path::PathBuf, process::ExitCode};
+
+// Scan the confidential transactions of a token account, decrypting the amounts with the owner's keys,
+// or with keys exported by `snapshot export-keys` when given --keys
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of the .env keypair owning the token account
+    #[arg(long, default_value = "wallet_1")]
+    wallet: String,
+
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+
+    /// Number of latest transactions of the token account to scan
+    #[arg(long, default_value_t = 100)]
+    limit: usize,
+
+    /// Output format: text, json, or csv for accounting imports
+    #[arg(long, default_value_t = HistoryFormat::Text)]
+    format: HistoryFormat,
+
+    /// Key cache written by `snapshot export-keys`, used instead of the wallet keypair
+    #[arg(long, value_name = "PATH")]
+    keys: Option<PathBuf>,
+
+    /// Passphrase of the key cache
+    #[arg(long, requires = "keys")]
+    passphrase: Option<String>,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let cache = match &args.keys {
+        Some(path) => Some(KeyCache::load(
+            path,
+            &read_passphrase(args.passphrase.clone())?,
+        )?),
+        None => None,
+    };
+    let derived_aes_key;
+    let (mint, token_account, aes_key) = match &cache {
+        Some(cache) => {
+            let cached = cache.find(&args.wallet, &args.mint)?;
+            cached.check(&ConfidentialAccountState::fetch(
+                &client,
+                &cached.keys.token_account,
+            )?)?;
+            (cached.mint, cached.keys.token_account, &cached.keys.aes_key)
+        }
+        None => {
+            let owner = get_or_create_keypair(&args.wallet)?;
+            let mint = args.mint.pubkey()?;
+
+            // Associated token address of the owner
+            let token_account = get_associated_token_address_with_program_id(
+                &owner.pubkey(), // Token account owner
+                &mint,           // Mint
+                &spl_token_2022::id(),
+            );
+
+            // Derive the AES key for the token account
+            (_, derived_aes_key) =
+                config
+                    .key_derivation
+                    .account_keys(&client, &owner, &token_account)?;
+            (mint, token_account, &derived_aes_key)
+        }
+    };
+
+    // Amounts are shown with the mint decimals and ScaledUiAmount multiplier
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+
+    let entries = scan_history(&client, &token_account, aes_key, args.limit)?;
+
+    match args.format {
+        HistoryFormat::Csv => {
+            println!("{}", CSV_HEADER);
+            for entry in &entries {
+                println!("{}", entry.to_csv(&ui_amount));
+            }
+        }
+        HistoryFormat::Json => {
+            let entries: Vec<Value> = entries
+                .iter()
+                .map(|entry| entry.to_json(&ui_amount))
+                .collect();
+            println!(
+                "{}",
+                json!({ "token_account": token_account.to_string(), "entries": entries })
+            );
+        }
+        HistoryFormat::Text => {
+            if entries.is_empty() {
+                println!("No confidential transactions for {}", token_account);
+            }
+            for entry in &entries {
+                print_entry(entry, &ui_amount, &config);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_entry(entry: &HistoryEntry, ui_amount: &UiAmount, config: &Config) {
+    println!(
+        "{} {}",
+        entry.date().unwrap_or_else(|| "-".to_string()),
+        entry.direction.as_str()
+    );
+    if let Some(counterparty) = entry.counterparty {
+        println!("  Counterparty: {}", counterparty);
+    }
+    println!(
+        "  Amount:       {}",
+        ui_amount.format_decrypted(entry.amount)
+    );
+    println!("  Fee:          {} SOL", lamports_to_sol(entry.fee));
+    if let Some(memo) = &entry.memo {
+        println!("  Memo:         {}", memo);
+    }
+    println!("  {}\n", config.explorer.tx_url(&entry.signature));
+}
@@ -1,14 +1,11 @@
 // cargo run --bin main
+use clap::Parser;
 use solana_client::{
     nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
 };
 use solana_sdk::{
-    commitment_config::CommitmentConfig,
-    instruction::Instruction,
-    native_token::LAMPORTS_PER_SOL,
-    signature::{Keypair, Signer},
-    system_instruction::create_account,
-    transaction::Transaction,
+    commitment_config::CommitmentConfig, instruction::Instruction, native_token::LAMPORTS_PER_SOL,
+    signature::Signer, system_instruction::create_account,
 };
 use spl_associated_token_account::{
     get_associated_token_address_with_program_id, instruction::create_associated_token_account,
@@ -16,65 +13,97 @@ use spl_associated_token_account::{
 use spl_token_2022::{
     error::TokenError,
     extension::{
-        confidential_transfer::{
-            account_info::{
-                ApplyPendingBalanceAccountInfo, TransferAccountInfo, WithdrawAccountInfo,
-            },
-            instruction::{
-                apply_pending_balance, configure_account, deposit, transfer_with_split_proofs,
-                withdraw, PubkeyValidityData, TransferSplitContextStateAccounts,
-            },
-            ConfidentialTransferAccount, ConfidentialTransferMint,
-        },
-        BaseStateWithExtensions, ExtensionType, StateWithExtensionsOwned,
+        confidential_transfer::instruction::{configure_account, deposit, PubkeyValidityData},
+        ExtensionType,
     },
     instruction::{initialize_mint, mint_to, reallocate},
     proof::ProofLocation,
-    solana_zk_token_sdk::{
-        encryption::{
-            auth_encryption::AeKey,
-            elgamal::{self, ElGamalKeypair},
-        },
-        instruction::ciphertext_commitment_equality::CiphertextCommitmentEqualityProofContext,
-        zk_token_elgamal::pod::ElGamalPubkey,
-        zk_token_proof_instruction::{
-            close_context_state, BatchedGroupedCiphertext2HandlesValidityProofContext,
-            BatchedRangeProofContext, ContextStateInfo, ProofInstruction, WithdrawProofContext,
-        },
-        zk_token_proof_program,
-        zk_token_proof_state::ProofContextState,
-    },
-    state::{Account, Mint},
 };
 use spl_token_client::{
     client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
     token::{ExtensionInitializationParams, Token},
 };
-use std::{error::Error, mem::size_of, sync::Arc};
-
-use keypair_utils::get_or_create_keypair;
+use std::{error::Error, process::ExitCode, sync::Arc};
+
+use keypair_utils::{
+    cli::{terminal_output, CreateMintArgs},
+    config::Config,
+    exit_code,
+    flows::{self, FlowContext},
+    get_or_create_keypair,
+    journal::Journal,
+    mint::MintSelector,
+    pausable::initialize_pausable,
+    proof_cache::ProofCache,
+    rent::{MintLayout, RentCalculator},
+    seed::KeypairSource,
+    shutdown,
+    ui_amount::{initialize_scaled_ui_amount, UiAmount},
+    verify::BalanceChange,
+};
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> ExitCode {
+    exit_code::report(run().await)
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let args = CreateMintArgs::parse();
+
     // 1. Create sender and recipient wallet keypairs -----------------------------------
 
     let wallet_1 = get_or_create_keypair("wallet_1")?;
     let wallet_2 = get_or_create_keypair("wallet_2")?;
 
-    let client = RpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
-        CommitmentConfig::confirmed(),
-    );
+    let config = Config::load()?;
+
+    // Stop at the next step on Ctrl-C, closing the proof accounts created so far instead of leaking their rent
+    shutdown::install()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
 
     client.request_airdrop(&wallet_1.pubkey(), LAMPORTS_PER_SOL)?;
     client.request_airdrop(&wallet_2.pubkey(), LAMPORTS_PER_SOL)?;
 
+    // Rent, fees and progress over the whole run, including the transfer and withdraw flows
+    let mut ctx = FlowContext::new(
+        &client,
+        5 + flows::apply_pending::STEPS + flows::transfer::STEPS + flows::withdraw::STEPS,
+    )
+    .with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "setup";
+    ctx.heap_frame_bytes = config.heap_frame_bytes;
+    ctx.verify = args.flow.verify;
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
+    ctx.memo = args.flow.memo.clone();
+    ctx.proof_cache = Some(ProofCache::open(&config.journal_path)?);
+    ctx.policy = config.policy()?;
+    ctx.keypairs = KeypairSource::new(config.seed.clone());
+    let decimals = args.decimals;
+
+    // Balances are shown with the decimals and ScaledUiAmount multiplier of the mint created below
+    let progress = terminal_output(
+        &mut ctx.events,
+        &config.explorer,
+        UiAmount::new(decimals, args.scaled_ui_multiplier),
+    );
+    ctx.review = args.flow.review(&progress);
+
     // 2. Create Mint Account ----------------------------------------------------
 
-    let mint = Keypair::new();
+    ctx.start_step("Creating mint account");
+    // A fresh mint for every run, unless a labelled mint is selected with `--mint`
+    let mint = match &args.flow.mint {
+        MintSelector::Default => ctx.keypairs.keypair("mint")?,
+        selector => selector.keypair()?,
+    };
     let mint_authority = &wallet_1;
     let freeze_authority = &wallet_1;
-    let decimals = 2;
 
     // Confidential Transfer Extension authority
     // Authority to modify the `ConfidentialTransferMint` configuration and to approve new accounts (if `auto_approve_new_accounts` is false?)
@@ -82,7 +111,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // Auditor ElGamal pubkey
     // Authority to decrypt any encrypted amounts for the mint
-    let auditor_elgamal_keypair = ElGamalKeypair::new_rand();
+    let auditor_elgamal_keypair = ctx.keypairs.elgamal_keypair("auditor")?;
 
     // ConfidentialTransferMint extension parameters
     let confidential_transfer_mint_extension =
@@ -92,20 +121,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
             auditor_elgamal_pubkey: Some((*auditor_elgamal_keypair.pubkey()).into()),
         };
 
-    // Calculate the space required for the mint account with the extension
-    let space = ExtensionType::try_calculate_account_len::<Mint>(&[
-        ExtensionType::ConfidentialTransferMint,
-    ])?;
-
-    // Calculate the lamports required for the mint account
-    let rent = client.get_minimum_balance_for_rent_exemption(space)?;
+    // Calculate the space required for the mint account with the extension,
+    // plus the ScaledUiAmount and Pausable extensions if selected
+    let rent = RentCalculator::fetch(&client)?.mint(&MintLayout {
+        extensions: vec![ExtensionType::ConfidentialTransferMint],
+        scaled_ui_amount: args.scaled_ui_multiplier.is_some(),
+        pausable: args.pausable,
+    })?;
+    ctx.report.record_rent(rent.lamports);
 
     // Instructions to create the mint account
     let create_account_instruction = create_account(
         &wallet_1.pubkey(),
         &mint.pubkey(),
-        rent,
-        space as u64,
+        rent.lamports,
+        rent.space as u64,
         &spl_token_2022::id(),
     );
 
@@ -122,28 +152,36 @@ async fn main() -> Result<(), Box<dyn Error>> {
         decimals,
     )?;
 
-    let instructions = vec![
-        create_account_instruction,
-        extension_instruction,
-        initialize_mint_instruction,
-    ];
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
+    let mut instructions = vec![create_account_instruction, extension_instruction];
+
+    // ScaledUiAmount extension instruction, the mint authority can update the multiplier
+    if let Some(multiplier) = args.scaled_ui_multiplier {
+        instructions.push(initialize_scaled_ui_amount(
+            &mint.pubkey(),
+            Some(&mint_authority.pubkey()),
+            multiplier,
+        ));
+    }
+    // Pausable extension instruction, the mint authority can pause and resume the mint
+    if args.pausable {
+        instructions.push(initialize_pausable(
+            &mint.pubkey(),
+            &mint_authority.pubkey(),
+        ));
+    }
+    instructions.push(initialize_mint_instruction);
+
+    ctx.send(
+        "Create Mint Account",
         &instructions,
-        Some(&wallet_1.pubkey()),
+        &wallet_1.pubkey(),
         &[&wallet_1, &mint],
-        recent_blockhash,
-    );
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nCreate Mint Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
+    )?;
+    ctx.finish_step();
 
     // 3. Create Sender Token Account -------------------------------------------
 
+    ctx.start_step("Creating sender token account");
     // Associated token address of the sender
     let sender_associated_token_address = get_associated_token_address_with_program_id(
         &wallet_1.pubkey(), // Token account owner
@@ -151,30 +189,31 @@ async fn main() -> Result<(), Box<dyn Error>> {
         &spl_token_2022::id(),
     );
 
-    // Instruction to create associated token account
+    // Instruction to create associated token account, with its rent funded by the rent funder
     let create_associated_token_account_instruction = create_associated_token_account(
-        &wallet_1.pubkey(), // Funding account
-        &wallet_1.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &ctx.payers.rent_funder(&wallet_1.pubkey()), // Funding account
+        &wallet_1.pubkey(),                          // Token account owner
+        &mint.pubkey(),                              // Mint
         &spl_token_2022::id(),
     );
 
     // Instruction to reallocate the token account to include the `ConfidentialTransferAccount` extension
     let reallocate_instruction = reallocate(
         &spl_token_2022::id(),
-        &sender_associated_token_address, // Token account
-        &wallet_1.pubkey(),               // Payer
-        &wallet_1.pubkey(),               // Token account owner
-        &[&wallet_1.pubkey()],            // Signers
+        &sender_associated_token_address,            // Token account
+        &ctx.payers.rent_funder(&wallet_1.pubkey()), // Payer
+        &wallet_1.pubkey(),                          // Token account owner
+        &[&wallet_1.pubkey()],                       // Signers
         &[ExtensionType::ConfidentialTransferAccount], // Extension to reallocate space for
     )?;
 
     // Create the ElGamal keypair and AES key for the sender token account
-    let elgamal_keypair =
-        ElGamalKeypair::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes())
-            .unwrap();
-    let aes_key =
-        AeKey::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes()).unwrap();
+    let elgamal_keypair = config
+        .key_derivation
+        .elgamal_keypair(&wallet_1, &sender_associated_token_address)?;
+    let aes_key = config
+        .key_derivation
+        .aes_key(&wallet_1, &sender_associated_token_address)?;
 
     // The maximum number of `Deposit` and `Transfer` instructions that can
     // credit `pending_balance` before the `ApplyPendingBalance` instruction is executed
@@ -197,7 +236,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Appends the `VerifyPubkeyValidityProof` instruction right after the `ConfigureAccount` instruction.
     let configure_account_instruction = configure_account(
         &spl_token_2022::id(),                  // Program ID
-        &&sender_associated_token_address,      // Token account
+        &sender_associated_token_address,       // Token account
         &mint.pubkey(),                         // Mint
         decryptable_balance,                    // Initial balance
         maximum_pending_balance_credit_counter, // Maximum pending balance credit counter
@@ -214,25 +253,24 @@ async fn main() -> Result<(), Box<dyn Error>> {
     ];
     instructions.extend(configure_account_instruction);
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
+    ctx.send(
+        "Create Sender Token Account",
         &instructions,
-        Some(&wallet_1.pubkey()),
+        &wallet_1.pubkey(),
         &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
+    )?;
+    ctx.finish_step();
 
-    println!(
-        "\nCreate Sender Token Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
+    // Rent for the token account is funded by the associated token account program
+    ctx.report
+        .record_rent(client.get_balance(&sender_associated_token_address)?);
 
     // 4. Mint Tokens ----------------------------------------------------------
 
+    ctx.start_step("Minting tokens");
     // Mint 100.00 tokens
     let amount = 100_00;
+    let balances = ctx.snapshot_balances(&sender_associated_token_address, &wallet_1)?;
 
     // Instruction to mint tokens
     let mint_to_instruction: Instruction = mint_to(
@@ -244,27 +282,31 @@ async fn main() -> Result<(), Box<dyn Error>> {
         amount,                           // Amount to mint
     )?;
 
-    let transaction = Transaction::new_signed_with_payer(
+    ctx.send(
+        "Mint Tokens",
         &[mint_to_instruction],
-        Some(&wallet_1.pubkey()),
+        &wallet_1.pubkey(),
         &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nMint Tokens: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
+    )?;
+    ctx.verify_balances(
+        balances,
+        &wallet_1,
+        BalanceChange {
+            public: amount as i128,
+            ..BalanceChange::default()
+        },
+    )?;
+    ctx.finish_step();
 
     // 5. Deposit Tokens -------------------------------------------------------
 
+    ctx.start_step("Depositing tokens");
     // Confidential balance has separate "pending" and "available" balances
     // Must first deposit tokens from non-confidential balance to  "pending" confidential balance
 
     // Amount to deposit, 50.00 tokens
     let deposit_amount = 50_00;
+    let balances = ctx.snapshot_balances(&sender_associated_token_address, &wallet_1)?;
 
     // Instruction to deposit from non-confidential balance to "pending" balance
     let deposit_instruction = deposit(
@@ -277,28 +319,45 @@ async fn main() -> Result<(), Box<dyn Error>> {
         &[&wallet_1.pubkey()],            // Signers
     )?;
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[deposit_instruction],
-        Some(&wallet_1.pubkey()),
+    ctx.send(
+        "Deposit Tokens",
+        &ctx.attach_memo(&[deposit_instruction]),
+        &wallet_1.pubkey(),
         &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nDeposit Tokens: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
+    )?;
+    ctx.verify_balances(
+        balances,
+        &wallet_1,
+        BalanceChange {
+            public: -(deposit_amount as i128),
+            pending: deposit_amount as i128,
+            ..BalanceChange::default()
+        },
+    )?;
+    ctx.finish_step();
 
     // 6. Apply Pending Balance -------------------------------------------------
 
     // The "pending" balance must be applied to "available" balance before it can be transferred
+    // See `flows::apply_pending` for the details
+    let balances = ctx.snapshot_balances(&sender_associated_token_address, &wallet_1)?;
+    flows::apply_pending::apply_pending_balance(
+        &mut ctx,
+        &sender_associated_token_address,
+        &wallet_1,
+        flows::apply_pending::DEFAULT_MAX_ROUNDS,
+        flows::apply_pending::DEFAULT_ROUND_PAUSE,
+    )?;
+    let expected = balances
+        .as_ref()
+        .map(BalanceChange::apply_pending)
+        .unwrap_or_default();
+    ctx.verify_balances(balances, &wallet_1, expected)?;
+    ctx.flow = "setup";
 
     // A "non-blocking" RPC client (for async calls)
     let rpc_client = NonBlockingRpcClient::new_with_commitment(
-        String::from("http://127.0.0.1:8899"),
+        config.rpc_url.clone(),
         CommitmentConfig::confirmed(),
     );
 
@@ -314,55 +373,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Arc::new(wallet_1.insecure_clone()),
     );
 
-    // Get sender token account data
-    let token_account_info = token
-        .get_account_info(&sender_associated_token_address)
-        .await?;
-
-    // Unpack the ConfidentialTransferAccount extension portion of the token account data
-    let confidential_transfer_account =
-        token_account_info.get_extension::<ConfidentialTransferAccount>()?;
-
-    // ConfidentialTransferAccount extension information needed to construct an `ApplyPendingBalance` instruction.
-    let apply_pending_balance_account_info =
-        ApplyPendingBalanceAccountInfo::new(confidential_transfer_account);
-
-    // Return the number of times the pending balance has been credited
-    let expected_pending_balance_credit_counter =
-        apply_pending_balance_account_info.pending_balance_credit_counter();
-
-    // Update the decryptable available balance (add pending balance to available balance)
-    let new_decryptable_available_balance = apply_pending_balance_account_info
-        .new_decryptable_available_balance(&elgamal_keypair.secret(), &aes_key)
-        .map_err(|_| TokenError::AccountDecryption)?;
-
-    // Create a `ApplyPendingBalance` instruction
-    let apply_pending_balance_instruction = apply_pending_balance(
-        &spl_token_2022::id(),
-        &sender_associated_token_address,        // Token account
-        expected_pending_balance_credit_counter, // Expected number of times the pending balance has been credited
-        new_decryptable_available_balance, // Cipher text of the new decryptable available balance
-        &wallet_1.pubkey(),                // Token account owner
-        &[&wallet_1.pubkey()],             // Additional signers
-    )?;
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[apply_pending_balance_instruction],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nApply Pending Balance: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
-
     // 7. Create Recipient Token Account -----------------------------------------
 
+    ctx.start_step("Creating recipient token account");
     // Associated token address of the recipient
     let recipient_associated_token_address = get_associated_token_address_with_program_id(
         &wallet_2.pubkey(), // Token account owner
@@ -370,11 +383,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
         &spl_token_2022::id(),
     );
 
-    // Instruction to create associated token account
+    // Instruction to create associated token account, with its rent funded by the rent funder
     let create_associated_token_account_instruction = create_associated_token_account(
-        &wallet_2.pubkey(), // Funding account
-        &wallet_2.pubkey(), // Token account owner
-        &mint.pubkey(),     // Mint
+        &ctx.payers.rent_funder(&wallet_2.pubkey()), // Funding account
+        &wallet_2.pubkey(),                          // Token account owner
+        &mint.pubkey(),                              // Mint
         &spl_token_2022::id(),
     );
 
@@ -382,18 +395,19 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let reallocate_instruction = reallocate(
         &spl_token_2022::id(),
         &recipient_associated_token_address,
-        &wallet_2.pubkey(),    // payer
-        &wallet_2.pubkey(),    // owner
-        &[&wallet_2.pubkey()], // signers
+        &ctx.payers.rent_funder(&wallet_2.pubkey()), // payer
+        &wallet_2.pubkey(),                          // owner
+        &[&wallet_2.pubkey()],                       // signers
         &[ExtensionType::ConfidentialTransferAccount],
     )?;
 
     // Create the ElGamal keypair and AES key for the recipient token account
-    let elgamal_keypair =
-        ElGamalKeypair::new_from_signer(&wallet_2, &recipient_associated_token_address.to_bytes())
-            .unwrap();
-    let aes_key =
-        AeKey::new_from_signer(&wallet_2, &recipient_associated_token_address.to_bytes()).unwrap();
+    let elgamal_keypair = config
+        .key_derivation
+        .elgamal_keypair(&wallet_2, &recipient_associated_token_address)?;
+    let aes_key = config
+        .key_derivation
+        .aes_key(&wallet_2, &recipient_associated_token_address)?;
 
     let maximum_pending_balance_credit_counter = 65536; // Default value or custom
     let decryptable_balance = aes_key.encrypt(0);
@@ -426,478 +440,58 @@ async fn main() -> Result<(), Box<dyn Error>> {
     ];
     instructions.extend(configure_account_instruction);
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
+    ctx.send(
+        "Create Recipient Token Account",
         &instructions,
-        Some(&wallet_2.pubkey()),
+        &wallet_2.pubkey(),
         &[&wallet_2],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nCreate Recipient Token Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
-
-    // 8. Prepare proof data ---------------------------------------------------
-
-    // Must first create 3 accounts to store proofs before transferring tokens
-    // This must be done in a separate transactions because the proofs are too large for single transaction
-
-    // Equality Proof - prove that two ciphertexts encrypt the same value
-    // Ciphertext Validity Proof - prove that ciphertexts are properly generated
-    // Range Proof - prove that ciphertexts encrypt a value in a specified range (0, u64::MAX)
-
-    // "Authority" for the proof accounts (to close the accounts after the transfer)
-    let context_state_authority = &wallet_1;
-
-    // Generate address for equality proof account
-    let equality_proof_context_state_account = Keypair::new();
-    let equality_proof_pubkey = equality_proof_context_state_account.pubkey();
-
-    // Generate address for ciphertext validity proof account
-    let ciphertext_validity_proof_context_state_account = Keypair::new();
-    let ciphertext_validity_proof_pubkey = ciphertext_validity_proof_context_state_account.pubkey();
-
-    // Generate address for range proof account
-    let range_proof_context_state_account = Keypair::new();
-    let range_proof_pubkey = range_proof_context_state_account.pubkey();
-
-    // Type for split transfer (without fee) instruction proof context state account addresses intended to be used as parameters to functions.
-    let transfer_context_state_accounts = TransferSplitContextStateAccounts {
-        equality_proof: &equality_proof_pubkey,
-        ciphertext_validity_proof: &ciphertext_validity_proof_pubkey,
-        range_proof: &range_proof_pubkey,
-        authority: &context_state_authority.pubkey(),
-        no_op_on_uninitialized_split_context_state: false,
-        close_split_context_state_accounts: None,
-    };
-
-    // Get sender token account data
-    let token_account_info = token
-        .get_account_info(&sender_associated_token_address)
-        .await?;
-
-    let extension_data = token_account_info.get_extension::<ConfidentialTransferAccount>()?;
-
-    // ConfidentialTransferAccount extension information needed to create proof data
-    let transfer_account_info = TransferAccountInfo::new(extension_data);
-
-    let transfer_amount = 50_00;
-
-    let sender_elgamal_keypair =
-        ElGamalKeypair::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes())?;
-    let sender_aes_key =
-        AeKey::new_from_signer(&wallet_1, &sender_associated_token_address.to_bytes())?;
-
-    // Get recipient token account data
-    let recipient_account = token
-        .get_account(recipient_associated_token_address)
-        .await?;
-
-    // Get recipient ElGamal pubkey from the recipient token account data and convert to elgamal::ElGamalPubkey
-    let recipient_elgamal_pubkey: elgamal::ElGamalPubkey =
-        StateWithExtensionsOwned::<Account>::unpack(recipient_account.data)?
-            .get_extension::<ConfidentialTransferAccount>()?
-            .elgamal_pubkey
-            .try_into()?;
-
-    // Get mint account data
-    let mint_account = token.get_account(mint.pubkey()).await?;
-
-    // Get auditor ElGamal pubkey from the mint account data
-    let auditor_elgamal_pubkey_option = Option::<ElGamalPubkey>::from(
-        StateWithExtensionsOwned::<Mint>::unpack(mint_account.data)?
-            .get_extension::<ConfidentialTransferMint>()?
-            .auditor_elgamal_pubkey,
-    );
-
-    // Convert auditor ElGamal pubkey to elgamal::ElGamalPubkey type
-    let auditor_elgamal_pubkey: elgamal::ElGamalPubkey = auditor_elgamal_pubkey_option
-        .ok_or("No Auditor ElGamal pubkey")?
-        .try_into()?;
-
-    // Generate proof data
-    let (
-        equality_proof_data,
-        ciphertext_validity_proof_data,
-        range_proof_data,
-        source_decrypt_handles,
-    ) = transfer_account_info
-        .generate_split_transfer_proof_data(
-            transfer_amount,
-            &sender_elgamal_keypair,
-            &sender_aes_key,
-            &recipient_elgamal_pubkey,
-            Some(&auditor_elgamal_pubkey),
-        )
-        .unwrap();
-
-    // 9. Create 3 proofs ------------------------------------------------------
-
-    // Range Proof ------------------------------------------------------------------------------
-
-    // space and rent required for range proof account
-    let space = size_of::<ProofContextState<BatchedRangeProofContext>>();
-    let rent = client.get_minimum_balance_for_rent_exemption(space)?;
-
-    // Create Account for Range Proof
-    let create_range_proof_account_instruction = create_account(
-        &wallet_1.pubkey(),
-        &range_proof_context_state_account.pubkey(),
-        rent,
-        space as u64,
-        &zk_token_proof_program::id(),
-    );
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[create_range_proof_account_instruction],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1, &range_proof_context_state_account], // Signers
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nCreate Range Proof Context State: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
-
-    // Instruction to initialize account with proof data
-    // Sent as separate transaction because proof instruction too large
-    let verify_proof_instruction = ProofInstruction::VerifyBatchedRangeProofU128
-        .encode_verify_proof(
-            Some(ContextStateInfo {
-                context_state_account: transfer_context_state_accounts.range_proof,
-                context_state_authority: transfer_context_state_accounts.authority,
-            }),
-            &range_proof_data,
-        );
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[verify_proof_instruction],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nInitialize Range Proof Context State: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
-
-    // Equality Proof ---------------------------------------------------------------------------
-
-    // Calculate the space required for the account
-    let space = size_of::<ProofContextState<CiphertextCommitmentEqualityProofContext>>();
-    let rent = client.get_minimum_balance_for_rent_exemption(space)?;
-
-    // Create Account for Equality Proof
-    let create_equality_proof_account_instruction = create_account(
-        &wallet_1.pubkey(),
-        &transfer_context_state_accounts.equality_proof,
-        rent,
-        space as u64,
-        &zk_token_proof_program::id(),
-    );
-
-    // Instruction to initialize account with proof data
-    let verify_equality_proof_instruction = ProofInstruction::VerifyCiphertextCommitmentEquality
-        .encode_verify_proof(
-            Some(ContextStateInfo {
-                context_state_account: transfer_context_state_accounts.equality_proof,
-                context_state_authority: transfer_context_state_accounts.authority,
-            }),
-            &equality_proof_data,
-        );
-
-    let instructions = vec![
-        create_equality_proof_account_instruction,
-        verify_equality_proof_instruction,
-    ];
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &instructions,
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1, &equality_proof_context_state_account], // Signers
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-            "\nCreate and Initialize Equality Proof Context State: https://solana.fm/tx/{}?cluster=localnet-solana",
-            transaction_signature
-        );
-
-    // Ciphertext Validity Proof ----------------------------------------------------------------
-
-    let space =
-        size_of::<ProofContextState<BatchedGroupedCiphertext2HandlesValidityProofContext>>();
-    let rent = client.get_minimum_balance_for_rent_exemption(space)?;
-
-    // Create Account for Ciphertext Validity Proof
-    let create_ciphertext_validity_proof_account_instruction = create_account(
-        &wallet_1.pubkey(),
-        &transfer_context_state_accounts.ciphertext_validity_proof,
-        rent,
-        space as u64,
-        &zk_token_proof_program::id(),
-    );
-
-    // Instruction to initialize account with proof data
-    let verify_ciphertext_validity_proof_instruction =
-        ProofInstruction::VerifyBatchedGroupedCiphertext2HandlesValidity.encode_verify_proof(
-            Some(ContextStateInfo {
-                context_state_account: transfer_context_state_accounts.ciphertext_validity_proof,
-                context_state_authority: transfer_context_state_accounts.authority,
-            }),
-            &ciphertext_validity_proof_data,
-        );
-
-    let instructions = vec![
-        create_ciphertext_validity_proof_account_instruction,
-        verify_ciphertext_validity_proof_instruction,
-    ];
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &instructions,
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1, &ciphertext_validity_proof_context_state_account], // Signers
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-            "\nCreate and Initialize Ciphertext Validity Proof Context State: https://solana.fm/tx/{}?cluster=localnet-solana",
-            transaction_signature
-        );
-
-    // 10. Transfer with Split Proofs -------------------------------------------
-
-    // Get sender token account data
-    let token_account_info = token
-        .get_account_info(&sender_associated_token_address)
-        .await?;
-
-    let extension_data = token_account_info.get_extension::<ConfidentialTransferAccount>()?;
-
-    // ConfidentialTransferAccount extension information needed to create proof data
-    let transfer_account_info = TransferAccountInfo::new(extension_data);
-
-    // Calculate the new decryptable available balance
-    let new_decryptable_available_balance = transfer_account_info
-        .new_decryptable_available_balance(transfer_amount, &sender_aes_key)
-        .map_err(|_| TokenError::AccountDecryption)?;
-
-    // Create the 'transfer_with_split_proofs' instruction
-    let transfer_with_split_proofs_instruction = transfer_with_split_proofs(
-        &spl_token_2022::id(),
-        &sender_associated_token_address,    // Source token account
-        &mint.pubkey(),                      // Mint
-        &recipient_associated_token_address, // Destination token account
-        new_decryptable_available_balance.into(), // Updated source token account available balance
-        &wallet_1.pubkey(),                  // Source token account owner
-        transfer_context_state_accounts,     // Proof context state accounts
-        &source_decrypt_handles, // The ElGamal ciphertext decryption handle of the transfer amount under the source public key of the transfer.
     )?;
+    ctx.finish_step();
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[transfer_with_split_proofs_instruction],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
+    ctx.report
+        .record_rent(client.get_balance(&recipient_associated_token_address)?);
 
-    println!(
-            "\nConfidential Transfer with Split Proofs: https://solana.fm/tx/{}?cluster=localnet-solana",
-            transaction_signature
-        );
+    // 8. Confidential Transfer ------------------------------------------------
 
-    // 11. Close Proof Accounts --------------------------------------------------
-
-    // Authority to close the proof accounts
-    let context_state_authority_pubkey = context_state_authority.pubkey();
-    // Lamports from the closed proof accounts will be sent to this account
-    let destination_account = &wallet_1.pubkey();
+    // Create the 3 proof accounts, then transfer using the proofs, which closes the proof accounts
+    // See `flows::transfer` for the details
+    let transfer_amount = 50_00;
 
-    // Close the equality proof account
-    let close_equality_proof_instruction = close_context_state(
-        ContextStateInfo {
-            context_state_account: &equality_proof_pubkey,
-            context_state_authority: &context_state_authority_pubkey,
-        },
-        &destination_account,
-    );
+    // Recipient balances before the transfer, the flow itself only verifies the sender
+    let recipient_balances =
+        ctx.snapshot_balances(&recipient_associated_token_address, &wallet_2)?;
 
-    // Close the ciphertext validity proof account
-    let close_ciphertext_validity_proof_instruction = close_context_state(
-        ContextStateInfo {
-            context_state_account: &ciphertext_validity_proof_pubkey,
-            context_state_authority: &context_state_authority_pubkey,
-        },
-        &destination_account,
-    );
-
-    // Close the range proof account
-    let close_range_proof_instruction = close_context_state(
-        ContextStateInfo {
-            context_state_account: &range_proof_pubkey,
-            context_state_authority: &context_state_authority_pubkey,
+    flows::transfer::transfer_tokens(
+        &mut ctx,
+        &token,
+        &wallet_1,
+        &recipient_associated_token_address,
+        transfer_amount,
+    )
+    .await?;
+
+    // The transfer is credited to the recipient's pending balance
+    ctx.verify_balances(
+        recipient_balances,
+        &wallet_2,
+        BalanceChange {
+            pending: transfer_amount as i128,
+            ..BalanceChange::default()
         },
-        &destination_account,
-    );
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[
-            close_equality_proof_instruction,
-            close_ciphertext_validity_proof_instruction,
-            close_range_proof_instruction,
-        ],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1], // Signers
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nClose Proof Accounts: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
+    )?;
 
-    // 12. Withdraw Tokens ------------------------------------------------------
+    // 9. Withdraw Tokens ------------------------------------------------------
 
+    // Create and verify a withdraw proof account, then withdraw using the proof
+    // See `flows::withdraw` for the details
     let withdraw_amount = 20_00;
 
-    // Get recipient token account data
-    let token_account = token
-        .get_account_info(&sender_associated_token_address)
+    flows::withdraw::withdraw_tokens(&mut ctx, &token, &wallet_1, withdraw_amount, decimals)
         .await?;
 
-    // Unpack the ConfidentialTransferAccount extension portion of the token account data
-    let extension_data = token_account.get_extension::<ConfidentialTransferAccount>()?;
-
-    // Confidential Transfer extension information needed to construct a `Withdraw` instruction.
-    let withdraw_account_info = WithdrawAccountInfo::new(extension_data);
-
-    // Create a withdraw proof data
-    let proof_data = withdraw_account_info.generate_proof_data(
-        withdraw_amount,
-        &sender_elgamal_keypair,
-        &sender_aes_key,
-    )?;
-
-    // Generate address for withdraw proof account
-    let withdraw_proof_context_state_account = Keypair::new();
-    let withdraw_proof_pubkey = withdraw_proof_context_state_account.pubkey();
-    // Authority for the withdraw proof account (to close the account)
-    let context_state_authority = &wallet_1;
-
-    let space = std::mem::size_of::<ProofContextState<WithdrawProofContext>>();
-    let rent = client.get_minimum_balance_for_rent_exemption(space)?;
-
-    let withdraw_proof_context_state_info = ContextStateInfo {
-        context_state_account: &withdraw_proof_pubkey,
-        context_state_authority: &context_state_authority.pubkey(),
-    };
-
-    // Instruction to create the withdraw proof account
-    let create_withdraw_proof_account = create_account(
-        &wallet_1.pubkey(),
-        &withdraw_proof_pubkey,
-        rent,
-        space as u64,
-        &zk_token_proof_program::id(),
-    );
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[create_withdraw_proof_account],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1, &withdraw_proof_context_state_account],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nCreate Withdraw Proof Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
-
-    // Instruction to initialize account with proof data
-    // Sent as separate transaction because proof instruction too large
-    let verify_withdraw_proof_instruction = ProofInstruction::VerifyWithdraw
-        .encode_verify_proof(Some(withdraw_proof_context_state_info), &proof_data);
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[verify_withdraw_proof_instruction],
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nInitialize Withdraw Proof Account: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
-
-    // Update the decryptable available balance
-    let new_decryptable_available_balance = withdraw_account_info
-        .new_decryptable_available_balance(withdraw_amount, &sender_aes_key)
-        .map_err(|_| TokenError::AccountDecryption)?;
-
-    // let balance = new_decryptable_available_balance.decrypt(&aes_key);
-    // print!("\nAvailable Balance: {:?}", balance);
-
-    // The proof is pre-verified into a context state account.
-    let proof_location = ProofLocation::ContextStateAccount(&withdraw_proof_pubkey);
-
-    // Create a `Withdraw` instruction
-    let withdraw_instruction = withdraw(
-        &spl_token_2022::id(),
-        &sender_associated_token_address,
-        &mint.pubkey(),
-        withdraw_amount,
-        decimals,
-        new_decryptable_available_balance,
-        &wallet_1.pubkey(),
-        &[&wallet_1.pubkey()],
-        proof_location,
-    )?;
-
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &withdraw_instruction,
-        Some(&wallet_1.pubkey()),
-        &[&wallet_1],
-        recent_blockhash,
-    );
-
-    let transaction_signature = client.send_and_confirm_transaction(&transaction)?;
-
-    println!(
-        "\nWithdraw Tokens: https://solana.fm/tx/{}?cluster=localnet-solana",
-        transaction_signature
-    );
+    progress.finish();
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
 
     Ok(())
 }
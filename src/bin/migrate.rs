@@ -0,0 +1,125 @@
+// cargo run --bin migrate -- --to-mint <NEW_MINT>
+use clap::Parser;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use spl_token_client::{
+    client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
+    token::Token,
+};
+use std::{error::Error, process::ExitCode, sync::Arc};
+
+use keypair_utils::{
+    cli::{terminal_output, FlowArgs},
+    config::Config,
+    exit_code,
+    flows::{migrate, FlowContext},
+    get_or_create_keypair,
+    journal::Journal,
+    mint::MintSelector,
+    proof_cache::ProofCache,
+    seed::KeypairSource,
+    shutdown,
+    ui_amount::UiAmount,
+};
+
+// Move a holder's tokens from the --mint Token-2022 mint to a reissued mint, under either token program.
+// See `flows::migrate` for the details
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of the .env keypair holding the tokens
+    #[arg(long, default_value = "wallet_1")]
+    wallet: String,
+
+    /// Reissued mint: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY")]
+    to_mint: MintSelector,
+
+    /// Name of the .env keypair that is the mint authority of the reissued mint
+    #[arg(long, value_name = "NAME", default_value = "wallet_1")]
+    mint_authority: String,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    exit_code::report(run().await)
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let owner = get_or_create_keypair(&args.wallet)?;
+    let mint_authority = get_or_create_keypair(&args.mint_authority)?;
+    let mint = args.flow.mint.pubkey()?;
+    let new_mint = args.to_mint.pubkey()?;
+    if new_mint == mint {
+        return Err("--to-mint must be a different mint than --mint".into());
+    }
+
+    let config = Config::load()?;
+
+    // Stop at the next step on Ctrl-C, closing the proof accounts created so far instead of leaking their rent
+    shutdown::install()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.flow.check_mint(&client, &mint)?;
+
+    // A "non-blocking" RPC client (for async calls)
+    let rpc_client = NonBlockingRpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        CommitmentConfig::confirmed(),
+    );
+
+    let program_client =
+        ProgramRpcClient::new(Arc::new(rpc_client), ProgramRpcClientSendTransaction);
+
+    // Create a "token" client, to use various helper functions for Token Extensions
+    let token = Token::new(
+        Arc::new(program_client),
+        &spl_token_2022::id(),
+        &mint,
+        None,
+        Arc::new(owner.insecure_clone()),
+    );
+
+    let mut ctx = FlowContext::new(&client, migrate::TOTAL_STEPS)
+        .with_journal(Journal::open(&config.journal_path)?);
+    ctx.heap_frame_bytes = config.heap_frame_bytes;
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
+    ctx.memo = args.flow.memo.clone();
+    ctx.proof_cache = Some(ProofCache::open(&config.journal_path)?);
+    ctx.policy = config.policy()?;
+    ctx.keypairs = KeypairSource::new(config.seed.clone());
+    let progress = terminal_output(
+        &mut ctx.events,
+        &config.explorer,
+        UiAmount::fetch(&client, &mint)?,
+    );
+    ctx.review = args.flow.review(&progress);
+
+    let outcome =
+        migrate::migrate_holder(&mut ctx, &token, &owner, &new_mint, &mint_authority).await?;
+
+    progress.finish();
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+    let new_ui_amount = UiAmount::fetch(&client, &new_mint)?;
+    println!(
+        "\nMigrated {} ({} withdrawn from the confidential balance) to {} in {}",
+        ui_amount.format(outcome.burned),
+        ui_amount.format(outcome.withdrawn),
+        new_ui_amount.format(outcome.minted),
+        outcome.new_token_account
+    );
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
+    Ok(())
+}
@@ -0,0 +1,60 @@
+// cargo run --bin mint-expectation -- --out mint.expected.json
+use clap::Parser;
+use keypair_utils::{
+    config::Config,
+    exit_code,
+    mint::MintSelector,
+    mint_expectation::{MintConfiguration, MintExpectation},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{error::Error, fs, path::PathBuf, process::ExitCode};
+
+// Write the expected configuration of a mint as it is now: its decimals, extensions and authorities.
+// Flow binaries given the file with --strict abort before any proof work if the mint stops matching it,
+// e.g. after an authority was rotated or the mint address was swapped in the configuration.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+
+    /// File to write the expectation to, which must not exist yet. Printed when not given
+    #[arg(long, value_name = "PATH")]
+    out: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let mint = args.mint.pubkey()?;
+
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let expectation = MintExpectation::of(&MintConfiguration::fetch(&client, &mint)?);
+    let json = serde_json::to_string_pretty(&expectation.to_json())?;
+
+    match &args.out {
+        Some(out) => {
+            if out.exists() {
+                return Err(format!("{} already exists", out.display()).into());
+            }
+            fs::write(out, json)?;
+            println!(
+                "Wrote the expected configuration of mint {} to {}: {} extensions, {} authorities",
+                mint,
+                out.display(),
+                expectation.extensions.len(),
+                expectation.authorities.len()
+            );
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
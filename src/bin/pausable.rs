@@ -0,0 +1,133 @@
+// cargo run --bin pausable -- pause
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    cli::FlowArgs,
+    config::Config,
+    exit_code,
+    flows::FlowContext,
+    get_or_create_keypair,
+    journal::Journal,
+    pausable::{get_pausable_config, pause, resume},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signer};
+use std::{error::Error, process::ExitCode};
+
+// Pause and resume a mint created with the Pausable extension (`--pausable`)
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Pause all transfers, mints, burns and withdraws of the mint's tokens
+    Pause(AuthorityArgs),
+    /// Resume a paused mint
+    Resume(AuthorityArgs),
+    /// Show whether the mint is paused
+    Status(StatusArgs),
+}
+
+#[derive(Args, Debug)]
+struct AuthorityArgs {
+    /// Name of the .env keypair of the pause authority
+    #[arg(long, default_value = "wallet_1")]
+    authority: String,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[derive(Args, Debug)]
+struct StatusArgs {
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    match cli.command {
+        Command::Status(args) => {
+            let mint = args.flow.mint.pubkey()?;
+            match get_pausable_config(&client.get_account_data(&mint)?)? {
+                Some(pausable) => println!(
+                    "Mint: {}\nPause Authority: {}\nPaused: {}",
+                    mint,
+                    pausable
+                        .authority
+                        .map_or("none".to_string(), |authority| authority.to_string()),
+                    pausable.paused
+                ),
+                None => println!("Mint {} doesn't have the Pausable extension", mint),
+            }
+        }
+        Command::Pause(args) => set_paused(&client, &config, &args, true)?,
+        Command::Resume(args) => set_paused(&client, &config, &args, false)?,
+    }
+    Ok(())
+}
+
+fn set_paused(
+    client: &RpcClient,
+    config: &Config,
+    args: &AuthorityArgs,
+    paused: bool,
+) -> Result<(), Box<dyn Error>> {
+    let authority = get_or_create_keypair(&args.authority)?;
+    let mint = args.flow.mint.pubkey()?;
+
+    // Check the mint state first, for a clear error instead of a failed transaction
+    let pausable = get_pausable_config(&client.get_account_data(&mint)?)?
+        .ok_or(format!("Mint {} doesn't have the Pausable extension", mint))?;
+    if pausable.authority != Some(authority.pubkey()) {
+        return Err(format!(
+            "{} is not the pause authority of mint {}",
+            authority.pubkey(),
+            mint
+        )
+        .into());
+    }
+    if pausable.paused == paused {
+        println!(
+            "Mint {} is already {}",
+            mint,
+            if paused { "paused" } else { "resumed" }
+        );
+        return Ok(());
+    }
+
+    let mut ctx = FlowContext::new(client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "pausable";
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+
+    let (label, instruction) = if paused {
+        ("Pause Mint", pause(&mint, &authority.pubkey()))
+    } else {
+        ("Resume Mint", resume(&mint, &authority.pubkey()))
+    };
+    let transaction_signature =
+        ctx.send(label, &[instruction], &authority.pubkey(), &[&authority])?;
+
+    println!(
+        "\n{}: {}",
+        label,
+        config.explorer.tx_url(&transaction_signature)
+    );
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
+    Ok(())
+}
@@ -0,0 +1,155 @@
+// cargo run --bin payroll -- --csv payroll.csv
+use clap::Parser;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use spl_token_client::{
+    client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
+    token::Token,
+};
+use std::{error::Error, fs, path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+
+use keypair_utils::{
+    cli::{terminal_output, FlowArgs},
+    config::Config,
+    contacts::AddressBook,
+    exit_code,
+    flows::{
+        payroll::{check_funds, pay_payroll, prepare_payroll, DEFAULT_MIN_INTERVAL},
+        transfer, FlowContext,
+    },
+    get_or_create_keypair,
+    journal::Journal,
+    payroll::parse_payroll,
+    proof_cache::ProofCache,
+    seed::KeypairSource,
+    shutdown,
+    ui_amount::UiAmount,
+};
+
+// Pay every row of a CSV of `recipient,amount` with a confidential transfer from wallet_1, then reconcile
+// the sender's available balance and the journal with what was paid.
+// Recipients are contact names from the address book or wallet addresses, amounts are in UI units, e.g. 12.50
+#[derive(Parser, Debug)]
+struct Args {
+    /// CSV file of the payroll
+    #[arg(long, value_name = "FILE")]
+    csv: PathBuf,
+
+    /// Least number of seconds between the start of two transfers
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_MIN_INTERVAL.as_secs())]
+    min_interval: u64,
+
+    /// Only check the payroll against the address book, token accounts and available balance, without paying
+    #[arg(long)]
+    check: bool,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    exit_code::report(run().await)
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let wallet_1 = get_or_create_keypair("wallet_1")?;
+    let mint = args.flow.mint.pubkey()?;
+
+    let config = Config::load()?;
+
+    // Stop after the current transfer on Ctrl-C, closing its proof accounts instead of leaking their rent
+    shutdown::install()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.flow.check_mint(&client, &mint)?;
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+
+    let csv = fs::read_to_string(&args.csv)
+        .map_err(|error| format!("Could not read {}: {}", args.csv.display(), error))?;
+    let lines = parse_payroll(&csv, &ui_amount)?;
+
+    // A "non-blocking" RPC client (for async calls), used to set up the "token" client
+    let rpc_client = NonBlockingRpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        CommitmentConfig::confirmed(),
+    );
+
+    let program_client =
+        ProgramRpcClient::new(Arc::new(rpc_client), ProgramRpcClientSendTransaction);
+
+    // Create a "token" client, to use various helper functions for Token Extensions
+    let token = Token::new(
+        Arc::new(program_client),
+        &spl_token_2022::id(),
+        &mint,
+        Some(ui_amount.decimals),
+        Arc::new(wallet_1.insecure_clone()),
+    );
+
+    let mut ctx = FlowContext::new(&client, transfer::STEPS * lines.len())
+        .with_journal(Journal::open(&config.journal_path)?);
+    ctx.heap_frame_bytes = config.heap_frame_bytes;
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
+    ctx.memo = args.flow.memo.clone();
+    ctx.proof_cache = Some(ProofCache::open(&config.journal_path)?);
+    ctx.policy = config.policy()?;
+    ctx.keypairs = KeypairSource::new(config.seed.clone());
+
+    let payments = prepare_payroll(
+        &ctx,
+        &mint,
+        &AddressBook::open(&config.contacts_path)?,
+        &lines,
+    )?;
+    let total: u64 = payments.iter().map(|payment| payment.amount).sum();
+    eprintln!(
+        "Payroll of {} rows totalling {}",
+        payments.len(),
+        ui_amount.format(total)
+    );
+    if args.check {
+        check_funds(&ctx, &wallet_1, &mint, total)?;
+        println!("The payroll is ready to pay");
+        return Ok(());
+    }
+
+    let progress = terminal_output(&mut ctx.events, &config.explorer, ui_amount);
+    ctx.review = args.flow.review(&progress);
+    let report = pay_payroll(
+        &mut ctx,
+        &token,
+        &wallet_1,
+        payments,
+        Duration::from_secs(args.min_interval),
+    )
+    .await?;
+    progress.finish();
+
+    if args.flow.json {
+        println!("{}", report.to_json());
+    } else {
+        println!("\n{}", report);
+    }
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
+
+    if !report.is_reconciled() || report.failed() > 0 {
+        return Err(format!(
+            "{} of {} payroll rows weren't paid or reconciled",
+            report.failed(),
+            report.payments.len()
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+// cargo run --bin portfolio -- --wallet wallet_1
+use clap::Parser;
+use keypair_utils::{config::Config, exit_code, get_or_create_keypair, portfolio::Portfolio};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signer};
+use std::{error::Error, process::ExitCode};
+
+// List every Token-2022 account a wallet owns, with its extensions, public balance and decrypted confidential balances
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of the .env keypair of the wallet, whose keys decrypt the confidential balances
+    #[arg(long, default_value = "wallet_1", conflicts_with = "address")]
+    wallet: String,
+
+    /// Address of the wallet, for wallets without a keypair in .env. Confidential balances stay encrypted
+    #[arg(long, value_name = "PUBKEY")]
+    address: Option<Pubkey>,
+
+    /// Print the portfolio as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let config = Config::load()?;
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let portfolio = match args.address {
+        Some(address) => Portfolio::fetch(&client, &address)?,
+        None => {
+            let owner = get_or_create_keypair(&args.wallet)?;
+            let mut portfolio = Portfolio::fetch(&client, &owner.pubkey())?;
+            portfolio.decrypt(&owner, &config.key_derivation)?;
+            portfolio
+        }
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&portfolio.to_json())?);
+        return Ok(());
+    }
+
+    println!(
+        "{} Token-2022 accounts owned by {}",
+        portfolio.accounts.len(),
+        portfolio.owner
+    );
+    for account in &portfolio.accounts {
+        let extensions: Vec<String> = account
+            .extensions
+            .iter()
+            .map(|extension| format!("{:?}", extension))
+            .collect();
+        println!("\nToken Account: {}", account.address);
+        println!("  Mint:        {}", account.mint);
+        println!("  Extensions:  {}", extensions.join(", "));
+        println!(
+            "  Public:      {}",
+            account.ui_amount.format(account.public_balance)
+        );
+        match (&account.confidential, &account.audit) {
+            (None, _) => {}
+            (Some(_), Some(audit)) => {
+                println!(
+                    "  Available:   {}",
+                    account.ui_amount.format_decrypted(audit.available_balance)
+                );
+                println!(
+                    "  Pending:     {}",
+                    account.ui_amount.format_decrypted(audit.pending_balance)
+                );
+            }
+            (Some(state), None) => println!(
+                "  Confidential balances encrypted, no keys for ElGamal pubkey {}",
+                state.elgamal_pubkey
+            ),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+// cargo run --bin registry -- lookup --wallet wallet_2
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    cli::FlowArgs,
+    config::Config,
+    exit_code,
+    flows::FlowContext,
+    get_or_create_keypair,
+    journal::Journal,
+    registry::{create_registry, fetch_registry, get_registry_address, update_registry},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signer};
+use std::{error::Error, process::ExitCode};
+
+// Look up and publish ElGamal pubkeys in the SPL ElGamal registry
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the ElGamal pubkey a wallet published
+    Lookup(LookupArgs),
+    /// Publish the ElGamal pubkey proven in a pre-verified pubkey validity proof account
+    Publish(Box<PublishArgs>),
+}
+
+#[derive(Args, Debug)]
+struct LookupArgs {
+    /// Name of the .env keypair of the wallet
+    #[arg(long, default_value = "wallet_2", conflicts_with = "address")]
+    wallet: String,
+
+    /// Address of the wallet, for wallets without a keypair in .env
+    #[arg(long, value_name = "PUBKEY")]
+    address: Option<Pubkey>,
+}
+
+#[derive(Args, Debug)]
+struct PublishArgs {
+    /// Name of the .env keypair of the wallet publishing its key
+    #[arg(long, default_value = "wallet_2")]
+    wallet: String,
+
+    /// zk-elgamal-proof context state account holding the verified pubkey validity proof
+    #[arg(long, value_name = "PUBKEY")]
+    proof_account: Pubkey,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    match cli.command {
+        Command::Lookup(args) => {
+            let wallet = match args.address {
+                Some(address) => address,
+                None => get_or_create_keypair(&args.wallet)?.pubkey(),
+            };
+            match fetch_registry(&client, &wallet)? {
+                Some(registry) => println!(
+                    "ElGamal Registry: {}\nOwner: {}\nElGamal Pubkey: {}",
+                    get_registry_address(&wallet),
+                    registry.owner,
+                    registry.elgamal_pubkey
+                ),
+                None => println!("{} has not published an ElGamal pubkey", wallet),
+            }
+        }
+        Command::Publish(args) => {
+            let owner = get_or_create_keypair(&args.wallet)?;
+
+            let mut ctx =
+                FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+            ctx.flow = "registry";
+            ctx.wait = args.flow.wait;
+            ctx.payers = args.flow.payers()?;
+            ctx.send_strategy = args.flow.send_strategy(&config)?;
+            ctx.confirmation_timeout = config.confirmation_timeout;
+
+            // Create the registry the first time, replace the published key afterwards
+            let (label, instruction) = match fetch_registry(&client, &owner.pubkey())? {
+                None => (
+                    "Create ElGamal Registry",
+                    create_registry(&owner.pubkey(), &args.proof_account),
+                ),
+                Some(_) => (
+                    "Update ElGamal Registry",
+                    update_registry(&owner.pubkey(), &args.proof_account),
+                ),
+            };
+
+            let transaction_signature =
+                ctx.send(label, &[instruction], &owner.pubkey(), &[&owner])?;
+
+            println!(
+                "\n{}: {}",
+                label,
+                config.explorer.tx_url(&transaction_signature)
+            );
+            ctx.report
+                .print(args.flow.json, args.flow.fiat_price().as_ref());
+        }
+    }
+    Ok(())
+}
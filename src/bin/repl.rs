@@ -0,0 +1,365 @@
+// cargo run --bin repl
+use clap::{CommandFactory, Parser, Subcommand};
+use dialoguer::{Completion, History, Input};
+use keypair_utils::{
+    account_state::ConfidentialAccountState,
+    audit::BalanceAudit,
+    cli::terminal_output,
+    config::Config,
+    contacts::{AddressBook, Recipient},
+    exit_code,
+    flows::{self, FlowContext},
+    get_or_create_keypair,
+    journal::Journal,
+    mint::MintSelector,
+    proof_cache::ProofCache,
+    seed::KeypairSource,
+    snapshot::EncryptionKeys,
+    ui_amount::UiAmount,
+};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_client::{
+    client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
+    token::Token,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fs,
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+};
+
+// Lines typed in the REPL, kept across sessions
+const HISTORY_PATH: &str = ".repl_history";
+const MAX_HISTORY: usize = 500;
+
+// Interactive shell running the flows one command at a time, e.g.
+//
+// > balance
+// > transfer 1000 --to alice
+// > wallet wallet_2
+// > apply
+//
+// The RPC connections, the keys derived for each token account and the last decrypted balances are kept between
+// commands, so decrypting an unchanged account again is instant. Tab completes command names, arrow keys
+// walk the history.
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true, disable_version_flag = true)]
+struct Line {
+    #[command(subcommand)]
+    command: ReplCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ReplCommand {
+    /// Show or switch the wallet the commands act for, by .env keypair name
+    Wallet { name: Option<String> },
+    /// Show or switch the mint: the label of a stored `mint:<label>` keypair, or a mint address
+    Mint { mint: Option<MintSelector> },
+    /// Decrypt the balances of the wallet's token account
+    Balance,
+    /// Apply the pending balance of the wallet's token account
+    Apply,
+    /// Confidential transfer from the wallet, in base units
+    Transfer {
+        amount: u64,
+        /// Recipient: a contact name from the address book, or a wallet address
+        #[arg(long, value_name = "NAME|PUBKEY")]
+        to: Recipient,
+    },
+    /// Withdraw from the available balance to the public balance, in base units
+    Withdraw { amount: u64 },
+    /// Leave the REPL
+    #[command(alias = "quit")]
+    Exit,
+}
+
+struct Session {
+    config: Config,
+    client: RpcClient,
+    program_client: Arc<ProgramRpcClient<ProgramRpcClientSendTransaction>>,
+    wallet_name: String,
+    wallet: Keypair,
+    mint: Pubkey,
+    // Keys derived from the owner per token account, deriving them takes a signature each
+    keys: HashMap<Pubkey, EncryptionKeys>,
+    // Last decrypted balances per token account, valid while its state is unchanged
+    balances: HashMap<Pubkey, (ConfidentialAccountState, BalanceAudit)>,
+}
+
+impl Session {
+    fn token_account(&self) -> Pubkey {
+        get_associated_token_address_with_program_id(
+            &self.wallet.pubkey(),
+            &self.mint,
+            &spl_token_2022::id(),
+        )
+    }
+
+    fn context(&self, total_steps: usize) -> Result<FlowContext<'_>, Box<dyn Error>> {
+        let mut ctx = FlowContext::new(&self.client, total_steps)
+            .with_journal(Journal::open(&self.config.journal_path)?);
+        ctx.heap_frame_bytes = self.config.heap_frame_bytes;
+        ctx.confirmation_timeout = self.config.confirmation_timeout;
+        ctx.proof_cache = Some(ProofCache::open(&self.config.journal_path)?);
+        ctx.policy = self.config.policy()?;
+        ctx.keypairs = KeypairSource::new(self.config.seed.clone());
+        ctx.key_derivation = self.config.key_derivation.clone();
+        Ok(ctx)
+    }
+
+    fn token(&self, decimals: u8) -> Token<ProgramRpcClientSendTransaction> {
+        Token::new(
+            self.program_client.clone(),
+            &spl_token_2022::id(),
+            &self.mint,
+            Some(decimals),
+            Arc::new(self.wallet.insecure_clone()),
+        )
+    }
+
+    fn balance(&mut self) -> Result<BalanceAudit, Box<dyn Error>> {
+        let token_account = self.token_account();
+        let state = ConfidentialAccountState::fetch(&self.client, &token_account)?;
+        if let Some((cached, audit)) = self.balances.get(&token_account) {
+            if *cached == state {
+                return Ok(audit.clone());
+            }
+        }
+        if !self.keys.contains_key(&token_account) {
+            let keys = EncryptionKeys::fetch(
+                &self.client,
+                &self.wallet,
+                &token_account,
+                &self.config.key_derivation,
+            )?;
+            self.keys.insert(token_account, keys);
+        }
+        let keys = &self.keys[&token_account];
+        let audit = state.audit(
+            &keys.elgamal_keypair,
+            &keys.aes_key,
+            UiAmount::fetch(&self.client, &self.mint)?,
+        )?;
+        self.balances.insert(token_account, (state, audit.clone()));
+        Ok(audit)
+    }
+
+    async fn run(&mut self, command: ReplCommand) -> Result<(), Box<dyn Error>> {
+        match command {
+            ReplCommand::Wallet { name: None } => {
+                println!("{} ({})", self.wallet_name, self.wallet.pubkey())
+            }
+            ReplCommand::Wallet { name: Some(name) } => {
+                self.wallet = get_or_create_keypair(&name)?;
+                self.wallet_name = name;
+                println!("{} ({})", self.wallet_name, self.wallet.pubkey());
+            }
+            ReplCommand::Mint { mint: None } => println!("{}", self.mint),
+            ReplCommand::Mint { mint: Some(mint) } => {
+                self.mint = mint.pubkey()?;
+                println!("{}", self.mint);
+            }
+            ReplCommand::Balance => print!("{}", self.balance()?),
+            ReplCommand::Apply => {
+                let token_account = self.token_account();
+                let mut ctx = self.context(flows::apply_pending::STEPS)?;
+                let outcome = flows::apply_pending::apply_pending_balance(
+                    &mut ctx,
+                    &token_account,
+                    &self.wallet,
+                    flows::apply_pending::DEFAULT_MAX_ROUNDS,
+                    flows::apply_pending::DEFAULT_ROUND_PAUSE,
+                )?;
+                if outcome.signatures.is_empty() {
+                    println!("No pending balance to apply for {}", token_account);
+                }
+                for signature in &outcome.signatures {
+                    println!(
+                        "Apply Pending Balance: {}",
+                        self.config.explorer.tx_url(signature)
+                    );
+                }
+            }
+            ReplCommand::Transfer { amount, to } => {
+                let recipient = to.resolve(&AddressBook::open(&self.config.contacts_path)?)?;
+                let recipient_token_account = get_associated_token_address_with_program_id(
+                    &recipient.address,
+                    &self.mint,
+                    &spl_token_2022::id(),
+                );
+                recipient.check_elgamal_pubkey(&self.client, &recipient_token_account)?;
+
+                let ui_amount = UiAmount::fetch(&self.client, &self.mint)?;
+                let token = self.token(ui_amount.decimals);
+                let mut ctx = self.context(flows::transfer::STEPS)?;
+                let progress = terminal_output(&mut ctx.events, &self.config.explorer, ui_amount);
+                flows::transfer::transfer_tokens(
+                    &mut ctx,
+                    &token,
+                    &self.wallet,
+                    &recipient_token_account,
+                    amount,
+                )
+                .await?;
+                progress.finish();
+            }
+            ReplCommand::Withdraw { amount } => {
+                let ui_amount = UiAmount::fetch(&self.client, &self.mint)?;
+                let token = self.token(ui_amount.decimals);
+                let mut ctx = self.context(flows::withdraw::STEPS)?;
+                let progress = terminal_output(&mut ctx.events, &self.config.explorer, ui_amount);
+                flows::withdraw::withdraw_tokens(
+                    &mut ctx,
+                    &token,
+                    &self.wallet,
+                    amount,
+                    ui_amount.decimals,
+                )
+                .await?;
+                progress.finish();
+            }
+            ReplCommand::Exit => {}
+        }
+        Ok(())
+    }
+}
+
+// Completes the command name being typed, when only one command starts with it
+struct CommandCompletion {
+    names: Vec<String>,
+}
+
+impl Completion for CommandCompletion {
+    fn get(&self, input: &str) -> Option<String> {
+        if input.contains(' ') {
+            return None;
+        }
+        let mut matches = self.names.iter().filter(|name| name.starts_with(input));
+        match (matches.next(), matches.next()) {
+            (Some(name), None) => Some(format!("{} ", name)),
+            _ => None,
+        }
+    }
+}
+
+// Most recent line first, appended to `HISTORY_PATH` as it is entered
+struct FileHistory {
+    path: PathBuf,
+    lines: VecDeque<String>,
+}
+
+impl FileHistory {
+    fn load(path: PathBuf) -> Self {
+        let lines = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .rev()
+            .take(MAX_HISTORY)
+            .map(str::to_string)
+            .collect();
+        Self { path, lines }
+    }
+}
+
+impl History<String> for FileHistory {
+    fn read(&self, pos: usize) -> Option<String> {
+        self.lines.get(pos).cloned()
+    }
+
+    fn write(&mut self, line: &String) {
+        if line.trim().is_empty() || self.lines.front() == Some(line) {
+            return;
+        }
+        self.lines.push_front(line.clone());
+        self.lines.truncate(MAX_HISTORY);
+        let contents: Vec<&str> = self.lines.iter().rev().map(String::as_str).collect();
+        // History is a convenience, failing to save it doesn't stop the REPL
+        if let Err(error) = fs::write(&self.path, contents.join("\n") + "\n") {
+            eprintln!("Could not save the history: {}", error);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    exit_code::report(run().await)
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    let rpc_client = NonBlockingRpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        CommitmentConfig::confirmed(),
+    );
+    let mut session = Session {
+        program_client: Arc::new(ProgramRpcClient::new(
+            Arc::new(rpc_client),
+            ProgramRpcClientSendTransaction,
+        )),
+        config,
+        client,
+        wallet_name: "wallet_1".to_string(),
+        wallet: get_or_create_keypair("wallet_1")?,
+        mint: MintSelector::Default.pubkey()?,
+        keys: HashMap::new(),
+        balances: HashMap::new(),
+    };
+
+    let completion = CommandCompletion {
+        names: Line::command()
+            .get_subcommands()
+            .map(|command| command.get_name().to_string())
+            .collect(),
+    };
+    let mut history = FileHistory::load(PathBuf::from(HISTORY_PATH));
+    println!(
+        "Connected to {}, acting for {} ({}). Type `help` for the commands.",
+        session.config.rpc_url,
+        session.wallet_name,
+        session.wallet.pubkey()
+    );
+
+    loop {
+        let line: String = Input::new()
+            .with_prompt(&session.wallet_name)
+            .allow_empty(true)
+            .history_with(&mut history)
+            .completion_with(&completion)
+            .interact_text()?;
+        let words = match shell_words::split(&line) {
+            Ok(words) if words.is_empty() => continue,
+            Ok(words) => words,
+            Err(error) => {
+                eprintln!("{}", error);
+                continue;
+            }
+        };
+        // Usage errors and --help are printed without leaving the REPL
+        let command = match Line::try_parse_from(words) {
+            Ok(line) => line.command,
+            Err(error) => {
+                error.print()?;
+                continue;
+            }
+        };
+        if matches!(command, ReplCommand::Exit) {
+            return Ok(());
+        }
+        if let Err(error) = session.run(command).await {
+            eprintln!("Error: {}", error);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// CLUSTERS=localnet=http://127.0.0.1:8899,devnet=https://api.devnet.solana.com
+// cargo run --bin replicate -- --top-up-airdrop
+use clap::Parser;
+use keypair_utils::{
+    config::Config,
+    events::FlowEvent,
+    exit_code,
+    flows::{
+        setup::{self, DemoState, SetupOutcome},
+        FlowContext,
+    },
+    get_or_create_keypair,
+    journal::Journal,
+    mint::MintSelector,
+    seed::KeypairSource,
+    topup::{TopUp, TopUpSource},
+};
+use serde_json::json;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    signature::{Keypair, Signer},
+};
+use spl_token_2022::solana_zk_token_sdk::encryption::elgamal::ElGamalPubkey;
+use std::{error::Error, process::ExitCode, thread};
+
+// Set up the same demo state, the mint, the token accounts of wallet_1 and wallet_2 and wallet_1's deposited tokens,
+// on every cluster in CLUSTERS at once, one thread per cluster. The keypairs are shared, so the mint and token
+// accounts have the same addresses everywhere, and each cluster keeps its own journal (`Config::for_cluster`).
+// Parts already in place are skipped, so rerunning brings new or reset clusters in line with the others.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Only set up these clusters of CLUSTERS, by name. Defaults to all of them
+    #[arg(long = "cluster", value_name = "NAME")]
+    clusters: Vec<String>,
+
+    /// Mint to set up: the label of a stored `mint:<label>` keypair
+    #[arg(long, value_name = "LABEL", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+
+    /// Airdrop SOL to the wallets whose balance is below 0.5 SOL (devnet, testnet and local validators)
+    #[arg(long)]
+    top_up_airdrop: bool,
+
+    /// Print the outcome of each cluster as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let config = Config::load()?;
+    if config.clusters.is_empty() {
+        return Err(
+            "No clusters configured, set CLUSTERS to e.g. localnet=http://127.0.0.1:8899,devnet=https://api.devnet.solana.com"
+                .into(),
+        );
+    }
+    for name in &args.clusters {
+        if !config.clusters.iter().any(|(cluster, _)| cluster == name) {
+            return Err(format!("No cluster named {} in CLUSTERS", name).into());
+        }
+    }
+    let clusters: Vec<(&str, Config)> = config
+        .clusters
+        .iter()
+        .filter(|(name, _)| args.clusters.is_empty() || args.clusters.contains(name))
+        .map(|(name, rpc_url)| (name.as_str(), config.for_cluster(name, rpc_url)))
+        .collect();
+
+    // Keypairs are read or created once, before the clusters run side by side
+    let wallet_1 = get_or_create_keypair("wallet_1")?;
+    let wallet_2 = get_or_create_keypair("wallet_2")?;
+    let mint = args.mint.keypair()?;
+    // The same auditor on every cluster, random per run unless a SEED is set
+    let auditor = *KeypairSource::new(config.seed.clone())
+        .elgamal_keypair("auditor")?
+        .pubkey();
+
+    let outcomes: Vec<Result<SetupOutcome, String>> = thread::scope(|scope| {
+        let handles: Vec<_> = clusters
+            .iter()
+            .map(|(name, cluster)| {
+                let (wallet_1, wallet_2, mint, auditor) = (&wallet_1, &wallet_2, &mint, &auditor);
+                let top_up_airdrop = args.top_up_airdrop;
+                scope.spawn(move || {
+                    replicate(
+                        cluster,
+                        name,
+                        wallet_1,
+                        wallet_2,
+                        mint,
+                        auditor,
+                        top_up_airdrop,
+                    )
+                    .map_err(|error| error.to_string())
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err("The cluster's thread panicked".to_string()))
+            })
+            .collect()
+    });
+
+    if args.json {
+        let outcomes: Vec<_> = clusters
+            .iter()
+            .zip(&outcomes)
+            .map(|((name, cluster), outcome)| match outcome {
+                Ok(outcome) => json!({
+                    "cluster": name,
+                    "rpc_url": cluster.rpc_url,
+                    "journal": cluster.journal_path,
+                    "mint_created": outcome.mint_created,
+                    "accounts_configured": outcome.accounts_configured,
+                    "minted": outcome.minted,
+                    "deposited": outcome.deposited,
+                    "applied": outcome.applied,
+                }),
+                Err(error) => json!({
+                    "cluster": name,
+                    "rpc_url": cluster.rpc_url,
+                    "journal": cluster.journal_path,
+                    "error": error,
+                }),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&outcomes)?);
+    } else {
+        println!("\nMint {} on {} clusters:", mint.pubkey(), clusters.len());
+        for ((name, cluster), outcome) in clusters.iter().zip(&outcomes) {
+            match outcome {
+                Ok(outcome) => {
+                    let already = |done: bool| if done { "" } else { "already " };
+                    println!(
+                        "  {:<10} in sync: mint {}created, {} accounts configured, {}minted, {}deposited ({})",
+                        name,
+                        already(outcome.mint_created),
+                        outcome.accounts_configured,
+                        already(outcome.minted),
+                        already(outcome.deposited),
+                        cluster.journal_path
+                    )
+                }
+                Err(error) => println!("  {:<10} failed: {}", name, error),
+            }
+        }
+    }
+
+    let failed = outcomes.iter().filter(|outcome| outcome.is_err()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} clusters failed", failed, outcomes.len()).into());
+    }
+    Ok(())
+}
+
+// Set up one cluster, printing its transactions prefixed with the cluster's name
+fn replicate(
+    config: &Config,
+    name: &str,
+    wallet_1: &Keypair,
+    wallet_2: &Keypair,
+    mint: &Keypair,
+    auditor: &ElGamalPubkey,
+    top_up_airdrop: bool,
+) -> Result<SetupOutcome, Box<dyn Error>> {
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let mut ctx = FlowContext::new(&client, setup::steps(2))
+        .with_journal(Journal::open(&config.journal_path)?);
+    ctx.heap_frame_bytes = config.heap_frame_bytes;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
+    let prefix = name.to_string();
+    let explorer = config.explorer.clone();
+    ctx.events.subscribe(move |event| match event {
+        FlowEvent::TransactionConfirmed { label, signature } => {
+            println!("[{}] {}: {}", prefix, label, explorer.tx_url(signature))
+        }
+        FlowEvent::FeePayerToppedUp { payer, .. } => println!("[{}] Topped up {}", prefix, payer),
+        _ => {}
+    });
+
+    if top_up_airdrop {
+        let top_up = TopUp::new(TopUpSource::Airdrop, 0.5, 1.0);
+        top_up.check_cluster(&config.cluster)?;
+        for wallet in [wallet_1, wallet_2] {
+            top_up.ensure_funded(&mut ctx, &wallet.pubkey())?;
+        }
+    }
+
+    setup::setup_demo(
+        &mut ctx,
+        mint,
+        wallet_1,
+        auditor,
+        &[wallet_1, wallet_2],
+        DemoState::default(),
+    )
+}
@@ -0,0 +1,121 @@
+// cargo run --bin resume
+use clap::Parser;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signer};
+use spl_token_client::{
+    client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
+    token::Token,
+};
+use std::{error::Error, process::ExitCode, sync::Arc};
+
+use keypair_utils::{
+    cli::{terminal_output, FlowArgs},
+    config::Config,
+    exit_code,
+    flows::{resume, FlowContext},
+    get_or_create_keypair,
+    journal::Journal,
+    ui_amount::UiAmount,
+};
+
+// Recover from a transfer or withdraw that was interrupted after its proofs were verified,
+// see `flows::resume` for what is completed and what is rolled back
+#[derive(Parser, Debug)]
+struct ResumeArgs {
+    /// Name of the .env keypair that owns the interrupted flow
+    #[arg(long, default_value = "wallet_1")]
+    wallet: String,
+
+    /// Only list the proof accounts left on chain, without completing or closing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    exit_code::report(run().await)
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let args = ResumeArgs::parse();
+
+    let owner = get_or_create_keypair(&args.wallet)?;
+    let mint = args.flow.mint.pubkey()?;
+
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.flow.check_mint(&client, &mint)?;
+
+    // Amounts are checked by the token program, and shown, with the decimals of the mint
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+    let decimals = ui_amount.decimals;
+
+    // A "non-blocking" RPC client (for async calls)
+    let rpc_client = NonBlockingRpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        CommitmentConfig::confirmed(),
+    );
+
+    let program_client =
+        ProgramRpcClient::new(Arc::new(rpc_client), ProgramRpcClientSendTransaction);
+
+    // Create a "token" client, to use various helper functions for Token Extensions
+    let token = Token::new(
+        Arc::new(program_client),
+        &spl_token_2022::id(),
+        &mint,
+        Some(decimals),
+        Arc::new(owner.insecure_clone()),
+    );
+
+    let mut ctx =
+        FlowContext::new(&client, resume::STEPS).with_journal(Journal::open(&config.journal_path)?);
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
+    ctx.memo = args.flow.memo.clone();
+
+    if args.dry_run {
+        for proof_account in resume::find_proof_accounts(&mut ctx, &owner.pubkey())? {
+            println!(
+                "{:?} proof account: {} ({} lamports)",
+                proof_account.proof_type, proof_account.account, proof_account.lamports
+            );
+        }
+        return Ok(());
+    }
+
+    let progress = terminal_output(&mut ctx.events, &config.explorer, ui_amount);
+
+    let outcome = resume::resume_flows(&mut ctx, &token, &owner, decimals).await?;
+
+    progress.finish();
+    match outcome.withdraw {
+        Some((_, amount)) => println!(
+            "\nCompleted interrupted withdraw of {} tokens",
+            ui_amount.format(amount)
+        ),
+        None => println!("\nNo interrupted withdraw to complete"),
+    }
+    println!(
+        "Closed {} proof accounts, reclaimed {} lamports",
+        outcome.closed.len(),
+        outcome.reclaimed_lamports
+    );
+    for account in &outcome.skipped {
+        println!("Skipped proof account {}", account);
+    }
+
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
+    Ok(())
+}
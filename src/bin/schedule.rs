@@ -0,0 +1,262 @@
+// cargo run --bin schedule -- add --withdraw 1000 --at 2024-06-01T12:00:00Z
+// cargo run --bin schedule -- add --transfer 500 --to alice --at slot:300000000
+// cargo run --bin schedule -- run
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    cli::{terminal_output, FlowArgs},
+    clock::ClusterClock,
+    config::Config,
+    contacts::{AddressBook, Recipient},
+    events::FlowEvent,
+    exit_code,
+    flows::{schedule::run_schedule, FlowContext},
+    journal::Journal,
+    mint::MintSelector,
+    proof_cache::ProofCache,
+    schedule::{ExecuteAt, Schedule, ScheduleStatus, ScheduledAction},
+    seed::KeypairSource,
+    shutdown,
+    ui_amount::UiAmount,
+};
+use serde_json::Value;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Keypair};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_client::{
+    client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
+    token::Token,
+};
+use std::{error::Error, process::ExitCode, sync::Arc, time::Duration};
+
+// Withdraws and transfers executed at a later time or slot. Requests are kept in the journal's database,
+// and `run` executes them as they come due, generating their proofs shortly before, see `flows::schedule`
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Schedule a withdraw or a transfer
+    Add(AddArgs),
+    /// List scheduled requests, newest first
+    List(ListArgs),
+    /// Cancel a request that hasn't been executed yet
+    Cancel(CancelArgs),
+    /// Execute the requests of the mint as they come due, until Ctrl-C
+    Run(RunArgs),
+}
+
+#[derive(Args, Debug)]
+struct AddArgs {
+    /// Name of the .env keypair owning the token account, read when the request is executed
+    #[arg(long, default_value = "wallet_1")]
+    wallet: String,
+
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+
+    /// Withdraw this many base units from the available balance
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        required_unless_present = "transfer",
+        conflicts_with = "transfer"
+    )]
+    withdraw: Option<u64>,
+
+    /// Transfer this many base units to --to
+    #[arg(long, value_name = "AMOUNT", requires = "to")]
+    transfer: Option<u64>,
+
+    /// Recipient of the transfer: a contact name from the address book, or a wallet address
+    #[arg(long, value_name = "NAME|PUBKEY")]
+    to: Option<Recipient>,
+
+    /// When to execute: slot:<slot>, a unix timestamp, or an RFC 3339 date
+    #[arg(long, value_name = "WHEN")]
+    at: ExecuteAt,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    /// Only show requests with this status
+    #[arg(long, value_parser = ["scheduled", "prepared", "executing", "submitted", "failed", "cancelled"])]
+    status: Option<String>,
+
+    /// Print the requests as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct CancelArgs {
+    /// Id of the request, as listed
+    id: i64,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Generate the proofs of a request this many seconds before it is due
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    prepare_before: u64,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    exit_code::report(run().await)
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+    let schedule = Schedule::open(&config.journal_path)?;
+
+    match cli.command {
+        Command::Add(args) => {
+            let mint = args.mint.pubkey()?;
+            let (action, amount) = match (args.withdraw, args.transfer, &args.to) {
+                (Some(amount), _, _) => (ScheduledAction::Withdraw, amount),
+                (None, Some(amount), Some(to)) => {
+                    let recipient = to.resolve(&AddressBook::open(&config.contacts_path)?)?;
+                    // Associated token address of the recipient
+                    let recipient = get_associated_token_address_with_program_id(
+                        &recipient.address, // Token account owner
+                        &mint,              // Mint
+                        &spl_token_2022::id(),
+                    );
+                    (ScheduledAction::Transfer { recipient }, amount)
+                }
+                _ => return Err("Give --withdraw, or --transfer and --to".into()),
+            };
+            let id = schedule.add(&args.wallet, &mint, action, amount, args.at)?;
+            println!(
+                "Scheduled #{}: {} of {} by {} at {}",
+                id, action, amount, args.wallet, args.at
+            );
+        }
+        Command::List(args) => {
+            let status = args
+                .status
+                .map(|status| status.parse::<ScheduleStatus>())
+                .transpose()?;
+            let entries = schedule.entries(status)?;
+
+            if args.json {
+                let entries: Vec<Value> = entries.iter().map(|entry| entry.to_json()).collect();
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if entries.is_empty() {
+                println!("No scheduled requests in {}", config.journal_path);
+            } else {
+                for entry in entries {
+                    println!("{}\n", entry);
+                }
+            }
+        }
+        Command::Cancel(args) => {
+            schedule.cancel(args.id)?;
+            println!("Cancelled #{}", args.id);
+        }
+        Command::Run(args) => run_scheduler(&config, &schedule, args).await?,
+    }
+    Ok(())
+}
+
+async fn run_scheduler(
+    config: &Config,
+    schedule: &Schedule,
+    args: RunArgs,
+) -> Result<(), Box<dyn Error>> {
+    let mint = args.flow.mint.pubkey()?;
+
+    // Stop between requests on Ctrl-C, or at the next step of a running one, closing its proof accounts
+    shutdown::install()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.flow.check_mint(&client, &mint)?;
+
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+    let decimals = ui_amount.decimals;
+
+    // A "non-blocking" RPC client (for async calls), used to set up the "token" client
+    let rpc_client = NonBlockingRpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        CommitmentConfig::confirmed(),
+    );
+
+    let program_client =
+        ProgramRpcClient::new(Arc::new(rpc_client), ProgramRpcClientSendTransaction);
+
+    // Create a "token" client, to use various helper functions for Token Extensions.
+    // The flows only read accounts with it and sign with the wallet of each request, so its payer is never used
+    let token = Token::new(
+        Arc::new(program_client),
+        &spl_token_2022::id(),
+        &mint,
+        Some(decimals),
+        Arc::new(Keypair::new()),
+    );
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.heap_frame_bytes = config.heap_frame_bytes;
+    ctx.verify = args.flow.verify;
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
+    ctx.memo = args.flow.memo.clone();
+    // Where proofs are generated ahead of each request
+    ctx.proof_cache = Some(ProofCache::open(&config.journal_path)?);
+    ctx.policy = config.policy()?;
+    ctx.keypairs = KeypairSource::new(config.seed.clone());
+    let progress = terminal_output(&mut ctx.events, &config.explorer, ui_amount);
+    let listener = progress.clone();
+    let explorer = config.explorer.clone();
+    ctx.events.subscribe(move |event| match event {
+        FlowEvent::ScheduledRequestPrepared { id, reused: false } => {
+            listener.println(&format!("\nScheduled #{}: proofs generated", id))
+        }
+        FlowEvent::ScheduledRequestPrepared { id, reused: true } => listener.println(&format!(
+            "\nScheduled #{}: proofs already in the proof cache",
+            id
+        )),
+        FlowEvent::ScheduledRequestSubmitted { id, signature } => listener.println(&format!(
+            "\nScheduled #{} submitted: {}",
+            id,
+            explorer.tx_url(signature)
+        )),
+        FlowEvent::ScheduledRequestFailed { id, error } => {
+            listener.println(&format!("\nScheduled #{} failed: {}", id, error))
+        }
+        _ => {}
+    });
+
+    let clock = ClusterClock::subscribe(&client, &config.ws_url)?;
+    println!(
+        "Running the schedule of mint {}, press Ctrl-C to stop",
+        mint
+    );
+    run_schedule(
+        &mut ctx,
+        &token,
+        decimals,
+        schedule,
+        &clock,
+        Duration::from_secs(args.prepare_before),
+    )
+    .await?;
+
+    progress.finish();
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
+    Ok(())
+}
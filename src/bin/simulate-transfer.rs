@@ -0,0 +1,119 @@
+// cargo run --bin simulate-transfer -- --to alice --amount 10000
+use clap::Parser;
+use keypair_utils::{
+    config::Config,
+    contacts::{AddressBook, Contact, Recipient},
+    exit_code,
+    flows::{simulate_transfer, FlowContext},
+    get_or_create_keypair,
+    mint::MintSelector,
+    ui_amount::UiAmount,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, packet::PACKET_DATA_SIZE, signature::Signer,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::{error::Error, process::ExitCode};
+
+// Validate a confidential transfer from wallet_1 before running it for real: generate its proofs against a copy of
+// the sender's state under synthetic keys, simulate their verification on the cluster and build the transfer,
+// without sending anything. See `flows::simulate_transfer` for what is and isn't checked.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Recipient: a contact name from the address book, or a wallet address. Defaults to wallet_2
+    #[arg(long, value_name = "NAME|PUBKEY")]
+    to: Option<Recipient>,
+
+    /// Amount to transfer, in base units
+    #[arg(long, default_value_t = 100_00)]
+    amount: u64,
+
+    /// Available balance of the forked sender state, in base units. Defaults to the sender's decrypted balance
+    #[arg(long)]
+    balance: Option<u64>,
+
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+
+    /// Print the simulation as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let config = Config::load()?;
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let wallet_1 = get_or_create_keypair("wallet_1")?;
+    let mint = args.mint.pubkey()?;
+    let recipient = match &args.to {
+        Some(recipient) => recipient.resolve(&AddressBook::open(&config.contacts_path)?)?,
+        None => Contact {
+            name: "wallet_2".to_string(),
+            address: get_or_create_keypair("wallet_2")?.pubkey(),
+            elgamal_pubkey: None,
+        },
+    };
+    let recipient_token_account = get_associated_token_address_with_program_id(
+        &recipient.address,
+        &mint,
+        &spl_token_2022::id(),
+    );
+
+    let mut ctx = FlowContext::new(&client, simulate_transfer::STEPS);
+    ctx.heap_frame_bytes = config.heap_frame_bytes;
+    ctx.key_derivation = config.key_derivation.clone();
+    let simulation = simulate_transfer::simulate_transfer(
+        &mut ctx,
+        &wallet_1,
+        &mint,
+        &recipient_token_account,
+        args.amount,
+        args.balance,
+    )?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&simulation.to_json())?);
+    } else {
+        let ui_amount = UiAmount::fetch(&client, &mint)?;
+        println!(
+            "Transfer of {} to {} ({})",
+            ui_amount.format(args.amount),
+            recipient.name,
+            recipient_token_account
+        );
+        for proof in &simulation.proofs {
+            match &proof.error {
+                None => println!(
+                    "  ✔ {:?} proof verified, {} compute units",
+                    proof.proof,
+                    proof
+                        .units_consumed
+                        .map_or("unknown".to_string(), |units| units.to_string())
+                ),
+                Some(error) => println!("  ✘ {:?} proof rejected: {}", proof.proof, error),
+            }
+        }
+        println!(
+            "  {} Transfer transaction of {} bytes",
+            if simulation.transfer_size <= PACKET_DATA_SIZE {
+                "✔"
+            } else {
+                "✘"
+            },
+            simulation.transfer_size
+        );
+    }
+    if !simulation.is_ok() {
+        return Err("The simulated transfer would fail".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+// cargo run --bin snapshot -- export --out snapshot.json
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    config::Config,
+    exit_code,
+    journal::Journal,
+    key_cache::KeyCache,
+    mint::MintSelector,
+    snapshot::{read_passphrase, Snapshot, SnapshotSecrets},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{error::Error, fs, path::PathBuf, process::ExitCode};
+
+// Move a working setup between machines, or share it with a teammate: the mints, accounts and journal,
+// with the .env keypairs and token account encryption keys encrypted under a passphrase.
+// The passphrase is read from --passphrase or the SNAPSHOT_PASSPHRASE variable.
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write a snapshot of the .env keypairs, their token accounts and the journal
+    Export(ExportArgs),
+    /// Add the keypairs and journal entries of a snapshot to this machine's .env file and journal
+    Import(ImportArgs),
+    /// Write only the encryption keys of token accounts, for `audit-balance` and `history` to read their balances
+    /// with --keys on a machine without the wallet keypairs
+    ExportKeys(ExportKeysArgs),
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// File to write the snapshot to, which must not exist yet
+    #[arg(long, value_name = "PATH", default_value = "snapshot.json")]
+    out: PathBuf,
+
+    /// Passphrase encrypting the keypairs and encryption keys
+    #[arg(long)]
+    passphrase: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ImportArgs {
+    /// Snapshot file written by `snapshot export`
+    #[arg(value_name = "PATH")]
+    path: PathBuf,
+
+    /// Passphrase the snapshot was exported with
+    #[arg(long)]
+    passphrase: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ExportKeysArgs {
+    /// File to write the keys to, which must not exist yet
+    #[arg(long, value_name = "PATH", default_value = "keys.json")]
+    out: PathBuf,
+
+    /// Name of an .env keypair whose token account keys to export, can be repeated
+    #[arg(long = "wallet", default_values = ["wallet_1", "wallet_2"])]
+    wallets: Vec<String>,
+
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    mint: MintSelector,
+
+    /// Passphrase encrypting the keys
+    #[arg(long)]
+    passphrase: Option<String>,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+    let journal = Journal::open(&config.journal_path)?;
+
+    match cli.command {
+        Command::Export(args) => {
+            if args.out.exists() {
+                return Err(format!("{} already exists", args.out.display()).into());
+            }
+            let passphrase = read_passphrase(args.passphrase)?;
+            let client = RpcClient::new_with_commitment(
+                config.rpc_url.clone(),
+                CommitmentConfig::confirmed(),
+            );
+
+            let snapshot = Snapshot::capture(
+                &client,
+                &config.rpc_url,
+                &journal,
+                &passphrase,
+                &config.key_derivation,
+            )?;
+            fs::write(
+                &args.out,
+                serde_json::to_string_pretty(&snapshot.to_json())?,
+            )?;
+
+            println!(
+                "Exported {} mints, {} accounts, {} token accounts and {} journal entries to {}",
+                snapshot.mints.len(),
+                snapshot.accounts.len(),
+                snapshot.token_accounts.len(),
+                snapshot.journal.len(),
+                args.out.display()
+            );
+        }
+        Command::Import(args) => {
+            let contents = fs::read_to_string(&args.path)?;
+            let snapshot = Snapshot::from_json(&serde_json::from_str(&contents)?)?;
+            let secrets =
+                SnapshotSecrets::decrypt(&snapshot.secrets, &read_passphrase(args.passphrase)?)?;
+
+            if snapshot.rpc_url != config.rpc_url {
+                eprintln!(
+                    "The snapshot was exported against {}, this machine uses {}",
+                    snapshot.rpc_url, config.rpc_url
+                );
+            }
+            let outcome = snapshot.restore(&secrets, &journal, &config.key_derivation)?;
+
+            println!(
+                "Imported {} keypairs ({} already present), {} encryption keys checked and {} journal entries",
+                outcome.keypairs_added,
+                outcome.keypairs_present,
+                secrets.encryption_keys.len(),
+                outcome.journal_entries_added
+            );
+            for (name, address) in &snapshot.mints {
+                println!("  Mint {}: {}", name, address);
+            }
+            for token_account in &snapshot.token_accounts {
+                println!(
+                    "  Token account of {}: {} (mint {})",
+                    token_account.owner, token_account.address, token_account.mint
+                );
+            }
+        }
+        Command::ExportKeys(args) => {
+            if args.out.exists() {
+                return Err(format!("{} already exists", args.out.display()).into());
+            }
+            let passphrase = read_passphrase(args.passphrase)?;
+            let client = RpcClient::new_with_commitment(
+                config.rpc_url.clone(),
+                CommitmentConfig::confirmed(),
+            );
+
+            let cache =
+                KeyCache::export(&client, &args.wallets, &args.mint, &config.key_derivation)?;
+            fs::write(
+                &args.out,
+                serde_json::to_string_pretty(&cache.to_json(&passphrase)?)?,
+            )?;
+
+            println!(
+                "Exported the encryption keys of {} token accounts to {}",
+                cache.accounts.len(),
+                args.out.display()
+            );
+            for cached in &cache.accounts {
+                println!(
+                    "  Token account of {}: {} (mint {})",
+                    cached.owner, cached.keys.token_account, cached.mint
+                );
+            }
+        }
+    }
+    Ok(())
+}
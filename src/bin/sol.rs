@@ -0,0 +1,204 @@
+// cargo run --bin sol -- transfer --to wallet_2 --amount 0.1
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    cli::FlowArgs,
+    config::Config,
+    contacts::{AddressBook, Recipient},
+    exit_code,
+    flows::FlowContext,
+    get_or_create_keypair,
+    journal::Journal,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::Signer,
+    system_instruction, system_program,
+};
+use std::{error::Error, process::ExitCode};
+
+// Plain SOL operations of the system program, for the non-token half of a wallet's workflow
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Transfer SOL to a contact or address
+    Transfer(TransferArgs),
+    /// Create an account at an address derived from the wallet and a seed
+    CreateAccountWithSeed(CreateAccountWithSeedArgs),
+    /// Show the SOL balance of a wallet, contact or address
+    Balance(BalanceArgs),
+}
+
+#[derive(Args, Debug)]
+struct TransferArgs {
+    /// Name of the .env keypair sending the SOL
+    #[arg(long, default_value = "wallet_1")]
+    wallet: String,
+
+    /// Recipient: a contact name from the address book, or an address
+    #[arg(long, value_name = "NAME|PUBKEY")]
+    to: Recipient,
+
+    /// SOL to transfer
+    #[arg(long, value_name = "SOL")]
+    amount: f64,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[derive(Args, Debug)]
+struct CreateAccountWithSeedArgs {
+    /// Name of the .env keypair the address is derived from, which signs as the base
+    #[arg(long, default_value = "wallet_1")]
+    wallet: String,
+
+    /// Seed the address is derived from, at most 32 bytes
+    #[arg(long)]
+    seed: String,
+
+    /// Size of the account data in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = 0)]
+    space: u64,
+
+    /// Program owning the new account
+    #[arg(long, value_name = "PUBKEY", default_value_t = system_program::id())]
+    owner: Pubkey,
+
+    /// SOL deposited into the account, the rent exempt minimum for its size if not given
+    #[arg(long, value_name = "SOL")]
+    amount: Option<f64>,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[derive(Args, Debug)]
+struct BalanceArgs {
+    /// Name of the .env keypair to show the balance of
+    #[arg(long, default_value = "wallet_1", conflicts_with = "address")]
+    wallet: String,
+
+    /// A contact name from the address book, or an address, instead of a .env keypair
+    #[arg(long, value_name = "NAME|PUBKEY")]
+    address: Option<Recipient>,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    match cli.command {
+        Command::Transfer(args) => {
+            let wallet = get_or_create_keypair(&args.wallet)?;
+            let recipient = args
+                .to
+                .resolve(&AddressBook::open(&config.contacts_path)?)?;
+
+            let mut ctx =
+                FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+            ctx.flow = "sol-transfer";
+            ctx.wait = args.flow.wait;
+            ctx.payers = args.flow.payers()?;
+            ctx.send_strategy = args.flow.send_strategy(&config)?;
+            ctx.confirmation_timeout = config.confirmation_timeout;
+
+            let instruction = system_instruction::transfer(
+                &wallet.pubkey(),   // Sender
+                &recipient.address, // Recipient
+                sol_to_lamports(args.amount),
+            );
+            let transaction_signature =
+                ctx.send("Transfer SOL", &[instruction], &wallet.pubkey(), &[&wallet])?;
+
+            println!(
+                "\nTransferred {} SOL to {}: {}",
+                args.amount,
+                recipient.name,
+                config.explorer.tx_url(&transaction_signature)
+            );
+            ctx.report
+                .print(args.flow.json, args.flow.fiat_price().as_ref());
+        }
+        Command::CreateAccountWithSeed(args) => {
+            let wallet = get_or_create_keypair(&args.wallet)?;
+            let address = Pubkey::create_with_seed(&wallet.pubkey(), &args.seed, &args.owner)?;
+            if client
+                .get_account_with_commitment(&address, client.commitment())?
+                .value
+                .is_some()
+            {
+                return Err(format!("Account {} already exists", address).into());
+            }
+
+            let mut ctx =
+                FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+            ctx.flow = "create-account-with-seed";
+            ctx.wait = args.flow.wait;
+            ctx.payers = args.flow.payers()?;
+            ctx.send_strategy = args.flow.send_strategy(&config)?;
+            ctx.confirmation_timeout = config.confirmation_timeout;
+
+            let lamports = match args.amount {
+                Some(amount) => sol_to_lamports(amount),
+                None => client.get_minimum_balance_for_rent_exemption(args.space as usize)?,
+            };
+            ctx.report.record_rent(lamports);
+
+            let instruction = system_instruction::create_account_with_seed(
+                &ctx.payers.rent_funder(&wallet.pubkey()), // Funding account
+                &address,                                  // New account
+                &wallet.pubkey(),                          // Base
+                &args.seed,
+                lamports,
+                args.space,
+                &args.owner,
+            );
+            let transaction_signature = ctx.send(
+                "Create Account With Seed",
+                &[instruction],
+                &wallet.pubkey(),
+                &[&wallet],
+            )?;
+
+            println!(
+                "\nCreated {} (seed {:?}, owner {}): {}",
+                address,
+                args.seed,
+                args.owner,
+                config.explorer.tx_url(&transaction_signature)
+            );
+            ctx.report
+                .print(args.flow.json, args.flow.fiat_price().as_ref());
+        }
+        Command::Balance(args) => {
+            let (name, address) = match args.address {
+                Some(recipient) => {
+                    let contact = recipient.resolve(&AddressBook::open(&config.contacts_path)?)?;
+                    (contact.name, contact.address)
+                }
+                None => {
+                    let address = get_or_create_keypair(&args.wallet)?.pubkey();
+                    (args.wallet, address)
+                }
+            };
+            let balance = client.get_balance(&address)?;
+            println!("{} ({}): {} SOL", name, address, lamports_to_sol(balance));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,226 @@
+// cargo run --bin stake -- create --amount 2
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    cli::FlowArgs,
+    config::Config,
+    exit_code,
+    flows::FlowContext,
+    get_or_create_keypair,
+    journal::Journal,
+    read_keypair,
+    stake::{
+        check_vote_account, create_stake_account, deactivate_stake, delegate_stake,
+        fetch_stake_account, minimum_stake_lamports, withdraw_stake,
+    },
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use std::{error::Error, process::ExitCode};
+
+// Stake a wallet's SOL with a validator, next to its confidential token balances.
+// Stake accounts are stored in .env as `stake:<label>` keypairs, with the wallet as staker and withdrawer.
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create and fund a stake account
+    Create(CreateArgs),
+    /// Delegate a stake account to a validator's vote account
+    Delegate(DelegateArgs),
+    /// Deactivate a stake account, making it withdrawable after the cooldown
+    Deactivate(StakeArgs),
+    /// Withdraw SOL from an inactive stake account
+    Withdraw(WithdrawArgs),
+    /// Show a stake account
+    Show(StakeArgs),
+}
+
+#[derive(Args, Debug)]
+struct StakeArgs {
+    /// Name of the .env keypair staking and withdrawing
+    #[arg(long, default_value = "wallet_1")]
+    wallet: String,
+
+    /// Label of the stake account keypair, stored in .env as `stake:<label>`
+    #[arg(long, default_value = "treasury")]
+    stake: String,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+#[derive(Args, Debug)]
+struct CreateArgs {
+    /// SOL moved into the stake account, including its rent
+    #[arg(long, value_name = "SOL")]
+    amount: f64,
+
+    #[command(flatten)]
+    stake: StakeArgs,
+}
+
+#[derive(Args, Debug)]
+struct DelegateArgs {
+    /// Vote account of the validator to delegate to
+    #[arg(long, value_name = "PUBKEY")]
+    vote: Pubkey,
+
+    #[command(flatten)]
+    stake: StakeArgs,
+}
+
+#[derive(Args, Debug)]
+struct WithdrawArgs {
+    /// SOL to withdraw, all of the stake account's balance if not given
+    #[arg(long, value_name = "SOL")]
+    amount: Option<f64>,
+
+    /// Recipient of the SOL, the wallet if not given
+    #[arg(long, value_name = "PUBKEY")]
+    to: Option<Pubkey>,
+
+    #[command(flatten)]
+    stake: StakeArgs,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let args = match &cli.command {
+        Command::Create(args) => &args.stake,
+        Command::Delegate(args) => &args.stake,
+        Command::Withdraw(args) => &args.stake,
+        Command::Deactivate(args) | Command::Show(args) => args,
+    };
+    let wallet = get_or_create_keypair(&args.wallet)?;
+    let stake_name = format!("stake:{}", args.stake);
+
+    if let Command::Show(_) = &cli.command {
+        let stake_account = stake_keypair(&stake_name)?;
+        match fetch_stake_account(&client, &stake_account.pubkey())? {
+            Some(stake_account) => println!("{}", stake_account),
+            None => println!("{} doesn't exist yet", stake_account.pubkey()),
+        }
+        return Ok(());
+    }
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.flow = "stake";
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+
+    let (label, transaction_signature) = match &cli.command {
+        Command::Create(create) => {
+            let stake_account = get_or_create_keypair(&stake_name)?;
+            if fetch_stake_account(&client, &stake_account.pubkey())?.is_some() {
+                return Err(format!(
+                    "Stake account {} already exists, pick another --stake label",
+                    stake_account.pubkey()
+                )
+                .into());
+            }
+            let lamports = sol_to_lamports(create.amount);
+            let minimum = minimum_stake_lamports(&client)?;
+            if lamports < minimum {
+                return Err(format!(
+                    "A stake account needs at least {} SOL for its rent and the minimum delegation",
+                    lamports_to_sol(minimum)
+                )
+                .into());
+            }
+
+            let instructions = create_stake_account(
+                &wallet.pubkey(),        // Funding account
+                &stake_account.pubkey(), // Stake account
+                &wallet.pubkey(),        // Staker and withdrawer
+                lamports,
+            );
+            let label = "Create Stake Account";
+            let signature = ctx.send(
+                label,
+                &instructions,
+                &wallet.pubkey(),
+                &[&wallet, &stake_account],
+            )?;
+            (label, signature)
+        }
+        Command::Delegate(delegate) => {
+            let stake_account = stake_keypair(&stake_name)?.pubkey();
+            check_vote_account(&client, &delegate.vote)?;
+
+            let instruction = delegate_stake(&stake_account, &wallet.pubkey(), &delegate.vote);
+            let label = "Delegate Stake";
+            let signature = ctx.send(label, &[instruction], &wallet.pubkey(), &[&wallet])?;
+            (label, signature)
+        }
+        Command::Deactivate(_) => {
+            let stake_account = stake_keypair(&stake_name)?.pubkey();
+
+            let instruction = deactivate_stake(&stake_account, &wallet.pubkey());
+            let label = "Deactivate Stake";
+            let signature = ctx.send(label, &[instruction], &wallet.pubkey(), &[&wallet])?;
+            (label, signature)
+        }
+        Command::Withdraw(withdraw) => {
+            let stake_account = stake_keypair(&stake_name)?.pubkey();
+            let lamports = match withdraw.amount {
+                Some(amount) => sol_to_lamports(amount),
+                None => {
+                    fetch_stake_account(&client, &stake_account)?
+                        .ok_or(format!("Stake account {} doesn't exist", stake_account))?
+                        .lamports
+                }
+            };
+
+            let instruction = withdraw_stake(
+                &stake_account,
+                &wallet.pubkey(),
+                &withdraw.to.unwrap_or(wallet.pubkey()),
+                lamports,
+            );
+            let label = "Withdraw Stake";
+            let signature = ctx.send(label, &[instruction], &wallet.pubkey(), &[&wallet])?;
+            (label, signature)
+        }
+        Command::Show(_) => unreachable!("shown above"),
+    };
+
+    println!(
+        "\n{}: {}",
+        label,
+        config.explorer.tx_url(&transaction_signature)
+    );
+    ctx.report
+        .print(args.flow.json, args.flow.fiat_price().as_ref());
+    Ok(())
+}
+
+// The keypair of an existing stake account, only ever created by `stake create`
+fn stake_keypair(name: &str) -> Result<Keypair, Box<dyn Error>> {
+    read_keypair(name)?.ok_or_else(|| {
+        format!(
+            "No {} keypair in the .env file, create it with `cargo run --bin stake -- create`",
+            name
+        )
+        .into()
+    })
+}
@@ -0,0 +1,208 @@
+// cargo run --bin sweep -- --wallet-glob 'wallets/*.json' --max-dust 100 --interval 3600
+use clap::Parser;
+use keypair_utils::{
+    batch::{load_wallet_glob, run_bounded, DEFAULT_PARALLELISM},
+    cli::FlowArgs,
+    config::Config,
+    exit_code,
+    flows::{
+        sweep::{find_dust, sweep_dust, DustAccount, SweepOutcome},
+        FlowContext,
+    },
+    get_or_create_keypair,
+    journal::Journal,
+    report::CostReport,
+    shutdown,
+    ui_amount::UiAmount,
+};
+use serde_json::json;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signer};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::{
+    error::Error,
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+// Sweep dust public balances from many token accounts into a treasury, as the permanent delegate of the mint.
+// Create the mint with `2_create_mint --permanent-delegate <NAME>` for the delegate to be able to.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Keypair files of the wallets whose associated token accounts are swept, e.g. 'wallets/*.json'.
+    /// Only their addresses are read, the wallets don't sign
+    #[arg(long, value_name = "PATTERN")]
+    wallet_glob: Option<String>,
+
+    /// Token account to sweep, besides the wallets' (repeatable)
+    #[arg(long = "account", value_name = "PUBKEY")]
+    accounts: Vec<Pubkey>,
+
+    /// Name of the .env keypair of the mint's permanent delegate, which signs and pays for the sweeps
+    #[arg(long, default_value = "wallet_1")]
+    delegate: String,
+
+    /// Token account receiving the dust. Defaults to the delegate's associated token account
+    #[arg(long, value_name = "PUBKEY")]
+    treasury: Option<Pubkey>,
+
+    /// Largest public balance swept, in base units
+    #[arg(long, value_name = "AMOUNT")]
+    max_dust: u64,
+
+    /// Sweep again every SECONDS until Ctrl-C, instead of once
+    #[arg(long, value_name = "SECONDS")]
+    interval: Option<u64>,
+
+    /// Number of transactions sent at once
+    #[arg(long, default_value_t = DEFAULT_PARALLELISM)]
+    parallelism: usize,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    shutdown::install()?;
+
+    let delegate = get_or_create_keypair(&args.delegate)?;
+    let mint = args.flow.mint.pubkey()?;
+    let treasury = args.treasury.unwrap_or_else(|| {
+        get_associated_token_address_with_program_id(
+            &delegate.pubkey(), // Token account owner
+            &mint,              // Mint
+            &spl_token_2022::id(),
+        )
+    });
+
+    let mut accounts = args.accounts.clone();
+    if let Some(pattern) = &args.wallet_glob {
+        for wallet in load_wallet_glob(pattern)? {
+            accounts.push(get_associated_token_address_with_program_id(
+                &wallet.keypair.pubkey(), // Token account owner
+                &mint,                    // Mint
+                &spl_token_2022::id(),
+            ));
+        }
+    }
+    if accounts.is_empty() {
+        return Err("Nothing to sweep, pass --wallet-glob or --account".into());
+    }
+
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.flow.check_mint(&client, &mint)?;
+
+    if client.get_account(&treasury).is_err() {
+        return Err(format!("Treasury token account {} doesn't exist", treasury).into());
+    }
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+
+    let mut report = CostReport::new();
+    let mut failed = 0;
+    loop {
+        let dust = find_dust(&client, &mint, &accounts, &treasury, args.max_dust)?;
+        eprintln!(
+            "{} of {} accounts hold dust, {} at a time",
+            dust.len(),
+            accounts.len(),
+            args.parallelism
+        );
+
+        // The transfers are split evenly across the threads, each packing its share into transactions
+        let per_thread = dust.len().div_ceil(args.parallelism.max(1)).max(1);
+        let shares: Vec<&[DustAccount]> = dust.chunks(per_thread).collect();
+        let results = run_bounded(&shares, args.parallelism, |share: &&[DustAccount]| {
+            // A context per thread, as contexts (and their journal connection) aren't shared across threads
+            let mut ctx = FlowContext::new(&client, 0);
+            match Journal::open(&config.journal_path) {
+                Ok(journal) => ctx.journal = Some(journal),
+                Err(error) => eprintln!("\nCould not open the transaction journal: {}", error),
+            }
+            ctx.wait = args.flow.wait;
+
+            let outcome = args.flow.payers().and_then(|payers| {
+                ctx.payers = payers;
+                ctx.send_strategy = args.flow.send_strategy(&config)?;
+                ctx.confirmation_timeout = config.confirmation_timeout;
+                sweep_dust(
+                    &mut ctx,
+                    &delegate,
+                    &mint,
+                    ui_amount.decimals,
+                    &treasury,
+                    share,
+                )
+            });
+            (outcome.map_err(|error| error.to_string()), ctx.report)
+        });
+
+        let mut swept = SweepOutcome::default();
+        for (outcome, share_report) in results {
+            report.merge(share_report);
+            match outcome {
+                Ok(outcome) => {
+                    swept.swept += outcome.swept;
+                    swept.accounts += outcome.accounts;
+                    swept.signatures.extend(outcome.signatures);
+                }
+                Err(error) => {
+                    failed += 1;
+                    eprintln!("\nSweep failed: {}", error);
+                }
+            }
+        }
+
+        if args.flow.json {
+            println!(
+                "{}",
+                json!({
+                    "treasury": treasury.to_string(),
+                    "accounts": swept.accounts,
+                    "swept": ui_amount.format(swept.swept),
+                    "signatures": swept
+                        .signatures
+                        .iter()
+                        .map(|signature| signature.to_string())
+                        .collect::<Vec<_>>(),
+                })
+            );
+        } else {
+            println!(
+                "\nSwept {} from {} accounts into {}",
+                ui_amount.format(swept.swept),
+                swept.accounts,
+                treasury
+            );
+            for signature in &swept.signatures {
+                println!("  {}", config.explorer.tx_url(signature));
+            }
+        }
+
+        let Some(interval) = args.interval else {
+            break;
+        };
+        // Sleep in short naps, so Ctrl-C stops the sweeper right away
+        let next_sweep = Instant::now() + Duration::from_secs(interval);
+        while !shutdown::interrupted() && Instant::now() < next_sweep {
+            thread::sleep(Duration::from_millis(200));
+        }
+        if shutdown::interrupted() {
+            break;
+        }
+    }
+
+    report.print(args.flow.json, args.flow.fiat_price().as_ref());
+    if failed > 0 {
+        return Err(format!("{} sweep batches failed", failed).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,254 @@
+// cargo run --bin tx -- log --flow transfer --limit 10
+// cargo run --bin tx -- status
+use clap::{Args, Parser, Subcommand};
+use keypair_utils::{
+    config::Config,
+    exit_code,
+    journal::{Journal, JournalEntry, JournalQuery, TxStatus},
+    retry_queue::{RetryQueue, RetryStatus},
+    send::{landing_status, LandingStatus},
+};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use std::{error::Error, process::ExitCode};
+
+// Query the local journal of transactions sent by the flows
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List journaled transactions, newest first
+    Log(LogArgs),
+    /// List transactions queued to be sent again after the RPC node was unreachable
+    Queue(QueueArgs),
+    /// Classify sent transactions as confirmed, rejected, expired or still pending, updating the journal
+    Status(StatusArgs),
+}
+
+#[derive(Args, Debug)]
+struct LogArgs {
+    /// Only show transactions sent by this flow, e.g. transfer or withdraw
+    #[arg(long)]
+    flow: Option<String>,
+
+    /// Only show transactions with this status
+    #[arg(long, value_parser = ["processed", "pending", "confirmed", "finalized", "failed", "expired"])]
+    status: Option<String>,
+
+    /// Only show transactions touching this account
+    #[arg(long, value_name = "PUBKEY")]
+    account: Option<Pubkey>,
+
+    /// Maximum number of transactions to show
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+
+    /// Print the transactions as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct QueueArgs {
+    /// Only show queued transactions with this status
+    #[arg(long, value_parser = ["queued", "landed", "expired", "gave-up"])]
+    status: Option<String>,
+
+    /// Print the queued transactions as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct StatusArgs {
+    /// Transactions to classify. Defaults to the journaled transactions still processed or pending
+    signatures: Vec<Signature>,
+
+    /// Block height the blockhash of the given transactions is valid to, for transactions missing from the journal,
+    /// e.g. signed offline
+    #[arg(long, value_name = "HEIGHT")]
+    last_valid_block_height: Option<u64>,
+
+    /// Print the statuses as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load()?;
+    let journal = Journal::open(&config.journal_path)?;
+
+    match cli.command {
+        Command::Log(args) => {
+            let query = JournalQuery {
+                flow: args.flow,
+                status: args
+                    .status
+                    .map(|status| status.parse::<TxStatus>())
+                    .transpose()?,
+                account: args.account,
+                signature: None,
+                limit: Some(args.limit),
+            };
+            let entries = journal.entries(&query)?;
+
+            if args.json {
+                let entries: Vec<Value> = entries.iter().map(|entry| entry.to_json()).collect();
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if entries.is_empty() {
+                println!("No transactions in {}", config.journal_path);
+            } else {
+                for entry in entries {
+                    println!("{}", entry);
+                    if let Some(signature) = entry.signature {
+                        println!("  {}", config.explorer.tx_url(&signature));
+                    }
+                    println!();
+                }
+            }
+        }
+        Command::Queue(args) => {
+            let retry_queue = RetryQueue::open(&config.journal_path)?;
+            let status = args
+                .status
+                .map(|status| status.parse::<RetryStatus>())
+                .transpose()?;
+            let entries = retry_queue.entries(status)?;
+
+            if args.json {
+                let entries: Vec<Value> = entries.iter().map(|entry| entry.to_json()).collect();
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if entries.is_empty() {
+                println!("No queued transactions in {}", config.journal_path);
+            } else {
+                for entry in entries {
+                    println!("{}\n", entry);
+                }
+            }
+        }
+        Command::Status(args) => {
+            let client = RpcClient::new_with_commitment(
+                config.rpc_url.clone(),
+                CommitmentConfig::confirmed(),
+            );
+            let mut transactions: Vec<(Signature, Option<JournalEntry>)> = Vec::new();
+            if args.signatures.is_empty() {
+                for status in [TxStatus::Processed, TxStatus::Pending] {
+                    let entries = journal.entries(&JournalQuery {
+                        status: Some(status),
+                        ..JournalQuery::default()
+                    })?;
+                    for entry in entries {
+                        if let Some(signature) = entry.signature {
+                            transactions.push((signature, Some(entry)));
+                        }
+                    }
+                }
+            } else {
+                for signature in args.signatures {
+                    let entry = journal
+                        .entries(&JournalQuery {
+                            signature: Some(signature),
+                            limit: Some(1),
+                            ..JournalQuery::default()
+                        })?
+                        .into_iter()
+                        .next();
+                    transactions.push((signature, entry));
+                }
+            }
+
+            let mut statuses = Vec::new();
+            for (signature, entry) in transactions {
+                let last_valid_block_height = entry
+                    .as_ref()
+                    .and_then(|entry| entry.last_valid_block_height)
+                    .or(args.last_valid_block_height);
+                let status = landing_status(
+                    &client,
+                    &signature,
+                    last_valid_block_height,
+                    client.commitment(),
+                )?;
+                // Only final statuses are written back, and only over a journaled status that isn't final yet:
+                // a pending transaction keeps its status, and a finalized one isn't downgraded to confirmed
+                if entry.as_ref().is_some_and(|entry| !entry.status.is_final()) {
+                    match &status {
+                        LandingStatus::Confirmed => {
+                            journal.update_status(&signature, TxStatus::Confirmed, None)?
+                        }
+                        LandingStatus::Rejected(error) => journal.update_status(
+                            &signature,
+                            TxStatus::Failed,
+                            Some(&error.to_string()),
+                        )?,
+                        LandingStatus::Expired => journal.update_status(
+                            &signature,
+                            TxStatus::Expired,
+                            Some("Blockhash expired before the transaction landed"),
+                        )?,
+                        LandingStatus::Pending => {}
+                    }
+                }
+                statuses.push((signature, entry, last_valid_block_height, status));
+            }
+
+            if args.json {
+                let statuses: Vec<Value> = statuses
+                    .iter()
+                    .map(|(signature, entry, last_valid_block_height, status)| {
+                        json!({
+                            "signature": signature.to_string(),
+                            "label": entry.as_ref().map(|entry| entry.label.clone()),
+                            "last_valid_block_height": last_valid_block_height,
+                            "status": status.as_str(),
+                            "error": match status {
+                                LandingStatus::Rejected(error) => Some(error.to_string()),
+                                _ => None,
+                            },
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&statuses)?);
+            } else if statuses.is_empty() {
+                println!(
+                    "No processed or pending transactions in {}",
+                    config.journal_path
+                );
+            } else {
+                for (signature, entry, last_valid_block_height, status) in statuses {
+                    match &entry {
+                        Some(entry) => println!("[{}] {}", entry.flow, entry.label),
+                        None => println!("Not journaled"),
+                    }
+                    println!("  Signature: {}", signature);
+                    match status {
+                        LandingStatus::Rejected(error) => {
+                            println!("  Status:    rejected: {}", error)
+                        }
+                        LandingStatus::Pending if last_valid_block_height.is_none() => {
+                            println!("  Status:    pending, expiry unknown without its last valid block height")
+                        }
+                        LandingStatus::Pending => println!(
+                            "  Status:    pending, can land until block height {}",
+                            last_valid_block_height.unwrap_or_default()
+                        ),
+                        status => println!("  Status:    {}", status.as_str()),
+                    }
+                    println!();
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,198 @@
+// cargo run --bin watch -- --wallet wallet_2
+use clap::Parser;
+use keypair_utils::{
+    cli::FlowArgs,
+    config::Config,
+    events::FlowEvent,
+    exit_code,
+    flows::{
+        watch::{watch_token_account, WatchPolicy, WatchSchedule},
+        FlowContext,
+    },
+    get_or_create_keypair,
+    journal::Journal,
+    proof_cache::ProofCache,
+    read_keypair,
+    retry_queue::{RetryOutcome, RetryQueue},
+    topup::{TopUp, TopUpSource},
+    ui_amount::UiAmount,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, native_token::lamports_to_sol, signature::Signer,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::{error::Error, process::ExitCode, time::Duration};
+
+// Watch a confidential token account and apply its pending balance as credits arrive,
+// so incoming transfers become spendable without running 6_apply_pending_balance by hand
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of the .env keypair owning the token account
+    #[arg(long, default_value = "wallet_2")]
+    wallet: String,
+
+    /// Only apply once the pending balance reaches this many base units
+    #[arg(long, value_name = "AMOUNT", default_value_t = 0)]
+    min_pending: u64,
+
+    /// Apply regardless of --min-pending once the pending balance was credited this many times
+    #[arg(long, value_name = "COUNT")]
+    max_credits: Option<u64>,
+
+    /// Least number of seconds between two applies
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    min_interval: u64,
+
+    /// Slots between checks of the token account without a notification
+    #[arg(long, value_name = "SLOTS", default_value_t = WatchSchedule::default().recheck_slots)]
+    recheck_slots: u64,
+
+    /// Slots between retries of queued transactions and top up checks
+    #[arg(long, value_name = "SLOTS", default_value_t = WatchSchedule::default().maintenance_slots)]
+    maintenance_slots: u64,
+
+    /// Apply regardless of --min-pending in the last SLOTS slots of each epoch
+    #[arg(long, value_name = "SLOTS")]
+    apply_before_epoch_end: Option<u64>,
+
+    /// Name of the .env keypair topping up the wallet's SOL when it runs low
+    #[arg(long, value_name = "NAME", conflicts_with = "top_up_airdrop")]
+    treasury: Option<String>,
+
+    /// Top up the wallet's SOL with airdrops when it runs low (devnet, testnet and local validators)
+    #[arg(long)]
+    top_up_airdrop: bool,
+
+    /// Top up once the wallet's balance drops below this many SOL
+    #[arg(long, value_name = "SOL", default_value_t = 0.05)]
+    top_up_below: f64,
+
+    /// SOL added by each top up
+    #[arg(long, value_name = "SOL", default_value_t = 0.5)]
+    top_up_amount: f64,
+
+    #[command(flatten)]
+    flow: FlowArgs,
+}
+
+fn main() -> ExitCode {
+    exit_code::report(run())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let owner = get_or_create_keypair(&args.wallet)?;
+    let mint = args.flow.mint.pubkey()?;
+
+    // Associated token address of the owner
+    let associated_token_address = get_associated_token_address_with_program_id(
+        &owner.pubkey(), // Token account owner
+        &mint,           // Mint
+        &spl_token_2022::id(),
+    );
+
+    let config = Config::load()?;
+
+    let client =
+        RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+    args.flow.check_mint(&client, &mint)?;
+
+    let mut ctx = FlowContext::new(&client, 0).with_journal(Journal::open(&config.journal_path)?);
+    ctx.wait = args.flow.wait;
+    ctx.payers = args.flow.payers()?;
+    ctx.send_strategy = args.flow.send_strategy(&config)?;
+    ctx.confirmation_timeout = config.confirmation_timeout;
+    ctx.key_derivation = config.key_derivation.clone();
+    // Kept in the journal's database, so failed sends survive a restart of the watcher
+    ctx.retry_queue = Some(RetryQueue::open(&config.journal_path)?);
+    // Only pruned by the watcher, which doesn't generate proofs
+    ctx.proof_cache = Some(ProofCache::open(&config.journal_path)?);
+
+    let ui_amount = UiAmount::fetch(&client, &mint)?;
+    let explorer = config.explorer.clone();
+    ctx.events.subscribe(move |event| match event {
+        FlowEvent::PendingBalanceCredited {
+            credits, pending, ..
+        } => println!(
+            "\nPending balance credited, {} credits pending: {}",
+            credits,
+            ui_amount.format(*pending)
+        ),
+        FlowEvent::TransactionConfirmed { label, signature } => {
+            println!("{}: {}", label, explorer.tx_url(signature))
+        }
+        FlowEvent::AvailableBalanceChanged { before, after } => println!(
+            "Available Balance Before: {}\nAvailable Balance After: {}",
+            ui_amount.format_decrypted(*before),
+            ui_amount.format_decrypted(*after)
+        ),
+        FlowEvent::TransactionQueued { label, id } => {
+            println!("\n{} failed to send, queued for retry as #{}", label, id)
+        }
+        FlowEvent::TransactionRetried { label, id, outcome } => match outcome {
+            RetryOutcome::Landed(_) => println!("Queued #{} ({}) landed", id, label),
+            RetryOutcome::Rescheduled(delay) => println!(
+                "Queued #{} ({}) failed again, next attempt in {} seconds",
+                id,
+                label,
+                delay.as_secs()
+            ),
+            RetryOutcome::Expired => println!(
+                "Queued #{} ({}) expired and can't be re-signed by the wallet, see `cargo run --bin resume`",
+                id, label
+            ),
+            RetryOutcome::GaveUp => println!("Queued #{} ({}) gave up", id, label),
+        },
+        FlowEvent::FeePayerToppedUp {
+            payer,
+            lamports,
+            balance,
+        } => println!(
+            "\nTopped up {} with {} SOL, its balance was {} SOL",
+            payer,
+            lamports_to_sol(*lamports),
+            lamports_to_sol(*balance)
+        ),
+        _ => {}
+    });
+
+    let top_up_source = match (&args.treasury, args.top_up_airdrop) {
+        (Some(treasury), _) => Some(TopUpSource::Treasury(Box::new(
+            read_keypair(treasury)?.ok_or(format!("No {} keypair in the .env file", treasury))?,
+        ))),
+        (None, true) => Some(TopUpSource::Airdrop),
+        (None, false) => None,
+    };
+    let top_up =
+        top_up_source.map(|source| TopUp::new(source, args.top_up_below, args.top_up_amount));
+    if let Some(top_up) = &top_up {
+        top_up.check_cluster(&config.cluster)?;
+    }
+
+    let policy = WatchPolicy {
+        min_pending: args.min_pending,
+        max_credits: args.max_credits,
+        min_interval: Duration::from_secs(args.min_interval),
+    };
+    let schedule = WatchSchedule {
+        recheck_slots: args.recheck_slots,
+        maintenance_slots: args.maintenance_slots,
+        apply_before_epoch_end: args.apply_before_epoch_end,
+    };
+
+    println!(
+        "Watching {} over {}, press Ctrl-C to stop",
+        associated_token_address, config.ws_url
+    );
+    watch_token_account(
+        &mut ctx,
+        &config.ws_url,
+        &associated_token_address,
+        &owner,
+        policy,
+        schedule,
+        top_up,
+    )
+}
@@ -0,0 +1,245 @@
+use crate::{
+    account_diff::TransactionReview,
+    config::Config,
+    events::{FlowEvent, FlowEvents},
+    explorer::Explorer,
+    mint::MintSelector,
+    mint_expectation::MintExpectation,
+    payers::Payers,
+    price::{FiatPrice, PriceSource},
+    progress::FlowProgress,
+    send::WaitFor,
+    send_strategy::{
+        BroadcastSend, JitoBundleSend, RpcSend, SendStrategy, SendStrategyKind, TpuSend,
+        DEFAULT_JITO_TIP_LAMPORTS, DEFAULT_JITO_URL,
+    },
+    ui_amount::UiAmount,
+};
+use clap::Parser;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+// Command line options shared by the flow binaries
+#[derive(Parser, Debug, Default)]
+pub struct FlowArgs {
+    /// Mint to use: the label of a stored `mint:<label>` keypair, or a mint address
+    #[arg(long, value_name = "LABEL|PUBKEY", default_value_t = MintSelector::Default)]
+    pub mint: MintSelector,
+
+    /// Print the end of run report as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Refetch and decrypt balances after the flow and fail unless they changed by exactly the expected amounts
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Commitment each transaction must reach before the flow proceeds: confirmed or finalized,
+    /// or processed to send the proof and transfer transactions back to back and confirm them at the end
+    #[arg(long, value_name = "COMMITMENT", default_value_t = WaitFor::Confirmed)]
+    pub wait: WaitFor,
+
+    /// Fixed SOL price used to estimate the fiat cost of fees
+    #[arg(long, value_name = "PRICE", conflicts_with = "sol_price_url")]
+    pub sol_price: Option<f64>,
+
+    /// JSON endpoint to fetch the SOL price from, e.g. CoinGecko's simple price API
+    #[arg(long, value_name = "URL")]
+    pub sol_price_url: Option<String>,
+
+    /// JSON pointer to the price inside the --sol-price-url response
+    #[arg(long, value_name = "POINTER", default_value = "/solana/usd")]
+    pub sol_price_pointer: String,
+
+    /// Currency the SOL price is quoted in
+    #[arg(long, value_name = "CODE", default_value = "USD")]
+    pub fiat_currency: String,
+
+    /// Name of the .env keypair paying transaction fees instead of the wallet
+    #[arg(long, value_name = "NAME")]
+    pub fee_payer: Option<String>,
+
+    /// Name of the .env keypair funding the rent of new proof and token accounts instead of the wallet
+    #[arg(long, value_name = "NAME")]
+    pub rent_funder: Option<String>,
+
+    /// Memo attached to deposit, transfer and withdraw transactions, e.g. "invoice 123"
+    #[arg(long, value_name = "TEXT")]
+    pub memo: Option<String>,
+
+    /// How transactions are submitted: rpc, broadcast to the --send-endpoint RPC nodes too,
+    /// jito bundles through a block engine, or tpu straight to the leaders
+    #[arg(long, value_name = "STRATEGY", default_value_t = SendStrategyKind::Rpc)]
+    pub send_strategy: SendStrategyKind,
+
+    /// RPC node to broadcast to, can be repeated, or the block engine URL for jito
+    #[arg(long = "send-endpoint", value_name = "URL")]
+    pub send_endpoints: Vec<String>,
+
+    /// Tip paid to the block engine with each jito bundle, in lamports
+    #[arg(long, value_name = "LAMPORTS", default_value_t = DEFAULT_JITO_TIP_LAMPORTS)]
+    pub jito_tip: u64,
+
+    /// Expected mint configuration written by `mint-expectation`: abort before any proof work unless the mint's
+    /// extensions and authorities match it exactly
+    #[arg(long, value_name = "FILE")]
+    pub strict: Option<PathBuf>,
+
+    /// Simulate each transaction before sending it, show how it changes the mint and token accounts it writes,
+    /// decoded extensions included, and ask whether to send it
+    #[arg(long)]
+    pub review: bool,
+}
+
+// Command line options of the binaries creating a mint
+#[derive(Parser, Debug, Default)]
+pub struct CreateMintArgs {
+    /// Decimals of the new mint
+    #[arg(long, default_value_t = 2)]
+    pub decimals: u8,
+
+    /// Add the ScaledUiAmount extension, displaying balances multiplied by this value
+    #[arg(long, value_name = "MULTIPLIER", value_parser = parse_multiplier)]
+    pub scaled_ui_multiplier: Option<f64>,
+
+    /// Add the Pausable extension, with the mint authority as the pause authority
+    #[arg(long)]
+    pub pausable: bool,
+
+    /// Add the PermanentDelegate extension, with this .env keypair able to move funds out of any account of the mint
+    #[arg(long, value_name = "NAME")]
+    pub permanent_delegate: Option<String>,
+
+    #[command(flatten)]
+    pub flow: FlowArgs,
+}
+
+// The program only accepts positive, finite multipliers
+fn parse_multiplier(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(multiplier) if multiplier.is_finite() && multiplier > 0.0 => Ok(multiplier),
+        _ => Err(format!("{} is not a positive multiplier", value)),
+    }
+}
+
+impl FlowArgs {
+    // With --strict, fail unless the mint matches the expectation file
+    pub fn check_mint(&self, client: &RpcClient, mint: &Pubkey) -> Result<(), Box<dyn Error>> {
+        match &self.strict {
+            Some(path) => MintExpectation::load(path)?.check(client, mint),
+            None => Ok(()),
+        }
+    }
+
+    // With --review, print each transaction's simulated account changes and ask on the terminal whether to send it
+    pub fn review(&self, progress: &Arc<FlowProgress>) -> Option<TransactionReview> {
+        if !self.review {
+            return None;
+        }
+        let progress = progress.clone();
+        Some(Box::new(move |diff| {
+            progress.suspend(|| {
+                println!("\n{}", diff);
+                print!("Send {}? [y/N] ", diff.label);
+                let _ = io::stdout().flush();
+                let mut answer = String::new();
+                io::stdin().lock().read_line(&mut answer).is_ok()
+                    && matches!(answer.trim(), "y" | "Y" | "yes")
+            })
+        }))
+    }
+
+    pub fn payers(&self) -> Result<Payers, Box<dyn Error>> {
+        Payers::load(self.fee_payer.as_deref(), self.rent_funder.as_deref())
+    }
+
+    pub fn send_strategy(&self, config: &Config) -> Result<Box<dyn SendStrategy>, Box<dyn Error>> {
+        Ok(match self.send_strategy {
+            SendStrategyKind::Rpc => Box::new(RpcSend),
+            SendStrategyKind::Broadcast if self.send_endpoints.is_empty() => {
+                return Err("--send-strategy broadcast needs at least one --send-endpoint".into())
+            }
+            SendStrategyKind::Broadcast => Box::new(BroadcastSend::new(&self.send_endpoints)),
+            SendStrategyKind::Jito => Box::new(JitoBundleSend::new(
+                self.send_endpoints
+                    .first()
+                    .map_or(DEFAULT_JITO_URL, String::as_str),
+                self.jito_tip,
+            )),
+            SendStrategyKind::Tpu => Box::new(TpuSend::new(&config.rpc_url, &config.ws_url)?),
+        })
+    }
+
+    pub fn price_source(&self) -> Option<PriceSource> {
+        match (self.sol_price, &self.sol_price_url) {
+            (Some(price), _) => Some(PriceSource::Fixed(price)),
+            (None, Some(url)) => Some(PriceSource::Url {
+                url: url.clone(),
+                pointer: self.sol_price_pointer.clone(),
+            }),
+            (None, None) => None,
+        }
+    }
+
+    // The fiat estimate is optional, so a failed price lookup is reported without failing the flow
+    pub fn fiat_price(&self) -> Option<FiatPrice> {
+        let source = self.price_source()?;
+        match source.fetch() {
+            Ok(price) => Some(FiatPrice {
+                price,
+                currency: self.fiat_currency.clone(),
+            }),
+            Err(error) => {
+                eprintln!("\nCould not fetch SOL price: {}", error);
+                None
+            }
+        }
+    }
+}
+
+// Render flow events on the terminal: a spinner per step, and a link per confirmed transaction
+pub fn terminal_output(
+    events: &mut FlowEvents,
+    explorer: &Explorer,
+    ui_amount: UiAmount,
+) -> Arc<FlowProgress> {
+    let progress = Arc::new(FlowProgress::new());
+    let listener = progress.clone();
+    let explorer = explorer.clone();
+    events.subscribe(move |event| {
+        listener.handle(event);
+        match event {
+            FlowEvent::TransactionConfirmed { label, signature } => {
+                listener.println(&format!("\n{}: {}", label, explorer.tx_url(signature)))
+            }
+            FlowEvent::AvailableBalanceChanged { before, after } => listener.println(&format!(
+                "\nAvailable Balance Before: {}\nAvailable Balance After: {}",
+                ui_amount.format_decrypted(*before),
+                ui_amount.format_decrypted(*after)
+            )),
+            FlowEvent::HeapFrameDropped { label } => listener.println(&format!(
+                "\n⚠ {} has no room for the heap frame request and is sent without it, \
+                 its proof verification runs with the default heap",
+                label
+            )),
+            FlowEvent::ProofReused { proof } => listener.println(&format!(
+                "\nReusing the cached {:?} proof data of an earlier run",
+                proof
+            )),
+            FlowEvent::TransactionFinalized { signature } => {
+                listener.println(&format!("✔ Finalized {}", signature))
+            }
+            FlowEvent::BalancesVerified { token_account } => {
+                listener.println(&format!("\n✔ Balances of {} verified", token_account))
+            }
+            _ => {}
+        }
+    });
+    progress
+}
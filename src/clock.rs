@@ -0,0 +1,166 @@
+use solana_client::{pubsub_client::PubsubClient, rpc_client::RpcClient};
+use solana_sdk::{commitment_config::CommitmentConfig, epoch_schedule::EpochSchedule};
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// Without a slot notification for this long the subscription is assumed stalled, and the slot is fetched over RPC
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+// How often the subscription thread checks whether the clock was dropped
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Pause before subscribing again after the websocket connection dropped
+const RESUBSCRIBE_PAUSE: Duration = Duration::from_secs(2);
+
+// A point in cluster time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterTime {
+    pub slot: u64,
+    pub epoch: u64,
+    // Slot within the epoch
+    pub slot_index: u64,
+    pub slots_in_epoch: u64,
+}
+
+impl ClusterTime {
+    pub fn new(epoch_schedule: &EpochSchedule, slot: u64) -> Self {
+        let (epoch, slot_index) = epoch_schedule.get_epoch_and_slot_index(slot);
+        Self {
+            slot,
+            epoch,
+            slot_index,
+            slots_in_epoch: epoch_schedule.get_slots_in_epoch(epoch),
+        }
+    }
+
+    pub fn slots_until_epoch_end(&self) -> u64 {
+        self.slots_in_epoch
+            .saturating_sub(self.slot_index)
+            .saturating_sub(1)
+    }
+}
+
+struct ClockState {
+    slot: u64,
+    updated: Instant,
+}
+
+// Slot and epoch of the cluster, kept current by a websocket slot subscription on a background thread,
+// so long running processes like `watch` can schedule work in cluster time without polling `getSlot`.
+// The epoch schedule is fetched once, epochs are derived from the slot.
+pub struct ClusterClock {
+    client: RpcClient,
+    epoch_schedule: EpochSchedule,
+    state: Arc<Mutex<ClockState>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ClusterClock {
+    pub fn subscribe(client: &RpcClient, ws_url: &str) -> Result<Self, Box<dyn Error>> {
+        // Processed, the slot the cluster is working on rather than the last one voted on
+        let client = RpcClient::new_with_commitment(client.url(), CommitmentConfig::processed());
+        let epoch_schedule = client.get_epoch_schedule()?;
+        let state = Arc::new(Mutex::new(ClockState {
+            slot: client.get_slot()?,
+            updated: Instant::now(),
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let ws_url = ws_url.to_string();
+        let thread_state = state.clone();
+        let thread_stopped = stopped.clone();
+        thread::spawn(move || {
+            while !thread_stopped.load(Ordering::Relaxed) {
+                let (_subscription, receiver) = match PubsubClient::slot_subscribe(&ws_url) {
+                    Ok(subscription) => subscription,
+                    Err(error) => {
+                        eprintln!("\nCould not subscribe to slots: {}", error);
+                        thread::sleep(RESUBSCRIBE_PAUSE);
+                        continue;
+                    }
+                };
+                while !thread_stopped.load(Ordering::Relaxed) {
+                    match receiver.recv_timeout(POLL_INTERVAL) {
+                        Ok(slot_info) => {
+                            let mut state = thread_state.lock().unwrap();
+                            // Notifications of forks can arrive out of order
+                            state.slot = state.slot.max(slot_info.slot);
+                            state.updated = Instant::now();
+                        }
+                        Err(error) if error.is_timeout() => {}
+                        Err(_) => break,
+                    }
+                }
+                thread::sleep(RESUBSCRIBE_PAUSE);
+            }
+        });
+
+        Ok(Self {
+            client,
+            epoch_schedule,
+            state,
+            stopped,
+        })
+    }
+
+    pub fn epoch_schedule(&self) -> &EpochSchedule {
+        &self.epoch_schedule
+    }
+
+    // The latest slot seen, refreshed over RPC if the subscription has stalled
+    pub fn slot(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        if state.updated.elapsed() > STALE_AFTER {
+            match self.client.get_slot() {
+                Ok(slot) => {
+                    state.slot = state.slot.max(slot);
+                    state.updated = Instant::now();
+                }
+                Err(error) => eprintln!("\nCould not fetch the slot: {}", error),
+            }
+        }
+        state.slot
+    }
+
+    pub fn now(&self) -> ClusterTime {
+        ClusterTime::new(&self.epoch_schedule, self.slot())
+    }
+}
+
+impl Drop for ClusterClock {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+// Work due every `slots` slots, e.g. `SlotInterval::new(150)` for about once a minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotInterval {
+    pub slots: u64,
+    last: Option<u64>,
+}
+
+impl SlotInterval {
+    pub fn new(slots: u64) -> Self {
+        Self { slots, last: None }
+    }
+
+    // Whether the work is due at `slot`, which it always is the first time. Marks it as done.
+    pub fn due(&mut self, slot: u64) -> bool {
+        if self
+            .last
+            .is_some_and(|last| slot < last.saturating_add(self.slots))
+        {
+            return false;
+        }
+        self.last = Some(slot);
+        true
+    }
+}
@@ -0,0 +1,171 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::{json, Value};
+use solana_client::{rpc_client::RpcClient, rpc_request::MAX_MULTIPLE_ACCOUNTS};
+use solana_sdk::{account::Account, program_option::COption, pubkey::Pubkey};
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::ConfidentialTransferMint, StateWithExtensionsMut,
+        StateWithExtensionsOwned,
+    },
+    state::{Account as TokenAccount, Mint},
+};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const MAINNET_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+
+// A mint and token accounts fetched from another cluster, to be loaded into the local test validator
+// so flows can be rehearsed against the extension configuration of a real mint.
+//
+// The test validator loads accounts at startup only, either fetched with `--clone <ADDRESS>` from `--url`,
+// or from `solana account --output json` dumps with `--account <ADDRESS> <FILE>`. Dumps can be edited first,
+// e.g. to hand the mint authority to a local keypair so tokens can be minted for the rehearsal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountClone {
+    pub source_url: String,
+    pub mint: Pubkey,
+    // The mint first, then the token accounts
+    pub accounts: Vec<(Pubkey, Account)>,
+    // Whether an account was changed after fetching, so it can only be loaded from its dump
+    pub edited: bool,
+}
+
+impl AccountClone {
+    // Fetch the mint, the given token accounts and the `largest` largest token accounts of the mint
+    pub fn fetch(
+        source: &RpcClient,
+        mint: &Pubkey,
+        token_accounts: &[Pubkey],
+        largest: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mint_account = source.get_account(mint)?;
+        if mint_account.owner != spl_token_2022::id() {
+            return Err(format!(
+                "{} is owned by {}, not the Token-2022 program",
+                mint, mint_account.owner
+            )
+            .into());
+        }
+        StateWithExtensionsOwned::<Mint>::unpack(mint_account.data.clone())
+            .map_err(|error| format!("{} is not a mint: {}", mint, error))?;
+
+        let mut addresses = token_accounts.to_vec();
+        if largest > 0 {
+            for holder in source
+                .get_token_largest_accounts(mint)?
+                .iter()
+                .take(largest)
+            {
+                let address = holder.address.parse()?;
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+
+        let mut accounts = vec![(*mint, mint_account)];
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            for (address, account) in chunk.iter().zip(source.get_multiple_accounts(chunk)?) {
+                let account = account.ok_or(format!("Token account {} not found", address))?;
+                let token_account =
+                    StateWithExtensionsOwned::<TokenAccount>::unpack(account.data.clone())
+                        .map_err(|error| {
+                            format!("{} is not a token account: {}", address, error)
+                        })?;
+                if token_account.base.mint != *mint {
+                    return Err(format!(
+                        "Token account {} belongs to mint {}, not {}",
+                        address, token_account.base.mint, mint
+                    )
+                    .into());
+                }
+                accounts.push((*address, account));
+            }
+        }
+
+        Ok(Self {
+            source_url: source.url(),
+            mint: *mint,
+            accounts,
+            edited: false,
+        })
+    }
+
+    // Hand the mint authority, and the confidential transfer authority if the mint has one, to `authority`
+    pub fn set_mint_authority(&mut self, authority: &Pubkey) -> Result<(), Box<dyn Error>> {
+        let (_, mint_account) = &mut self.accounts[0];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack(&mut mint_account.data)?;
+        state.base.mint_authority = COption::Some(*authority);
+        state.pack_base();
+        if let Ok(extension) = state.get_extension_mut::<ConfidentialTransferMint>() {
+            if Option::<Pubkey>::from(extension.authority).is_some() {
+                extension.authority = Some(*authority).try_into()?;
+            }
+        }
+        self.edited = true;
+        Ok(())
+    }
+
+    // Write one dump per account to `dir`, named after the address, returning their paths
+    pub fn write_dumps<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        fs::create_dir_all(&dir)?;
+        let mut paths = Vec::new();
+        for (address, account) in &self.accounts {
+            let path = dir.as_ref().join(format!("{}.json", address));
+            fs::write(
+                &path,
+                serde_json::to_string_pretty(&account_dump(address, account))?,
+            )?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    // Arguments of `solana-test-validator` loading the dumps written to `dir`
+    pub fn account_args<P: AsRef<Path>>(&self, dir: P) -> Vec<String> {
+        self.accounts
+            .iter()
+            .flat_map(|(address, _)| {
+                [
+                    "--account".to_string(),
+                    address.to_string(),
+                    dir.as_ref()
+                        .join(format!("{}.json", address))
+                        .display()
+                        .to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    // Arguments of `solana-test-validator` fetching the accounts itself, `None` once they were edited
+    pub fn clone_args(&self) -> Option<Vec<String>> {
+        if self.edited {
+            return None;
+        }
+        let mut args = vec!["--url".to_string(), self.source_url.clone()];
+        for (address, _) in &self.accounts {
+            args.push("--clone".to_string());
+            args.push(address.to_string());
+        }
+        Some(args)
+    }
+}
+
+// An account in the format of `solana account --output json`, which `solana-test-validator --account` reads
+pub fn account_dump(address: &Pubkey, account: &Account) -> Value {
+    json!({
+        "pubkey": address.to_string(),
+        "account": {
+            "lamports": account.lamports,
+            "data": [BASE64_STANDARD.encode(&account.data), "base64"],
+            "owner": account.owner.to_string(),
+            "executable": account.executable,
+            "rentEpoch": account.rent_epoch,
+            "space": account.data.len(),
+        },
+    })
+}
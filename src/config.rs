@@ -0,0 +1,230 @@
+use crate::{
+    explorer::{Cluster, Explorer, ExplorerKind},
+    key_derivation::KeyDerivation,
+    policy::Policy,
+    seed::Seed,
+};
+use std::{env, error::Error, fmt, time::Duration};
+
+pub const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+pub const DEFAULT_JOURNAL_PATH: &str = "journal.sqlite3";
+pub const DEFAULT_CONTACTS_PATH: &str = "contacts.sqlite3";
+// The largest heap frame a transaction can request
+pub const DEFAULT_HEAP_FRAME_BYTES: u32 = 256 * 1024;
+
+// Settings shared by all binaries, read from the environment or the .env file
+//
+// RPC_URL   - JSON RPC endpoint, defaults to the local test validator
+// EXPLORER  - solana.fm (default), explorer.solana.com, solscan, or a URL template such as
+//             "https://explorer.example.com/tx/{signature}?cluster={cluster}"
+// JOURNAL   - SQLite file recording every sent transaction, defaults to journal.sqlite3
+// WS_URL    - websocket endpoint for subscriptions, defaults to the RPC_URL with a ws:// or wss:// scheme
+//             and, when it has a port, the next port (the test validator serves websockets on 8900)
+// CONTACTS  - SQLite address book of named recipients, defaults to contacts.sqlite3
+// POLICY    - JSON file with the limits checked before signing transfers and withdraws, see `policy::Policy`
+// SEED      - derive generated keypairs from this string instead of at random, see `seed::Seed`
+// HEAP_FRAME_BYTES - heap frame requested by proof verification transactions, a multiple of 1024
+//             between 32768 and 262144 (default), or 0 to not request one
+// CONFIRMATION_TIMEOUT - seconds to wait for each transaction to confirm before failing with `send::Pending`,
+//             by default until its blockhash expires
+// KEY_DERIVATION - v1 (default) or v2, how the encryption keys of new token accounts are derived,
+//             see `key_derivation::KeyDerivation`
+// KEY_DERIVATION_SALT - salt mixed into v2 keys
+// CLUSTERS  - named clusters `replicate` sets up side by side, e.g.
+//             "localnet=http://127.0.0.1:8899,devnet=https://api.devnet.solana.com"
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub rpc_url: String,
+    pub ws_url: String,
+    pub cluster: Cluster,
+    pub explorer: Explorer,
+    pub journal_path: String,
+    pub contacts_path: String,
+    pub policy_path: Option<String>,
+    pub heap_frame_bytes: Option<u32>,
+    pub seed: Option<Seed>,
+    pub confirmation_timeout: Option<Duration>,
+    pub key_derivation: KeyDerivation,
+    // Name and RPC URL of each cluster in CLUSTERS
+    pub clusters: Vec<(String, String)>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        Self::from_env().map_err(|error| ConfigError(error).into())
+    }
+
+    fn from_env() -> Result<Self, Box<dyn Error>> {
+        dotenv::dotenv().ok();
+
+        let rpc_url = env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+        let cluster = Cluster::from_rpc_url(&rpc_url);
+        let ws_url = env::var("WS_URL").unwrap_or_else(|_| websocket_url(&rpc_url));
+
+        let explorer_kind = match env::var("EXPLORER") {
+            Ok(value) => value.parse()?,
+            Err(_) => ExplorerKind::SolanaFm,
+        };
+
+        let journal_path = env::var("JOURNAL").unwrap_or_else(|_| DEFAULT_JOURNAL_PATH.to_string());
+
+        let contacts_path =
+            env::var("CONTACTS").unwrap_or_else(|_| DEFAULT_CONTACTS_PATH.to_string());
+        let policy_path = env::var("POLICY").ok();
+
+        let heap_frame_bytes = match env::var("HEAP_FRAME_BYTES") {
+            Ok(value) => parse_heap_frame_bytes(&value)?,
+            Err(_) => Some(DEFAULT_HEAP_FRAME_BYTES),
+        };
+
+        let seed = Seed::from_env()?;
+
+        let confirmation_timeout = match env::var("CONFIRMATION_TIMEOUT") {
+            Ok(value) => Some(parse_confirmation_timeout(&value)?),
+            Err(_) => None,
+        };
+
+        let key_derivation = KeyDerivation::from_env()?;
+
+        let clusters = match env::var("CLUSTERS") {
+            Ok(value) => parse_clusters(&value)?,
+            Err(_) => Vec::new(),
+        };
+
+        Ok(Self {
+            explorer: Explorer::new(explorer_kind, cluster.clone()),
+            rpc_url,
+            ws_url,
+            cluster,
+            journal_path,
+            contacts_path,
+            policy_path,
+            heap_frame_bytes,
+            seed,
+            confirmation_timeout,
+            key_derivation,
+            clusters,
+        })
+    }
+
+    // The configuration for another cluster: its RPC and websocket URLs, explorer links, and a journal of its own
+    // next to the configured one, e.g. journal.devnet.sqlite3, so the transactions of clusters don't mix
+    pub fn for_cluster(&self, name: &str, rpc_url: &str) -> Self {
+        let cluster = Cluster::from_rpc_url(rpc_url);
+        let journal_path = match self.journal_path.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() && !extension.contains('/') => {
+                format!("{}.{}.{}", stem, name, extension)
+            }
+            _ => format!("{}.{}", self.journal_path, name),
+        };
+        Self {
+            rpc_url: rpc_url.to_string(),
+            ws_url: websocket_url(rpc_url),
+            explorer: Explorer::new(self.explorer.kind.clone(), cluster.clone()),
+            cluster,
+            journal_path,
+            ..self.clone()
+        }
+    }
+
+    // The signing policy of the POLICY file, if one is configured
+    pub fn policy(&self) -> Result<Option<Policy>, Box<dyn Error>> {
+        self.policy_path
+            .as_deref()
+            .map(Policy::load)
+            .transpose()
+            .map_err(|error| ConfigError(error).into())
+    }
+}
+
+// An invalid setting in the environment or a file it points to, exiting with `exit_code::CliError::Config`
+#[derive(Debug)]
+pub struct ConfigError(pub Box<dyn Error>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration: {}", self.0)
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+// Websocket URL served next to an RPC URL, following the Solana CLI's convention
+fn websocket_url(rpc_url: &str) -> String {
+    let (scheme, rest) = match rpc_url.split_once("://") {
+        Some(("https", rest)) => ("wss", rest),
+        Some((_, rest)) => ("ws", rest),
+        None => ("ws", rpc_url),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) => match port.parse::<u16>() {
+            Ok(port) => format!("{}:{}", name, port.saturating_add(1)),
+            Err(_) => host.to_string(),
+        },
+        None => host.to_string(),
+    };
+    format!("{}://{}{}", scheme, host, path)
+}
+
+// The runtime rejects heap frames outside 32 KiB..=256 KiB or not a multiple of 1 KiB
+fn parse_heap_frame_bytes(value: &str) -> Result<Option<u32>, Box<dyn Error>> {
+    let bytes: u32 = value.parse().map_err(|_| {
+        format!(
+            "Invalid HEAP_FRAME_BYTES {:?}, expected a number of bytes",
+            value
+        )
+    })?;
+    if bytes == 0 {
+        return Ok(None);
+    }
+    if !(32 * 1024..=DEFAULT_HEAP_FRAME_BYTES).contains(&bytes) || !bytes.is_multiple_of(1024) {
+        return Err(format!(
+            "Invalid HEAP_FRAME_BYTES {}, expected a multiple of 1024 between 32768 and {}",
+            bytes, DEFAULT_HEAP_FRAME_BYTES
+        )
+        .into());
+    }
+    Ok(Some(bytes))
+}
+
+fn parse_confirmation_timeout(value: &str) -> Result<Duration, Box<dyn Error>> {
+    match value.parse::<u64>() {
+        Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+        _ => Err(format!(
+            "Invalid CONFIRMATION_TIMEOUT {:?}, expected a number of seconds above 0",
+            value
+        )
+        .into()),
+    }
+}
+
+fn parse_clusters(value: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut clusters: Vec<(String, String)> = Vec::new();
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (name, rpc_url) = entry
+            .split_once('=')
+            .map(|(name, rpc_url)| (name.trim(), rpc_url.trim()))
+            .filter(|(name, rpc_url)| !name.is_empty() && !rpc_url.is_empty())
+            .ok_or(format!(
+                "Invalid CLUSTERS entry {:?}, expected <name>=<rpc url>",
+                entry
+            ))?;
+        if clusters.iter().any(|(existing, _)| existing == name) {
+            return Err(format!("Cluster {} is listed twice in CLUSTERS", name).into());
+        }
+        clusters.push((name.to_string(), rpc_url.to_string()));
+    }
+    Ok(clusters)
+}
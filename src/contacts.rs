@@ -0,0 +1,186 @@
+use crate::account_state::ConfidentialAccountState;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use rusqlite::{params, Connection, OptionalExtension};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::solana_zk_token_sdk::zk_token_elgamal::pod::ElGamalPubkey;
+use std::{error::Error, fmt, path::Path, str::FromStr};
+
+// A named recipient: the wallet that owns their token accounts, and optionally the ElGamal pubkey
+// transfers to them are expected to be encrypted under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contact {
+    pub name: String,
+    pub address: Pubkey,
+    pub elgamal_pubkey: Option<ElGamalPubkey>,
+}
+
+impl Contact {
+    // Fail if the contact has a known ElGamal pubkey and the token account is configured with another one,
+    // so a swapped token account key is caught before encrypting a transfer to it
+    pub fn check_elgamal_pubkey(
+        &self,
+        client: &RpcClient,
+        token_account: &Pubkey,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(expected) = self.elgamal_pubkey else {
+            return Ok(());
+        };
+        let configured = ConfidentialAccountState::fetch(client, token_account)?.elgamal_pubkey;
+        if configured != expected {
+            return Err(format!(
+                "Token account {} of contact {} is configured with ElGamal pubkey {}, not the {} in the address book",
+                token_account, self.name, configured, expected
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Contact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.address)?;
+        if let Some(elgamal_pubkey) = &self.elgamal_pubkey {
+            write!(f, "\n  ElGamal Pubkey: {}", elgamal_pubkey)?;
+        }
+        Ok(())
+    }
+}
+
+// ElGamal pubkeys are written in base64, like `ElGamalPubkey`'s Display
+pub fn parse_elgamal_pubkey(value: &str) -> Result<ElGamalPubkey, String> {
+    let bytes = BASE64_STANDARD
+        .decode(value)
+        .map_err(|error| format!("Invalid ElGamal pubkey {}: {}", value, error))?;
+    Ok(ElGamalPubkey(bytes.try_into().map_err(|_| {
+        format!("Invalid ElGamal pubkey {}, expected 32 bytes", value)
+    })?))
+}
+
+// Recipient given on the command line: a contact name, or an address used as is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recipient {
+    Name(String),
+    Address(Pubkey),
+}
+
+impl FromStr for Recipient {
+    type Err = String;
+
+    // A base58 address is used directly, anything else is looked up in the address book
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match Pubkey::from_str(value) {
+            Ok(address) => Recipient::Address(address),
+            Err(_) => Recipient::Name(value.to_string()),
+        })
+    }
+}
+
+impl Recipient {
+    // The contact for the recipient, an anonymous one without an ElGamal pubkey for a plain address
+    pub fn resolve(&self, address_book: &AddressBook) -> Result<Contact, Box<dyn Error>> {
+        match self {
+            Recipient::Name(name) => address_book
+                .get(name)?
+                .ok_or_else(|| format!("No contact named {} in the address book", name).into()),
+            Recipient::Address(address) => Ok(Contact {
+                name: address.to_string(),
+                address: *address,
+                elgamal_pubkey: None,
+            }),
+        }
+    }
+}
+
+// Local SQLite address book of named recipients, kept across runs
+pub struct AddressBook {
+    connection: Connection,
+}
+
+impl AddressBook {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS contacts (
+                name           TEXT PRIMARY KEY,
+                address        TEXT NOT NULL,
+                elgamal_pubkey TEXT
+            );",
+        )?;
+        Ok(Self { connection })
+    }
+
+    // Add a contact, replacing any contact with the same name
+    pub fn save(&self, contact: &Contact) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO contacts (name, address, elgamal_pubkey) VALUES (?1, ?2, ?3)",
+            params![
+                contact.name,
+                contact.address.to_string(),
+                contact
+                    .elgamal_pubkey
+                    .map(|elgamal_pubkey| elgamal_pubkey.to_string()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Remove a contact, returning whether it existed
+    pub fn remove(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self
+            .connection
+            .execute("DELETE FROM contacts WHERE name = ?1", params![name])?
+            > 0)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Contact>, Box<dyn Error>> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT name, address, elgamal_pubkey FROM contacts WHERE name = ?1",
+                params![name],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        row.map(Self::contact).transpose()
+    }
+
+    // All contacts, sorted by name
+    pub fn list(&self) -> Result<Vec<Contact>, Box<dyn Error>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, address, elgamal_pubkey FROM contacts ORDER BY name")?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?;
+
+        let mut contacts = Vec::new();
+        for row in rows {
+            contacts.push(Self::contact(row?)?);
+        }
+        Ok(contacts)
+    }
+
+    fn contact(
+        (name, address, elgamal_pubkey): (String, String, Option<String>),
+    ) -> Result<Contact, Box<dyn Error>> {
+        Ok(Contact {
+            name,
+            address: address.parse()?,
+            elgamal_pubkey: elgamal_pubkey
+                .map(|elgamal_pubkey| parse_elgamal_pubkey(&elgamal_pubkey))
+                .transpose()?,
+        })
+    }
+}
@@ -0,0 +1,231 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::{json, Value};
+use solana_client::{nonce_utils, rpc_client::RpcClient};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction::advance_nonce_account,
+    transaction::Transaction,
+};
+use std::{error::Error, fs, path::PathBuf, str::FromStr};
+
+pub const BLOB_VERSION: u64 = 1;
+
+// A transaction passed between co-signers until it carries every signature it needs, then broadcast by anyone,
+// e.g. one acting for an SPL Token multisig that owns a confidential token account.
+//
+// Collecting signatures can take longer than a blockhash lives, about a minute, so the transaction can use
+// a durable nonce instead: its first instruction advances the nonce, signed by the nonce authority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialTransaction {
+    pub label: String,
+    pub transaction: Transaction,
+    pub nonce_account: Option<Pubkey>,
+}
+
+impl PartialTransaction {
+    // An unsigned transaction of the instructions, with the blockhash of `client` or the nonce of `nonce_account`
+    pub fn new(
+        client: &RpcClient,
+        label: &str,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        nonce: Option<(Pubkey, Pubkey)>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (instructions, blockhash) = match nonce {
+            Some((nonce_account, nonce_authority)) => {
+                let account = nonce_utils::get_account_with_commitment(
+                    client,
+                    &nonce_account,
+                    client.commitment(),
+                )?;
+                let nonce_data = nonce_utils::data_from_account(&account)?;
+                if nonce_data.authority != nonce_authority {
+                    return Err(format!(
+                        "The authority of nonce account {} is {}, not {}",
+                        nonce_account, nonce_data.authority, nonce_authority
+                    )
+                    .into());
+                }
+                // Advancing the nonce must come first, it is what makes the transaction valid with an old blockhash
+                let mut with_advance =
+                    vec![advance_nonce_account(&nonce_account, &nonce_authority)];
+                with_advance.extend_from_slice(instructions);
+                (with_advance, nonce_data.blockhash())
+            }
+            None => (instructions.to_vec(), client.get_latest_blockhash()?),
+        };
+
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(payer));
+        transaction.message.recent_blockhash = blockhash;
+        Ok(Self {
+            label: label.to_string(),
+            transaction,
+            nonce_account: nonce.map(|(nonce_account, _)| nonce_account),
+        })
+    }
+
+    // Identifies the transaction across its copies, whatever signatures they carry
+    pub fn id(&self) -> Hash {
+        self.transaction.message.hash()
+    }
+
+    pub fn blockhash(&self) -> Hash {
+        self.transaction.message.recent_blockhash
+    }
+
+    pub fn required_signers(&self) -> &[Pubkey] {
+        let count = self.transaction.message.header.num_required_signatures as usize;
+        &self.transaction.message.account_keys[..count]
+    }
+
+    pub fn missing_signers(&self) -> Vec<Pubkey> {
+        self.required_signers()
+            .iter()
+            .zip(&self.transaction.signatures)
+            .filter(|(_, signature)| **signature == Signature::default())
+            .map(|(pubkey, _)| *pubkey)
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing_signers().is_empty()
+    }
+
+    // Add the keypair's signature, failing if the transaction doesn't need it
+    pub fn sign(&mut self, keypair: &Keypair) -> Result<(), Box<dyn Error>> {
+        if !self.required_signers().contains(&keypair.pubkey()) {
+            return Err(format!("{} doesn't need to sign {}", keypair.pubkey(), self.label).into());
+        }
+        let blockhash = self.blockhash();
+        self.transaction.try_partial_sign(&[keypair], blockhash)?;
+        Ok(())
+    }
+
+    // Take the signatures of another copy of the same transaction, e.g. one signed by another co-signer.
+    // Signatures that don't verify are rejected, so a tampered copy can't spoil the transaction.
+    pub fn merge(&mut self, other: &PartialTransaction) -> Result<(), Box<dyn Error>> {
+        if other.transaction.message != self.transaction.message {
+            return Err(format!(
+                "{} is a different transaction than {}",
+                other.id(),
+                self.id()
+            )
+            .into());
+        }
+        let message = self.transaction.message_data();
+        for ((pubkey, signature), merged) in other
+            .required_signers()
+            .iter()
+            .zip(&other.transaction.signatures)
+            .zip(self.transaction.signatures.iter_mut())
+        {
+            if *signature == Signature::default() {
+                continue;
+            }
+            if !signature.verify(pubkey.as_ref(), &message) {
+                return Err(format!("Invalid signature of {} in {}", pubkey, other.id()).into());
+            }
+            *merged = *signature;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<Value, Box<dyn Error>> {
+        Ok(json!({
+            "version": BLOB_VERSION,
+            "label": self.label,
+            "nonce_account": self.nonce_account.map(|pubkey| pubkey.to_string()),
+            "transaction": BASE64_STANDARD.encode(bincode::serialize(&self.transaction)?),
+        }))
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let version = value["version"].as_u64().ok_or("Missing blob version")?;
+        if version != BLOB_VERSION {
+            return Err(format!("Unsupported blob version {}", version).into());
+        }
+        let transaction = BASE64_STANDARD.decode(
+            value["transaction"]
+                .as_str()
+                .ok_or("Missing transaction in blob")?,
+        )?;
+        Ok(Self {
+            label: value["label"].as_str().unwrap_or_default().to_string(),
+            transaction: bincode::deserialize(&transaction)?,
+            nonce_account: value["nonce_account"]
+                .as_str()
+                .map(Pubkey::from_str)
+                .transpose()?,
+        })
+    }
+}
+
+// Where a blob is shared: a file, or a relay URL.
+// A relay is any HTTP endpoint that returns the last body PUT to a URL on a GET of the same URL, e.g.
+// https://relay.example.com/cosign/<id>. Copies are merged before being written, so co-signers can share one URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobLocation {
+    File(PathBuf),
+    Relay(String),
+}
+
+impl FromStr for BlobLocation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.starts_with("http://") || value.starts_with("https://") {
+            Ok(BlobLocation::Relay(value.to_string()))
+        } else if value.is_empty() {
+            Err("Expected a file path or a relay URL".to_string())
+        } else {
+            Ok(BlobLocation::File(PathBuf::from(value)))
+        }
+    }
+}
+
+impl BlobLocation {
+    // The blob at this location, `None` if there is none yet
+    pub fn read(&self) -> Result<Option<PartialTransaction>, Box<dyn Error>> {
+        let contents = match self {
+            BlobLocation::File(path) if !path.exists() => return Ok(None),
+            BlobLocation::File(path) => fs::read_to_string(path)?,
+            BlobLocation::Relay(url) => {
+                let response = reqwest::blocking::get(url)?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                response.error_for_status()?.text()?
+            }
+        };
+        Ok(Some(PartialTransaction::from_json(&serde_json::from_str(
+            &contents,
+        )?)?))
+    }
+
+    // Write the blob, merged with the copy already at this location so no signature is lost
+    pub fn write(
+        &self,
+        partial: &PartialTransaction,
+    ) -> Result<PartialTransaction, Box<dyn Error>> {
+        let mut merged = partial.clone();
+        if let Some(existing) = self.read()? {
+            merged.merge(&existing)?;
+        }
+        let contents = serde_json::to_string_pretty(&merged.to_json()?)?;
+        match self {
+            BlobLocation::File(path) => fs::write(path, contents)?,
+            BlobLocation::Relay(url) => {
+                reqwest::blocking::Client::new()
+                    .put(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(contents)
+                    .send()?
+                    .error_for_status()?;
+            }
+        }
+        Ok(merged)
+    }
+}
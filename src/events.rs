@@ -0,0 +1,147 @@
+use crate::retry_queue::RetryOutcome;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{sync::mpsc, time::Duration};
+
+// Zero-knowledge proofs generated and verified by the flows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofKind {
+    PubkeyValidity,
+    Equality,
+    CiphertextValidity,
+    Range,
+    // Fee proofs of transfers of mints with a transfer fee
+    FeeSigma,
+    FeeCiphertextValidity,
+    Withdraw,
+}
+
+// Typed progress events emitted by the flows, so applications embedding them can drive their own UI
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlowEvent {
+    StepStarted {
+        step: usize,
+        total_steps: usize,
+        name: &'static str,
+    },
+    StepFinished {
+        step: usize,
+        name: &'static str,
+        elapsed: Duration,
+    },
+    ProofGenerated {
+        proof: ProofKind,
+    },
+    // Proof data generated by an earlier run against the same account state was taken from the proof cache
+    // (`FlowContext::proof_cache`)
+    ProofReused {
+        proof: ProofKind,
+    },
+    ProofAccountCreated {
+        proof: ProofKind,
+        account: Pubkey,
+    },
+    ProofVerified {
+        proof: ProofKind,
+        account: Pubkey,
+    },
+    ProofAccountsClosed {
+        accounts: Vec<Pubkey>,
+        reclaimed_lamports: u64,
+    },
+    // Decrypted available balance of the token account before and after the flow's final instruction
+    AvailableBalanceChanged {
+        before: Option<u64>,
+        after: Option<u64>,
+    },
+    TransactionConfirmed {
+        label: String,
+        signature: Signature,
+    },
+    // A proof verification transaction had no room for the heap frame request and is sent without it, so it runs
+    // with the default heap (`FlowContext::packer`)
+    HeapFrameDropped {
+        label: String,
+    },
+    // A confirmed transaction reached finalized commitment (`FlowContext::wait`)
+    TransactionFinalized {
+        signature: Signature,
+    },
+    // A transaction failed because the RPC node was unreachable and was queued to be sent again (`FlowContext::retry_queue`)
+    TransactionQueued {
+        label: String,
+        id: i64,
+    },
+    // A queued transaction was attempted again (`FlowContext::retry_queued`)
+    TransactionRetried {
+        label: String,
+        id: i64,
+        outcome: RetryOutcome,
+    },
+    // The pending balance of a watched token account was credited (`flows::watch`)
+    PendingBalanceCredited {
+        token_account: Pubkey,
+        // Credits since the pending balance was last applied
+        credits: u64,
+        pending: u64,
+    },
+    // The fee payer's balance dropped below the top up threshold and was topped up (`topup::TopUp`)
+    FeePayerToppedUp {
+        payer: Pubkey,
+        lamports: u64,
+        // Balance before the top up
+        balance: u64,
+    },
+    // The proofs of a scheduled request were generated ahead of its execution, or found in the proof cache
+    // (`flows::schedule`)
+    ScheduledRequestPrepared {
+        id: i64,
+        reused: bool,
+    },
+    // A scheduled request was executed, with the signature of its withdraw or transfer
+    ScheduledRequestSubmitted {
+        id: i64,
+        signature: Signature,
+    },
+    ScheduledRequestFailed {
+        id: i64,
+        error: String,
+    },
+    // Balances of the token account changed by exactly the expected amounts (`FlowContext::verify`)
+    BalancesVerified {
+        token_account: Pubkey,
+    },
+}
+
+type Listener = Box<dyn Fn(&FlowEvent) + Send + Sync>;
+
+// Delivers flow events to every subscribed callback and channel
+#[derive(Default)]
+pub struct FlowEvents {
+    listeners: Vec<Listener>,
+}
+
+impl FlowEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self, listener: impl Fn(&FlowEvent) + Send + Sync + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    // Receive events over a channel instead of a callback, e.g. to forward them to a UI thread
+    pub fn channel(&mut self) -> mpsc::Receiver<FlowEvent> {
+        let (sender, receiver) = mpsc::channel();
+        // A dropped receiver just means nobody is listening anymore, so send errors are ignored
+        self.subscribe(move |event| {
+            let _ = sender.send(event.clone());
+        });
+        receiver
+    }
+
+    pub fn emit(&self, event: FlowEvent) {
+        for listener in &self.listeners {
+            listener(&event);
+        }
+    }
+}
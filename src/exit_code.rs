@@ -0,0 +1,155 @@
+use crate::{
+    account_diff::TransactionDeclined,
+    config::ConfigError,
+    mint_expectation::MintMismatch,
+    policy::PolicyError,
+    send::SendError,
+    shutdown::{Interrupted, INTERRUPTED_EXIT_CODE},
+};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use spl_token_2022::{error::TokenError, solana_zk_token_sdk::errors::ProofError};
+use spl_token_client::token::TokenError as TokenClientError;
+use std::{error::Error, fmt, process::ExitCode};
+
+// Exit codes of the binaries, so scripts can branch on why a command failed:
+//
+//   0   success
+//   1   any other failure
+//   2   invalid command line arguments (clap)
+//   3   config error: an invalid setting in the environment, the .env file or the POLICY file,
+//       or a mint that doesn't match the --strict expectation
+//   4   RPC failure: the RPC node couldn't be reached, answered with an error, or the transaction didn't confirm in time
+//   5   proof failure: proof data could not be generated, e.g. the balance doesn't cover the amount
+//   6   on-chain rejection: the cluster rejected a transaction, in preflight or when it landed
+//   7   policy violation: the POLICY file refused a transfer or withdraw before anything was signed
+//   130 interrupted by Ctrl-C, or a transaction declined after reviewing it with --review
+pub const OTHER: u8 = 1;
+pub const CONFIG: u8 = 3;
+pub const RPC: u8 = 4;
+pub const PROOF: u8 = 5;
+pub const REJECTED: u8 = 6;
+pub const POLICY: u8 = 7;
+
+// The error a binary exits with, classified by the kind of failure
+#[derive(Debug)]
+pub enum CliError {
+    Config(Box<dyn Error>),
+    Rpc(Box<dyn Error>),
+    Proof(Box<dyn Error>),
+    Rejected(Box<dyn Error>),
+    Policy(Box<dyn Error>),
+    Interrupted,
+    Other(Box<dyn Error>),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Config(_) => CONFIG,
+            CliError::Rpc(_) => RPC,
+            CliError::Proof(_) => PROOF,
+            CliError::Rejected(_) => REJECTED,
+            CliError::Policy(_) => POLICY,
+            CliError::Interrupted => INTERRUPTED_EXIT_CODE,
+            CliError::Other(_) => OTHER,
+        }
+    }
+}
+
+// Classify an error by the first error in its source chain with a known kind
+impl From<Box<dyn Error>> for CliError {
+    fn from(error: Box<dyn Error>) -> Self {
+        match classify_chain(error.as_ref()) {
+            Some(variant) => variant(error),
+            None => CliError::Other(error),
+        }
+    }
+}
+
+type Variant = fn(Box<dyn Error>) -> CliError;
+
+fn classify_chain(error: &(dyn Error + 'static)) -> Option<Variant> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(variant) = classify(error) {
+            return Some(variant);
+        }
+        current = error.source();
+    }
+    None
+}
+
+fn classify(error: &(dyn Error + 'static)) -> Option<Variant> {
+    if error.is::<ConfigError>() || error.is::<MintMismatch>() {
+        return Some(CliError::Config);
+    }
+    if error.is::<PolicyError>() {
+        return Some(CliError::Policy);
+    }
+    if error.is::<Interrupted>() || error.is::<TransactionDeclined>() {
+        return Some(|_| CliError::Interrupted);
+    }
+    if error.is::<ProofError>() {
+        return Some(CliError::Proof);
+    }
+    if let Some(error) = error.downcast_ref::<SendError>() {
+        return classify_client_error(&error.error);
+    }
+    if let Some(error) = error.downcast_ref::<ClientError>() {
+        return classify_client_error(error);
+    }
+    // Errors of the token client, used by the numbered binaries
+    if let Some(error) = error.downcast_ref::<TokenClientError>() {
+        return match error {
+            TokenClientError::Client(error) => classify_chain(error.as_ref()),
+            TokenClientError::ProofGeneration
+            | TokenClientError::NotEnoughFunds
+            | TokenClientError::AccountDecryption => Some(CliError::Proof),
+            _ => None,
+        };
+    }
+    if let Some(TokenError::ProofGeneration) = error.downcast_ref::<TokenError>() {
+        return Some(CliError::Proof);
+    }
+    None
+}
+
+// Either the cluster rejected the transaction, or the RPC node failed to take or confirm it
+fn classify_client_error(error: &ClientError) -> Option<Variant> {
+    if error.get_transaction_error().is_some() {
+        return Some(CliError::Rejected);
+    }
+    match error.kind() {
+        ClientErrorKind::SigningError(_) => None,
+        _ => Some(CliError::Rpc),
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Config(error)
+            | CliError::Rpc(error)
+            | CliError::Proof(error)
+            | CliError::Rejected(error)
+            | CliError::Policy(error)
+            | CliError::Other(error) => write!(f, "{}", error),
+            CliError::Interrupted => write!(f, "Interrupted"),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+// Print the error of a binary's `run` and turn it into its exit code, e.g.
+// `fn main() -> ExitCode { exit_code::report(run()) }`
+pub fn report(result: Result<(), Box<dyn Error>>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            let error = CliError::from(error);
+            eprintln!("Error: {}", error);
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use solana_sdk::signature::Signature;
+use std::{error::Error, fmt, str::FromStr};
+
+// Cluster the RPC endpoint belongs to, used to point explorer links at the right network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cluster {
+    Mainnet,
+    Devnet,
+    Testnet,
+    Localnet(String),
+    Custom(String),
+}
+
+impl Cluster {
+    // Best guess of the cluster from the RPC URL
+    pub fn from_rpc_url(rpc_url: &str) -> Self {
+        if rpc_url.contains("devnet") {
+            Cluster::Devnet
+        } else if rpc_url.contains("testnet") {
+            Cluster::Testnet
+        } else if rpc_url.contains("mainnet") {
+            Cluster::Mainnet
+        } else if rpc_url.contains("localhost") || rpc_url.contains("127.0.0.1") {
+            Cluster::Localnet(rpc_url.to_string())
+        } else {
+            Cluster::Custom(rpc_url.to_string())
+        }
+    }
+
+    // Short name of the cluster, also available as `{cluster}` in custom explorer templates
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cluster::Mainnet => "mainnet-beta",
+            Cluster::Devnet => "devnet",
+            Cluster::Testnet => "testnet",
+            Cluster::Localnet(_) => "localnet",
+            Cluster::Custom(_) => "custom",
+        }
+    }
+
+    fn rpc_url(&self) -> Option<&str> {
+        match self {
+            Cluster::Localnet(url) | Cluster::Custom(url) => Some(url),
+            _ => None,
+        }
+    }
+}
+
+// Block explorer used for the transaction links printed by the flows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExplorerKind {
+    SolanaFm,
+    SolanaExplorer,
+    Solscan,
+    // URL template with `{signature}`, `{cluster}` and `{rpc_url}` placeholders
+    Custom(String),
+}
+
+impl FromStr for ExplorerKind {
+    type Err = Box<dyn Error>;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "solana.fm" | "solanafm" => Ok(ExplorerKind::SolanaFm),
+            "explorer.solana.com" | "solana-explorer" => Ok(ExplorerKind::SolanaExplorer),
+            "solscan" | "solscan.io" => Ok(ExplorerKind::Solscan),
+            template if template.contains("{signature}") => {
+                Ok(ExplorerKind::Custom(template.to_string()))
+            }
+            other => Err(format!(
+                "Unknown explorer {:?}, expected solana.fm, explorer.solana.com, solscan or a URL template containing {{signature}}",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+// Builds explorer links for the active cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explorer {
+    pub kind: ExplorerKind,
+    pub cluster: Cluster,
+}
+
+impl Explorer {
+    pub fn new(kind: ExplorerKind, cluster: Cluster) -> Self {
+        Self { kind, cluster }
+    }
+
+    pub fn tx_url(&self, signature: &Signature) -> String {
+        match &self.kind {
+            ExplorerKind::SolanaFm => {
+                let cluster = match &self.cluster {
+                    Cluster::Mainnet => "mainnet-alpha",
+                    Cluster::Devnet => "devnet-solana",
+                    Cluster::Testnet => "testnet-solana",
+                    Cluster::Localnet(_) | Cluster::Custom(_) => "localnet-solana",
+                };
+                format!("https://solana.fm/tx/{}?cluster={}", signature, cluster)
+            }
+            ExplorerKind::SolanaExplorer => format!(
+                "https://explorer.solana.com/tx/{}{}",
+                signature,
+                self.cluster_query()
+            ),
+            ExplorerKind::Solscan => {
+                format!(
+                    "https://solscan.io/tx/{}{}",
+                    signature,
+                    self.cluster_query()
+                )
+            }
+            ExplorerKind::Custom(template) => template
+                .replace("{signature}", &signature.to_string())
+                .replace("{cluster}", self.cluster.name())
+                .replace("{rpc_url}", self.cluster.rpc_url().unwrap_or_default()),
+        }
+    }
+
+    // Query string used by explorer.solana.com and solscan to select the cluster
+    fn cluster_query(&self) -> String {
+        match &self.cluster {
+            Cluster::Mainnet => String::new(),
+            Cluster::Devnet | Cluster::Testnet => format!("?cluster={}", self.cluster.name()),
+            Cluster::Localnet(url) | Cluster::Custom(url) => {
+                format!("?cluster=custom&customUrl={}", url)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Explorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match &self.kind {
+            ExplorerKind::SolanaFm => "solana.fm",
+            ExplorerKind::SolanaExplorer => "explorer.solana.com",
+            ExplorerKind::Solscan => "solscan",
+            ExplorerKind::Custom(template) => template,
+        };
+        write!(f, "{} ({})", kind, self.cluster.name())
+    }
+}
@@ -0,0 +1,136 @@
+use super::FlowContext;
+use crate::{account_state::ConfidentialAccountState, events::FlowEvent};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use spl_token_2022::{
+    extension::confidential_transfer::{instruction, ConfidentialTransferAccount},
+    solana_zk_token_sdk::encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
+};
+use std::{error::Error, thread, time::Duration};
+
+// Number of steps reported by `apply_pending_balance`
+pub const STEPS: usize = 1;
+
+// Rounds before giving up on a token account that keeps being credited
+pub const DEFAULT_MAX_ROUNDS: usize = 5;
+
+// Pause between rounds, letting incoming credits land before applying again
+pub const DEFAULT_ROUND_PAUSE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyPendingOutcome {
+    // One signature per ApplyPendingBalance round, empty if there was nothing to apply
+    pub signatures: Vec<Signature>,
+    // Pending balance moved to the available balance over all rounds, as decrypted before each round
+    pub applied: u64,
+}
+
+// Apply the pending balance of a token account to its available balance.
+//
+// ApplyPendingBalance moves the whole pending balance, including credits that land between fetching the account
+// and the instruction executing, while the new decryptable (AES) available balance only covers the credits the
+// client decrypted. The program records the credit counter the client expected next to the actual one,
+// so a mismatch after a round means credits raced in: the flow pauses and applies again, computing the
+// decryptable balance from the ElGamal available balance this time, until the counters match.
+pub fn apply_pending_balance(
+    ctx: &mut FlowContext<'_>,
+    token_account: &Pubkey,
+    owner: &Keypair,
+    max_rounds: usize,
+    round_pause: Duration,
+) -> Result<ApplyPendingOutcome, Box<dyn Error>> {
+    ctx.flow = "apply-pending-balance";
+    ctx.start_step("Applying pending balance");
+
+    // Derive the ElGamal keypair and AES key for the token account
+    let (elgamal_keypair, aes_key) =
+        ctx.key_derivation
+            .account_keys(ctx.client, owner, token_account)?;
+
+    let mut outcome = ApplyPendingOutcome::default();
+    for round in 0..max_rounds {
+        if round > 0 {
+            thread::sleep(round_pause);
+        }
+
+        let state = ConfidentialAccountState::fetch(ctx.client, token_account)?;
+        // Nothing credited, and no earlier apply raced by credits to catch up on
+        if !state.needs_apply() {
+            break;
+        }
+        // Number of times the pending balance has been credited (deposits and transfers to the account)
+        let credit_counter = state.pending_balance_credit_counter;
+
+        let (available, pending) =
+            balances_to_apply(token_account, state.extension(), &elgamal_keypair, &aes_key)?;
+
+        let new_decryptable_available_balance = aes_key.encrypt(available + pending);
+
+        // Create a `ApplyPendingBalance` instruction
+        let apply_pending_balance_instruction = instruction::apply_pending_balance(
+            &spl_token_2022::id(),
+            token_account,                     // Token account
+            credit_counter, // Expected number of times the pending balance has been credited
+            new_decryptable_available_balance, // Cipher text of the new decryptable available balance
+            &owner.pubkey(),                   // Token account owner
+            &[&owner.pubkey()],                // Additional signers
+        )?;
+
+        let transaction_signature = ctx.send(
+            "Apply Pending Balance",
+            &[apply_pending_balance_instruction],
+            &owner.pubkey(),
+            &[owner],
+        )?;
+        ctx.emit(FlowEvent::AvailableBalanceChanged {
+            before: Some(available),
+            after: Some(available + pending),
+        });
+        outcome.signatures.push(transaction_signature);
+        outcome.applied += pending;
+
+        // Done once the program saw exactly the credits this round decrypted
+        if !ConfidentialAccountState::fetch(ctx.client, token_account)?.is_stale() {
+            ctx.finish_step();
+            return Ok(outcome);
+        }
+    }
+
+    // Every round was raced by new credits, the available balance is correct but the decryptable one is behind
+    if !outcome.signatures.is_empty() {
+        return Err(format!(
+            "Token account {} was still being credited after {} ApplyPendingBalance rounds, run it again later",
+            token_account, max_rounds
+        )
+        .into());
+    }
+
+    // Nothing pending
+    ctx.finish_step();
+    Ok(outcome)
+}
+
+// Available and pending balance of a token account, as combined into the new decryptable available balance
+// of an ApplyPendingBalance
+pub fn balances_to_apply(
+    token_account: &Pubkey,
+    extension: &ConfidentialTransferAccount,
+    elgamal_keypair: &ElGamalKeypair,
+    aes_key: &AeKey,
+) -> Result<(u64, u64), Box<dyn Error>> {
+    let state = ConfidentialAccountState::new(*token_account, 0, *extension);
+    let pending = state
+        .decrypt_pending_balance(elgamal_keypair)?
+        .ok_or("Could not decrypt the pending balance")?;
+    // The ElGamal available balance is what the program holds, but decrypting it is slow,
+    // so the AES balance is used unless credits raced an earlier apply and left it behind
+    let available = if state.is_stale() {
+        state.decrypt_available_balance(elgamal_keypair)?
+    } else {
+        state.decrypt_decryptable_available_balance(aes_key)?
+    }
+    .ok_or("Could not decrypt the available balance")?;
+    Ok((available, pending))
+}
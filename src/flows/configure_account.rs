@@ -0,0 +1,111 @@
+use super::FlowContext;
+use crate::account_state::ConfidentialAccountState;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id, instruction::create_associated_token_account,
+};
+use spl_token_2022::{
+    extension::{confidential_transfer::instruction::configure_account, ExtensionType},
+    instruction::reallocate,
+    proof::ProofLocation,
+    solana_zk_token_sdk::zk_token_proof_instruction::PubkeyValidityData,
+};
+use std::error::Error;
+
+// Number of steps reported by `create_confidential_account`
+pub const STEPS: usize = 1;
+
+// Upper bound on the `Deposit` and `Transfer` instructions crediting the pending balance between two applies,
+// keeping the pending balance small enough to decrypt
+pub const MAXIMUM_PENDING_BALANCE_CREDIT_COUNTER: u64 = 65536;
+
+// Create the owner's associated token account with the `ConfidentialTransferAccount` extension,
+// as done step by step in 3_create_sender_account.
+// Returns `None` without sending anything if the token account is already configured, so it can be rerun.
+pub fn create_confidential_account(
+    ctx: &mut FlowContext<'_>,
+    owner: &Keypair,
+    mint: &Pubkey,
+) -> Result<Option<Signature>, Box<dyn Error>> {
+    ctx.flow = "create-account";
+    ctx.start_step("Creating confidential token account");
+
+    // Associated token address of the owner
+    let associated_token_address = get_associated_token_address_with_program_id(
+        &owner.pubkey(), // Token account owner
+        mint,            // Mint
+        &spl_token_2022::id(),
+    );
+
+    let existing = ctx
+        .client
+        .get_account_with_commitment(&associated_token_address, ctx.client.commitment())?
+        .value;
+    if let Some(existing) = &existing {
+        if ConfidentialAccountState::unpack(&associated_token_address, existing.data.clone())?
+            .is_some()
+        {
+            ctx.finish_step();
+            return Ok(None);
+        }
+    }
+
+    // Create the account if it doesn't exist yet, then make room for the extension
+    let rent_funder = ctx.payers.rent_funder(&owner.pubkey());
+    let mut instructions = Vec::new();
+    if existing.is_none() {
+        instructions.push(create_associated_token_account(
+            &rent_funder,    // Funding account
+            &owner.pubkey(), // Token account owner
+            mint,            // Mint
+            &spl_token_2022::id(),
+        ));
+    }
+    instructions.push(reallocate(
+        &spl_token_2022::id(),
+        &associated_token_address,
+        &rent_funder,
+        &owner.pubkey(),
+        &[&owner.pubkey()],
+        &[ExtensionType::ConfidentialTransferAccount],
+    )?);
+
+    // Derive the ElGamal keypair and AES key for the token account, under the configured derivation
+    let elgamal_keypair = ctx
+        .key_derivation
+        .elgamal_keypair(owner, &associated_token_address)?;
+    let aes_key = ctx
+        .key_derivation
+        .aes_key(owner, &associated_token_address)?;
+
+    // Pubkey validity proof included in the same transaction, right after `ConfigureAccount`
+    let proof_data = PubkeyValidityData::new(&elgamal_keypair)?;
+    let proof_location = ProofLocation::InstructionOffset(1.try_into()?, &proof_data);
+
+    instructions.extend(configure_account(
+        &spl_token_2022::id(),
+        &associated_token_address,
+        mint,
+        aes_key.encrypt(0),
+        MAXIMUM_PENDING_BALANCE_CREDIT_COUNTER,
+        &owner.pubkey(),
+        &[],
+        proof_location,
+    )?);
+
+    let transaction_signature = ctx.send(
+        "Create Confidential Token Account",
+        &instructions,
+        &owner.pubkey(),
+        &[owner],
+    )?;
+
+    // Rent for the token account is funded by the rent funder, the owner unless set
+    ctx.report
+        .record_rent(ctx.client.get_balance(&associated_token_address)?);
+    ctx.finish_step();
+    Ok(Some(transaction_signature))
+}
@@ -0,0 +1,252 @@
+use super::{apply_pending, withdraw, FlowContext};
+use crate::{account_state::ConfidentialAccountState, pausable::check_not_paused};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::{
+    check_spl_token_program_account,
+    extension::{
+        confidential_transfer::instruction::empty_account, StateWithExtensions,
+        StateWithExtensionsOwned,
+    },
+    instruction::{burn_checked, close_account, mint_to_checked},
+    proof::ProofLocation,
+    solana_zk_token_sdk::{
+        encryption::elgamal::ElGamalCiphertext, instruction::ZeroBalanceProofData,
+    },
+    state::{Account, Mint},
+};
+use spl_token_client::{
+    client::{SendTransaction, SimulateTransaction},
+    token::Token,
+};
+use std::error::Error;
+
+// Number of steps reported by `migrate_holder` itself, besides the apply and withdraw flows it runs
+pub const STEPS: usize = 2;
+
+// Steps of a whole migration, for sizing the progress
+pub const TOTAL_STEPS: usize = apply_pending::STEPS + withdraw::STEPS + STEPS;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrateOutcome {
+    // Confidential balance withdrawn to the public balance before migrating
+    pub withdrawn: u64,
+    // Tokens burned from the old token account, in the old mint's decimals
+    pub burned: u64,
+    // Tokens minted to the new token account, in the new mint's decimals
+    pub minted: u64,
+    pub new_token_account: Pubkey,
+    // Signature of the transaction closing the old token account
+    pub signature: Signature,
+}
+
+// Move a holder from a Token-2022 mint to a reissued mint, which may live under the legacy token program.
+//
+// Tokens can't be transferred across mints, so the holder's confidential balance is withdrawn to the public balance,
+// then burned from the old mint and minted by the new mint's authority in one transaction, so neither happens
+// without the other. The emptied old token account is closed last, returning its rent.
+pub async fn migrate_holder<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    owner: &Keypair,
+    new_mint: &Pubkey,
+    new_mint_authority: &Keypair,
+) -> Result<MigrateOutcome, Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    let mint = *token.get_address();
+    let token_account = get_associated_token_address_with_program_id(
+        &owner.pubkey(), // Token account owner
+        &mint,           // Mint
+        &spl_token_2022::id(),
+    );
+
+    // The new mint decides the program of the new token account, and the decimals minted in
+    let new_mint_account = ctx.client.get_account(new_mint)?;
+    let new_program = new_mint_account.owner;
+    if check_spl_token_program_account(&new_program).is_err() {
+        return Err(format!("{} is not a token mint", new_mint).into());
+    }
+    let new_decimals = StateWithExtensions::<Mint>::unpack(&new_mint_account.data)?
+        .base
+        .decimals;
+    let decimals = token.get_mint_info().await?.base.decimals;
+
+    // Burning is rejected while the old mint is paused, so fail before withdrawing anything
+    check_not_paused(ctx.client, &mint)?;
+
+    let confidential = ConfidentialAccountState::unpack(
+        &token_account,
+        ctx.client.get_account_data(&token_account)?,
+    )?
+    .is_some();
+    // Closing a confidential token account takes a zero balance proof, so check the cluster can verify it
+    // before moving any tokens
+    if confidential {
+        ctx.proof_support()?;
+    }
+
+    // Confidential balance to public balance ------------------------------------------------------------------
+
+    let mut withdrawn = 0;
+    if confidential {
+        // Only the available balance can be withdrawn
+        apply_pending::apply_pending_balance(
+            ctx,
+            &token_account,
+            owner,
+            apply_pending::DEFAULT_MAX_ROUNDS,
+            apply_pending::DEFAULT_ROUND_PAUSE,
+        )?;
+
+        let state = ConfidentialAccountState::fetch(ctx.client, &token_account)?;
+        let (elgamal_keypair, aes_key) =
+            ctx.key_derivation
+                .account_keys(ctx.client, owner, &token_account)?;
+        let (available, _) = apply_pending::balances_to_apply(
+            &token_account,
+            state.extension(),
+            &elgamal_keypair,
+            &aes_key,
+        )?;
+
+        if available > 0 {
+            withdraw::withdraw_tokens(ctx, token, owner, available, decimals).await?;
+            withdrawn = available;
+        }
+    }
+    ctx.flow = "migrate";
+
+    // Burn on the old mint, mint on the new mint -------------------------------------------------------------
+
+    ctx.stop_if_interrupted(owner)?;
+    ctx.start_step("Moving tokens to the new mint");
+
+    let burned =
+        StateWithExtensionsOwned::<Account>::unpack(ctx.client.get_account_data(&token_account)?)?
+            .base
+            .amount;
+    let minted = convert_amount(burned, decimals, new_decimals)?;
+
+    let new_token_account =
+        get_associated_token_address_with_program_id(&owner.pubkey(), new_mint, &new_program);
+    let mut instructions = vec![create_associated_token_account_idempotent(
+        &ctx.payers.rent_funder(&owner.pubkey()), // Funding account
+        &owner.pubkey(),                          // Token account owner
+        new_mint,                                 // Mint
+        &new_program,
+    )];
+    if burned > 0 {
+        instructions.push(burn_checked(
+            &spl_token_2022::id(),
+            &token_account,     // Token account
+            &mint,              // Mint
+            &owner.pubkey(),    // Token account owner
+            &[&owner.pubkey()], // Signers
+            burned,
+            decimals,
+        )?);
+        // The token-2022 builders also encode instructions of the legacy token program
+        instructions.push(mint_to_checked(
+            &new_program,
+            new_mint,                        // Mint
+            &new_token_account,              // Token account
+            &new_mint_authority.pubkey(),    // Mint authority
+            &[&new_mint_authority.pubkey()], // Signers
+            minted,
+            new_decimals,
+        )?);
+    }
+    ctx.send(
+        "Migrate Tokens",
+        &instructions,
+        &owner.pubkey(),
+        &[owner, new_mint_authority],
+    )?;
+    ctx.finish_step();
+
+    // Close the old token account --------------------------------------------------------------------------
+
+    ctx.stop_if_interrupted(owner)?;
+    ctx.start_step("Closing the old token account");
+
+    let account = ctx.client.get_account(&token_account)?;
+    let rent = account.lamports;
+
+    let mut instructions = Vec::new();
+    if confidential {
+        // A token account with the confidential transfer extension only closes once its available balance
+        // is proven to be zero
+        let state = ConfidentialAccountState::unpack(&token_account, account.data)?
+            .ok_or("The old token account lost its confidential transfer extension")?;
+        let elgamal_keypair = ctx
+            .key_derivation
+            .resolve(owner, &token_account, &state.elgamal_pubkey)?
+            .elgamal_keypair(owner, &token_account)?;
+        let available_balance = ElGamalCiphertext::try_from(state.extension().available_balance)?;
+        let proof_data = ZeroBalanceProofData::new(&elgamal_keypair, &available_balance)?;
+
+        // The proof is verified by the instruction right after `EmptyAccount`
+        instructions.extend(empty_account(
+            &spl_token_2022::id(),
+            &token_account,     // Token account
+            &owner.pubkey(),    // Token account owner
+            &[&owner.pubkey()], // Signers
+            ProofLocation::InstructionOffset(1.try_into()?, &proof_data),
+        )?);
+    }
+    instructions.push(close_account(
+        &spl_token_2022::id(),
+        &token_account,                           // Token account
+        &ctx.payers.rent_funder(&owner.pubkey()), // Destination of the rent
+        &owner.pubkey(),                          // Token account owner
+        &[&owner.pubkey()],                       // Signers
+    )?);
+    let signature = ctx.send(
+        "Close Old Token Account",
+        &instructions,
+        &owner.pubkey(),
+        &[owner],
+    )?;
+    ctx.report.record_reclaimed(rent);
+    ctx.finish_step();
+
+    Ok(MigrateOutcome {
+        withdrawn,
+        burned,
+        minted,
+        new_token_account,
+        signature,
+    })
+}
+
+// An amount of the old mint in the new mint's decimals, failing if it can't be represented exactly
+pub fn convert_amount(amount: u64, decimals: u8, new_decimals: u8) -> Result<u64, Box<dyn Error>> {
+    let scale = |difference: u8| {
+        10u64
+            .checked_pow(difference.into())
+            .ok_or("Decimals of the mints are too far apart")
+    };
+    if new_decimals >= decimals {
+        amount
+            .checked_mul(scale(new_decimals - decimals)?)
+            .ok_or_else(|| format!("{} overflows in {} decimals", amount, new_decimals).into())
+    } else {
+        let divisor = scale(decimals - new_decimals)?;
+        if !amount.is_multiple_of(divisor) {
+            return Err(format!(
+                "{} can't be represented in {} decimals without losing tokens",
+                amount, new_decimals
+            )
+            .into());
+        }
+        Ok(amount / divisor)
+    }
+}
@@ -0,0 +1,811 @@
+pub mod apply_pending;
+pub mod configure_account;
+pub mod migrate;
+pub mod payroll;
+pub mod resume;
+pub mod schedule;
+pub mod setup;
+pub mod simulate_transfer;
+pub mod sweep;
+pub mod transfer;
+pub mod watch;
+pub mod withdraw;
+
+#[cfg(feature = "telemetry")]
+use crate::telemetry::{TelemetrySample, TelemetrySink};
+use crate::{
+    account_diff::{TransactionDeclined, TransactionDiff, TransactionReview},
+    config::DEFAULT_HEAP_FRAME_BYTES,
+    events::{FlowEvent, FlowEvents},
+    journal::{self, Journal, JournalEntry, TxStatus},
+    key_derivation::KeyDerivation,
+    packer::TransactionPacker,
+    payers::Payers,
+    policy::{Policy, PolicyAction, PolicyError, DAILY_CAP_WINDOW_SECS},
+    proof_cache::{CachedProof, ProofCache, ProofKey},
+    proof_program::ProofSupport,
+    report::CostReport,
+    retry_queue::{RetryOutcome, RetryQueue},
+    seed::KeypairSource,
+    send::{
+        send_instructions_with_strategy, wait_for_confirmed, wait_for_finalized, Pending,
+        SendError, SentTransaction, WaitFor,
+    },
+    send_strategy::{RpcSend, SendStrategy},
+    shutdown::{self, Interrupted},
+    verify::{BalanceChange, BalanceSnapshot},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction, message::Message, pubkey::Pubkey, signature::Keypair,
+    signature::Signature, signature::Signer,
+};
+use spl_token_2022::solana_zk_token_sdk::zk_token_proof_instruction::{
+    close_context_state, ContextStateInfo,
+};
+#[cfg(feature = "telemetry")]
+use std::sync::Arc;
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+// Everything a flow needs besides its own inputs: the RPC client, cost accounting, event listeners
+// and the optional transaction journal.
+// Steps are numbered across the whole context, so several flows can run as one larger flow.
+pub struct FlowContext<'a> {
+    pub client: &'a RpcClient,
+    pub report: CostReport,
+    pub events: FlowEvents,
+    pub journal: Option<Journal>,
+    // Name of the running flow, recorded with each transaction in the journal
+    pub flow: &'static str,
+    // Heap frame requested by proof verification transactions, `None` to not request one
+    pub heap_frame_bytes: Option<u32>,
+    // Refetch and decrypt balances after each flow and fail unless they changed by exactly the expected amounts
+    pub verify: bool,
+    // Limits checked before signing transfers and withdraws, `None` to not restrict them
+    pub policy: Option<Policy>,
+    // Commitment each sent transaction must reach before `send` returns
+    pub wait: WaitFor,
+    // How transactions are submitted, over the RPC node unless set
+    pub send_strategy: Box<dyn SendStrategy>,
+    // How long `send` waits for each transaction to confirm before failing with `Pending`,
+    // `None` to wait until its blockhash expires
+    pub confirmation_timeout: Option<Duration>,
+    // Keypairs of the accounts created during the run, random unless seeded
+    pub keypairs: KeypairSource,
+    // How the encryption keys of new token accounts are derived, configured ones are resolved under any scheme
+    pub key_derivation: KeyDerivation,
+    // Fee payer and rent funder, when they aren't the token owner
+    pub payers: Payers,
+    // Where transactions that failed because the RPC node was unreachable are kept to be sent again,
+    // `None` to just fail the flow
+    pub retry_queue: Option<RetryQueue>,
+    // Memo attached to deposit, transfer and withdraw transactions, `None` for no memo
+    pub memo: Option<String>,
+    // Where proof data is kept until the flow that generated it completes, so a rerun after a failed send
+    // reuses it, `None` to always generate proofs
+    pub proof_cache: Option<ProofCache>,
+    // Shown how each transaction would change the accounts it writes before it is sent, and decides whether it is,
+    // `None` to send without simulating
+    pub review: Option<TransactionReview>,
+    // Where anonymized timings, proof sizes and retry counts are recorded, `None` to not record them
+    #[cfg(feature = "telemetry")]
+    pub telemetry: Option<Arc<dyn TelemetrySink>>,
+    // Proof verification support of the cluster, detected on first use
+    proof_support: Option<ProofSupport>,
+    // Proof context state accounts created by the running flow and not used yet
+    proof_accounts: Vec<Pubkey>,
+    // Between `begin_optimistic` and `reconcile` with `WaitFor::Processed`, transactions only wait until processed
+    optimistic: bool,
+    // Labels and signatures of the transactions sent optimistically and not confirmed yet
+    unconfirmed: Vec<(String, Signature)>,
+    total_steps: usize,
+    current_step: usize,
+    step_started: Option<(&'static str, Instant)>,
+}
+
+impl<'a> FlowContext<'a> {
+    pub fn new(client: &'a RpcClient, total_steps: usize) -> Self {
+        Self {
+            client,
+            report: CostReport::new(),
+            events: FlowEvents::new(),
+            journal: None,
+            flow: "",
+            heap_frame_bytes: Some(DEFAULT_HEAP_FRAME_BYTES),
+            verify: false,
+            wait: WaitFor::Confirmed,
+            send_strategy: Box::new(RpcSend),
+            confirmation_timeout: None,
+            policy: None,
+            keypairs: KeypairSource::default(),
+            key_derivation: KeyDerivation::default(),
+            payers: Payers::default(),
+            retry_queue: None,
+            memo: None,
+            proof_cache: None,
+            review: None,
+            #[cfg(feature = "telemetry")]
+            telemetry: None,
+            proof_support: None,
+            proof_accounts: Vec::new(),
+            optimistic: false,
+            unconfirmed: Vec::new(),
+            total_steps,
+            current_step: 0,
+            step_started: None,
+        }
+    }
+
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    // The instructions with an SPL Memo instruction in front, when a memo is set.
+    // The memo lists no signers, so it adds no signature to the transaction.
+    pub fn attach_memo(&self, instructions: &[Instruction]) -> Vec<Instruction> {
+        self.memo
+            .iter()
+            .map(|memo| spl_memo::build_memo(memo.as_bytes(), &[]))
+            .chain(instructions.iter().cloned())
+            .collect()
+    }
+
+    // The proof features active on the cluster, see `ProofSupport::detect`
+    pub fn proof_support(&mut self) -> Result<ProofSupport, Box<dyn Error>> {
+        if let Some(proof_support) = self.proof_support {
+            return Ok(proof_support);
+        }
+        let proof_support = ProofSupport::detect(self.client)?;
+        self.proof_support = Some(proof_support);
+        Ok(proof_support)
+    }
+
+    // Prefix proof verification instructions with a heap frame request, since batched range proof verification
+    // can exceed the default heap on some validator versions.
+    // The largest verify transactions (batched range proof, withdraw) have no room left for the extra instruction
+    // within the packet size, so they are returned unchanged and `FlowEvent::HeapFrameDropped` is emitted.
+    pub fn with_heap_frame(
+        &self,
+        label: &str,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+    ) -> Vec<Instruction> {
+        let mut packer = self.packer(payer);
+        packer.push(label, instructions.to_vec());
+        match packer.pack() {
+            Ok(transactions) if transactions.len() == 1 => {
+                if transactions[0].prefix_dropped {
+                    self.emit(FlowEvent::HeapFrameDropped {
+                        label: label.to_string(),
+                    });
+                }
+                transactions[0].instructions.clone()
+            }
+            _ => instructions.to_vec(),
+        }
+    }
+
+    // Packer for transactions sent by `send_packed`, with the heap frame request in front of each transaction
+    // that has room for it, and room left for the tip of the send strategy. `send_packed` emits
+    // `FlowEvent::HeapFrameDropped` for the transactions without room for it
+    pub fn packer(&self, payer: &Pubkey) -> TransactionPacker {
+        let fee_payer = self.payers.fee_payer(payer);
+        TransactionPacker::new(fee_payer)
+            .with_prefix(
+                self.heap_frame_bytes
+                    .map(ComputeBudgetInstruction::request_heap_frame)
+                    .into_iter()
+                    .collect(),
+            )
+            .with_reserved(
+                self.send_strategy
+                    .tip(&fee_payer)
+                    .map(|(tip, _)| tip)
+                    .into_iter()
+                    .collect(),
+            )
+    }
+
+    // Send the packed groups as the fewest transactions they fit in, in order, labeled with the groups they hold
+    pub fn send_packed(
+        &mut self,
+        packer: &TransactionPacker,
+        payer: &Pubkey,
+        signers: &[&dyn Signer],
+    ) -> Result<Vec<Signature>, Box<dyn Error>> {
+        let mut signatures = Vec::new();
+        for transaction in packer.pack()? {
+            if transaction.prefix_dropped {
+                self.emit(FlowEvent::HeapFrameDropped {
+                    label: transaction.label(),
+                });
+            }
+            signatures.push(self.send(
+                &transaction.label(),
+                &transaction.instructions,
+                payer,
+                signers,
+            )?);
+        }
+        Ok(signatures)
+    }
+
+    // Snapshot the balances of a token account before a flow, only when verifying
+    pub fn snapshot_balances(
+        &self,
+        token_account: &Pubkey,
+        owner: &Keypair,
+    ) -> Result<Option<BalanceSnapshot>, Box<dyn Error>> {
+        if !self.verify {
+            return Ok(None);
+        }
+        Ok(Some(BalanceSnapshot::fetch(
+            self.client,
+            token_account,
+            owner,
+            &self.key_derivation,
+        )?))
+    }
+
+    // Check the balances changed from the snapshot taken by `snapshot_balances` by exactly `expected`
+    pub fn verify_balances(
+        &self,
+        before: Option<BalanceSnapshot>,
+        owner: &Keypair,
+        expected: BalanceChange,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(before) = before else {
+            return Ok(());
+        };
+        BalanceSnapshot::fetch(
+            self.client,
+            &before.token_account,
+            owner,
+            &self.key_derivation,
+        )?
+        .verify_change(&before, expected)?;
+        self.emit(FlowEvent::BalancesVerified {
+            token_account: before.token_account,
+        });
+        Ok(())
+    }
+
+    // Check a transfer or withdraw of `amount` base units of `mint` against the policy, before anything is signed
+    pub fn check_policy(
+        &self,
+        mint: &Pubkey,
+        action: PolicyAction,
+        amount: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
+        let spent = match (policy.daily_cap, &self.journal) {
+            (None, _) => 0,
+            (Some(_), Some(journal)) => {
+                journal.spent_since(mint, journal::now().saturating_sub(DAILY_CAP_WINDOW_SECS))?
+            }
+            (Some(_), None) => return Err(PolicyError::NoSpendHistory.into()),
+        };
+        policy.check(&action, amount, spent)?;
+        Ok(())
+    }
+
+    // Record a confirmed transfer or withdraw towards the policy's daily cap.
+    // Like the rest of the journal, failing to write it is reported without failing the flow.
+    pub fn record_spend(&self, signature: &Signature, mint: &Pubkey, amount: u64) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        if let Err(error) = journal.record_spend(signature, self.flow, mint, amount) {
+            eprintln!("\nCould not record the spend in the journal: {}", error);
+        }
+    }
+
+    // Proof data for `key` from the proof cache, or generated by `generate` and cached until `forget_proof`.
+    // Returns whether it was reused. Like the journal, failing to read or write the cache is reported without
+    // failing the flow.
+    pub fn cached_proof<P: CachedProof>(
+        &self,
+        owner: &Keypair,
+        key: &ProofKey,
+        generate: impl FnOnce() -> Result<P, Box<dyn Error>>,
+    ) -> Result<(P, bool), Box<dyn Error>> {
+        let Some(proof_cache) = &self.proof_cache else {
+            return Ok((self.generate_proof(generate)?, false));
+        };
+        match proof_cache.get(owner, key) {
+            Ok(Some(proof)) => {
+                #[cfg(feature = "telemetry")]
+                self.record(TelemetrySample::Proof {
+                    flow: self.flow,
+                    bytes: P::to_bytes(&proof).len(),
+                    generation: None,
+                });
+                return Ok((proof, true));
+            }
+            Ok(None) => {}
+            Err(error) => eprintln!("\nCould not read the proof cache: {}", error),
+        }
+        let proof = self.generate_proof(generate)?;
+        if let Err(error) = proof_cache.put(owner, key, &proof) {
+            eprintln!("\nCould not write to the proof cache: {}", error);
+        }
+        Ok((proof, false))
+    }
+
+    fn generate_proof<P: CachedProof>(
+        &self,
+        generate: impl FnOnce() -> Result<P, Box<dyn Error>>,
+    ) -> Result<P, Box<dyn Error>> {
+        #[cfg(feature = "telemetry")]
+        let started = Instant::now();
+        let proof = generate()?;
+        #[cfg(feature = "telemetry")]
+        self.record(TelemetrySample::Proof {
+            flow: self.flow,
+            bytes: proof.to_bytes().len(),
+            generation: Some(started.elapsed()),
+        });
+        Ok(proof)
+    }
+
+    // Drop cached proof data once the flow's final instruction used it
+    pub fn forget_proof(&self, key: &ProofKey) {
+        let Some(proof_cache) = &self.proof_cache else {
+            return;
+        };
+        if let Err(error) = proof_cache.remove(key) {
+            eprintln!("\nCould not write to the proof cache: {}", error);
+        }
+    }
+
+    pub fn emit(&self, event: FlowEvent) {
+        self.events.emit(event);
+    }
+
+    #[cfg(feature = "telemetry")]
+    fn record(&self, sample: TelemetrySample<'_>) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record(&sample);
+        }
+    }
+
+    pub fn start_step(&mut self, name: &'static str) {
+        self.current_step += 1;
+        self.step_started = Some((name, Instant::now()));
+        self.emit(FlowEvent::StepStarted {
+            step: self.current_step,
+            total_steps: self.total_steps,
+            name,
+        });
+    }
+
+    pub fn finish_step(&mut self) {
+        if let Some((name, started)) = self.step_started.take() {
+            let elapsed = started.elapsed();
+            #[cfg(feature = "telemetry")]
+            self.record(TelemetrySample::StepFinished {
+                flow: self.flow,
+                step: name,
+                elapsed,
+            });
+            self.emit(FlowEvent::StepFinished {
+                step: self.current_step,
+                name,
+                elapsed,
+            });
+        }
+    }
+
+    // Remember a proof context state account created by the running flow, to close it if the flow is interrupted
+    pub fn track_proof_account(&mut self, account: Pubkey) {
+        self.proof_accounts.push(account);
+    }
+
+    // Forget proof accounts once the flow's final instruction used them
+    pub fn untrack_proof_accounts(&mut self, accounts: &[Pubkey]) {
+        self.proof_accounts
+            .retain(|account| !accounts.contains(account));
+    }
+
+    // Stop the flow if Ctrl-C was pressed (`shutdown::install`), first closing the proof accounts it created
+    // so their rent returns to the rent funder (`payers.rent_funder`). Flows check between steps, never between
+    // creating a proof account and verifying its proof, since an account without a verified proof can't be closed.
+    // Proof accounts that fail to close are left in the journal with the failed transaction, for `resume` to close.
+    pub fn stop_if_interrupted(&mut self, authority: &Keypair) -> Result<(), Box<dyn Error>> {
+        if !shutdown::interrupted() {
+            return Ok(());
+        }
+        if let Err(error) = self.close_proof_accounts(authority) {
+            eprintln!(
+                "\nCould not close the proof accounts, run `cargo run --bin resume` to close them: {}",
+                error
+            );
+        }
+        Err(Interrupted.into())
+    }
+
+    // With `WaitFor::Processed`, send the following transactions of the flow as soon as the previous one is processed,
+    // without waiting for the cluster to confirm it, until `reconcile`
+    pub fn begin_optimistic(&mut self) {
+        self.optimistic = self.wait == WaitFor::Processed;
+    }
+
+    // Wait until every transaction sent since `begin_optimistic` is confirmed, announcing each one.
+    // A transaction dropped or rejected after it was processed also sinks the transactions built on it, so the proof
+    // accounts that did land are closed, returning their rent, and the flow fails naming the transaction.
+    pub fn reconcile(&mut self, authority: &Keypair) -> Result<(), Box<dyn Error>> {
+        self.optimistic = false;
+        let Some((label, error)) = self.confirm_unconfirmed().into_iter().next() else {
+            return Ok(());
+        };
+        if let Err(error) = self.close_proof_accounts(authority) {
+            eprintln!(
+                "\nCould not close the proof accounts, run `cargo run --bin resume` to close them: {}",
+                error
+            );
+        }
+        Err(format!("{} was processed but didn't land: {}", label, error).into())
+    }
+
+    // Wait for the unconfirmed transactions, updating their journal status, and return those that didn't land
+    fn confirm_unconfirmed(&mut self) -> Vec<(String, Box<dyn Error>)> {
+        let mut failed = Vec::new();
+        for (label, signature) in std::mem::take(&mut self.unconfirmed) {
+            let result = wait_for_confirmed(self.client, &signature);
+            if let Some(journal) = &self.journal {
+                let (status, error) = match &result {
+                    Ok(()) => (TxStatus::Confirmed, None),
+                    Err(error) => (TxStatus::Failed, Some(error.to_string())),
+                };
+                if let Err(error) = journal.update_status(&signature, status, error.as_deref()) {
+                    eprintln!("\nCould not write to the transaction journal: {}", error);
+                }
+            }
+            match result {
+                Ok(()) => self.emit(FlowEvent::TransactionConfirmed { label, signature }),
+                Err(error) => failed.push((label, error)),
+            }
+        }
+        failed
+    }
+
+    fn close_proof_accounts(&mut self, authority: &dyn Signer) -> Result<(), Box<dyn Error>> {
+        // Closing only works once the accounts are confirmed
+        self.optimistic = false;
+        self.confirm_unconfirmed();
+        if self.proof_accounts.is_empty() {
+            return Ok(());
+        }
+        let proof_accounts = std::mem::take(&mut self.proof_accounts);
+        // Accounts whose creation didn't land have nothing to close, the others hold the rent to reclaim
+        let existing: Vec<(Pubkey, u64)> = proof_accounts
+            .iter()
+            .zip(self.client.get_multiple_accounts(&proof_accounts)?)
+            .filter_map(|(address, account)| Some((*address, account?.lamports)))
+            .collect();
+
+        for accounts in existing.chunks(resume::CLOSE_BATCH_SIZE) {
+            let batch: Vec<Pubkey> = accounts.iter().map(|(address, _)| *address).collect();
+            let reclaimed_lamports: u64 = accounts.iter().map(|(_, lamports)| lamports).sum();
+            // Lamports from the closed proof accounts are returned to whoever funded them
+            let rent_funder = self.payers.rent_funder(&authority.pubkey());
+            let instructions: Vec<_> = batch
+                .iter()
+                .map(|account| {
+                    close_context_state(
+                        ContextStateInfo {
+                            context_state_account: account,
+                            context_state_authority: &authority.pubkey(),
+                        },
+                        &rent_funder,
+                    )
+                })
+                .collect();
+
+            self.send(
+                "Close Proof Accounts",
+                &instructions,
+                &authority.pubkey(),
+                &[authority],
+            )?;
+            self.report.record_reclaimed(reclaimed_lamports);
+            self.emit(FlowEvent::ProofAccountsClosed {
+                accounts: batch,
+                reclaimed_lamports,
+            });
+        }
+        Ok(())
+    }
+
+    // Simulate the transaction and let `review` decide whether it is sent. A declined transaction stops the flow,
+    // closing the proof accounts it created with the first signer, the token owner in every flow.
+    fn review_transaction(
+        &mut self,
+        label: &str,
+        message: &Message,
+        signers: &[&dyn Signer],
+    ) -> Result<(), Box<dyn Error>> {
+        let diff = TransactionDiff::simulate(self.client, label, message)?;
+        if self.review.as_ref().is_some_and(|review| review(&diff)) {
+            return Ok(());
+        }
+
+        // Closing the proof accounts isn't reviewed again
+        let review = self.review.take();
+        if let Some(authority) = signers.first() {
+            if let Err(error) = self.close_proof_accounts(*authority) {
+                eprintln!(
+                    "\nCould not close the proof accounts, run `cargo run --bin resume` to close them: {}",
+                    error
+                );
+            }
+        }
+        self.review = review;
+        Err(TransactionDeclined {
+            label: label.to_string(),
+        }
+        .into())
+    }
+
+    // Send and confirm the instructions as one transaction, recording its fee, journaling it and announcing the signature.
+    // With `WaitFor::Finalized` the transaction is also waited on until finalized, so the flow only moves on
+    // once the transaction can no longer be rolled back. Between `begin_optimistic` and `reconcile` with
+    // `WaitFor::Processed`, it is only waited on until processed, and confirmed by `reconcile`.
+    // The fee is paid by `payers.fee_payer` if set, otherwise `payer`. The configured payers sign when the instructions
+    // need them, and signers the transaction doesn't need, like an owner whose fees and rent are paid by others, are left out.
+    pub fn send(
+        &mut self,
+        label: &str,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &[&dyn Signer],
+    ) -> Result<Signature, Box<dyn Error>> {
+        let payer = &self.payers.fee_payer(payer);
+        let message = Message::new(instructions, Some(payer));
+        if self.review.is_some() {
+            self.review_transaction(label, &message, signers)?;
+        }
+
+        let required = message.signer_keys();
+        let mut transaction_signers: Vec<&dyn Signer> = Vec::new();
+        for signer in signers
+            .iter()
+            .copied()
+            .chain(self.payers.keypairs().map(|keypair| keypair as &dyn Signer))
+        {
+            let pubkey = signer.pubkey();
+            if required.contains(&&pubkey)
+                && !transaction_signers
+                    .iter()
+                    .any(|added| added.pubkey() == pubkey)
+            {
+                transaction_signers.push(signer);
+            }
+        }
+
+        let commitment = if self.optimistic {
+            CommitmentConfig::processed()
+        } else {
+            self.client.commitment()
+        };
+        #[cfg(feature = "telemetry")]
+        let started = Instant::now();
+        let result = send_instructions_with_strategy(
+            self.client,
+            self.send_strategy.as_ref(),
+            instructions,
+            payer,
+            &transaction_signers,
+            &mut self.report,
+            commitment,
+            self.confirmation_timeout,
+        );
+        #[cfg(feature = "telemetry")]
+        self.record(TelemetrySample::TransactionSent {
+            flow: self.flow,
+            elapsed: started.elapsed(),
+            succeeded: result.is_ok(),
+        });
+        match &result {
+            Ok(sent) if self.optimistic => {
+                self.unconfirmed.push((label.to_string(), sent.signature));
+            }
+            Ok(sent) => self.emit(FlowEvent::TransactionConfirmed {
+                label: label.to_string(),
+                signature: sent.signature,
+            }),
+            Err(_) => {}
+        }
+
+        let finalized = match (&result, self.wait) {
+            (Ok(sent), WaitFor::Finalized) => {
+                Some(wait_for_finalized(self.client, &sent.signature))
+            }
+            _ => None,
+        };
+        // A transaction that didn't finalize in time is still journaled as confirmed
+        let status = match &finalized {
+            Some(Ok(())) => TxStatus::Finalized,
+            _ if self.optimistic => TxStatus::Processed,
+            _ => TxStatus::Confirmed,
+        };
+        self.journal_transaction(label, instructions, payer, &result, status);
+        if let Err(error) = &result {
+            self.queue_transaction(label, error.as_ref());
+        }
+        if result.is_err() && self.optimistic {
+            // The transaction may have been built on a transaction that was dropped, which is the error to report
+            self.optimistic = false;
+            if let Some((label, error)) = self.confirm_unconfirmed().into_iter().next() {
+                return Err(format!("{} was processed but didn't land: {}", label, error).into());
+            }
+        }
+        let transaction_signature = result?.signature;
+
+        if let Some(finalized) = finalized {
+            finalized?;
+            self.emit(FlowEvent::TransactionFinalized {
+                signature: transaction_signature,
+            });
+        }
+        Ok(transaction_signature)
+    }
+
+    // Attempt the due transactions of the retry queue once, re-signing those whose blockhash expired with `signers`
+    pub fn retry_queued(&mut self, signers: &[&Keypair]) -> Result<(), Box<dyn Error>> {
+        let Some(retry_queue) = &self.retry_queue else {
+            return Ok(());
+        };
+
+        let mut signers = signers.to_vec();
+        signers.extend(self.payers.keypairs());
+        for (queued, outcome) in retry_queue.retry_due(self.client, &signers)? {
+            if let RetryOutcome::Landed(signature) = outcome {
+                self.emit(FlowEvent::TransactionConfirmed {
+                    label: queued.label.clone(),
+                    signature,
+                });
+                if let Some(journal) = &self.journal {
+                    let entry = JournalEntry {
+                        recorded_at: journal::now(),
+                        signature: Some(signature),
+                        flow: queued.flow.clone(),
+                        label: queued.label.clone(),
+                        slot: None,
+                        status: TxStatus::Confirmed,
+                        error: None,
+                        fee: None,
+                        accounts: queued.transaction.message.account_keys.clone(),
+                        last_valid_block_height: Some(queued.last_valid_block_height),
+                    };
+                    if let Err(error) = journal.record(&entry) {
+                        eprintln!("\nCould not write to the transaction journal: {}", error);
+                    }
+                }
+            }
+            #[cfg(feature = "telemetry")]
+            self.record(TelemetrySample::TransactionRetried {
+                flow: &queued.flow,
+                attempts: queued.attempts,
+                landed: matches!(outcome, RetryOutcome::Landed(_)),
+            });
+            self.emit(FlowEvent::TransactionRetried {
+                label: queued.label,
+                id: queued.id,
+                outcome,
+            });
+        }
+        Ok(())
+    }
+
+    // Queue a transaction that failed because the RPC node was unreachable, if there is a retry queue.
+    // Like the journal, failing to write to the queue is reported without failing the flow any further.
+    fn queue_transaction(&self, label: &str, error: &(dyn Error + 'static)) {
+        let Some(retry_queue) = &self.retry_queue else {
+            return;
+        };
+        let Some(error) = error
+            .downcast_ref::<SendError>()
+            .filter(|error| error.is_transient())
+        else {
+            return;
+        };
+
+        match retry_queue.enqueue(
+            self.flow,
+            label,
+            &error.transaction,
+            error.last_valid_block_height,
+            &error.to_string(),
+        ) {
+            Ok(id) => self.emit(FlowEvent::TransactionQueued {
+                label: label.to_string(),
+                id,
+            }),
+            Err(error) => eprintln!("\nCould not write to the retry queue: {}", error),
+        }
+    }
+
+    // Journal the outcome of a sent transaction, with `confirmed_status` if it landed. The journal is an audit trail,
+    // so failing to write to it is reported without failing the flow.
+    fn journal_transaction(
+        &self,
+        label: &str,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        result: &Result<SentTransaction, Box<dyn Error>>,
+        confirmed_status: TxStatus,
+    ) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+
+        let (signature, last_valid_block_height, status, error) = match result {
+            Ok(sent) => (
+                Some(sent.signature),
+                Some(sent.last_valid_block_height),
+                confirmed_status,
+                None,
+            ),
+            // A pending transaction may still land, so it is journaled with its signature rather than as failed
+            Err(error) => match error.downcast_ref::<Pending>() {
+                Some(pending) => (
+                    Some(pending.signature),
+                    Some(pending.last_valid_block_height),
+                    TxStatus::Pending,
+                    Some(error.to_string()),
+                ),
+                None => {
+                    let send_error = error
+                        .downcast_ref::<SendError>()
+                        .filter(|error| error.signature.is_some());
+                    (
+                        send_error.and_then(|error| error.signature),
+                        send_error.map(|error| error.last_valid_block_height),
+                        TxStatus::Failed,
+                        Some(error.to_string()),
+                    )
+                }
+            },
+        };
+        let slot = signature.and_then(|signature| {
+            self.client
+                .get_signature_statuses(&[signature])
+                .ok()
+                .and_then(|statuses| statuses.value.into_iter().next().flatten())
+                .map(|status| status.slot)
+        });
+        let fee = match result {
+            Ok(sent) => self
+                .report
+                .transactions
+                .iter()
+                .find(|fee| fee.signature == sent.signature)
+                .map(|fee| fee.lamports),
+            Err(_) => None,
+        };
+
+        let entry = JournalEntry {
+            recorded_at: journal::now(),
+            signature,
+            flow: self.flow.to_string(),
+            label: label.to_string(),
+            slot,
+            status,
+            error,
+            fee,
+            accounts: Message::new(instructions, Some(payer)).account_keys,
+            last_valid_block_height,
+        };
+        if let Err(error) = journal.record(&entry) {
+            eprintln!("\nCould not write to the transaction journal: {}", error);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use super::{transfer, FlowContext};
+use crate::{
+    account_state::ConfidentialAccountState,
+    contacts::AddressBook,
+    journal::JournalQuery,
+    payroll::{Payment, PaymentOutcome, PayrollLine, PayrollReport},
+    shutdown::{self, Interrupted},
+    ui_amount::UiAmount,
+    verify::BalanceSnapshot,
+};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_client::{
+    client::{SendTransaction, SimulateTransaction},
+    token::Token,
+};
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+// Least time between the start of two transfers by default, to stay under the rate limits of public RPC nodes
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(2);
+
+// Resolve the recipients of the payroll to their token accounts and check each is configured for confidential
+// transfers with the ElGamal pubkey in the address book, so a bad row fails the payroll before anything is paid
+pub fn prepare_payroll(
+    ctx: &FlowContext<'_>,
+    mint: &Pubkey,
+    address_book: &AddressBook,
+    lines: &[PayrollLine],
+) -> Result<Vec<Payment>, Box<dyn Error>> {
+    let mut payments = Vec::new();
+    for line in lines {
+        let contact = line
+            .recipient
+            .resolve(address_book)
+            .map_err(|error| format!("Line {}: {}", line.line, error))?;
+        // Associated token address of the recipient
+        let token_account = get_associated_token_address_with_program_id(
+            &contact.address, // Token account owner
+            mint,             // Mint
+            &spl_token_2022::id(),
+        );
+        ConfidentialAccountState::fetch(ctx.client, &token_account).map_err(|error| {
+            format!(
+                "Line {}: token account {} of {} can't receive confidential transfers: {}",
+                line.line, token_account, contact.name, error
+            )
+        })?;
+        contact
+            .check_elgamal_pubkey(ctx.client, &token_account)
+            .map_err(|error| format!("Line {}: {}", line.line, error))?;
+        payments.push(Payment {
+            line: line.line,
+            recipient: contact.name,
+            token_account,
+            amount: line.amount,
+            outcome: PaymentOutcome::Skipped,
+            journal_status: None,
+        });
+    }
+    Ok(payments)
+}
+
+// Fail unless the decrypted available balance of the sender covers `total`, returning the balance
+pub fn check_funds(
+    ctx: &FlowContext<'_>,
+    sender: &Keypair,
+    mint: &Pubkey,
+    total: u64,
+) -> Result<u64, Box<dyn Error>> {
+    let ui_amount = UiAmount::fetch(ctx.client, mint)?;
+
+    // Associated token address of the sender
+    let sender_associated_token_address = get_associated_token_address_with_program_id(
+        &sender.pubkey(), // Token account owner
+        mint,             // Mint
+        &spl_token_2022::id(),
+    );
+
+    let before = BalanceSnapshot::fetch(
+        ctx.client,
+        &sender_associated_token_address,
+        sender,
+        &ctx.key_derivation,
+    )?;
+    let available = before
+        .available
+        .ok_or("Could not decrypt the available balance of the sender")?;
+    if total > available {
+        let mut message = format!(
+            "The payroll totals {}, more than the available balance of {}",
+            ui_amount.format(total),
+            ui_amount.format(available)
+        );
+        if before.pending.is_some_and(|pending| pending > 0) {
+            message.push_str(", apply the pending balance first");
+        }
+        return Err(message.into());
+    }
+    Ok(available)
+}
+
+// Pay each row with a confidential transfer, one at a time as each transfer spends the balance the next one
+// proves against, starting transfers at least `min_interval` apart.
+//
+// The total is checked against the decrypted available balance first. A failed transfer doesn't stop the payroll,
+// Ctrl-C does, after the current transfer. The report reconciles the available balance afterwards
+// with the amounts paid, and each transfer with its status in the journal.
+pub async fn pay_payroll<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    sender: &Keypair,
+    mut payments: Vec<Payment>,
+    min_interval: Duration,
+) -> Result<PayrollReport, Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    let mint = *token.get_address();
+    let ui_amount = UiAmount::fetch(ctx.client, &mint)?;
+
+    // Associated token address of the sender
+    let sender_associated_token_address = get_associated_token_address_with_program_id(
+        &sender.pubkey(), // Token account owner
+        &mint,            // Mint
+        &spl_token_2022::id(),
+    );
+
+    let total: u64 = payments.iter().map(|payment| payment.amount).sum();
+    let available_before = check_funds(ctx, sender, &mint, total)?;
+
+    let mut last_started: Option<Instant> = None;
+    for payment in &mut payments {
+        if shutdown::interrupted() {
+            break;
+        }
+        if let Some(wait) = last_started
+            .map(|started| min_interval.saturating_sub(started.elapsed()))
+            .filter(|wait| !wait.is_zero())
+        {
+            tokio::time::sleep(wait).await;
+        }
+        last_started = Some(Instant::now());
+
+        let result =
+            transfer::transfer_tokens(ctx, token, sender, &payment.token_account, payment.amount)
+                .await;
+        match result {
+            Ok(signature) => payment.outcome = PaymentOutcome::Paid(signature),
+            Err(error) if error.downcast_ref::<Interrupted>().is_some() => break,
+            Err(error) => payment.outcome = PaymentOutcome::Failed(error.to_string()),
+        }
+    }
+
+    // Reconcile the journal, where a transfer that timed out may still be pending
+    if let Some(journal) = &ctx.journal {
+        for payment in &mut payments {
+            let PaymentOutcome::Paid(signature) = payment.outcome else {
+                continue;
+            };
+            payment.journal_status = journal
+                .entries(&JournalQuery {
+                    signature: Some(signature),
+                    limit: Some(1),
+                    ..JournalQuery::default()
+                })?
+                .first()
+                .map(|entry| entry.status);
+        }
+    }
+    let after = BalanceSnapshot::fetch(
+        ctx.client,
+        &sender_associated_token_address,
+        sender,
+        &ctx.key_derivation,
+    )?;
+
+    Ok(PayrollReport {
+        ui_amount,
+        payments,
+        available_before,
+        available_after: after.available,
+    })
+}
@@ -0,0 +1,311 @@
+use super::FlowContext;
+use crate::events::FlowEvent;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::{
+            account_info::WithdrawAccountInfo, instruction::withdraw, ConfidentialTransferAccount,
+        },
+        BaseStateWithExtensions,
+    },
+    proof::ProofLocation,
+    solana_zk_token_sdk::{
+        encryption::{
+            auth_encryption::{AeCiphertext, AeKey},
+            elgamal::{ElGamalCiphertext, ElGamalKeypair},
+        },
+        zk_token_elgamal::pod,
+        zk_token_proof_instruction::{
+            close_context_state, ContextStateInfo, ProofType, WithdrawProofContext,
+        },
+        zk_token_proof_program,
+        zk_token_proof_state::{ProofContextState, ProofContextStateMeta},
+    },
+};
+use spl_token_client::{
+    client::{SendTransaction, SimulateTransaction},
+    token::Token,
+};
+use std::error::Error;
+
+// Number of steps reported by `resume_flows`
+pub const STEPS: usize = 3;
+
+// Proof context state accounts closed per transaction, same grouping as the transfer flow
+pub const CLOSE_BATCH_SIZE: usize = 3;
+
+// A verified proof context state account left on chain by a flow
+#[derive(Debug, Clone)]
+pub struct ProofAccount {
+    pub account: Pubkey,
+    pub proof_type: ProofType,
+    pub lamports: u64,
+    data: Vec<u8>,
+}
+
+// What `resume_flows` did with the proof accounts it found
+#[derive(Debug, Default, Clone)]
+pub struct ResumeOutcome {
+    // Withdraw completed from a verified withdraw proof, with the withdrawn amount
+    pub withdraw: Option<(Signature, u64)>,
+    // Proof accounts closed, either after completing the withdraw or to roll back an interrupted flow
+    pub closed: Vec<Pubkey>,
+    pub reclaimed_lamports: u64,
+    // Proof accounts left alone, e.g. withdraw proofs for another token account
+    pub skipped: Vec<Pubkey>,
+}
+
+// Scan the chain for proof context state accounts with the given close authority.
+// The authority is the first field of every context state account, so the scan is a single memcmp filter.
+pub fn find_proof_accounts(
+    ctx: &mut FlowContext<'_>,
+    authority: &Pubkey,
+) -> Result<Vec<ProofAccount>, Box<dyn Error>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            0,
+            authority.as_ref(),
+        ))]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    let mut proof_accounts = Vec::new();
+    for (account, data) in ctx
+        .client
+        .get_program_accounts_with_config(&zk_token_proof_program::id(), config)?
+    {
+        let meta = ProofContextStateMeta::try_from_bytes(&data.data)?;
+        let proof_type = ProofType::try_from(meta.proof_type)?;
+        if proof_type == ProofType::Uninitialized {
+            continue;
+        }
+        proof_accounts.push(ProofAccount {
+            account,
+            proof_type,
+            lamports: data.lamports,
+            data: data.data,
+        });
+    }
+    Ok(proof_accounts)
+}
+
+// Recover from a transfer or withdraw that died after its proofs were verified but before the final instruction landed.
+//
+// Withdraw: the verified proof holds the available balance *after* the withdraw, encrypted under the owner's ElGamal key,
+// so subtracting it from the current available balance recovers the withdrawn amount and the withdraw is completed.
+// A withdraw proof that no longer matches the available balance (already used, or the balance moved on), or of
+// 2^32 base units or more, is rolled back. If completing the withdraw fails, the proof accounts are still closed
+// and the error is returned afterwards.
+//
+// Transfer: the transfer instruction needs the decrypt handles of the amount under the sender's key,
+// which depend on randomness that only lived in the crashed process, so the transfer is rolled back instead
+// and can simply be sent again.
+//
+// Rolling back closes the proof accounts and returns their rent to the owner.
+// Don't run this while a flow for the same owner is still in progress, its proof accounts would be closed under it.
+pub async fn resume_flows<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    owner: &Keypair,
+    decimals: u8,
+) -> Result<ResumeOutcome, Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    ctx.flow = "resume";
+    let mint = *token.get_address();
+    let mut outcome = ResumeOutcome::default();
+
+    // Associated token address of the owner
+    let associated_token_address = get_associated_token_address_with_program_id(
+        &owner.pubkey(), // Token account owner
+        &mint,           // Mint
+        &spl_token_2022::id(),
+    );
+
+    ctx.start_step("Scanning for proof accounts");
+    let proof_accounts = find_proof_accounts(ctx, &owner.pubkey())?;
+    ctx.finish_step();
+
+    ctx.start_step("Completing interrupted withdraw");
+
+    // Derive the ElGamal keypair and AES key for the token account
+    let (elgamal_keypair, aes_key) =
+        ctx.key_derivation
+            .account_keys(ctx.client, owner, &associated_token_address)?;
+    let elgamal_pubkey = pod::ElGamalPubkey::from(*elgamal_keypair.pubkey());
+
+    let mut to_close = Vec::new();
+    let mut withdraw_error = None;
+    for proof_account in proof_accounts {
+        match proof_account.proof_type {
+            ProofType::Withdraw => {
+                let context =
+                    ProofContextState::<WithdrawProofContext>::try_from_bytes(&proof_account.data)?
+                        .proof_context;
+                if context.pubkey != elgamal_pubkey {
+                    outcome.skipped.push(proof_account.account);
+                    continue;
+                }
+
+                // The proof account is closed whether or not the withdraw completes, so its rent isn't stranded
+                if outcome.withdraw.is_none() && withdraw_error.is_none() {
+                    match complete_withdraw(
+                        ctx,
+                        token,
+                        owner,
+                        &associated_token_address,
+                        &proof_account.account,
+                        &context,
+                        &elgamal_keypair,
+                        &aes_key,
+                        decimals,
+                    )
+                    .await
+                    {
+                        Ok(withdraw) => outcome.withdraw = withdraw,
+                        Err(error) => withdraw_error = Some(error),
+                    }
+                }
+                to_close.push(proof_account);
+            }
+            // Proofs created by the transfer flow
+            ProofType::CiphertextCommitmentEquality
+            | ProofType::BatchedGroupedCiphertext2HandlesValidity
+            | ProofType::BatchedRangeProofU128
+            | ProofType::FeeSigma
+            | ProofType::BatchedRangeProofU256 => to_close.push(proof_account),
+            _ => outcome.skipped.push(proof_account.account),
+        }
+    }
+    ctx.finish_step();
+
+    ctx.start_step("Closing proof accounts");
+    let rent_funder = ctx.payers.rent_funder(&owner.pubkey());
+    for batch in to_close.chunks(CLOSE_BATCH_SIZE) {
+        // Lamports from the closed proof accounts are returned to the rent funder, the owner unless set
+        let instructions: Vec<_> = batch
+            .iter()
+            .map(|proof_account| {
+                close_context_state(
+                    ContextStateInfo {
+                        context_state_account: &proof_account.account,
+                        context_state_authority: &owner.pubkey(),
+                    },
+                    &rent_funder,
+                )
+            })
+            .collect();
+
+        ctx.send(
+            "Close Proof Accounts",
+            &instructions,
+            &owner.pubkey(),
+            &[owner],
+        )?;
+
+        let reclaimed_lamports: u64 = batch.iter().map(|account| account.lamports).sum();
+        let accounts: Vec<Pubkey> = batch.iter().map(|account| account.account).collect();
+        ctx.report.record_reclaimed(reclaimed_lamports);
+        ctx.emit(FlowEvent::ProofAccountsClosed {
+            accounts: accounts.clone(),
+            reclaimed_lamports,
+        });
+        outcome.closed.extend(accounts);
+        outcome.reclaimed_lamports += reclaimed_lamports;
+    }
+    ctx.finish_step();
+
+    // Reported once the proof accounts are closed
+    if let Some(error) = withdraw_error {
+        return Err(error);
+    }
+    Ok(outcome)
+}
+
+// Send the withdraw for a verified withdraw proof, if the proof still matches the available balance.
+// Returns the signature and amount of the withdraw, or `None` if the proof can only be rolled back.
+#[allow(clippy::too_many_arguments)]
+async fn complete_withdraw<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    owner: &Keypair,
+    associated_token_address: &Pubkey,
+    proof_account: &Pubkey,
+    context: &WithdrawProofContext,
+    elgamal_keypair: &ElGamalKeypair,
+    aes_key: &AeKey,
+    decimals: u8,
+) -> Result<Option<(Signature, u64)>, Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    // Get token account data
+    let token_account = token.get_account_info(associated_token_address).await?;
+    let extension_data = token_account.get_extension::<ConfidentialTransferAccount>()?;
+    let withdraw_account_info = WithdrawAccountInfo::new(extension_data);
+
+    // Available balance now, decrypted with the AES key so it isn't limited to the 32-bit range of ElGamal decryption
+    let decryptable_available_balance: AeCiphertext = withdraw_account_info
+        .decryptable_available_balance
+        .try_into()?;
+    let available_balance = aes_key
+        .decrypt(&decryptable_available_balance)
+        .ok_or("Could not decrypt the available balance")?;
+
+    // The proof holds the available balance after the withdraw, the current available balance minus the amount,
+    // so their difference encrypts the amount alone. It only decrypts while the proof matches the available balance.
+    let current_ciphertext: ElGamalCiphertext =
+        withdraw_account_info.available_balance.try_into()?;
+    let final_ciphertext: ElGamalCiphertext = context.final_ciphertext.try_into()?;
+    let Some(withdraw_amount) = (current_ciphertext - final_ciphertext)
+        .decrypt_u32(elgamal_keypair.secret())
+        .filter(|amount| *amount > 0 && *amount <= available_balance)
+    else {
+        // Nothing left to withdraw: the withdraw already landed, or the balance no longer matches the proof
+        return Ok(None);
+    };
+
+    // Update the decryptable available balance
+    let new_decryptable_available_balance =
+        withdraw_account_info.new_decryptable_available_balance(withdraw_amount, aes_key)?;
+
+    ctx.emit(FlowEvent::AvailableBalanceChanged {
+        before: Some(available_balance),
+        after: new_decryptable_available_balance.decrypt(aes_key),
+    });
+
+    // Create a `Withdraw` instruction, using the already verified proof
+    let withdraw_instruction = withdraw(
+        &spl_token_2022::id(),
+        associated_token_address,
+        token.get_address(),
+        withdraw_amount,
+        decimals,
+        new_decryptable_available_balance,
+        &owner.pubkey(),
+        &[&owner.pubkey()],
+        ProofLocation::ContextStateAccount(proof_account),
+    )?;
+
+    let transaction_signature = ctx.send(
+        "Withdraw Tokens",
+        &ctx.attach_memo(&withdraw_instruction),
+        &owner.pubkey(),
+        &[owner],
+    )?;
+    Ok(Some((transaction_signature, withdraw_amount)))
+}
@@ -0,0 +1,153 @@
+use super::{transfer, withdraw, FlowContext};
+use crate::{
+    clock::ClusterClock,
+    events::FlowEvent,
+    journal::now,
+    read_keypair,
+    schedule::{Schedule, ScheduleStatus, ScheduledAction, ScheduledRequest},
+    shutdown::{self, Interrupted},
+};
+use solana_sdk::signature::{Keypair, Signature};
+use spl_token_client::{
+    client::{SendTransaction, SimulateTransaction},
+    token::Token,
+};
+use std::{collections::HashSet, error::Error, time::Duration};
+
+// How often the scheduler checks for requests that are due
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Execute the scheduled requests of the token's mint as they come due, until Ctrl-C (`shutdown::install`).
+//
+// Requests within `prepare_before` of their execution time have their proofs generated into the context's proof
+// cache, so they are submitted right when due instead of after the range proof is generated. The flows reuse
+// the proofs as long as the balance they were generated against is unchanged, and generate them again otherwise.
+// Due requests are executed one at a time, in the order they were added, timed by `clock` for slot targets.
+//
+// Requests found executing were left by a scheduler stopped halfway through and are marked failed rather than
+// executed again, since some of their transactions may have landed: `resume` finishes or cleans them up.
+pub async fn run_schedule<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    decimals: u8,
+    schedule: &Schedule,
+    clock: &ClusterClock,
+    prepare_before: Duration,
+) -> Result<(), Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    for request in schedule.entries(Some(ScheduleStatus::Executing))? {
+        let error = "The scheduler stopped while executing it, see `cargo run --bin resume`";
+        schedule.mark_failed(request.id, error)?;
+        ctx.emit(FlowEvent::ScheduledRequestFailed {
+            id: request.id,
+            error: error.to_string(),
+        });
+    }
+
+    // Requests whose preparation was attempted, so failures aren't retried every tick
+    let mut prepared = HashSet::new();
+    while !shutdown::interrupted() {
+        let slot = clock.now().slot;
+        for request in schedule.pending(token.get_address())? {
+            if shutdown::interrupted() {
+                break;
+            }
+            if request.execute_at.is_due(now(), slot) {
+                execute(ctx, token, decimals, schedule, &request).await?;
+            } else if request.execute_at.is_near(now(), slot, prepare_before)
+                && prepared.insert(request.id)
+            {
+                match prepare(ctx, &request) {
+                    Ok(reused) => {
+                        schedule.mark_prepared(request.id)?;
+                        ctx.emit(FlowEvent::ScheduledRequestPrepared {
+                            id: request.id,
+                            reused,
+                        });
+                    }
+                    // Tried again when the request is executed
+                    Err(error) => eprintln!(
+                        "\nCould not prepare scheduled request #{}: {}",
+                        request.id, error
+                    ),
+                }
+            }
+        }
+        tokio::time::sleep(TICK_INTERVAL).await;
+    }
+    Ok(())
+}
+
+fn wallet_keypair(request: &ScheduledRequest) -> Result<Keypair, Box<dyn Error>> {
+    Ok(read_keypair(&request.wallet)?
+        .ok_or(format!("No keypair named {} in .env", request.wallet))?)
+}
+
+fn prepare(ctx: &FlowContext<'_>, request: &ScheduledRequest) -> Result<bool, Box<dyn Error>> {
+    let owner = wallet_keypair(request)?;
+    match request.action {
+        ScheduledAction::Withdraw => {
+            withdraw::prepare_withdraw(ctx, &request.mint, &owner, request.amount)
+        }
+        ScheduledAction::Transfer { recipient } => {
+            transfer::prepare_transfer(ctx, &request.mint, &owner, &recipient, request.amount)
+        }
+    }
+}
+
+// Run the request's flow, recording its outcome. Only an interrupted flow stops the scheduler.
+async fn execute<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    decimals: u8,
+    schedule: &Schedule,
+    request: &ScheduledRequest,
+) -> Result<(), Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    schedule.mark_executing(request.id)?;
+    match run_flow(ctx, token, decimals, request).await {
+        Ok(signature) => {
+            schedule.mark_submitted(request.id, &signature)?;
+            ctx.emit(FlowEvent::ScheduledRequestSubmitted {
+                id: request.id,
+                signature,
+            });
+            Ok(())
+        }
+        Err(error) => {
+            schedule.mark_failed(request.id, &error.to_string())?;
+            ctx.emit(FlowEvent::ScheduledRequestFailed {
+                id: request.id,
+                error: error.to_string(),
+            });
+            match error.downcast_ref::<Interrupted>() {
+                Some(_) => Err(error),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+async fn run_flow<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    decimals: u8,
+    request: &ScheduledRequest,
+) -> Result<Signature, Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    let owner = wallet_keypair(request)?;
+    match request.action {
+        ScheduledAction::Withdraw => {
+            withdraw::withdraw_tokens(ctx, token, &owner, request.amount, decimals).await
+        }
+        ScheduledAction::Transfer { recipient } => {
+            transfer::transfer_tokens(ctx, token, &owner, &recipient, request.amount).await
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use super::{apply_pending, configure_account, FlowContext};
+use crate::rent::{MintLayout, RentCalculator};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_instruction::create_account,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::instruction::deposit, ExtensionType, StateWithExtensionsOwned,
+    },
+    instruction::{initialize_mint, mint_to},
+    solana_zk_token_sdk::encryption::elgamal::ElGamalPubkey,
+    state::{Account, Mint},
+};
+use spl_token_client::token::ExtensionInitializationParams;
+use std::error::Error;
+
+// Number of steps reported by `setup_demo` for `holders` token accounts
+pub fn steps(holders: usize) -> usize {
+    3 + holders * configure_account::STEPS + apply_pending::STEPS
+}
+
+// The demo state of the 2_create_mint .. 6_apply_pending_balance binaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DemoState {
+    pub decimals: u8,
+    // Minted to the authority's token account
+    pub mint_amount: u64,
+    // Deposited from it to its confidential balance and applied
+    pub deposit_amount: u64,
+}
+
+impl Default for DemoState {
+    fn default() -> Self {
+        Self {
+            decimals: 2,
+            // 100.00 and 50.00 tokens, as in `main`
+            mint_amount: 100_00,
+            deposit_amount: 50_00,
+        }
+    }
+}
+
+// What `setup_demo` did on a cluster, the parts already in place being skipped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SetupOutcome {
+    pub mint_created: bool,
+    // Token accounts created or configured
+    pub accounts_configured: usize,
+    pub minted: bool,
+    pub deposited: bool,
+    pub applied: u64,
+}
+
+// Bring a cluster to the demo state: the mint with the `ConfidentialTransferMint` extension and `authority` as its
+// authorities, a confidential token account for each holder, and the first holder's tokens minted, deposited
+// and applied.
+//
+// Each part is skipped when already done, so rerunning brings a cluster that failed halfway, or was reset, to
+// the same state as the others: the mint is only created if missing, tokens only minted while the supply is 0,
+// and only deposited while the first holder still holds the whole minted amount publicly.
+pub fn setup_demo(
+    ctx: &mut FlowContext<'_>,
+    mint: &Keypair,
+    authority: &Keypair,
+    auditor: &ElGamalPubkey,
+    holders: &[&Keypair],
+    state: DemoState,
+) -> Result<SetupOutcome, Box<dyn Error>> {
+    ctx.flow = "setup";
+    let mut outcome = SetupOutcome::default();
+    let first_holder = holders.first().ok_or("The demo state needs a holder")?;
+
+    ctx.start_step("Creating mint account");
+    if ctx
+        .client
+        .get_account_with_commitment(&mint.pubkey(), ctx.client.commitment())?
+        .value
+        .is_none()
+    {
+        create_mint(ctx, mint, authority, auditor, state.decimals)?;
+        outcome.mint_created = true;
+    }
+    ctx.finish_step();
+
+    for holder in holders {
+        if configure_account::create_confidential_account(ctx, holder, &mint.pubkey())?.is_some() {
+            outcome.accounts_configured += 1;
+        }
+    }
+    ctx.flow = "setup";
+
+    // Associated token address of the first holder
+    let token_account = get_associated_token_address_with_program_id(
+        &first_holder.pubkey(), // Token account owner
+        &mint.pubkey(),         // Mint
+        &spl_token_2022::id(),
+    );
+
+    ctx.start_step("Minting tokens");
+    let supply =
+        StateWithExtensionsOwned::<Mint>::unpack(ctx.client.get_account_data(&mint.pubkey())?)?
+            .base
+            .supply;
+    if supply == 0 {
+        let mint_to_instruction = mint_to(
+            &spl_token_2022::id(),
+            &mint.pubkey(),         // Mint
+            &token_account,         // Token account to mint to
+            &authority.pubkey(),    // Mint authority
+            &[&authority.pubkey()], // Signers
+            state.mint_amount,      // Amount to mint
+        )?;
+        ctx.send(
+            "Mint Tokens",
+            &[mint_to_instruction],
+            &authority.pubkey(),
+            &[authority],
+        )?;
+        outcome.minted = true;
+    }
+    ctx.finish_step();
+
+    ctx.start_step("Depositing tokens");
+    let public_balance =
+        StateWithExtensionsOwned::<Account>::unpack(ctx.client.get_account_data(&token_account)?)?
+            .base
+            .amount;
+    if public_balance >= state.mint_amount {
+        // Instruction to deposit from non-confidential balance to "pending" balance
+        let deposit_instruction = deposit(
+            &spl_token_2022::id(),
+            &token_account,            // Token account
+            &mint.pubkey(),            // Mint
+            state.deposit_amount,      // Amount to deposit
+            state.decimals,            // Mint decimals
+            &first_holder.pubkey(),    // Token account owner
+            &[&first_holder.pubkey()], // Signers
+        )?;
+        ctx.send(
+            "Deposit Tokens",
+            &ctx.attach_memo(&[deposit_instruction]),
+            &first_holder.pubkey(),
+            &[first_holder],
+        )?;
+        outcome.deposited = true;
+    }
+    ctx.finish_step();
+
+    outcome.applied = apply_pending::apply_pending_balance(
+        ctx,
+        &token_account,
+        first_holder,
+        apply_pending::DEFAULT_MAX_ROUNDS,
+        apply_pending::DEFAULT_ROUND_PAUSE,
+    )?
+    .applied;
+    ctx.flow = "setup";
+
+    Ok(outcome)
+}
+
+// Create the mint account with the `ConfidentialTransferMint` extension, as in 2_create_mint
+fn create_mint(
+    ctx: &mut FlowContext<'_>,
+    mint: &Keypair,
+    authority: &Keypair,
+    auditor: &ElGamalPubkey,
+    decimals: u8,
+) -> Result<(), Box<dyn Error>> {
+    let rent = RentCalculator::fetch(ctx.client)?.mint(&MintLayout {
+        extensions: vec![ExtensionType::ConfidentialTransferMint],
+        scaled_ui_amount: false,
+        pausable: false,
+    })?;
+    ctx.report.record_rent(rent.lamports);
+
+    let confidential_transfer_mint_extension =
+        ExtensionInitializationParams::ConfidentialTransferMint {
+            authority: Some(authority.pubkey()),
+            auto_approve_new_accounts: true,
+            auditor_elgamal_pubkey: Some((*auditor).into()),
+        };
+    let instructions = vec![
+        create_account(
+            &authority.pubkey(),
+            &mint.pubkey(),
+            rent.lamports,
+            rent.space as u64,
+            &spl_token_2022::id(),
+        ),
+        confidential_transfer_mint_extension.instruction(&spl_token_2022::id(), &mint.pubkey())?,
+        initialize_mint(
+            &spl_token_2022::id(),
+            &mint.pubkey(),
+            &authority.pubkey(),       // Mint authority
+            Some(&authority.pubkey()), // Freeze authority
+            decimals,
+        )?,
+    ];
+    ctx.send(
+        "Create Mint Account",
+        &instructions,
+        &authority.pubkey(),
+        &[authority, mint],
+    )?;
+    Ok(())
+}
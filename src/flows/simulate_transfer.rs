@@ -0,0 +1,336 @@
+use super::{transfer, FlowContext};
+use crate::{account_state::ConfidentialAccountState, events::ProofKind};
+use serde_json::{json, Value};
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::{
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::{
+            account_info::TransferAccountInfo,
+            instruction::{
+                transfer_with_fee_and_split_proofs, transfer_with_split_proofs,
+                CloseSplitContextStateAccounts, TransferSplitContextStateAccounts,
+                TransferWithFeeSplitContextStateAccounts,
+            },
+            ConfidentialTransferMint,
+        },
+        BaseStateWithExtensions, StateWithExtensionsOwned,
+    },
+    solana_zk_token_sdk::{
+        encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
+        instruction::ZkProofData,
+        zk_token_elgamal::pod::ElGamalPubkey,
+        zk_token_proof_instruction::ProofInstruction,
+        zk_token_proof_program,
+    },
+    state::Mint,
+};
+use spl_token_client::proof_generation::transfer_with_fee_split_proof_data;
+use std::error::Error;
+
+// Number of steps reported by `simulate_transfer`
+pub const STEPS: usize = 3;
+
+// How one proof of the simulated transfer fared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofSimulation {
+    pub proof: ProofKind,
+    // Compute units the proof program used to verify it, if the node reported them
+    pub units_consumed: Option<u64>,
+    // Why the proof program rejected it, `None` if it verified
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferSimulation {
+    pub proofs: Vec<ProofSimulation>,
+    // Size of the signed transfer transaction, at most `PACKET_DATA_SIZE` to be sent
+    pub transfer_size: usize,
+}
+
+impl TransferSimulation {
+    pub fn is_ok(&self) -> bool {
+        self.proofs.iter().all(|proof| proof.error.is_none())
+            && self.transfer_size <= PACKET_DATA_SIZE
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "ok": self.is_ok(),
+            "proofs": self.proofs.iter().map(|proof| json!({
+                "proof": format!("{:?}", proof.proof),
+                "units_consumed": proof.units_consumed,
+                "error": proof.error,
+            })).collect::<Vec<_>>(),
+            "transfer_size": self.transfer_size,
+            "max_transfer_size": PACKET_DATA_SIZE,
+        })
+    }
+}
+
+// Run a confidential transfer from the sender's token account without touching it: the proofs are generated and
+// the instructions built against a copy of its state encrypted under synthetic keys, holding `balance` tokens
+// (the sender's decrypted available balance by default), so nothing can be signed for the real account.
+//
+// 1. Fork the sender's state under a random ElGamal keypair and AES key
+// 2. Generate the split transfer proofs for the recipient and auditor of the real accounts, and verify them locally,
+//    with the fee proofs for mints with a transfer fee
+// 3. Simulate each proof verification against the cluster's proof program, and build the transfer instruction
+//
+// The RPC of Solana 1.17 can't override accounts in simulateTransaction, so the transfer instruction itself isn't
+// executed against the fork, only checked to fit in a transaction. Nothing is sent and no account is created.
+pub fn simulate_transfer(
+    ctx: &mut FlowContext<'_>,
+    sender: &Keypair,
+    mint: &Pubkey,
+    recipient_token_account: &Pubkey,
+    amount: u64,
+    balance: Option<u64>,
+) -> Result<TransferSimulation, Box<dyn Error>> {
+    ctx.flow = "simulate-transfer";
+    let sender_token_account =
+        get_associated_token_address_with_program_id(&sender.pubkey(), mint, &spl_token_2022::id());
+
+    ctx.start_step("Forking sender state with synthetic keys");
+
+    let sender_state = ConfidentialAccountState::fetch(ctx.client, &sender_token_account)?;
+    let balance = match balance {
+        Some(balance) => balance,
+        None => {
+            let aes_key = ctx
+                .key_derivation
+                .resolve(sender, &sender_token_account, &sender_state.elgamal_pubkey)?
+                .aes_key(sender, &sender_token_account)?;
+            sender_state
+                .decrypt_decryptable_available_balance(&aes_key)?
+                .ok_or("Could not decrypt the available balance of the sender, pass --balance")?
+        }
+    };
+
+    let synthetic_elgamal_keypair = ElGamalKeypair::new_rand();
+    let synthetic_aes_key = AeKey::new_rand();
+    let mut forked = *sender_state.extension();
+    forked.elgamal_pubkey = (*synthetic_elgamal_keypair.pubkey()).into();
+    forked.available_balance = synthetic_elgamal_keypair.pubkey().encrypt(balance).into();
+    forked.decryptable_available_balance = synthetic_aes_key.encrypt(balance).into();
+    ctx.finish_step();
+
+    ctx.start_step("Generating transfer proofs");
+
+    let recipient_elgamal_pubkey =
+        ConfidentialAccountState::fetch(ctx.client, recipient_token_account)?
+            .elgamal_pubkey
+            .try_into()?;
+    let mint_state = StateWithExtensionsOwned::<Mint>::unpack(ctx.client.get_account_data(mint)?)?;
+    let fee = transfer::transfer_fee(ctx, &mint_state)?;
+    let range_proof_instruction = ctx
+        .proof_support()?
+        .transfer_range_proof(fee.is_some())
+        .map_err(|error| format!("Can't transfer tokens of mint {}: {}", mint, error))?;
+    let auditor_elgamal_pubkey = Option::<ElGamalPubkey>::from(
+        mint_state
+            .get_extension::<ConfidentialTransferMint>()?
+            .auditor_elgamal_pubkey,
+    )
+    .ok_or("No Auditor ElGamal pubkey")?
+    .try_into()?;
+
+    // The transfer as the flow would send it, with placeholder proof accounts
+    let placeholders = [
+        Keypair::new(),
+        Keypair::new(),
+        Keypair::new(),
+        Keypair::new(),
+        Keypair::new(),
+    ]
+    .map(|keypair| keypair.pubkey());
+    let proof_program_id = zk_token_proof_program::id();
+    let rent_funder = ctx.payers.rent_funder(&sender.pubkey());
+    let close_split_context_state_accounts = Some(CloseSplitContextStateAccounts {
+        lamport_destination: &rent_funder,
+        zk_token_proof_program: &proof_program_id,
+    });
+    let transfer_account_info = TransferAccountInfo::new(&forked);
+    let new_decryptable_available_balance =
+        transfer_account_info.new_decryptable_available_balance(amount, &synthetic_aes_key)?;
+
+    // Verified from instruction data alone, without a context state account, so no account is needed.
+    // Proofs the program would reject are caught locally before asking the cluster
+    let (verify_instructions, transfer_instruction) = match &fee {
+        None => {
+            let (
+                equality_proof_data,
+                ciphertext_validity_proof_data,
+                range_proof_data,
+                source_decrypt_handles,
+            ) = transfer_account_info.generate_split_transfer_proof_data(
+                amount,
+                &synthetic_elgamal_keypair,
+                &synthetic_aes_key,
+                &recipient_elgamal_pubkey,
+                Some(&auditor_elgamal_pubkey),
+            )?;
+            equality_proof_data.verify_proof()?;
+            ciphertext_validity_proof_data.verify_proof()?;
+            range_proof_data.verify_proof()?;
+
+            let verify_instructions = vec![
+                (
+                    ProofKind::Equality,
+                    ProofInstruction::VerifyCiphertextCommitmentEquality
+                        .encode_verify_proof(None, &equality_proof_data),
+                ),
+                (
+                    ProofKind::CiphertextValidity,
+                    ProofInstruction::VerifyBatchedGroupedCiphertext2HandlesValidity
+                        .encode_verify_proof(None, &ciphertext_validity_proof_data),
+                ),
+                (
+                    ProofKind::Range,
+                    range_proof_instruction.encode_verify_proof(None, &range_proof_data),
+                ),
+            ];
+            let transfer_instruction = transfer_with_split_proofs(
+                &spl_token_2022::id(),
+                &sender_token_account,
+                mint,
+                recipient_token_account,
+                new_decryptable_available_balance.into(),
+                &sender.pubkey(),
+                TransferSplitContextStateAccounts {
+                    equality_proof: &placeholders[0],
+                    ciphertext_validity_proof: &placeholders[1],
+                    range_proof: &placeholders[2],
+                    authority: &sender.pubkey(),
+                    no_op_on_uninitialized_split_context_state: false,
+                    close_split_context_state_accounts,
+                },
+                &source_decrypt_handles,
+            )?;
+            (verify_instructions, transfer_instruction)
+        }
+        // Mints with a transfer fee add the fee proofs, and a batched u256 range proof covering the fee amounts
+        Some(fee) => {
+            let (
+                equality_proof_data,
+                ciphertext_validity_proof_data,
+                fee_sigma_proof_data,
+                fee_ciphertext_validity_proof_data,
+                range_proof_data,
+                source_decrypt_handles,
+            ) = transfer_with_fee_split_proof_data(
+                &forked.available_balance.try_into()?,
+                &forked.decryptable_available_balance.try_into()?,
+                amount,
+                &synthetic_elgamal_keypair,
+                &synthetic_aes_key,
+                &recipient_elgamal_pubkey,
+                Some(&auditor_elgamal_pubkey),
+                &fee.withdraw_withheld_authority_elgamal_pubkey.try_into()?,
+                &fee.parameters,
+            )?;
+            equality_proof_data.verify_proof()?;
+            ciphertext_validity_proof_data.verify_proof()?;
+            fee_sigma_proof_data.verify_proof()?;
+            fee_ciphertext_validity_proof_data.verify_proof()?;
+            range_proof_data.verify_proof()?;
+
+            let verify_instructions = vec![
+                (
+                    ProofKind::Equality,
+                    ProofInstruction::VerifyCiphertextCommitmentEquality
+                        .encode_verify_proof(None, &equality_proof_data),
+                ),
+                (
+                    ProofKind::CiphertextValidity,
+                    ProofInstruction::VerifyBatchedGroupedCiphertext2HandlesValidity
+                        .encode_verify_proof(None, &ciphertext_validity_proof_data),
+                ),
+                (
+                    ProofKind::Range,
+                    range_proof_instruction.encode_verify_proof(None, &range_proof_data),
+                ),
+                (
+                    ProofKind::FeeSigma,
+                    ProofInstruction::VerifyFeeSigma
+                        .encode_verify_proof(None, &fee_sigma_proof_data),
+                ),
+                (
+                    ProofKind::FeeCiphertextValidity,
+                    ProofInstruction::VerifyBatchedGroupedCiphertext2HandlesValidity
+                        .encode_verify_proof(None, &fee_ciphertext_validity_proof_data),
+                ),
+            ];
+            let transfer_instruction = transfer_with_fee_and_split_proofs(
+                &spl_token_2022::id(),
+                &sender_token_account,
+                mint,
+                recipient_token_account,
+                new_decryptable_available_balance.into(),
+                &sender.pubkey(),
+                TransferWithFeeSplitContextStateAccounts {
+                    equality_proof: &placeholders[0],
+                    transfer_amount_ciphertext_validity_proof: &placeholders[1],
+                    fee_sigma_proof: &placeholders[3],
+                    fee_ciphertext_validity_proof: &placeholders[4],
+                    range_proof: &placeholders[2],
+                    authority: &sender.pubkey(),
+                    no_op_on_uninitialized_split_context_state: false,
+                    close_split_context_state_accounts,
+                },
+                &source_decrypt_handles,
+            )?;
+            (verify_instructions, transfer_instruction)
+        }
+    };
+    ctx.finish_step();
+
+    ctx.start_step("Simulating proof verification");
+
+    let fee_payer = ctx.payers.fee_payer(&sender.pubkey());
+    let mut proofs = Vec::new();
+    for (proof, instruction) in verify_instructions {
+        let transaction = Transaction::new_unsigned(Message::new(
+            &ctx.with_heap_frame(
+                &format!("Verify {:?} Proof", proof),
+                &[instruction],
+                &sender.pubkey(),
+            ),
+            Some(&fee_payer),
+        ));
+        // Unsigned, with the node's blockhash, so the synthetic keys never sign anything
+        let result = ctx
+            .client
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(ctx.client.commitment()),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )?
+            .value;
+        proofs.push(ProofSimulation {
+            proof,
+            units_consumed: result.units_consumed,
+            error: result.err.map(|error| error.to_string()),
+        });
+    }
+
+    let message = Message::new(&ctx.attach_memo(&[transfer_instruction]), Some(&fee_payer));
+    let transfer_size = bincode::serialized_size(&Transaction::new_unsigned(message))? as usize;
+    ctx.finish_step();
+
+    Ok(TransferSimulation {
+        proofs,
+        transfer_size,
+    })
+}
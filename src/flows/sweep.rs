@@ -0,0 +1,139 @@
+use super::FlowContext;
+use crate::pausable::check_not_paused;
+use solana_client::{rpc_client::RpcClient, rpc_request::MAX_MULTIPLE_ACCOUNTS};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use spl_token_2022::{
+    extension::{
+        permanent_delegate::PermanentDelegate, BaseStateWithExtensions, StateWithExtensionsOwned,
+    },
+    instruction::transfer_checked,
+    state::{Account, AccountState, Mint},
+};
+use std::error::Error;
+
+// Number of steps reported by `sweep_dust`, for sizing the progress of a larger flow
+pub const STEPS: usize = 1;
+
+// A token account holding a public balance small enough to sweep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DustAccount {
+    pub address: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+// What a sweep moved into the treasury
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SweepOutcome {
+    pub swept: u64,
+    pub accounts: usize,
+    pub signatures: Vec<Signature>,
+}
+
+// The permanent delegate of a mint, `None` when the mint has no PermanentDelegate extension or it was cleared
+pub fn permanent_delegate(
+    client: &RpcClient,
+    mint: &Pubkey,
+) -> Result<Option<Pubkey>, Box<dyn Error>> {
+    let state = StateWithExtensionsOwned::<Mint>::unpack(client.get_account_data(mint)?)?;
+    match state.get_extension::<PermanentDelegate>() {
+        Ok(extension) => Ok(Option::<Pubkey>::from(extension.delegate)),
+        Err(_) => Ok(None),
+    }
+}
+
+// Token accounts of the mint among `accounts` with a public balance of at most `max_dust`.
+// Empty, missing and frozen accounts are skipped, as is the treasury itself.
+// Only the public balance can be swept: the delegate can't decrypt, let alone move, confidential balances.
+pub fn find_dust(
+    client: &RpcClient,
+    mint: &Pubkey,
+    accounts: &[Pubkey],
+    treasury: &Pubkey,
+    max_dust: u64,
+) -> Result<Vec<DustAccount>, Box<dyn Error>> {
+    let mut dust = Vec::new();
+    for chunk in accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        for (address, account) in chunk.iter().zip(client.get_multiple_accounts(chunk)?) {
+            // Not created yet, or closed
+            let Some(account) = account else { continue };
+            if address == treasury || account.owner != spl_token_2022::id() {
+                continue;
+            }
+            let state = StateWithExtensionsOwned::<Account>::unpack(account.data)?;
+            if state.base.mint != *mint
+                || state.base.state == AccountState::Frozen
+                || state.base.amount == 0
+                || state.base.amount > max_dust
+            {
+                continue;
+            }
+            dust.push(DustAccount {
+                address: *address,
+                owner: state.base.owner,
+                amount: state.base.amount,
+            });
+        }
+    }
+    Ok(dust)
+}
+
+// Move the dust into the treasury with the permanent delegate of the mint as the transfer authority, so the owners
+// of the accounts don't sign. The transfers are packed into as few transactions as fit, paid for by the delegate.
+pub fn sweep_dust(
+    ctx: &mut FlowContext<'_>,
+    delegate: &Keypair,
+    mint: &Pubkey,
+    decimals: u8,
+    treasury: &Pubkey,
+    dust: &[DustAccount],
+) -> Result<SweepOutcome, Box<dyn Error>> {
+    ctx.flow = "sweep";
+
+    // The program only lets the permanent delegate move funds without the owner's signature
+    match permanent_delegate(ctx.client, mint)? {
+        Some(permanent_delegate) if permanent_delegate == delegate.pubkey() => {}
+        Some(permanent_delegate) => {
+            return Err(format!(
+                "The permanent delegate of mint {} is {}, not {}",
+                mint,
+                permanent_delegate,
+                delegate.pubkey()
+            )
+            .into())
+        }
+        None => return Err(format!("Mint {} has no permanent delegate", mint).into()),
+    }
+    // A paused mint rejects the transfers, so fail before sending any
+    check_not_paused(ctx.client, mint)?;
+
+    ctx.start_step("Sweeping dust into the treasury");
+    let mut packer = ctx.packer(&delegate.pubkey());
+    for account in dust {
+        // Instruction to transfer the whole public balance, signed by the permanent delegate instead of the owner
+        let instruction = transfer_checked(
+            &spl_token_2022::id(),
+            &account.address,   // Source
+            mint,               // Mint
+            treasury,           // Destination
+            &delegate.pubkey(), // Permanent delegate
+            &[],
+            account.amount,
+            decimals,
+        )?;
+        packer.push(&format!("Sweep {}", account.address), vec![instruction]);
+    }
+    if packer.is_empty() {
+        return Ok(SweepOutcome::default());
+    }
+    let signatures = ctx.send_packed(&packer, &delegate.pubkey(), &[delegate])?;
+
+    Ok(SweepOutcome {
+        swept: dust.iter().map(|account| account.amount).sum(),
+        accounts: dust.len(),
+        signatures,
+    })
+}
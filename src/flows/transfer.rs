@@ -0,0 +1,765 @@
+use super::FlowContext;
+use crate::{
+    account_state::ConfidentialAccountState,
+    events::{FlowEvent, ProofKind},
+    pausable::check_not_paused,
+    policy::PolicyAction,
+    proof_cache::{ProofKey, TransferProofData, TransferWithFeeProofData},
+    registry::fetch_registry,
+    rent::RentCalculator,
+    verify::BalanceChange,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction::create_account,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::{
+            account_info::TransferAccountInfo,
+            instruction::{
+                transfer_with_fee_and_split_proofs, transfer_with_split_proofs,
+                CloseSplitContextStateAccounts, TransferSplitContextStateAccounts,
+                TransferWithFeeSplitContextStateAccounts,
+            },
+            ConfidentialTransferAccount, ConfidentialTransferMint,
+        },
+        confidential_transfer_fee::ConfidentialTransferFeeConfig,
+        transfer_fee::TransferFeeConfig,
+        BaseStateWithExtensions, StateWithExtensionsOwned,
+    },
+    solana_zk_token_sdk::{
+        encryption::{auth_encryption::AeKey, elgamal},
+        instruction::{
+            ciphertext_commitment_equality::CiphertextCommitmentEqualityProofContext,
+            transfer::FeeParameters, FeeSigmaProofContext,
+        },
+        zk_token_elgamal::pod::ElGamalPubkey,
+        zk_token_proof_instruction::{
+            BatchedGroupedCiphertext2HandlesValidityProofContext, BatchedRangeProofContext,
+            ContextStateInfo, ProofInstruction,
+        },
+        zk_token_proof_program,
+    },
+    state::{Account, Mint},
+};
+use spl_token_client::{
+    client::{SendTransaction, SimulateTransaction},
+    proof_generation::transfer_with_fee_split_proof_data,
+    token::Token,
+};
+use std::error::Error;
+
+// Number of steps reported by `transfer_tokens`, for sizing the progress of a larger flow
+pub const STEPS: usize = 3;
+
+// Confidential transfer from the sender's associated token account to the recipient token account.
+//
+// Must first create 3 accounts to store proofs before sending the confidential transfer
+// The proofs are too large for a single transaction, so they are packed into as few as they fit in
+// (`packer::TransactionPacker`), the range proof instruction taking a transaction of its own
+//
+// Equality Proof - prove that ciphertexts encrypt the same value
+// Ciphertext Validity Proof - one batched proof that the lo and hi ciphertexts are properly encrypted for the sender, receiver and auditor
+// Range Proof - one batched u128 proof that the new balance and the lo and hi amounts are in range (positive amount, enough tokens to send)
+//
+// 1. Create the 3 proof accounts
+// 2. Perform the confidential transfer using the 3 proof accounts, which closes them on execution
+//
+// Mints with a transfer fee need 5 proof accounts instead:
+//
+// Fee Sigma Proof - prove that the fee is the fee rate of the transfer amount, or the maximum fee
+// Fee Ciphertext Validity Proof - prove that the lo and hi fee ciphertexts are properly encrypted for the recipient
+// and the withdraw withheld authority
+// Range Proof - one batched u256 proof, also covering the fee amounts
+pub async fn transfer_tokens<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    sender: &Keypair,
+    recipient_associated_token_address: &Pubkey,
+    transfer_amount: u64,
+) -> Result<Signature, Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    ctx.flow = "transfer";
+    let mint = *token.get_address();
+
+    // Associated token address of the sender
+    let sender_associated_token_address = get_associated_token_address_with_program_id(
+        &sender.pubkey(), // Token account owner
+        &mint,            // Mint
+        &spl_token_2022::id(),
+    );
+
+    // "Authority" for the proof accounts (to close the accounts after the transfer)
+    let context_state_authority = sender;
+
+    // Generate keypair to use as address for equality proof account
+    let equality_proof_context_state_account = ctx.keypairs.keypair("equality-proof")?;
+    let equality_proof_pubkey = equality_proof_context_state_account.pubkey();
+
+    // Generate keypair to use as address for ciphertext validity proof account
+    let ciphertext_validity_proof_context_state_account =
+        ctx.keypairs.keypair("ciphertext-validity-proof")?;
+    let ciphertext_validity_proof_pubkey = ciphertext_validity_proof_context_state_account.pubkey();
+
+    // Generate keypair to use as address for range proof account
+    let range_proof_context_state_account = ctx.keypairs.keypair("range-proof")?;
+    let range_proof_pubkey = range_proof_context_state_account.pubkey();
+
+    // Generate keypairs to use as addresses for the fee proof accounts, only created for mints with a transfer fee
+    let fee_sigma_proof_context_state_account = ctx.keypairs.keypair("fee-sigma-proof")?;
+    let fee_sigma_proof_pubkey = fee_sigma_proof_context_state_account.pubkey();
+    let fee_ciphertext_validity_proof_context_state_account =
+        ctx.keypairs.keypair("fee-ciphertext-validity-proof")?;
+    let fee_ciphertext_validity_proof_pubkey =
+        fee_ciphertext_validity_proof_context_state_account.pubkey();
+
+    let sender_pubkey = sender.pubkey();
+    let context_state_authority_pubkey = context_state_authority.pubkey();
+    // Funds the rent of the proof accounts and gets it back when the transfer closes them
+    let rent_funder = ctx.payers.rent_funder(&sender_pubkey);
+    // Proof accounts are owned by the ZK Token Proof program
+    let proof_support = ctx.proof_support()?;
+    let proof_program_id = zk_token_proof_program::id();
+
+    // A paused mint rejects the transfer, so fail before generating proofs and creating proof accounts
+    check_not_paused(ctx.client, &mint)?;
+
+    // Refuse transfers outside the policy before anything is signed.
+    // Recipients may be allowed by token account or by owner.
+    let recipient_owner = StateWithExtensionsOwned::<Account>::unpack(
+        ctx.client
+            .get_account_data(recipient_associated_token_address)?,
+    )?
+    .base
+    .owner;
+    ctx.check_policy(
+        &mint,
+        PolicyAction::Transfer {
+            recipient: *recipient_associated_token_address,
+            recipient_owner,
+        },
+        transfer_amount,
+    )?;
+
+    // Sender balances before the transfer, when verifying
+    let sender_balances = ctx.snapshot_balances(&sender_associated_token_address, sender)?;
+
+    ctx.start_step("Generating transfer proofs");
+
+    // Get the confidential transfer state of the sender token account
+    let sender_state =
+        ConfidentialAccountState::fetch(ctx.client, &sender_associated_token_address)?;
+
+    // confidential transfer extension data needed to create proof data for the transfer (available balance)
+    let transfer_account_info = TransferAccountInfo::new(sender_state.extension());
+
+    // Derive the ElGamal keypair and AES key for the sender token account
+    let (sender_elgamal_keypair, sender_aes_key) =
+        ctx.key_derivation
+            .account_keys(ctx.client, sender, &sender_associated_token_address)?;
+
+    // Get recipient token account data
+    let recipient_account = token
+        .get_account(*recipient_associated_token_address)
+        .await?;
+
+    // Get recipient ElGamal pubkey, used to encrypt the transfer amount under the recipient ElGamal pubkey.
+    // The transfer only lands on a configured account, whose key is the one the program checks
+    let recipient_state = StateWithExtensionsOwned::<Account>::unpack(recipient_account.data)?;
+    if recipient_state
+        .get_extension::<ConfidentialTransferAccount>()
+        .is_err()
+    {
+        return Err(format!(
+            "Token account {} isn't configured for confidential transfers yet",
+            recipient_associated_token_address
+        )
+        .into());
+    }
+    let recipient_elgamal_pod =
+        recipient_elgamal_pubkey(ctx, recipient_associated_token_address, &recipient_state)?;
+
+    // Get mint account data
+    let mint_account = token.get_account(mint).await?;
+    let mint_state = StateWithExtensionsOwned::<Mint>::unpack(mint_account.data)?;
+
+    // The fee of the current epoch, for mints with a transfer fee
+    let fee = transfer_fee(ctx, &mint_state)?;
+
+    // Choose the range proof for the mint from what the cluster verifies, failing before any proof account is created
+    // if the cluster can't verify it (e.g. transfer with fee proofs not activated)
+    let range_proof_instruction = proof_support
+        .transfer_range_proof(fee.is_some())
+        .map_err(|error| format!("Can't transfer tokens of mint {}: {}", mint, error))?;
+
+    // Get auditor ElGamal pubkey from the mint account data
+    // Used to encrypt the transfer amount under the auditor ElGamal pubkey
+    let auditor_elgamal_pod = auditor_elgamal_pubkey(&mint_state)?;
+
+    // Generate proof data required for proof accounts to use in the transfer instruction,
+    // or reuse the proof data of an earlier run that failed against the same balances
+    let (proofs, proof_key, reused) = transfer_proofs(
+        ctx,
+        sender,
+        &sender_state,
+        &sender_elgamal_keypair,
+        &sender_aes_key,
+        transfer_amount,
+        &recipient_elgamal_pod,
+        &auditor_elgamal_pod,
+        fee.as_ref(),
+    )?;
+    let (equality_proof_data, ciphertext_validity_proof_data, source_decrypt_handles) =
+        match &proofs {
+            TransferProofs::WithoutFee((equality, ciphertext_validity, _, handles)) => {
+                (equality, ciphertext_validity, handles)
+            }
+            TransferProofs::WithFee(proofs) => {
+                let (equality, ciphertext_validity, _, _, _, handles) = proofs;
+                (equality, ciphertext_validity, handles)
+            }
+        };
+
+    for proof in proofs.kinds() {
+        ctx.emit(if reused {
+            FlowEvent::ProofReused { proof }
+        } else {
+            FlowEvent::ProofGenerated { proof }
+        });
+    }
+    ctx.finish_step();
+
+    // Proof Accounts ---------------------------------------------------------------------------
+
+    ctx.stop_if_interrupted(sender)?;
+    ctx.start_step("Creating and verifying proof accounts");
+
+    // The proof accounts and the transfer are sent back to back with `--wait processed`
+    ctx.begin_optimistic();
+
+    // Each proof account is created, then initialized with its verified proof. The packer fits these instructions,
+    // in order, into as few transactions as they fit in; the range proof instruction is too large to share one.
+    let mut packer = ctx.packer(&sender_pubkey);
+    let rent_calculator = RentCalculator::fetch(ctx.client)?;
+
+    // Range Proof
+    // space and rent required for range proof account, the same for u128 and u256 range proofs
+    let rent = rent_calculator.proof_account::<BatchedRangeProofContext>();
+    ctx.report.record_rent(rent.lamports);
+
+    // Create Account for Range Proof
+    packer.push(
+        "Create Range Proof Context State",
+        vec![create_account(
+            &rent_funder,
+            &range_proof_pubkey,
+            rent.lamports,
+            rent.space as u64,
+            &proof_program_id,
+        )],
+    );
+
+    // Instruction to initialize account with proof data
+    let range_proof_context_state_info = ContextStateInfo {
+        context_state_account: &range_proof_pubkey,
+        context_state_authority: &context_state_authority_pubkey,
+    };
+    packer.push(
+        "Initialize Range Proof Context State",
+        vec![match &proofs {
+            TransferProofs::WithoutFee((_, _, range_proof_data, _)) => range_proof_instruction
+                .encode_verify_proof(Some(range_proof_context_state_info), range_proof_data),
+            TransferProofs::WithFee(proofs) => range_proof_instruction
+                .encode_verify_proof(Some(range_proof_context_state_info), &proofs.4),
+        }],
+    );
+
+    // Equality Proof
+    // Calculate the space required for the account
+    let rent = rent_calculator.proof_account::<CiphertextCommitmentEqualityProofContext>();
+    ctx.report.record_rent(rent.lamports);
+
+    // Create Account for Equality Proof
+    packer.push(
+        "Create Equality Proof Context State",
+        vec![create_account(
+            &rent_funder,
+            &equality_proof_pubkey,
+            rent.lamports,
+            rent.space as u64,
+            &proof_program_id,
+        )],
+    );
+
+    // Instruction to initialize account with proof data
+    packer.push(
+        "Initialize Equality Proof Context State",
+        vec![
+            ProofInstruction::VerifyCiphertextCommitmentEquality.encode_verify_proof(
+                Some(ContextStateInfo {
+                    context_state_account: &equality_proof_pubkey,
+                    context_state_authority: &context_state_authority_pubkey,
+                }),
+                equality_proof_data,
+            ),
+        ],
+    );
+
+    // Ciphertext Validity Proof
+    // Calculate the space required for the account
+    let rent =
+        rent_calculator.proof_account::<BatchedGroupedCiphertext2HandlesValidityProofContext>();
+    ctx.report.record_rent(rent.lamports);
+
+    // Create Account for Ciphertext Validity Proof
+    packer.push(
+        "Create Ciphertext Validity Proof Context State",
+        vec![create_account(
+            &rent_funder,
+            &ciphertext_validity_proof_pubkey,
+            rent.lamports,
+            rent.space as u64,
+            &proof_program_id,
+        )],
+    );
+
+    // Instruction to initialize account with proof data
+    packer.push(
+        "Initialize Ciphertext Validity Proof Context State",
+        vec![
+            ProofInstruction::VerifyBatchedGroupedCiphertext2HandlesValidity.encode_verify_proof(
+                Some(ContextStateInfo {
+                    context_state_account: &ciphertext_validity_proof_pubkey,
+                    context_state_authority: &context_state_authority_pubkey,
+                }),
+                ciphertext_validity_proof_data,
+            ),
+        ],
+    );
+
+    let mut proof_accounts = vec![
+        equality_proof_pubkey,
+        ciphertext_validity_proof_pubkey,
+        range_proof_pubkey,
+    ];
+
+    if let TransferProofs::WithFee(proofs) = &proofs {
+        let (_, _, fee_sigma_proof_data, fee_ciphertext_validity_proof_data, _, _) = proofs;
+        // Fee Sigma Proof
+        // Calculate the space required for the account
+        let rent = rent_calculator.proof_account::<FeeSigmaProofContext>();
+        ctx.report.record_rent(rent.lamports);
+
+        // Create Account for Fee Sigma Proof
+        packer.push(
+            "Create Fee Sigma Proof Context State",
+            vec![create_account(
+                &rent_funder,
+                &fee_sigma_proof_pubkey,
+                rent.lamports,
+                rent.space as u64,
+                &proof_program_id,
+            )],
+        );
+
+        // Instruction to initialize account with proof data
+        packer.push(
+            "Initialize Fee Sigma Proof Context State",
+            vec![ProofInstruction::VerifyFeeSigma.encode_verify_proof(
+                Some(ContextStateInfo {
+                    context_state_account: &fee_sigma_proof_pubkey,
+                    context_state_authority: &context_state_authority_pubkey,
+                }),
+                fee_sigma_proof_data,
+            )],
+        );
+
+        // Fee Ciphertext Validity Proof
+        // Calculate the space required for the account
+        let rent =
+            rent_calculator.proof_account::<BatchedGroupedCiphertext2HandlesValidityProofContext>();
+        ctx.report.record_rent(rent.lamports);
+
+        // Create Account for Fee Ciphertext Validity Proof
+        packer.push(
+            "Create Fee Ciphertext Validity Proof Context State",
+            vec![create_account(
+                &rent_funder,
+                &fee_ciphertext_validity_proof_pubkey,
+                rent.lamports,
+                rent.space as u64,
+                &proof_program_id,
+            )],
+        );
+
+        // Instruction to initialize account with proof data
+        packer.push(
+            "Initialize Fee Ciphertext Validity Proof Context State",
+            vec![
+                ProofInstruction::VerifyBatchedGroupedCiphertext2HandlesValidity
+                    .encode_verify_proof(
+                        Some(ContextStateInfo {
+                            context_state_account: &fee_ciphertext_validity_proof_pubkey,
+                            context_state_authority: &context_state_authority_pubkey,
+                        }),
+                        fee_ciphertext_validity_proof_data,
+                    ),
+            ],
+        );
+
+        proof_accounts.push(fee_sigma_proof_pubkey);
+        proof_accounts.push(fee_ciphertext_validity_proof_pubkey);
+    }
+
+    // Tracked before sending, so the accounts that landed are closed if a later transaction fails
+    for pubkey in &proof_accounts {
+        ctx.track_proof_account(*pubkey);
+    }
+    ctx.send_packed(
+        &packer,
+        &sender_pubkey,
+        &[
+            sender,
+            &range_proof_context_state_account,
+            &equality_proof_context_state_account,
+            &ciphertext_validity_proof_context_state_account,
+            &fee_sigma_proof_context_state_account,
+            &fee_ciphertext_validity_proof_context_state_account,
+        ], // Signers, the fee proof accounts only sign for mints with a transfer fee
+    )?;
+    let mut created = vec![
+        (ProofKind::Range, range_proof_pubkey),
+        (ProofKind::Equality, equality_proof_pubkey),
+        (
+            ProofKind::CiphertextValidity,
+            ciphertext_validity_proof_pubkey,
+        ),
+    ];
+    if fee.is_some() {
+        created.push((ProofKind::FeeSigma, fee_sigma_proof_pubkey));
+        created.push((
+            ProofKind::FeeCiphertextValidity,
+            fee_ciphertext_validity_proof_pubkey,
+        ));
+    }
+    for (proof, account) in created {
+        ctx.emit(FlowEvent::ProofAccountCreated { proof, account });
+        ctx.emit(FlowEvent::ProofVerified { proof, account });
+    }
+    ctx.finish_step();
+
+    // Confidential Transfer with Split Proofs ---------------------------------------------------------------
+
+    ctx.stop_if_interrupted(sender)?;
+    ctx.start_step("Sending confidential transfer");
+
+    // Calculate the new decryptable available balance for the sender token account
+    // deducts the transfer amount from the available balance, and recalculates the new decryptable available balance.
+    // The fee is withheld from the amount the recipient receives, the sender is debited the transfer amount either way
+    let new_decryptable_available_balance = transfer_account_info
+        .new_decryptable_available_balance(transfer_amount, &sender_aes_key)?;
+
+    // Close the proof accounts in the transfer instruction itself, returning their rent to the rent funder
+    let close_split_context_state_accounts = Some(CloseSplitContextStateAccounts {
+        lamport_destination: &rent_funder,
+        zk_token_proof_program: &proof_program_id,
+    });
+
+    // Create the 'transfer_with_split_proofs' instruction, or 'transfer_with_fee_and_split_proofs' for fee mints
+    let transfer_with_split_proofs_instruction = if fee.is_some() {
+        transfer_with_fee_and_split_proofs(
+            &spl_token_2022::id(),
+            &sender_associated_token_address, // Source token account
+            &mint,                            // Mint
+            recipient_associated_token_address, // Destination token account
+            new_decryptable_available_balance.into(), // Updated source token account available balance
+            &sender.pubkey(),                         // Source token account owner
+            TransferWithFeeSplitContextStateAccounts {
+                equality_proof: &equality_proof_pubkey,
+                transfer_amount_ciphertext_validity_proof: &ciphertext_validity_proof_pubkey,
+                fee_sigma_proof: &fee_sigma_proof_pubkey,
+                fee_ciphertext_validity_proof: &fee_ciphertext_validity_proof_pubkey,
+                range_proof: &range_proof_pubkey,
+                authority: &context_state_authority_pubkey,
+                no_op_on_uninitialized_split_context_state: false,
+                close_split_context_state_accounts,
+            }, // Proof context state accounts
+            source_decrypt_handles, // The ElGamal ciphertext decryption handle of the transfer amount under the source public key of the transfer.
+        )?
+    } else {
+        transfer_with_split_proofs(
+            &spl_token_2022::id(),
+            &sender_associated_token_address, // Source token account
+            &mint,                            // Mint
+            recipient_associated_token_address, // Destination token account
+            new_decryptable_available_balance.into(), // Updated source token account available balance
+            &sender.pubkey(),                         // Source token account owner
+            TransferSplitContextStateAccounts {
+                equality_proof: &equality_proof_pubkey,
+                ciphertext_validity_proof: &ciphertext_validity_proof_pubkey,
+                range_proof: &range_proof_pubkey,
+                authority: &context_state_authority_pubkey,
+                no_op_on_uninitialized_split_context_state: false,
+                close_split_context_state_accounts,
+            }, // Proof context state accounts
+            source_decrypt_handles, // The ElGamal ciphertext decryption handle of the transfer amount under the source public key of the transfer.
+        )?
+    };
+
+    // Lamports held by the proof accounts are returned to the rent funder when the transfer closes them
+    let mut reclaimed_lamports = 0;
+    for pubkey in &proof_accounts {
+        // Processed, since the proof accounts may not be confirmed yet
+        reclaimed_lamports += ctx
+            .client
+            .get_balance_with_commitment(pubkey, CommitmentConfig::processed())?
+            .value;
+    }
+
+    let transfer_signature = ctx.send(
+        "Confidential Transfer with Split Proofs",
+        &ctx.attach_memo(&[transfer_with_split_proofs_instruction]),
+        &sender.pubkey(),
+        &[sender],
+    )?;
+    ctx.reconcile(sender)?;
+    ctx.record_spend(&transfer_signature, &mint, transfer_amount);
+    ctx.untrack_proof_accounts(&proof_accounts);
+    ctx.forget_proof(&proof_key);
+    ctx.finish_step();
+
+    ctx.report.record_reclaimed(reclaimed_lamports);
+    ctx.emit(FlowEvent::ProofAccountsClosed {
+        accounts: proof_accounts,
+        reclaimed_lamports,
+    });
+
+    // The transfer only debits the sender's available balance, the recipient is credited in its pending balance
+    ctx.verify_balances(
+        sender_balances,
+        sender,
+        BalanceChange {
+            available: -(transfer_amount as i128),
+            ..BalanceChange::default()
+        },
+    )?;
+
+    Ok(transfer_signature)
+}
+
+// Generate the transfer proof data into the proof cache ahead of the transfer, e.g. by the scheduler shortly before
+// a transfer is due. `transfer_tokens` reuses it as long as the sender's available balance is unchanged.
+// The recipient token account doesn't need to be configured yet when its owner published an ElGamal pubkey.
+// Returns whether the proof data was cached already.
+pub fn prepare_transfer(
+    ctx: &FlowContext<'_>,
+    mint: &Pubkey,
+    sender: &Keypair,
+    recipient_associated_token_address: &Pubkey,
+    transfer_amount: u64,
+) -> Result<bool, Box<dyn Error>> {
+    if ctx.proof_cache.is_none() {
+        return Err("Preparing a transfer needs a proof cache".into());
+    }
+
+    // Associated token address of the sender
+    let sender_associated_token_address = get_associated_token_address_with_program_id(
+        &sender.pubkey(), // Token account owner
+        mint,             // Mint
+        &spl_token_2022::id(),
+    );
+
+    let sender_state =
+        ConfidentialAccountState::fetch(ctx.client, &sender_associated_token_address)?;
+    let (sender_elgamal_keypair, sender_aes_key) =
+        ctx.key_derivation
+            .account_keys(ctx.client, sender, &sender_associated_token_address)?;
+
+    let recipient_state = StateWithExtensionsOwned::<Account>::unpack(
+        ctx.client
+            .get_account_data(recipient_associated_token_address)?,
+    )?;
+    let recipient_elgamal_pod =
+        recipient_elgamal_pubkey(ctx, recipient_associated_token_address, &recipient_state)?;
+    let mint_state = StateWithExtensionsOwned::<Mint>::unpack(ctx.client.get_account_data(mint)?)?;
+    let auditor_elgamal_pod = auditor_elgamal_pubkey(&mint_state)?;
+    let fee = transfer_fee(ctx, &mint_state)?;
+
+    // Same proof data and key as `transfer_tokens`
+    let (_, _, reused) = transfer_proofs(
+        ctx,
+        sender,
+        &sender_state,
+        &sender_elgamal_keypair,
+        &sender_aes_key,
+        transfer_amount,
+        &recipient_elgamal_pod,
+        &auditor_elgamal_pod,
+        fee.as_ref(),
+    )?;
+    Ok(reused)
+}
+
+// Proof data of a split proof transfer. Mints with a transfer fee add the fee sigma and fee ciphertext validity
+// proofs, and prove the amounts in range with a batched u256 range proof instead of a u128 one.
+#[allow(clippy::large_enum_variant)]
+enum TransferProofs {
+    WithoutFee(TransferProofData),
+    WithFee(TransferWithFeeProofData),
+}
+
+impl TransferProofs {
+    fn kinds(&self) -> Vec<ProofKind> {
+        let mut kinds = vec![
+            ProofKind::Equality,
+            ProofKind::CiphertextValidity,
+            ProofKind::Range,
+        ];
+        if let TransferProofs::WithFee(_) = self {
+            kinds.push(ProofKind::FeeSigma);
+            kinds.push(ProofKind::FeeCiphertextValidity);
+        }
+        kinds
+    }
+}
+
+// The transfer fee of the current epoch, and the ElGamal pubkey the withheld fees are encrypted under
+pub struct TransferFee {
+    pub parameters: FeeParameters,
+    pub withdraw_withheld_authority_elgamal_pubkey: ElGamalPubkey,
+}
+
+// The transfer fee of the mint, `None` for mints without the TransferFeeConfig extension
+pub fn transfer_fee(
+    ctx: &FlowContext<'_>,
+    mint_state: &StateWithExtensionsOwned<Mint>,
+) -> Result<Option<TransferFee>, Box<dyn Error>> {
+    let Ok(transfer_fee_config) = mint_state.get_extension::<TransferFeeConfig>() else {
+        return Ok(None);
+    };
+    let epoch_fee = transfer_fee_config.get_epoch_fee(ctx.client.get_epoch_info()?.epoch);
+    let withdraw_withheld_authority_elgamal_pubkey = mint_state
+        .get_extension::<ConfidentialTransferFeeConfig>()
+        .map_err(|_| {
+            "The mint has a transfer fee but no ConfidentialTransferFeeConfig extension, its fees can't be encrypted"
+        })?
+        .withdraw_withheld_authority_elgamal_pubkey;
+    Ok(Some(TransferFee {
+        parameters: FeeParameters {
+            fee_rate_basis_points: epoch_fee.transfer_fee_basis_points.into(),
+            maximum_fee: epoch_fee.maximum_fee.into(),
+        },
+        withdraw_withheld_authority_elgamal_pubkey,
+    }))
+}
+
+// Generate the proof data of a transfer, or reuse the proof data of an earlier run that failed against the same
+// balances. Returns the proof data, its key in the proof cache, and whether it was reused.
+#[allow(clippy::too_many_arguments)]
+fn transfer_proofs(
+    ctx: &FlowContext<'_>,
+    sender: &Keypair,
+    sender_state: &ConfidentialAccountState,
+    sender_elgamal_keypair: &elgamal::ElGamalKeypair,
+    sender_aes_key: &AeKey,
+    transfer_amount: u64,
+    recipient_elgamal_pod: &ElGamalPubkey,
+    auditor_elgamal_pod: &ElGamalPubkey,
+    fee: Option<&TransferFee>,
+) -> Result<(TransferProofs, ProofKey, bool), Box<dyn Error>> {
+    let transfer_account_info = TransferAccountInfo::new(sender_state.extension());
+    let recipient_elgamal_pubkey: elgamal::ElGamalPubkey = (*recipient_elgamal_pod).try_into()?;
+    let auditor_elgamal_pubkey: elgamal::ElGamalPubkey = (*auditor_elgamal_pod).try_into()?;
+
+    let Some(fee) = fee else {
+        let proof_key = ProofKey::new(
+            "transfer",
+            sender_state,
+            transfer_amount,
+            &[&recipient_elgamal_pod.0, &auditor_elgamal_pod.0],
+        );
+        let (proofs, reused) = ctx.cached_proof(sender, &proof_key, || {
+            Ok(transfer_account_info.generate_split_transfer_proof_data(
+                transfer_amount,
+                sender_elgamal_keypair,
+                sender_aes_key,
+                &recipient_elgamal_pubkey,
+                Some(&auditor_elgamal_pubkey),
+            )?)
+        })?;
+        return Ok((TransferProofs::WithoutFee(proofs), proof_key, reused));
+    };
+
+    // The fee proofs also depend on the fee parameters and the withdraw withheld authority
+    let proof_key = ProofKey::new(
+        "transfer-with-fee",
+        sender_state,
+        transfer_amount,
+        &[
+            &recipient_elgamal_pod.0,
+            &auditor_elgamal_pod.0,
+            &fee.withdraw_withheld_authority_elgamal_pubkey.0,
+            &fee.parameters.fee_rate_basis_points.to_le_bytes(),
+            &fee.parameters.maximum_fee.to_le_bytes(),
+        ],
+    );
+    let withdraw_withheld_authority_elgamal_pubkey: elgamal::ElGamalPubkey =
+        fee.withdraw_withheld_authority_elgamal_pubkey.try_into()?;
+    let (proofs, reused) = ctx.cached_proof(sender, &proof_key, || {
+        Ok(transfer_with_fee_split_proof_data(
+            &transfer_account_info.available_balance.try_into()?,
+            &transfer_account_info
+                .decryptable_available_balance
+                .try_into()?,
+            transfer_amount,
+            sender_elgamal_keypair,
+            sender_aes_key,
+            &recipient_elgamal_pubkey,
+            Some(&auditor_elgamal_pubkey),
+            &withdraw_withheld_authority_elgamal_pubkey,
+            &fee.parameters,
+        )?)
+    })?;
+    Ok((TransferProofs::WithFee(proofs), proof_key, reused))
+}
+
+// The recipient ElGamal pubkey to encrypt the transfer amount under.
+// The key configured on the recipient token account is the one the program checks, so it is used as is.
+// Only while the account isn't configured for confidential transfers yet is the registry asked for the key its
+// owner published, so `prepare_transfer` can generate proofs ahead of the account being configured.
+fn recipient_elgamal_pubkey(
+    ctx: &FlowContext<'_>,
+    recipient_associated_token_address: &Pubkey,
+    recipient_state: &StateWithExtensionsOwned<Account>,
+) -> Result<ElGamalPubkey, Box<dyn Error>> {
+    if let Ok(extension) = recipient_state.get_extension::<ConfidentialTransferAccount>() {
+        return Ok(extension.elgamal_pubkey);
+    }
+    let owner = recipient_state.base.owner;
+    match fetch_registry(ctx.client, &owner)? {
+        Some(registry) => Ok(registry.elgamal_pubkey),
+        None => Err(format!(
+            "Token account {} isn't configured for confidential transfers and {} hasn't published an ElGamal pubkey",
+            recipient_associated_token_address, owner
+        )
+        .into()),
+    }
+}
+
+// The auditor ElGamal pubkey configured on the mint
+fn auditor_elgamal_pubkey(
+    mint_state: &StateWithExtensionsOwned<Mint>,
+) -> Result<ElGamalPubkey, Box<dyn Error>> {
+    Ok(Option::<ElGamalPubkey>::from(
+        mint_state
+            .get_extension::<ConfidentialTransferMint>()?
+            .auditor_elgamal_pubkey,
+    )
+    .ok_or("No Auditor ElGamal pubkey")?)
+}
@@ -0,0 +1,284 @@
+use super::{apply_pending, FlowContext};
+use crate::{
+    account_state::ConfidentialAccountState,
+    clock::{ClusterClock, ClusterTime, SlotInterval},
+    events::FlowEvent,
+    journal::now,
+    topup::TopUp,
+};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use spl_token_2022::solana_zk_token_sdk::encryption::elgamal::ElGamalKeypair;
+use std::{
+    error::Error,
+    thread,
+    time::{Duration, Instant},
+};
+
+// How often the watcher wakes up without a notification to run the scheduled work that is due
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Finished retry queue entries are kept this long for `tx queue`, then deleted at an epoch boundary
+const RETRY_QUEUE_RETENTION_SECS: u64 = 24 * 60 * 60;
+
+// Pause before subscribing again after the websocket connection dropped
+const RESUBSCRIBE_PAUSE: Duration = Duration::from_secs(2);
+
+// When the watcher applies the pending balance of a token account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchPolicy {
+    // Wait until the pending balance reaches this many base units
+    pub min_pending: u64,
+    // Apply anyway once the account has been credited this many times, to stay below the account's
+    // maximum pending balance credit counter, past which further credits are rejected
+    pub max_credits: Option<u64>,
+    // Least time between two applies
+    pub min_interval: Duration,
+}
+
+impl Default for WatchPolicy {
+    fn default() -> Self {
+        Self {
+            min_pending: 0,
+            max_credits: None,
+            min_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl WatchPolicy {
+    pub fn should_apply(&self, pending: u64, credits: u64, maximum_credits: u64) -> bool {
+        // Leave room for one more credit below the account's maximum
+        let max_credits = self
+            .max_credits
+            .unwrap_or(u64::MAX)
+            .min(maximum_credits.saturating_sub(1));
+        credits > 0 && (pending >= self.min_pending || credits >= max_credits)
+    }
+}
+
+// When the watcher runs its non-urgent work, in slots of the cluster rather than wall clock time,
+// so it keeps pace with the cluster however fast its slots are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchSchedule {
+    // Check the token account without a notification, to apply credits deferred by the rate limit
+    // and to catch changes missed while the subscription was down
+    pub recheck_slots: u64,
+    // Retry queued transactions and top up the fee payer
+    pub maintenance_slots: u64,
+    // Apply credits held back by `WatchPolicy::min_pending` once the epoch is this close to its end,
+    // so small credits don't wait for the threshold forever
+    pub apply_before_epoch_end: Option<u64>,
+}
+
+impl Default for WatchSchedule {
+    fn default() -> Self {
+        Self {
+            // About 5 seconds and a minute at 400ms slots
+            recheck_slots: 12,
+            maintenance_slots: 150,
+            apply_before_epoch_end: None,
+        }
+    }
+}
+
+// Watch a token account and apply its pending balance as credits arrive, until the process is stopped.
+//
+// The account is subscribed to over the websocket endpoint, and each notification triggers a check
+// of the account fetched over RPC. Errors while checking or applying are reported and retried on the next
+// check, so a flaky RPC node doesn't stop the watcher.
+// Everything else runs on `schedule`, timed by a slot subscription (`clock::ClusterClock`):
+// the fee payer of the applies, the owner unless set in `ctx.payers`, is kept funded by `top_up` if given,
+// transactions in the context's retry queue are retried, re-signed by the owner once expired, and at each new epoch
+// stale proof cache and finished retry queue entries are deleted.
+pub fn watch_token_account(
+    ctx: &mut FlowContext<'_>,
+    ws_url: &str,
+    token_account: &Pubkey,
+    owner: &Keypair,
+    policy: WatchPolicy,
+    schedule: WatchSchedule,
+    top_up: Option<TopUp>,
+) -> Result<(), Box<dyn Error>> {
+    let clock = ClusterClock::subscribe(ctx.client, ws_url)?;
+    let mut watcher = Watcher {
+        token_account: *token_account,
+        elgamal_keypair: ctx
+            .key_derivation
+            .account_keys(ctx.client, owner, token_account)?
+            .0,
+        policy,
+        schedule,
+        top_up,
+        last_apply: None,
+        last_credits: 0,
+        recheck: SlotInterval::new(schedule.recheck_slots),
+        maintenance: SlotInterval::new(schedule.maintenance_slots),
+        epoch: None,
+    };
+
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(ctx.client.commitment()),
+        ..RpcAccountInfoConfig::default()
+    };
+    loop {
+        let (_subscription, receiver) =
+            PubsubClient::account_subscribe(ws_url, token_account, Some(config.clone()))?;
+
+        // Check on start, on each change of the account, and every `recheck_slots`
+        let mut notified = true;
+        loop {
+            watcher.run_scheduled(ctx, owner, clock.now(), notified);
+            notified = match receiver.recv_timeout(TICK_INTERVAL) {
+                Ok(_) => true,
+                Err(error) if error.is_timeout() => false,
+                Err(_) => break,
+            };
+        }
+
+        eprintln!(
+            "\nSubscription to {} closed, subscribing again",
+            token_account
+        );
+        thread::sleep(RESUBSCRIBE_PAUSE);
+    }
+}
+
+struct Watcher {
+    token_account: Pubkey,
+    elgamal_keypair: ElGamalKeypair,
+    policy: WatchPolicy,
+    schedule: WatchSchedule,
+    top_up: Option<TopUp>,
+    last_apply: Option<Instant>,
+    // Credit counter seen by the last check, to report new credits once
+    last_credits: u64,
+    recheck: SlotInterval,
+    maintenance: SlotInterval,
+    // Epoch of the last garbage collection
+    epoch: Option<u64>,
+}
+
+impl Watcher {
+    fn run_scheduled(
+        &mut self,
+        ctx: &mut FlowContext<'_>,
+        owner: &Keypair,
+        now: ClusterTime,
+        notified: bool,
+    ) {
+        if self.maintenance.due(now.slot) {
+            if let Some(top_up) = &self.top_up {
+                let fee_payer = ctx.payers.fee_payer(&owner.pubkey());
+                if let Err(error) = top_up.ensure_funded(ctx, &fee_payer) {
+                    eprintln!("\nCould not top up {}: {}", fee_payer, error);
+                }
+            }
+            if let Err(error) = ctx.retry_queued(&[owner]) {
+                eprintln!("\nCould not retry queued transactions: {}", error);
+            }
+        }
+        if self.epoch != Some(now.epoch) {
+            self.epoch = Some(now.epoch);
+            if let Err(error) = collect_garbage(ctx) {
+                eprintln!("\nCould not clean up: {}", error);
+            }
+        }
+        let end_of_epoch = self
+            .schedule
+            .apply_before_epoch_end
+            .is_some_and(|slots| now.slots_until_epoch_end() <= slots);
+        // A notification always checks
+        let recheck = self.recheck.due(now.slot);
+        if recheck || notified {
+            if let Err(error) = self.check(ctx, owner, end_of_epoch) {
+                eprintln!("\nCould not check {}: {}", self.token_account, error);
+            }
+        }
+    }
+
+    fn check(
+        &mut self,
+        ctx: &mut FlowContext<'_>,
+        owner: &Keypair,
+        end_of_epoch: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let state = ConfidentialAccountState::fetch(ctx.client, &self.token_account)?;
+
+        let credits = state.pending_balance_credit_counter;
+        if credits == 0 {
+            self.last_credits = 0;
+            return Ok(());
+        }
+
+        let pending = state
+            .decrypt_pending_balance(&self.elgamal_keypair)?
+            .ok_or("Could not decrypt the pending balance")?;
+        if credits != self.last_credits {
+            self.last_credits = credits;
+            ctx.emit(FlowEvent::PendingBalanceCredited {
+                token_account: self.token_account,
+                credits,
+                pending,
+            });
+        }
+        // Checked again on every notification and recheck, so credits held back by the thresholds
+        // or the rate limit are applied as soon as they qualify
+        self.apply(ctx, owner, &state, pending, credits, end_of_epoch)
+    }
+
+    fn apply(
+        &mut self,
+        ctx: &mut FlowContext<'_>,
+        owner: &Keypair,
+        state: &ConfidentialAccountState,
+        pending: u64,
+        credits: u64,
+        end_of_epoch: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let maximum_credits = state.maximum_pending_balance_credit_counter;
+        let policy = if end_of_epoch {
+            WatchPolicy {
+                min_pending: 0,
+                ..self.policy
+            }
+        } else {
+            self.policy
+        };
+        if !policy.should_apply(pending, credits, maximum_credits) {
+            return Ok(());
+        }
+        if self
+            .last_apply
+            .is_some_and(|last_apply| last_apply.elapsed() < self.policy.min_interval)
+        {
+            return Ok(());
+        }
+
+        self.last_apply = Some(Instant::now());
+        apply_pending::apply_pending_balance(
+            ctx,
+            &self.token_account,
+            owner,
+            apply_pending::DEFAULT_MAX_ROUNDS,
+            apply_pending::DEFAULT_ROUND_PAUSE,
+        )?;
+        Ok(())
+    }
+}
+
+// Delete proof cache entries too old to match any account state and retry queue entries that are done with
+fn collect_garbage(ctx: &FlowContext<'_>) -> Result<(), Box<dyn Error>> {
+    if let Some(proof_cache) = &ctx.proof_cache {
+        proof_cache.prune()?;
+    }
+    if let Some(retry_queue) = &ctx.retry_queue {
+        retry_queue.purge_finished(now().saturating_sub(RETRY_QUEUE_RETENTION_SECS))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use super::FlowContext;
+use crate::{
+    account_state::ConfidentialAccountState,
+    events::{FlowEvent, ProofKind},
+    pausable::check_not_paused,
+    policy::PolicyAction,
+    proof_cache::ProofKey,
+    rent::RentCalculator,
+    verify::BalanceChange,
+};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction::create_account,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::confidential_transfer::{account_info::WithdrawAccountInfo, instruction::withdraw},
+    proof::ProofLocation,
+    solana_zk_token_sdk::{
+        zk_token_proof_instruction::{ContextStateInfo, ProofInstruction, WithdrawProofContext},
+        zk_token_proof_program,
+    },
+};
+use spl_token_client::{
+    client::{SendTransaction, SimulateTransaction},
+    token::Token,
+};
+use std::error::Error;
+
+// Number of steps reported by `withdraw_tokens`, for sizing the progress of a larger flow
+pub const STEPS: usize = 3;
+
+// The "withdraw" instruction is used to convert the "available" confidential balance back to the non-confidential balance of the token account.
+// This requires creating a "withdraw proof" account
+pub async fn withdraw_tokens<T>(
+    ctx: &mut FlowContext<'_>,
+    token: &Token<T>,
+    owner: &Keypair,
+    withdraw_amount: u64,
+    decimals: u8,
+) -> Result<Signature, Box<dyn Error>>
+where
+    T: SendTransaction + SimulateTransaction,
+{
+    ctx.flow = "withdraw";
+    let mint = *token.get_address();
+
+    // Associated token address of the owner
+    let associated_token_address = get_associated_token_address_with_program_id(
+        &owner.pubkey(), // Token account owner
+        &mint,           // Mint
+        &spl_token_2022::id(),
+    );
+
+    // A paused mint rejects the withdraw, so fail before generating the proof and creating the proof account
+    check_not_paused(ctx.client, &mint)?;
+
+    // Refuse withdraws outside the policy before anything is signed
+    ctx.check_policy(&mint, PolicyAction::Withdraw, withdraw_amount)?;
+
+    // The proof account is owned by the ZK Token Proof program, fail before generating the proof if the cluster lacks it
+    ctx.proof_support()?;
+
+    // Balances before the withdraw, when verifying
+    let balances = ctx.snapshot_balances(&associated_token_address, owner)?;
+
+    ctx.start_step("Generating withdraw proof");
+
+    // Get the confidential transfer state of the token account
+    let state = ConfidentialAccountState::fetch(ctx.client, &associated_token_address)?;
+
+    // Confidential Transfer extension data needed to construct a `Withdraw` instruction (available balance,)
+    let withdraw_account_info = WithdrawAccountInfo::new(state.extension());
+
+    // Derive the ElGamal keypair and AES key for the token account
+    let (elgamal_keypair, aes_key) =
+        ctx.key_derivation
+            .account_keys(ctx.client, owner, &associated_token_address)?;
+
+    // Fail with a clear message rather than a proof generation error
+    if !state.can_withdraw(withdraw_amount, &aes_key)? {
+        return Err(format!(
+            "Can't withdraw {} from {}: the available balance is too low, or the pending balance must be applied first",
+            withdraw_amount, associated_token_address
+        )
+        .into());
+    }
+
+    // Create a withdraw proof data, or reuse the proof data of an earlier run that failed against the same balance
+    let proof_key = ProofKey::new("withdraw", &state, withdraw_amount, &[]);
+    let (proof_data, reused) = ctx.cached_proof(owner, &proof_key, || {
+        Ok(withdraw_account_info.generate_proof_data(
+            withdraw_amount,
+            &elgamal_keypair,
+            &aes_key,
+        )?)
+    })?;
+    let proof = ProofKind::Withdraw;
+    ctx.emit(if reused {
+        FlowEvent::ProofReused { proof }
+    } else {
+        FlowEvent::ProofGenerated { proof }
+    });
+    ctx.finish_step();
+
+    ctx.stop_if_interrupted(owner)?;
+    ctx.start_step("Creating and verifying withdraw proof account");
+
+    // The proof account and the withdraw are sent back to back with `--wait processed`
+    ctx.begin_optimistic();
+
+    // Generate address for withdraw proof account
+    let withdraw_proof_context_state_account = ctx.keypairs.keypair("withdraw-proof")?;
+    let withdraw_proof_pubkey = withdraw_proof_context_state_account.pubkey();
+    // Authority for the withdraw proof account (to close the account)
+    let context_state_authority = owner;
+
+    let rent = RentCalculator::fetch(ctx.client)?.proof_account::<WithdrawProofContext>();
+    ctx.report.record_rent(rent.lamports);
+
+    let withdraw_proof_context_state_info = ContextStateInfo {
+        context_state_account: &withdraw_proof_pubkey,
+        context_state_authority: &context_state_authority.pubkey(),
+    };
+
+    // Create the withdraw proof account, then initialize it with proof data,
+    // in one transaction if the proof instruction leaves room for it
+    let mut packer = ctx.packer(&owner.pubkey());
+    packer.push(
+        "Create Withdraw Proof Account",
+        vec![create_account(
+            &ctx.payers.rent_funder(&owner.pubkey()),
+            &withdraw_proof_pubkey,
+            rent.lamports,
+            rent.space as u64,
+            &zk_token_proof_program::id(),
+        )],
+    );
+    packer.push(
+        "Initialize Withdraw Proof Account",
+        vec![ProofInstruction::VerifyWithdraw
+            .encode_verify_proof(Some(withdraw_proof_context_state_info), &proof_data)],
+    );
+
+    ctx.track_proof_account(withdraw_proof_pubkey);
+    ctx.send_packed(
+        &packer,
+        &owner.pubkey(),
+        &[owner, &withdraw_proof_context_state_account],
+    )?;
+    ctx.emit(FlowEvent::ProofAccountCreated {
+        proof: ProofKind::Withdraw,
+        account: withdraw_proof_pubkey,
+    });
+    ctx.emit(FlowEvent::ProofVerified {
+        proof: ProofKind::Withdraw,
+        account: withdraw_proof_pubkey,
+    });
+    ctx.finish_step();
+
+    ctx.stop_if_interrupted(owner)?;
+    ctx.start_step("Withdrawing tokens");
+
+    // Update the decryptable available balance
+    let new_decryptable_available_balance =
+        withdraw_account_info.new_decryptable_available_balance(withdraw_amount, &aes_key)?;
+
+    // The available balance before and after the withdraw
+    ctx.emit(FlowEvent::AvailableBalanceChanged {
+        before: withdraw_account_info
+            .available_balance
+            .decrypt(elgamal_keypair.secret()),
+        after: new_decryptable_available_balance.decrypt(&aes_key),
+    });
+
+    // The proof is pre-verified into a context state account.
+    let proof_location = ProofLocation::ContextStateAccount(&withdraw_proof_pubkey);
+
+    // Create a `Withdraw` instruction
+    let withdraw_instruction = withdraw(
+        &spl_token_2022::id(),
+        &associated_token_address,
+        &mint,
+        withdraw_amount,
+        decimals,
+        new_decryptable_available_balance,
+        &owner.pubkey(),
+        &[&owner.pubkey()],
+        proof_location,
+    )?;
+
+    let transaction_signature = ctx.send(
+        "Withdraw Tokens",
+        &ctx.attach_memo(&withdraw_instruction),
+        &owner.pubkey(),
+        &[owner],
+    )?;
+    ctx.reconcile(owner)?;
+    ctx.record_spend(&transaction_signature, &mint, withdraw_amount);
+    ctx.untrack_proof_accounts(&[withdraw_proof_pubkey]);
+    ctx.forget_proof(&proof_key);
+    ctx.finish_step();
+
+    // The withdraw moves the amount from the available to the public balance
+    ctx.verify_balances(
+        balances,
+        owner,
+        BalanceChange {
+            public: withdraw_amount as i128,
+            available: -(withdraw_amount as i128),
+            ..BalanceChange::default()
+        },
+    )?;
+
+    Ok(transaction_signature)
+}
+
+// Generate the withdraw proof data into the proof cache ahead of the withdraw, e.g. by the scheduler shortly before
+// a withdraw is due. `withdraw_tokens` reuses it as long as the available balance is unchanged.
+// Returns whether the proof data was cached already.
+pub fn prepare_withdraw(
+    ctx: &FlowContext<'_>,
+    mint: &Pubkey,
+    owner: &Keypair,
+    withdraw_amount: u64,
+) -> Result<bool, Box<dyn Error>> {
+    if ctx.proof_cache.is_none() {
+        return Err("Preparing a withdraw needs a proof cache".into());
+    }
+
+    // Associated token address of the owner
+    let associated_token_address = get_associated_token_address_with_program_id(
+        &owner.pubkey(), // Token account owner
+        mint,            // Mint
+        &spl_token_2022::id(),
+    );
+
+    let state = ConfidentialAccountState::fetch(ctx.client, &associated_token_address)?;
+    let withdraw_account_info = WithdrawAccountInfo::new(state.extension());
+    let (elgamal_keypair, aes_key) =
+        ctx.key_derivation
+            .account_keys(ctx.client, owner, &associated_token_address)?;
+    if !state.can_withdraw(withdraw_amount, &aes_key)? {
+        return Err(format!(
+            "Can't withdraw {} from {}: the available balance is too low, or the pending balance must be applied first",
+            withdraw_amount, associated_token_address
+        )
+        .into());
+    }
+
+    // Same key as `withdraw_tokens`
+    let proof_key = ProofKey::new("withdraw", &state, withdraw_amount, &[]);
+    let (_, reused) = ctx.cached_proof(owner, &proof_key, || {
+        Ok(withdraw_account_info.generate_proof_data(
+            withdraw_amount,
+            &elgamal_keypair,
+            &aes_key,
+        )?)
+    })?;
+    Ok(reused)
+}
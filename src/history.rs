@@ -0,0 +1,385 @@
+use crate::ui_amount::UiAmount;
+use chrono::DateTime;
+use serde_json::{json, Value};
+use solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    instruction::CompiledInstruction, native_token::lamports_to_sol, pubkey::Pubkey,
+    signature::Signature,
+};
+use solana_transaction_status::UiTransactionEncoding;
+use spl_token_2022::{
+    extension::confidential_transfer::{
+        instruction::{
+            ApplyPendingBalanceData, ConfidentialTransferInstruction,
+            ConfigureAccountInstructionData, DepositInstructionData, TransferInstructionData,
+            TransferWithSplitProofsInstructionData, WithdrawInstructionData,
+        },
+        DecryptableBalance,
+    },
+    instruction::{decode_instruction_data, decode_instruction_type},
+    solana_zk_token_sdk::encryption::auth_encryption::{AeCiphertext, AeKey},
+};
+use std::{error::Error, fmt, str::FromStr};
+
+// Token instruction tag of the confidential transfer extension's instructions
+const CONFIDENTIAL_TRANSFER_EXTENSION: u8 = 27;
+
+// Columns of the CSV export, in order
+pub const CSV_HEADER: &str = "date,counterparty,direction,amount,fee,signature,memo";
+
+// How a confidential transaction moved tokens, seen from the scanned token account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // Public balance to pending balance
+    Deposit,
+    // Pending balance to available balance
+    Apply,
+    // Confidential transfer to another token account
+    Out,
+    // Confidential transfer from another token account, credited to the pending balance
+    In,
+    // Available balance to public balance
+    Withdraw,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Deposit => "deposit",
+            Direction::Apply => "apply",
+            Direction::Out => "out",
+            Direction::In => "in",
+            Direction::Withdraw => "withdraw",
+        }
+    }
+}
+
+// A confidential transaction of a token account, with its amount decrypted where the owner can
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    // Seconds since the unix epoch, `None` if the node didn't record the block time
+    pub block_time: Option<i64>,
+    pub signature: Signature,
+    pub direction: Direction,
+    // The other token account of a transfer
+    pub counterparty: Option<Pubkey>,
+    // Public for deposits and withdraws. Transfers out and applies are decrypted from the change of the
+    // decryptable available balance, so they are only known once an earlier entry set that balance.
+    // Incoming transfers are encrypted under the recipient's ElGamal key in proofs the transfer doesn't carry,
+    // their amounts show up in the next apply instead.
+    pub amount: Option<u64>,
+    // Lamports paid by the transaction's fee payer
+    pub fee: u64,
+    // Text of the transaction's SPL Memo instructions, joined with "; " if there are several
+    pub memo: Option<String>,
+}
+
+impl HistoryEntry {
+    // Date in RFC 3339, UTC
+    pub fn date(&self) -> Option<String> {
+        self.block_time
+            .and_then(|block_time| DateTime::from_timestamp(block_time, 0))
+            .map(|date| date.to_rfc3339())
+    }
+
+    pub fn to_json(&self, ui_amount: &UiAmount) -> Value {
+        json!({
+            "date": self.date(),
+            "signature": self.signature.to_string(),
+            "direction": self.direction.as_str(),
+            "counterparty": self.counterparty.map(|counterparty| counterparty.to_string()),
+            "amount": self.amount,
+            "amount_ui": self.amount.map(|amount| ui_amount.format(amount)),
+            "fee": self.fee,
+            "memo": self.memo,
+        })
+    }
+
+    // One row under `CSV_HEADER`. Amounts are in UI units and fees in SOL, unknown values are left empty
+    pub fn to_csv(&self, ui_amount: &UiAmount) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.date().unwrap_or_default(),
+            self.counterparty
+                .map(|counterparty| counterparty.to_string())
+                .unwrap_or_default(),
+            self.direction.as_str(),
+            self.amount
+                .map(|amount| ui_amount.format(amount))
+                .unwrap_or_default(),
+            lamports_to_sol(self.fee),
+            self.signature,
+            self.memo.as_deref().map(csv_field).unwrap_or_default()
+        )
+    }
+}
+
+// Memos are free text, quote them when they could break the row
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Output formats of the history
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for HistoryFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(HistoryFormat::Text),
+            "json" => Ok(HistoryFormat::Json),
+            "csv" => Ok(HistoryFormat::Csv),
+            other => Err(format!(
+                "Unknown format {:?}, expected text, json or csv",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for HistoryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryFormat::Text => write!(f, "text"),
+            HistoryFormat::Json => write!(f, "json"),
+            HistoryFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+// Scan the latest `limit` transactions of a token account for confidential transfer instructions, oldest first.
+//
+// The AES key of the token account decrypts the decryptable available balance each instruction writes,
+// and the difference between consecutive balances is the amount moved.
+pub fn scan_history(
+    client: &RpcClient,
+    token_account: &Pubkey,
+    aes_key: &AeKey,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    let mut signatures = client.get_signatures_for_address_with_config(
+        token_account,
+        GetConfirmedSignaturesForAddress2Config {
+            limit: Some(limit),
+            commitment: Some(client.commitment()),
+            ..GetConfirmedSignaturesForAddress2Config::default()
+        },
+    )?;
+    // Newest first from the node, balances are tracked from the oldest
+    signatures.reverse();
+
+    let decrypt = |balance: &DecryptableBalance| -> Option<u64> {
+        AeCiphertext::try_from(*balance).ok()?.decrypt(aes_key)
+    };
+
+    let mut entries = Vec::new();
+    // Decryptable available balance after the previous entry
+    let mut available: Option<u64> = None;
+    for status in signatures {
+        // Failed transactions didn't move anything
+        if status.err.is_some() {
+            continue;
+        }
+        let signature = Signature::from_str(&status.signature)?;
+        let transaction = client.get_transaction_with_config(
+            &signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(client.commitment()),
+                max_supported_transaction_version: Some(0),
+            },
+        )?;
+        let fee = transaction
+            .transaction
+            .meta
+            .as_ref()
+            .map(|meta| meta.fee)
+            .unwrap_or_default();
+        let Some(decoded) = transaction.transaction.transaction.decode() else {
+            continue;
+        };
+        let account_keys = decoded.message.static_account_keys();
+        let memo = transaction_memo(decoded.message.instructions(), account_keys);
+
+        for instruction in decoded.message.instructions() {
+            let program_id = account_keys.get(instruction.program_id_index as usize);
+            if program_id != Some(&spl_token_2022::id()) {
+                continue;
+            }
+            // Accounts of the instruction, `None` past the static keys (address lookup tables aren't resolved)
+            let account = |index: usize| {
+                instruction
+                    .accounts
+                    .get(index)
+                    .and_then(|key_index| account_keys.get(*key_index as usize))
+                    .copied()
+            };
+            if account(0) != Some(*token_account) && account(2) != Some(*token_account) {
+                continue;
+            }
+
+            let Some(history_instruction) = decode_history_instruction(&instruction.data)? else {
+                continue;
+            };
+            let entry = |direction, counterparty, amount| HistoryEntry {
+                block_time: transaction.block_time,
+                signature,
+                direction,
+                counterparty,
+                amount,
+                fee,
+                memo: memo.clone(),
+            };
+            match history_instruction {
+                HistoryInstruction::ConfigureAccount {
+                    decryptable_zero_balance,
+                } if account(0) == Some(*token_account) => {
+                    available = decrypt(&decryptable_zero_balance);
+                }
+                HistoryInstruction::Deposit { amount } if account(0) == Some(*token_account) => {
+                    entries.push(entry(Direction::Deposit, None, Some(amount)));
+                }
+                HistoryInstruction::ApplyPendingBalance {
+                    new_decryptable_available_balance,
+                } if account(0) == Some(*token_account) => {
+                    let after = decrypt(&new_decryptable_available_balance);
+                    let amount = available
+                        .zip(after)
+                        .and_then(|(before, after)| after.checked_sub(before));
+                    entries.push(entry(Direction::Apply, None, amount));
+                    available = after;
+                }
+                HistoryInstruction::Withdraw {
+                    amount,
+                    new_decryptable_available_balance,
+                } if account(0) == Some(*token_account) => {
+                    entries.push(entry(Direction::Withdraw, None, Some(amount)));
+                    available = decrypt(&new_decryptable_available_balance);
+                }
+                // Source and destination are accounts 0 and 2, around the mint
+                HistoryInstruction::Transfer {
+                    new_source_decryptable_available_balance,
+                } => {
+                    if account(0) == Some(*token_account) {
+                        let after = decrypt(&new_source_decryptable_available_balance);
+                        let amount = available
+                            .zip(after)
+                            .and_then(|(before, after)| before.checked_sub(after));
+                        entries.push(entry(Direction::Out, account(2), amount));
+                        available = after;
+                    } else {
+                        entries.push(entry(Direction::In, account(0), None));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(entries)
+}
+
+// Memos of the SPL Memo instructions of a transaction, `None` if it has none
+fn transaction_memo(
+    instructions: &[CompiledInstruction],
+    account_keys: &[Pubkey],
+) -> Option<String> {
+    let memos: Vec<String> = instructions
+        .iter()
+        .filter(|instruction| {
+            account_keys
+                .get(instruction.program_id_index as usize)
+                .is_some_and(|program_id| {
+                    *program_id == spl_memo::id() || *program_id == spl_memo::v1::id()
+                })
+        })
+        .map(|instruction| String::from_utf8_lossy(&instruction.data).into_owned())
+        .collect();
+    (!memos.is_empty()).then(|| memos.join("; "))
+}
+
+// The part of a confidential transfer instruction the history is built from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryInstruction {
+    ConfigureAccount {
+        decryptable_zero_balance: DecryptableBalance,
+    },
+    Deposit {
+        amount: u64,
+    },
+    ApplyPendingBalance {
+        new_decryptable_available_balance: DecryptableBalance,
+    },
+    Withdraw {
+        amount: u64,
+        new_decryptable_available_balance: DecryptableBalance,
+    },
+    // Transfer or TransferWithSplitProofs
+    Transfer {
+        new_source_decryptable_available_balance: DecryptableBalance,
+    },
+}
+
+// Decode the data of a token-2022 instruction, `None` unless it is one of the confidential transfer
+// instructions above. Instruction data comes from arbitrary transactions, so malformed data is an error.
+pub fn decode_history_instruction(
+    data: &[u8],
+) -> Result<Option<HistoryInstruction>, Box<dyn Error>> {
+    let Some((&CONFIDENTIAL_TRANSFER_EXTENSION, data)) = data.split_first() else {
+        return Ok(None);
+    };
+    Ok(Some(match decode_instruction_type(data)? {
+        ConfidentialTransferInstruction::ConfigureAccount => HistoryInstruction::ConfigureAccount {
+            decryptable_zero_balance: decode_instruction_data::<ConfigureAccountInstructionData>(
+                data,
+            )?
+            .decryptable_zero_balance,
+        },
+        ConfidentialTransferInstruction::Deposit => HistoryInstruction::Deposit {
+            amount: decode_instruction_data::<DepositInstructionData>(data)?
+                .amount
+                .into(),
+        },
+        ConfidentialTransferInstruction::ApplyPendingBalance => {
+            HistoryInstruction::ApplyPendingBalance {
+                new_decryptable_available_balance: decode_instruction_data::<
+                    ApplyPendingBalanceData,
+                >(data)?
+                .new_decryptable_available_balance,
+            }
+        }
+        ConfidentialTransferInstruction::Withdraw => {
+            let data = decode_instruction_data::<WithdrawInstructionData>(data)?;
+            HistoryInstruction::Withdraw {
+                amount: data.amount.into(),
+                new_decryptable_available_balance: data.new_decryptable_available_balance,
+            }
+        }
+        ConfidentialTransferInstruction::Transfer => HistoryInstruction::Transfer {
+            new_source_decryptable_available_balance: decode_instruction_data::<
+                TransferInstructionData,
+            >(data)?
+            .new_source_decryptable_available_balance,
+        },
+        ConfidentialTransferInstruction::TransferWithSplitProofs => HistoryInstruction::Transfer {
+            new_source_decryptable_available_balance: decode_instruction_data::<
+                TransferWithSplitProofsInstructionData,
+            >(data)?
+            .new_source_decryptable_available_balance,
+        },
+        _ => return Ok(None),
+    }))
+}
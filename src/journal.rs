@@ -0,0 +1,380 @@
+use rusqlite::{params, params_from_iter, Connection};
+use serde_json::{json, Value};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{
+    error::Error,
+    fmt,
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStatus {
+    // Processed by the leader and not confirmed yet (`--wait processed`)
+    Processed,
+    // Sent but not confirmed within the confirmation timeout, it can still land until its blockhash expires
+    Pending,
+    Confirmed,
+    // Confirmed, then waited on until finalized (`--wait finalized`)
+    Finalized,
+    Failed,
+    // Its blockhash expired without it landing, found by `tx status`, so it never will
+    Expired,
+}
+
+impl TxStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxStatus::Processed => "processed",
+            TxStatus::Pending => "pending",
+            TxStatus::Confirmed => "confirmed",
+            TxStatus::Finalized => "finalized",
+            TxStatus::Failed => "failed",
+            TxStatus::Expired => "expired",
+        }
+    }
+
+    // Whether the transaction is settled one way or the other, a later status check can't improve on it
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            TxStatus::Confirmed | TxStatus::Finalized | TxStatus::Failed | TxStatus::Expired
+        )
+    }
+}
+
+impl FromStr for TxStatus {
+    type Err = Box<dyn Error>;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "processed" => Ok(TxStatus::Processed),
+            "pending" => Ok(TxStatus::Pending),
+            "confirmed" => Ok(TxStatus::Confirmed),
+            "finalized" => Ok(TxStatus::Finalized),
+            "failed" => Ok(TxStatus::Failed),
+            "expired" => Ok(TxStatus::Expired),
+            other => Err(format!("Unknown transaction status {:?}", other).into()),
+        }
+    }
+}
+
+// A sent transaction as recorded in the journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    // Seconds since the unix epoch
+    pub recorded_at: u64,
+    // Missing when the transaction failed before it could be signed
+    pub signature: Option<Signature>,
+    pub flow: String,
+    pub label: String,
+    pub slot: Option<u64>,
+    pub status: TxStatus,
+    pub error: Option<String>,
+    pub fee: Option<u64>,
+    pub accounts: Vec<Pubkey>,
+    // Block height past which the transaction can no longer land, missing when it was never signed
+    pub last_valid_block_height: Option<u64>,
+}
+
+impl JournalEntry {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "recorded_at": self.recorded_at,
+            "signature": self.signature.map(|signature| signature.to_string()),
+            "flow": self.flow,
+            "label": self.label,
+            "slot": self.slot,
+            "status": self.status.as_str(),
+            "error": self.error,
+            "fee": self.fee,
+            "accounts": self.accounts.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+            "last_valid_block_height": self.last_valid_block_height,
+        })
+    }
+
+    // An entry as written by `to_json`, e.g. read back from a snapshot
+    pub fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let string = |field: &str| -> Result<Option<String>, Box<dyn Error>> {
+            match value.get(field) {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::String(string)) => Ok(Some(string.clone())),
+                Some(_) => Err(format!("Journal entry field {} must be a string", field).into()),
+            }
+        };
+        let number = |field: &str| -> Result<Option<u64>, Box<dyn Error>> {
+            match value.get(field) {
+                None | Some(Value::Null) => Ok(None),
+                Some(number) => Ok(Some(
+                    number
+                        .as_u64()
+                        .ok_or(format!("Journal entry field {} must be a number", field))?,
+                )),
+            }
+        };
+        let accounts = match value.get("accounts") {
+            Some(Value::Array(accounts)) => accounts
+                .iter()
+                .map(|account| {
+                    account
+                        .as_str()
+                        .ok_or("Journal entry accounts must be addresses")?
+                        .parse::<Pubkey>()
+                        .map_err(|error| error.into())
+                })
+                .collect::<Result<_, Box<dyn Error>>>()?,
+            _ => return Err("Journal entry accounts must be a list of addresses".into()),
+        };
+
+        Ok(Self {
+            recorded_at: number("recorded_at")?.ok_or("Journal entry without recorded_at")?,
+            signature: string("signature")?
+                .map(|signature| signature.parse())
+                .transpose()?,
+            flow: string("flow")?.unwrap_or_default(),
+            label: string("label")?.unwrap_or_default(),
+            slot: number("slot")?,
+            status: string("status")?
+                .ok_or("Journal entry without status")?
+                .parse()?,
+            error: string("error")?,
+            fee: number("fee")?,
+            accounts,
+            last_valid_block_height: number("last_valid_block_height")?,
+        })
+    }
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} [{}] {}: {}",
+            self.recorded_at,
+            self.flow,
+            self.label,
+            self.status.as_str()
+        )?;
+        match &self.signature {
+            Some(signature) => writeln!(f, "  Signature: {}", signature)?,
+            None => writeln!(f, "  Signature: -")?,
+        }
+        if let Some(slot) = self.slot {
+            writeln!(f, "  Slot:      {}", slot)?;
+        }
+        if let Some(fee) = self.fee {
+            writeln!(f, "  Fee:       {} lamports", fee)?;
+        }
+        if let Some(last_valid_block_height) = self.last_valid_block_height {
+            writeln!(f, "  Valid to:  block {}", last_valid_block_height)?;
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "  Error:     {}", error)?;
+        }
+        write!(f, "  Accounts:  {}", self.accounts.len())
+    }
+}
+
+// Filters for reading the journal back, newest entries first
+#[derive(Debug, Clone, Default)]
+pub struct JournalQuery {
+    pub flow: Option<String>,
+    pub status: Option<TxStatus>,
+    pub account: Option<Pubkey>,
+    pub signature: Option<Signature>,
+    pub limit: Option<usize>,
+}
+
+// Local SQLite audit trail of every transaction sent by the flows, kept across runs
+pub struct Journal {
+    connection: Connection,
+}
+
+impl Journal {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS transactions (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                recorded_at INTEGER NOT NULL,
+                signature   TEXT,
+                flow        TEXT NOT NULL,
+                label       TEXT NOT NULL,
+                slot        INTEGER,
+                status      TEXT NOT NULL,
+                error       TEXT,
+                fee         INTEGER,
+                accounts    TEXT NOT NULL,
+                last_valid_block_height INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS transactions_signature ON transactions (signature);
+            CREATE TABLE IF NOT EXISTS spends (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                recorded_at INTEGER NOT NULL,
+                signature   TEXT NOT NULL,
+                flow        TEXT NOT NULL,
+                mint        TEXT NOT NULL,
+                amount      INTEGER NOT NULL
+            );",
+        )?;
+        // Journals written before expiry tracking lack the column
+        let has_last_valid_block_height = connection
+            .prepare("SELECT 1 FROM pragma_table_info('transactions') WHERE name = 'last_valid_block_height'")?
+            .exists([])?;
+        if !has_last_valid_block_height {
+            connection.execute(
+                "ALTER TABLE transactions ADD COLUMN last_valid_block_height INTEGER",
+                [],
+            )?;
+        }
+        Ok(Self { connection })
+    }
+
+    pub fn record(&self, entry: &JournalEntry) -> Result<(), Box<dyn Error>> {
+        let accounts: Vec<String> = entry.accounts.iter().map(Pubkey::to_string).collect();
+        self.connection.execute(
+            "INSERT INTO transactions (recorded_at, signature, flow, label, slot, status, error, fee, accounts,
+                                       last_valid_block_height)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                entry.recorded_at,
+                entry.signature.map(|signature| signature.to_string()),
+                entry.flow,
+                entry.label,
+                entry.slot,
+                entry.status.as_str(),
+                entry.error,
+                entry.fee,
+                accounts.join(","),
+                entry.last_valid_block_height,
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Update the status of a journaled transaction, e.g. once a processed transaction is confirmed or dropped
+    pub fn update_status(
+        &self,
+        signature: &Signature,
+        status: TxStatus,
+        error: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "UPDATE transactions SET status = ?1, error = ?2 WHERE signature = ?3",
+            params![status.as_str(), error, signature.to_string()],
+        )?;
+        Ok(())
+    }
+
+    // Amount of a mint moved out of a confidential balance by a confirmed transfer or withdraw, for the policy's daily cap
+    pub fn record_spend(
+        &self,
+        signature: &Signature,
+        flow: &str,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "INSERT INTO spends (recorded_at, signature, flow, mint, amount) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![now(), signature.to_string(), flow, mint.to_string(), amount],
+        )?;
+        Ok(())
+    }
+
+    // Total amount of a mint spent since `since` (seconds since the unix epoch)
+    pub fn spent_since(&self, mint: &Pubkey, since: u64) -> Result<u64, Box<dyn Error>> {
+        let spent: Option<u64> = self.connection.query_row(
+            "SELECT SUM(amount) FROM spends WHERE mint = ?1 AND recorded_at >= ?2",
+            params![mint.to_string(), since],
+            |row| row.get(0),
+        )?;
+        Ok(spent.unwrap_or_default())
+    }
+
+    pub fn entries(&self, query: &JournalQuery) -> Result<Vec<JournalEntry>, Box<dyn Error>> {
+        let mut sql = String::from(
+            "SELECT recorded_at, signature, flow, label, slot, status, error, fee, accounts,
+                    last_valid_block_height
+             FROM transactions WHERE 1 = 1",
+        );
+        let mut values: Vec<String> = Vec::new();
+        if let Some(flow) = &query.flow {
+            values.push(flow.clone());
+            sql.push_str(&format!(" AND flow = ?{}", values.len()));
+        }
+        if let Some(status) = query.status {
+            values.push(status.as_str().to_string());
+            sql.push_str(&format!(" AND status = ?{}", values.len()));
+        }
+        if let Some(account) = query.account {
+            values.push(format!("%{}%", account));
+            sql.push_str(&format!(" AND accounts LIKE ?{}", values.len()));
+        }
+        if let Some(signature) = query.signature {
+            values.push(signature.to_string());
+            sql.push_str(&format!(" AND signature = ?{}", values.len()));
+        }
+        sql.push_str(" ORDER BY id DESC");
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let mut statement = self.connection.prepare(&sql)?;
+        let rows = statement.query_map(params_from_iter(values.iter()), |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<u64>>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, Option<u64>>(7)?,
+                row.get::<_, String>(8)?,
+                row.get::<_, Option<u64>>(9)?,
+            ))
+        })?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (
+                recorded_at,
+                signature,
+                flow,
+                label,
+                slot,
+                status,
+                error,
+                fee,
+                accounts,
+                last_valid_block_height,
+            ) = row?;
+            entries.push(JournalEntry {
+                recorded_at,
+                signature: signature.map(|signature| signature.parse()).transpose()?,
+                flow,
+                label,
+                slot,
+                status: status.parse()?,
+                error,
+                fee,
+                accounts: accounts
+                    .split(',')
+                    .filter(|account| !account.is_empty())
+                    .map(Pubkey::from_str)
+                    .collect::<Result<_, _>>()?,
+                last_valid_block_height,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+// Current time in seconds since the unix epoch, for `JournalEntry::recorded_at`
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
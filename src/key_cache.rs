@@ -0,0 +1,244 @@
+use crate::{
+    account_state::ConfidentialAccountState,
+    journal,
+    key_derivation::KeyDerivation,
+    mint::MintSelector,
+    read_keypair,
+    snapshot::{decrypt_json, encrypt_json, EncryptionKeys},
+};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::solana_zk_token_sdk::zk_token_elgamal::pod::ElGamalPubkey;
+use std::{error::Error, fs, path::Path};
+
+// Version of the key cache format, caches of other versions are refused
+pub const KEY_CACHE_VERSION: u64 = 1;
+
+// Encryption keys of one token account, with the names it was exported under
+#[derive(Debug)]
+pub struct CachedKeys {
+    // Name of the owner's .env keypair on the exporting machine
+    pub owner: String,
+    pub owner_address: Pubkey,
+    // Name of the mint's .env keypair, `None` if the mint was selected by address
+    pub mint_name: Option<String>,
+    pub mint: Pubkey,
+    pub keys: EncryptionKeys,
+}
+
+impl CachedKeys {
+    // Fail if the token account was configured with other keys since the export
+    pub fn check(&self, state: &ConfidentialAccountState) -> Result<(), Box<dyn Error>> {
+        if state.elgamal_pubkey != ElGamalPubkey::from(*self.keys.elgamal_keypair.pubkey()) {
+            return Err(format!(
+                "The cached keys of token account {} don't match its ElGamal pubkey, export them again",
+                self.keys.token_account
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Result<Value, Box<dyn Error>> {
+        Ok(json!({
+            "owner": self.owner,
+            "owner_address": self.owner_address.to_string(),
+            "mint_name": self.mint_name,
+            "mint": self.mint.to_string(),
+            "keys": self.keys.to_json()?,
+        }))
+    }
+
+    fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let address = |field: &str| -> Result<Pubkey, Box<dyn Error>> {
+            Ok(value[field]
+                .as_str()
+                .ok_or(format!("Cached keys without {}", field))?
+                .parse()?)
+        };
+        Ok(Self {
+            owner: value["owner"]
+                .as_str()
+                .ok_or("Cached keys without an owner")?
+                .to_string(),
+            owner_address: address("owner_address")?,
+            mint_name: value["mint_name"].as_str().map(str::to_string),
+            mint: address("mint")?,
+            keys: EncryptionKeys::from_json(&value["keys"])?,
+        })
+    }
+}
+
+// ElGamal keypairs and AES keys of token accounts, exported with `snapshot export-keys` and stored encrypted under
+// a passphrase like snapshot secrets. They decrypt balances and history on a machine holding none of the owners'
+// keypairs, and can't sign for the accounts.
+#[derive(Debug, Default)]
+pub struct KeyCache {
+    pub created_at: u64,
+    pub accounts: Vec<CachedKeys>,
+}
+
+impl KeyCache {
+    // Keys of the token accounts of the `owners` .env keypairs for the mint, under the derivation each account was
+    // configured with. Owners without a keypair or a configured token account fail the export.
+    pub fn export(
+        client: &RpcClient,
+        owners: &[String],
+        mint: &MintSelector,
+        derivation: &KeyDerivation,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mint_address = mint.pubkey()?;
+        let mut accounts = Vec::new();
+        for name in owners {
+            let owner = read_keypair(name)?.ok_or(format!("No keypair named {} in .env", name))?;
+            let token_account = get_associated_token_address_with_program_id(
+                &owner.pubkey(), // Token account owner
+                &mint_address,   // Mint
+                &spl_token_2022::id(),
+            );
+            let state = ConfidentialAccountState::fetch(client, &token_account)?;
+            let derivation = derivation.resolve(&owner, &token_account, &state.elgamal_pubkey)?;
+            accounts.push(CachedKeys {
+                owner: name.clone(),
+                owner_address: owner.pubkey(),
+                mint_name: mint.keypair_name(),
+                mint: mint_address,
+                keys: EncryptionKeys::derive(&owner, &token_account, &derivation)?,
+            });
+        }
+        Ok(Self {
+            created_at: journal::now(),
+            accounts,
+        })
+    }
+
+    // Read and decrypt a key cache file
+    pub fn load<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+        Self::from_json(&serde_json::from_str(&contents)?, passphrase)
+    }
+
+    // Keys of the token account of `owner` for the mint, selected by the names they were exported under
+    // or by mint address
+    pub fn find(&self, owner: &str, mint: &MintSelector) -> Result<&CachedKeys, Box<dyn Error>> {
+        self.accounts
+            .iter()
+            .find(|cached| {
+                cached.owner == owner
+                    && match mint {
+                        MintSelector::Address(address) => cached.mint == *address,
+                        _ => cached.mint_name == mint.keypair_name(),
+                    }
+            })
+            .ok_or_else(|| format!("No cached keys for {} and mint {}", owner, mint).into())
+    }
+
+    // The accounts are encrypted together, so the file reveals nothing but its version and age
+    pub fn to_json(&self, passphrase: &str) -> Result<Value, Box<dyn Error>> {
+        let accounts = self
+            .accounts
+            .iter()
+            .map(CachedKeys::to_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(json!({
+            "version": KEY_CACHE_VERSION,
+            "created_at": self.created_at,
+            "secrets": encrypt_json(&json!({ "accounts": accounts }), passphrase)?,
+        }))
+    }
+
+    pub fn from_json(value: &Value, passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        if value["version"].as_u64() != Some(KEY_CACHE_VERSION) {
+            return Err(format!(
+                "Unsupported key cache version {}, expected {}",
+                value["version"], KEY_CACHE_VERSION
+            )
+            .into());
+        }
+        let secrets = decrypt_json(&value["secrets"], passphrase)?;
+        Ok(Self {
+            created_at: value["created_at"].as_u64().unwrap_or_default(),
+            accounts: secrets["accounts"]
+                .as_array()
+                .ok_or("Key cache without accounts")?
+                .iter()
+                .map(CachedKeys::from_json)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+
+    fn key_cache() -> KeyCache {
+        let owner = keypair_from_seed(&[5; 32]).unwrap();
+        let mint = Pubkey::new_from_array([6; 32]);
+        let token_account = get_associated_token_address_with_program_id(
+            &owner.pubkey(),
+            &mint,
+            &spl_token_2022::id(),
+        );
+        KeyCache {
+            created_at: 1_700_000_000,
+            accounts: vec![CachedKeys {
+                owner: "wallet_1".to_string(),
+                owner_address: owner.pubkey(),
+                mint_name: Some("mint".to_string()),
+                mint,
+                keys: EncryptionKeys::derive(&owner, &token_account, &KeyDerivation::V1).unwrap(),
+            }],
+        }
+    }
+
+    #[test]
+    fn key_cache_round_trips_under_the_passphrase() {
+        let key_cache = key_cache();
+        let json = key_cache.to_json("passphrase").unwrap();
+        assert!(json["secrets"]["ciphertext"].is_string());
+        assert!(!json.to_string().contains("wallet_1"));
+
+        let loaded = KeyCache::from_json(&json, "passphrase").unwrap();
+        assert_eq!(loaded.created_at, key_cache.created_at);
+        assert_eq!(loaded.accounts.len(), 1);
+        let (cached, expected) = (&loaded.accounts[0], &key_cache.accounts[0]);
+        assert_eq!(cached.owner, expected.owner);
+        assert_eq!(cached.owner_address, expected.owner_address);
+        assert_eq!(cached.mint_name, expected.mint_name);
+        assert_eq!(cached.mint, expected.mint);
+        assert_eq!(cached.keys.token_account, expected.keys.token_account);
+        assert!(cached.keys.matches(&expected.keys).unwrap());
+
+        assert!(KeyCache::from_json(&json, "other passphrase").is_err());
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let json = json!({ "version": KEY_CACHE_VERSION + 1, "created_at": 0, "secrets": {} });
+        assert!(KeyCache::from_json(&json, "passphrase").is_err());
+    }
+
+    #[test]
+    fn find_selects_by_owner_and_mint() {
+        let key_cache = key_cache();
+        let mint = key_cache.accounts[0].mint;
+        assert!(key_cache.find("wallet_1", &MintSelector::Default).is_ok());
+        assert!(key_cache
+            .find("wallet_1", &MintSelector::Address(mint))
+            .is_ok());
+
+        assert!(key_cache.find("wallet_2", &MintSelector::Default).is_err());
+        assert!(key_cache
+            .find("wallet_1", &MintSelector::Label("usdc".to_string()))
+            .is_err());
+        assert!(key_cache
+            .find("wallet_1", &MintSelector::Address(Pubkey::new_unique()))
+            .is_err());
+    }
+}
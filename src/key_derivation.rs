@@ -0,0 +1,283 @@
+use crate::account_state::ConfidentialAccountState;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+use spl_token_2022::solana_zk_token_sdk::{
+    encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
+    zk_token_elgamal::pod::ElGamalPubkey,
+};
+use std::{env, error::Error, fmt, str::FromStr};
+
+// Prefix of the v2 seed message, so a signature over it can't be mistaken for one made for another purpose
+pub const DOMAIN_SEPARATOR: &[u8] = b"keypair_utils confidential transfer keys v2";
+
+// How the ElGamal keypair and AES key of a token account are derived from its owner's signature, read from
+// KEY_DERIVATION (v1 or v2) and KEY_DERIVATION_SALT.
+//
+// The zk-token-sdk derives both from the owner's signature of a seed message. v1 signs the token account address,
+// like the spl-token CLI, so the keys can be recovered by any wallet doing the same. v2 signs the address behind
+// a domain separator and followed by an optional salt, so keys for this client can't be derived by another
+// application asking the wallet to sign the address, and a salt keeps them apart from other v2 keys of the owner.
+//
+// The derivation only applies to new accounts: keys of a configured account are found by trying every scheme
+// against the ElGamal pubkey on the account, see `account_keys`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum KeyDerivation {
+    #[default]
+    V1,
+    V2 {
+        salt: Option<String>,
+    },
+}
+
+impl KeyDerivation {
+    // The derivation of KEY_DERIVATION and KEY_DERIVATION_SALT, v1 when unset
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        dotenv::dotenv().ok();
+
+        let derivation = match env::var("KEY_DERIVATION") {
+            Ok(value) => value.parse()?,
+            Err(_) => KeyDerivation::V1,
+        };
+        match (derivation, env::var("KEY_DERIVATION_SALT")) {
+            (KeyDerivation::V1, Ok(_)) => {
+                Err("KEY_DERIVATION_SALT needs KEY_DERIVATION=v2, v1 keys can't be salted".into())
+            }
+            (KeyDerivation::V2 { .. }, Ok(salt)) if salt.is_empty() => {
+                Err("KEY_DERIVATION_SALT can't be empty".into())
+            }
+            (KeyDerivation::V2 { .. }, Ok(salt)) => Ok(KeyDerivation::V2 { salt: Some(salt) }),
+            (derivation, Err(_)) => Ok(derivation),
+        }
+    }
+
+    // Message signed by the owner to derive the keys of the token account
+    pub fn seed_message(&self, token_account: &Pubkey) -> Vec<u8> {
+        match self {
+            KeyDerivation::V1 => token_account.to_bytes().to_vec(),
+            // The address has a fixed length, so the salt after it can't be confused with part of it
+            KeyDerivation::V2 { salt } => [
+                DOMAIN_SEPARATOR,
+                token_account.as_ref(),
+                salt.as_deref().unwrap_or_default().as_bytes(),
+            ]
+            .concat(),
+        }
+    }
+
+    pub fn elgamal_keypair(
+        &self,
+        owner: &dyn Signer,
+        token_account: &Pubkey,
+    ) -> Result<ElGamalKeypair, Box<dyn Error>> {
+        ElGamalKeypair::new_from_signer(owner, &self.seed_message(token_account))
+    }
+
+    pub fn aes_key(
+        &self,
+        owner: &dyn Signer,
+        token_account: &Pubkey,
+    ) -> Result<AeKey, Box<dyn Error>> {
+        AeKey::new_from_signer(owner, &self.seed_message(token_account))
+    }
+
+    // Schemes an existing account may have been configured under: this one first, then the unsalted ones
+    pub fn candidates(&self) -> Vec<KeyDerivation> {
+        let mut candidates = vec![self.clone()];
+        for derivation in [KeyDerivation::V2 { salt: None }, KeyDerivation::V1] {
+            if !candidates.contains(&derivation) {
+                candidates.push(derivation);
+            }
+        }
+        candidates
+    }
+
+    // The scheme the ElGamal pubkey configured on the token account was derived under, failing if it's none
+    // of the candidates, e.g. the account was configured with another salt or by another client
+    pub fn resolve(
+        &self,
+        owner: &dyn Signer,
+        token_account: &Pubkey,
+        elgamal_pubkey: &ElGamalPubkey,
+    ) -> Result<KeyDerivation, Box<dyn Error>> {
+        for derivation in self.candidates() {
+            let elgamal_keypair = derivation.elgamal_keypair(owner, token_account)?;
+            if ElGamalPubkey::from(*elgamal_keypair.pubkey()) == *elgamal_pubkey {
+                return Ok(derivation);
+            }
+        }
+        Err(format!(
+            "The ElGamal pubkey {} of token account {} wasn't derived from {} under any key derivation tried ({})",
+            elgamal_pubkey,
+            token_account,
+            owner.pubkey(),
+            self.candidates()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into())
+    }
+
+    // Keys of the token account under the scheme it was configured with. An account that isn't configured
+    // for confidential transfers yet gets keys under this scheme.
+    pub fn account_keys(
+        &self,
+        client: &RpcClient,
+        owner: &dyn Signer,
+        token_account: &Pubkey,
+    ) -> Result<(ElGamalKeypair, AeKey), Box<dyn Error>> {
+        let state = match client
+            .get_account_with_commitment(token_account, client.commitment())?
+            .value
+        {
+            Some(account) => ConfidentialAccountState::unpack(token_account, account.data)?,
+            None => None,
+        };
+        let derivation = match state {
+            Some(state) => self.resolve(owner, token_account, &state.elgamal_pubkey)?,
+            None => self.clone(),
+        };
+        Ok((
+            derivation.elgamal_keypair(owner, token_account)?,
+            derivation.aes_key(owner, token_account)?,
+        ))
+    }
+}
+
+impl FromStr for KeyDerivation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "v1" => Ok(KeyDerivation::V1),
+            "v2" => Ok(KeyDerivation::V2 { salt: None }),
+            other => Err(format!(
+                "Unknown key derivation {:?}, expected v1 or v2",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for KeyDerivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyDerivation::V1 => write!(f, "v1"),
+            KeyDerivation::V2 { salt: None } => write!(f, "v2"),
+            KeyDerivation::V2 { salt: Some(_) } => write!(f, "v2 (salted)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::keypair::{keypair_from_seed, Keypair};
+
+    fn owner() -> Keypair {
+        keypair_from_seed(&[7; 32]).unwrap()
+    }
+
+    fn token_account() -> Pubkey {
+        Pubkey::new_from_array([3; 32])
+    }
+
+    fn elgamal_pubkey(derivation: &KeyDerivation) -> ElGamalPubkey {
+        (*derivation
+            .elgamal_keypair(&owner(), &token_account())
+            .unwrap()
+            .pubkey())
+        .into()
+    }
+
+    #[test]
+    fn v1_signs_the_token_account_address() {
+        let owner = owner();
+        let token_account = token_account();
+        assert_eq!(
+            KeyDerivation::V1.seed_message(&token_account),
+            token_account.to_bytes()
+        );
+        let expected = ElGamalKeypair::new_from_signer(&owner, &token_account.to_bytes()).unwrap();
+        assert_eq!(
+            elgamal_pubkey(&KeyDerivation::V1),
+            (*expected.pubkey()).into()
+        );
+        let expected = AeKey::new_from_signer(&owner, &token_account.to_bytes()).unwrap();
+        let ciphertext = KeyDerivation::V1
+            .aes_key(&owner, &token_account)
+            .unwrap()
+            .encrypt(42);
+        assert_eq!(expected.decrypt(&ciphertext), Some(42));
+    }
+
+    // ElGamal pubkey of `token_account` for `owner` under the seed of the spl-token CLI, the token account address.
+    // Accounts configured under v1 can only be decrypted while this holds
+    #[test]
+    fn v1_keys_match_the_regression_vector() {
+        assert_eq!(
+            elgamal_pubkey(&KeyDerivation::V1).to_string(),
+            "EHPQ2wSPVbgjU2686fQBR6KKG9wSomSQIZJFQFVDYVw="
+        );
+    }
+
+    #[test]
+    fn v2_keys_change_with_the_domain_separator_and_salt() {
+        let token_account = token_account();
+        let unsalted = KeyDerivation::V2 { salt: None };
+        let salted = KeyDerivation::V2 {
+            salt: Some("a".to_string()),
+        };
+        let other_salt = KeyDerivation::V2 {
+            salt: Some("b".to_string()),
+        };
+        assert!(unsalted
+            .seed_message(&token_account)
+            .starts_with(DOMAIN_SEPARATOR));
+
+        let pubkeys = [
+            elgamal_pubkey(&KeyDerivation::V1),
+            elgamal_pubkey(&unsalted),
+            elgamal_pubkey(&salted),
+            elgamal_pubkey(&other_salt),
+        ];
+        for (index, pubkey) in pubkeys.iter().enumerate() {
+            assert!(!pubkeys[index + 1..].contains(pubkey));
+        }
+    }
+
+    #[test]
+    fn resolve_finds_the_scheme_an_account_was_configured_under() {
+        let owner = owner();
+        let token_account = token_account();
+        let v2 = KeyDerivation::V2 { salt: None };
+
+        // An account configured before v2, opened by a client now deriving v2 keys
+        let resolved = v2
+            .resolve(&owner, &token_account, &elgamal_pubkey(&KeyDerivation::V1))
+            .unwrap();
+        assert_eq!(resolved, KeyDerivation::V1);
+
+        let resolved = KeyDerivation::V1
+            .resolve(&owner, &token_account, &elgamal_pubkey(&v2))
+            .unwrap();
+        assert_eq!(resolved, v2);
+
+        let salted = KeyDerivation::V2 {
+            salt: Some("a".to_string()),
+        };
+        let resolved = salted
+            .resolve(&owner, &token_account, &elgamal_pubkey(&salted))
+            .unwrap();
+        assert_eq!(resolved, salted);
+
+        // Another salt isn't among the candidates
+        let other_salt = KeyDerivation::V2 {
+            salt: Some("b".to_string()),
+        };
+        assert!(KeyDerivation::V1
+            .resolve(&owner, &token_account, &elgamal_pubkey(&other_salt))
+            .is_err());
+    }
+}
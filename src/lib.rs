@@ -1,33 +1,144 @@
+pub mod account_diff;
+pub mod account_state;
+pub mod audit;
+pub mod batch;
+pub mod cli;
+pub mod clock;
+pub mod clone;
+pub mod config;
+pub mod contacts;
+pub mod cosign;
+pub mod events;
+pub mod exit_code;
+pub mod explorer;
+pub mod flows;
+pub mod history;
+pub mod journal;
+pub mod key_cache;
+pub mod key_derivation;
+pub mod mint;
+pub mod mint_expectation;
+pub mod mock;
+pub mod packer;
+pub mod pausable;
+pub mod payers;
+pub mod payroll;
+pub mod policy;
+pub mod portfolio;
+pub mod price;
+pub mod progress;
+pub mod proof_cache;
+pub mod proof_program;
+pub mod registry;
+pub mod rent;
+pub mod report;
+pub mod retry_queue;
+pub mod schedule;
+pub mod seed;
+pub mod send;
+pub mod send_strategy;
+pub mod shutdown;
+pub mod snapshot;
+pub mod stake;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+pub mod topup;
+pub mod ui_amount;
+pub mod verify;
+
+use seed::Seed;
 use solana_sdk::signer::keypair::Keypair;
 use std::env;
 use std::error::Error;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+
+// Keypairs can be namespaced as `<kind>:<label>`, e.g. `mint:usdc`.
+// .env keys can't contain ':', so they are stored as `<kind>.<label>`
+pub fn keypair_variable_name(name: &str) -> String {
+    name.replace(':', ".")
+}
 
-// Get or create a keypair from an .env file
-pub fn get_or_create_keypair(variable_name: &str) -> Result<Keypair, Box<dyn Error>> {
+// Read a keypair from an .env file, `None` if it hasn't been created yet
+pub fn read_keypair(name: &str) -> Result<Option<Keypair>, Box<dyn Error>> {
     dotenv::dotenv().ok();
 
-    match env::var(variable_name) {
+    match env::var(keypair_variable_name(name)) {
         Ok(secret_key_string) => {
             // Fallback to JSON format
             let decoded_secret_key: Vec<u8> = serde_json::from_str(&secret_key_string)?;
-            Ok(Keypair::from_bytes(&decoded_secret_key)?)
+            Ok(Some(Keypair::from_bytes(&decoded_secret_key)?))
         }
-        Err(_) => {
-            // Create a new keypair if the environment variable is not found
-            let keypair = Keypair::new();
+        Err(_) => Ok(None),
+    }
+}
+
+// Get or create a keypair from an .env file.
+//
+// Batch workers, the watch daemon and parallel tests can ask for the same new keypair at once, so creating one
+// happens under an exclusive lock on the .env file: the file is read again once locked, and a keypair another
+// process wrote in the meantime is returned instead of appending a second one.
+pub fn get_or_create_keypair(name: &str) -> Result<Keypair, Box<dyn Error>> {
+    if let Some(keypair) = read_keypair(name)? {
+        return Ok(keypair);
+    }
+
+    let mut file = lock_env_file()?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    if let Some(keypair) = find_keypair(&contents, name)? {
+        return Ok(keypair);
+    }
+
+    // Create a new keypair if the environment variable is not found,
+    // derived from the name when a SEED is set
+    let keypair = match Seed::from_env()? {
+        Some(seed) => seed.keypair(name)?,
+        None => Keypair::new(),
+    };
 
-            // Convert secret key to Vec<u8> and then to JSON, append to .env file
-            let secret_key_bytes = Vec::from(keypair.to_bytes());
-            let json_secret_key = serde_json::to_string(&secret_key_bytes)?;
+    append_keypair(&mut file, name, &keypair)?;
+    Ok(keypair)
+}
 
-            // Open .env file, create it if it does not exist
-            let mut file = OpenOptions::new().append(true).create(true).open(".env")?;
+// Append a keypair to the .env file
+pub fn write_keypair(name: &str, keypair: &Keypair) -> Result<(), Box<dyn Error>> {
+    append_keypair(&mut lock_env_file()?, name, keypair)
+}
 
-            writeln!(file, "{}={}", variable_name, json_secret_key)?;
+// The .env file, created if it does not exist, locked until the returned handle is dropped
+fn lock_env_file() -> Result<File, Box<dyn Error>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(".env")?;
+    file.lock()?;
+    Ok(file)
+}
 
-            Ok(keypair)
+// The first keypair of a name in the contents of an .env file, as dotenv keeps the first of duplicate variables
+fn find_keypair(contents: &str, name: &str) -> Result<Option<Keypair>, Box<dyn Error>> {
+    let variable_name = keypair_variable_name(name);
+    for line in contents.lines() {
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim() == variable_name {
+                let decoded_secret_key: Vec<u8> = serde_json::from_str(value.trim())?;
+                return Ok(Some(Keypair::from_bytes(&decoded_secret_key)?));
+            }
         }
     }
+    Ok(None)
+}
+
+fn append_keypair(file: &mut File, name: &str, keypair: &Keypair) -> Result<(), Box<dyn Error>> {
+    // Convert secret key to Vec<u8> and then to JSON, append to .env file
+    let secret_key_bytes = Vec::from(keypair.to_bytes());
+    let json_secret_key = serde_json::to_string(&secret_key_bytes)?;
+
+    // One write per line, so a reader never sees half a keypair
+    let line = format!("{}={}\n", keypair_variable_name(name), json_secret_key);
+    file.write_all(line.as_bytes())?;
+    file.sync_data()?;
+    Ok(())
 }
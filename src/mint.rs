@@ -0,0 +1,125 @@
+use crate::get_or_create_keypair;
+use solana_sdk::{
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use spl_token_2022::state::Account;
+use std::{error::Error, fmt, str::FromStr};
+
+// Name of the keypair of the default mint in .env
+pub const DEFAULT_MINT: &str = "mint";
+
+// Which mint a flow works with, so several mints can be managed side by side.
+// Labelled mints are stored in .env as `mint:<label>` keypairs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MintSelector {
+    // The `mint` keypair
+    #[default]
+    Default,
+    // The `mint:<label>` keypair
+    Label(String),
+    // Any existing mint by address, for flows that don't need to sign for the mint
+    Address(Pubkey),
+}
+
+impl MintSelector {
+    // Name of the stored keypair, `None` when selected by address
+    pub fn keypair_name(&self) -> Option<String> {
+        match self {
+            MintSelector::Default => Some(DEFAULT_MINT.to_string()),
+            MintSelector::Label(label) => Some(format!("{}:{}", DEFAULT_MINT, label)),
+            MintSelector::Address(_) => None,
+        }
+    }
+
+    // Keypair of the mint, only available for stored mints (needed to create the mint account)
+    pub fn keypair(&self) -> Result<Keypair, Box<dyn Error>> {
+        match self.keypair_name() {
+            Some(name) => get_or_create_keypair(&name),
+            None => Err(format!(
+                "Mint {} was selected by address, select a stored mint by label to sign for it",
+                self
+            )
+            .into()),
+        }
+    }
+
+    pub fn pubkey(&self) -> Result<Pubkey, Box<dyn Error>> {
+        match self {
+            MintSelector::Address(address) => Ok(*address),
+            _ => Ok(self.keypair()?.pubkey()),
+        }
+    }
+}
+
+impl FromStr for MintSelector {
+    type Err = String;
+
+    // A base58 address selects a mint directly, anything else is a label
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == DEFAULT_MINT {
+            return Ok(MintSelector::Default);
+        }
+        if let Ok(address) = Pubkey::from_str(value) {
+            return Ok(MintSelector::Address(address));
+        }
+        // Labels become part of an .env key, so they are limited to the characters .env keys allow
+        if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Ok(MintSelector::Label(value.to_string()));
+        }
+        Err(format!(
+            "Invalid mint {:?}, expected a label (letters, digits or '_') or a mint address",
+            value
+        ))
+    }
+}
+
+impl fmt::Display for MintSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MintSelector::Default => write!(f, "{}", DEFAULT_MINT),
+            MintSelector::Label(label) => write!(f, "{}:{}", DEFAULT_MINT, label),
+            MintSelector::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+// Extension type and raw data of a mint extension
+pub type MintExtension<'a> = (u16, &'a [u8]);
+
+// The extensions of a mint, in the order they are stored.
+// Mint extensions start after the mint padded to the size of a token account, and its account type byte.
+pub fn get_mint_extensions(mint_data: &[u8]) -> Result<Vec<MintExtension<'_>>, Box<dyn Error>> {
+    let mut extensions = Vec::new();
+    let mut offset = Account::LEN + 1;
+    while offset + 4 <= mint_data.len() {
+        let entry_type = u16::from_le_bytes(mint_data[offset..offset + 2].try_into()?);
+        let length = u16::from_le_bytes(mint_data[offset + 2..offset + 4].try_into()?) as usize;
+        let value_start = offset + 4;
+
+        // Nothing is written after an uninitialized entry
+        if entry_type == 0 {
+            break;
+        }
+        if value_start + length > mint_data.len() {
+            return Err("Invalid mint extension data".into());
+        }
+        extensions.push((entry_type, &mint_data[value_start..value_start + length]));
+        offset = value_start + length;
+    }
+    Ok(extensions)
+}
+
+// Raw data of a mint extension by its extension type, `None` if the mint doesn't have it.
+// Used for extensions newer than the token-2022 version this crate builds against, whose unpacking
+// fails on extension types it doesn't know.
+pub fn get_mint_extension_data(
+    mint_data: &[u8],
+    extension_type: u16,
+) -> Result<Option<&[u8]>, Box<dyn Error>> {
+    Ok(get_mint_extensions(mint_data)?
+        .into_iter()
+        .find(|(entry_type, _)| *entry_type == extension_type)
+        .map(|(_, data)| data))
+}
@@ -0,0 +1,273 @@
+use crate::{
+    mint::get_mint_extensions,
+    pausable::{get_pausable_config, PAUSABLE_EXTENSION_TYPE},
+    ui_amount::{get_scaled_ui_amount_config, SCALED_UI_AMOUNT_EXTENSION_TYPE},
+};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{program_option::COption, pubkey::Pubkey};
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::ConfidentialTransferMint, mint_close_authority::MintCloseAuthority,
+        permanent_delegate::PermanentDelegate, BaseStateWithExtensions, ExtensionType,
+        StateWithExtensionsOwned,
+    },
+    solana_zk_token_sdk::zk_token_elgamal::pod::ElGamalPubkey,
+    state::Mint,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt, fs,
+    path::Path,
+};
+
+// Name of an extension type, e.g. "ConfidentialTransferMint".
+// Extensions newer than the token-2022 version this crate builds against are named here
+pub fn extension_name(extension_type: u16) -> String {
+    match extension_type {
+        SCALED_UI_AMOUNT_EXTENSION_TYPE => "ScaledUiAmount".to_string(),
+        PAUSABLE_EXTENSION_TYPE => "Pausable".to_string(),
+        other => match ExtensionType::try_from(other) {
+            Ok(extension_type) => format!("{:?}", extension_type),
+            Err(_) => format!("Unknown({})", other),
+        },
+    }
+}
+
+// The parts of a mint's configuration that `--strict` compares: its decimals, extensions, and who holds each
+// of its authorities. An authority is `None` when it was never set or was revoked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MintConfiguration {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    // Extension names, e.g. "ConfidentialTransferMint"
+    pub extensions: BTreeSet<String>,
+    // Authority roles, e.g. "mint_authority", to the address or ElGamal pubkey holding them
+    pub authorities: BTreeMap<String, Option<String>>,
+}
+
+impl MintConfiguration {
+    pub fn fetch(client: &RpcClient, mint: &Pubkey) -> Result<Self, Box<dyn Error>> {
+        Self::from_mint_data(mint, client.get_account_data(mint)?)
+    }
+
+    pub fn from_mint_data(mint: &Pubkey, mint_data: Vec<u8>) -> Result<Self, Box<dyn Error>> {
+        let extensions = get_mint_extensions(&mint_data)?
+            .into_iter()
+            .map(|(extension_type, _)| extension_name(extension_type))
+            .collect();
+
+        let address = |authority: Option<Pubkey>| authority.map(|authority| authority.to_string());
+        let coption = |authority: COption<Pubkey>| address(Option::from(authority));
+        let mut authorities = BTreeMap::new();
+        let pausable = get_pausable_config(&mint_data)?;
+        let scaled_ui_amount = get_scaled_ui_amount_config(&mint_data)?;
+
+        let state = StateWithExtensionsOwned::<Mint>::unpack(mint_data)?;
+        authorities.insert(
+            "mint_authority".to_string(),
+            coption(state.base.mint_authority),
+        );
+        authorities.insert(
+            "freeze_authority".to_string(),
+            coption(state.base.freeze_authority),
+        );
+        if let Ok(extension) = state.get_extension::<ConfidentialTransferMint>() {
+            authorities.insert(
+                "confidential_transfer_authority".to_string(),
+                address(Option::from(extension.authority)),
+            );
+            authorities.insert(
+                "auditor_elgamal_pubkey".to_string(),
+                Option::<ElGamalPubkey>::from(extension.auditor_elgamal_pubkey)
+                    .map(|pubkey| pubkey.to_string()),
+            );
+        }
+        if let Ok(extension) = state.get_extension::<PermanentDelegate>() {
+            authorities.insert(
+                "permanent_delegate".to_string(),
+                address(Option::from(extension.delegate)),
+            );
+        }
+        if let Ok(extension) = state.get_extension::<MintCloseAuthority>() {
+            authorities.insert(
+                "close_authority".to_string(),
+                address(Option::from(extension.close_authority)),
+            );
+        }
+        if let Some(config) = pausable {
+            authorities.insert("pause_authority".to_string(), address(config.authority));
+        }
+        if let Some(config) = scaled_ui_amount {
+            authorities.insert(
+                "scaled_ui_amount_authority".to_string(),
+                address(config.authority),
+            );
+        }
+
+        Ok(Self {
+            mint: *mint,
+            decimals: state.base.decimals,
+            extensions,
+            authorities,
+        })
+    }
+}
+
+// A mint configuration declared in a JSON file, as written by `mint-expectation`:
+//
+// {
+//   "mint": "<address>",
+//   "decimals": 2,
+//   "extensions": ["ConfidentialTransferMint", "Pausable"],
+//   "authorities": { "mint_authority": "<address>", "freeze_authority": null, ... }
+// }
+//
+// "mint" and "decimals" are optional. The extensions and authorities must match exactly: a mint with an extra
+// extension or authority role, or any authority held by someone else, doesn't meet the expectation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MintExpectation {
+    pub mint: Option<Pubkey>,
+    pub decimals: Option<u8>,
+    pub extensions: BTreeSet<String>,
+    pub authorities: BTreeMap<String, Option<String>>,
+}
+
+impl MintExpectation {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+        Self::from_json(&serde_json::from_str(&contents)?).map_err(|error| {
+            format!("Invalid mint expectation {}: {}", path.display(), error).into()
+        })
+    }
+
+    // The expectation a mint meets exactly as it is configured now
+    pub fn of(configuration: &MintConfiguration) -> Self {
+        Self {
+            mint: Some(configuration.mint),
+            decimals: Some(configuration.decimals),
+            extensions: configuration.extensions.clone(),
+            authorities: configuration.authorities.clone(),
+        }
+    }
+
+    // How the mint differs from the expectation, empty if it meets it
+    pub fn differences(&self, actual: &MintConfiguration) -> Vec<String> {
+        let mut differences = Vec::new();
+        if let Some(mint) = self.mint.filter(|mint| *mint != actual.mint) {
+            differences.push(format!("mint is {}, expected {}", actual.mint, mint));
+        }
+        if let Some(decimals) = self
+            .decimals
+            .filter(|decimals| *decimals != actual.decimals)
+        {
+            differences.push(format!(
+                "decimals are {}, expected {}",
+                actual.decimals, decimals
+            ));
+        }
+        for extension in actual.extensions.difference(&self.extensions) {
+            differences.push(format!("unexpected extension {}", extension));
+        }
+        for extension in self.extensions.difference(&actual.extensions) {
+            differences.push(format!("missing extension {}", extension));
+        }
+        let show = |authority: &Option<String>| authority.as_deref().unwrap_or("none").to_string();
+        for (role, authority) in &actual.authorities {
+            match self.authorities.get(role) {
+                None => differences.push(format!(
+                    "unexpected authority {} held by {}",
+                    role,
+                    show(authority)
+                )),
+                Some(expected) if expected != authority => differences.push(format!(
+                    "{} is {}, expected {}",
+                    role,
+                    show(authority),
+                    show(expected)
+                )),
+                Some(_) => {}
+            }
+        }
+        for role in self.authorities.keys() {
+            if !actual.authorities.contains_key(role) {
+                differences.push(format!("missing authority {}", role));
+            }
+        }
+        differences
+    }
+
+    // Fetch the mint and fail with a `MintMismatch` unless it meets the expectation
+    pub fn check(&self, client: &RpcClient, mint: &Pubkey) -> Result<(), Box<dyn Error>> {
+        let differences = self.differences(&MintConfiguration::fetch(client, mint)?);
+        if differences.is_empty() {
+            return Ok(());
+        }
+        Err(Box::new(MintMismatch {
+            mint: *mint,
+            differences,
+        }))
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "mint": self.mint.map(|mint| mint.to_string()),
+            "decimals": self.decimals,
+            "extensions": self.extensions,
+            "authorities": self.authorities,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let extensions = value["extensions"]
+            .as_array()
+            .ok_or("expected a list of extensions")?
+            .iter()
+            .map(|extension| {
+                extension
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or("extensions are names")
+            })
+            .collect::<Result<_, _>>()?;
+        let authorities = value["authorities"]
+            .as_object()
+            .ok_or("expected an object of authorities")?
+            .iter()
+            .map(|(role, authority)| match authority {
+                Value::Null => Ok((role.clone(), None)),
+                Value::String(authority) => Ok((role.clone(), Some(authority.clone()))),
+                _ => Err(format!("authority {} is an address or null", role)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            mint: value["mint"].as_str().map(str::parse).transpose()?,
+            decimals: value["decimals"].as_u64().map(u8::try_from).transpose()?,
+            extensions,
+            authorities,
+        })
+    }
+}
+
+// The mint doesn't meet the expectation of `--strict`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MintMismatch {
+    pub mint: Pubkey,
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for MintMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Mint {} doesn't match the expected configuration: {}",
+            self.mint,
+            self.differences.join(", ")
+        )
+    }
+}
+
+impl Error for MintMismatch {}
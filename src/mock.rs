@@ -0,0 +1,266 @@
+use async_trait::async_trait;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::{json, Value};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+    nonblocking,
+    rpc_client::{RpcClient, RpcClientConfig},
+    rpc_config::RpcProgramAccountsConfig,
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    message::VersionedMessage,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Signature,
+    transaction::VersionedTransaction,
+};
+use spl_token_client::client::{ProgramRpcClient, ProgramRpcClientSendTransaction};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+// Fee charged per signature by the mock, the default of real clusters
+pub const MOCK_LAMPORTS_PER_SIGNATURE: u64 = 5000;
+
+// Slot and block height reported in every response
+const MOCK_SLOT: u64 = 1;
+
+// In-memory stand-in for a validator's JSON RPC, to run flows in tests without one.
+//
+// Accounts are canned with `set_account` and served as is: transactions sent through the mock are recorded
+// and reported as finalized, but never executed, so account data only changes when a test changes it.
+// Clones share the same accounts and recorded transactions.
+//
+// let mock = MockRpc::new();
+// mock.set_account(token_account, account);
+// let client = mock.rpc_client();
+// let mut ctx = FlowContext::new(&client, apply_pending::STEPS);
+// ...
+// assert_eq!(mock.transactions().len(), 1);
+#[derive(Debug, Clone, Default)]
+pub struct MockRpc {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    accounts: HashMap<Pubkey, Account>,
+    transactions: Vec<VersionedTransaction>,
+    // Signatures answered without a transaction, e.g. airdrops
+    signatures: Vec<Signature>,
+}
+
+impl MockRpc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_account(&self, address: Pubkey, account: Account) {
+        self.state.lock().unwrap().accounts.insert(address, account);
+    }
+
+    pub fn remove_account(&self, address: &Pubkey) -> Option<Account> {
+        self.state.lock().unwrap().accounts.remove(address)
+    }
+
+    pub fn account(&self, address: &Pubkey) -> Option<Account> {
+        self.state.lock().unwrap().accounts.get(address).cloned()
+    }
+
+    // Transactions sent so far, in order
+    pub fn transactions(&self) -> Vec<VersionedTransaction> {
+        self.state.lock().unwrap().transactions.clone()
+    }
+
+    // Blocking client, as used by `FlowContext`
+    pub fn rpc_client(&self) -> RpcClient {
+        RpcClient::new_sender(
+            self.clone(),
+            RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+        )
+    }
+
+    pub fn nonblocking_rpc_client(&self) -> nonblocking::rpc_client::RpcClient {
+        nonblocking::rpc_client::RpcClient::new_sender(
+            self.clone(),
+            RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+        )
+    }
+
+    // Program client for spl-token-client's `Token`, as used by the transfer and withdraw flows
+    pub fn program_client(&self) -> ProgramRpcClient<ProgramRpcClientSendTransaction> {
+        ProgramRpcClient::new(
+            Arc::new(self.nonblocking_rpc_client()),
+            ProgramRpcClientSendTransaction,
+        )
+    }
+
+    fn respond(&self, request: RpcRequest, params: &Value) -> Result<Value, String> {
+        let context = json!({ "slot": MOCK_SLOT });
+        let mut state = self.state.lock().unwrap();
+
+        Ok(match request {
+            RpcRequest::GetVersion => json!({ "solana-core": "1.17.10", "feature-set": 0 }),
+            RpcRequest::GetSlot | RpcRequest::GetBlockHeight => json!(MOCK_SLOT),
+            RpcRequest::GetLatestBlockhash => json!({
+                "context": context,
+                "value": {
+                    "blockhash": Hash::default().to_string(),
+                    "lastValidBlockHeight": MOCK_SLOT + 150,
+                },
+            }),
+            RpcRequest::IsBlockhashValid => json!({ "context": context, "value": true }),
+            RpcRequest::GetMinimumBalanceForRentExemption => {
+                let data_len = params[0].as_u64().ok_or("Expected a data length")?;
+                json!(Rent::default().minimum_balance(data_len as usize))
+            }
+            RpcRequest::GetFeeForMessage => {
+                let message = BASE64_STANDARD
+                    .decode(params[0].as_str().ok_or("Expected a base64 message")?)
+                    .map_err(|error| error.to_string())?;
+                let message: VersionedMessage =
+                    bincode::deserialize(&message).map_err(|error| error.to_string())?;
+                let signatures = message.header().num_required_signatures as u64;
+                json!({ "context": context, "value": signatures * MOCK_LAMPORTS_PER_SIGNATURE })
+            }
+            RpcRequest::GetBalance => {
+                let address = parse_pubkey(&params[0])?;
+                let lamports = state
+                    .accounts
+                    .get(&address)
+                    .map(|account| account.lamports)
+                    .unwrap_or_default();
+                json!({ "context": context, "value": lamports })
+            }
+            RpcRequest::GetAccountInfo => {
+                let address = parse_pubkey(&params[0])?;
+                let account = state.accounts.get(&address);
+                json!({ "context": context, "value": account.map(|account| encode(&address, account)) })
+            }
+            RpcRequest::GetMultipleAccounts => {
+                let addresses = params[0].as_array().ok_or("Expected a list of addresses")?;
+                let accounts = addresses
+                    .iter()
+                    .map(|address| {
+                        let address = parse_pubkey(address)?;
+                        Ok(state
+                            .accounts
+                            .get(&address)
+                            .map(|account| encode(&address, account)))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                json!({ "context": context, "value": accounts })
+            }
+            RpcRequest::GetProgramAccounts => {
+                let program_id = parse_pubkey(&params[0])?;
+                let config: RpcProgramAccountsConfig =
+                    serde_json::from_value(params[1].clone()).unwrap_or_default();
+                let filters = config.filters.unwrap_or_default();
+                let accounts: Vec<Value> = state
+                    .accounts
+                    .iter()
+                    .filter(|(_, account)| account.owner == program_id)
+                    .filter(|(_, account)| {
+                        let account = AccountSharedData::from((*account).clone());
+                        filters.iter().all(|filter| filter.allows(&account))
+                    })
+                    .map(|(address, account)| {
+                        json!({ "pubkey": address.to_string(), "account": encode(address, account) })
+                    })
+                    .collect();
+                json!(accounts)
+            }
+            RpcRequest::SendTransaction => {
+                if params[1]["encoding"] != json!("base64") {
+                    return Err("The mock only accepts base64 encoded transactions".to_string());
+                }
+                let transaction = BASE64_STANDARD
+                    .decode(params[0].as_str().ok_or("Expected a base64 transaction")?)
+                    .map_err(|error| error.to_string())?;
+                let transaction: VersionedTransaction =
+                    bincode::deserialize(&transaction).map_err(|error| error.to_string())?;
+                let signature = *transaction
+                    .signatures
+                    .first()
+                    .ok_or("Transaction has no signature")?;
+                state.transactions.push(transaction);
+                json!(signature.to_string())
+            }
+            RpcRequest::RequestAirdrop => {
+                let signature = Signature::new_unique();
+                state.signatures.push(signature);
+                json!(signature.to_string())
+            }
+            // Everything sent is finalized at once
+            RpcRequest::GetSignatureStatuses => {
+                let signatures = params[0]
+                    .as_array()
+                    .ok_or("Expected a list of signatures")?;
+                let statuses: Vec<Value> = signatures
+                    .iter()
+                    .map(|signature| {
+                        let signature = signature
+                            .as_str()
+                            .and_then(|signature| Signature::from_str(signature).ok());
+                        let known = signature.is_some_and(|signature| {
+                            state.signatures.contains(&signature)
+                                || state
+                                    .transactions
+                                    .iter()
+                                    .any(|transaction| transaction.signatures[0] == signature)
+                        });
+                        if known {
+                            json!({
+                                "slot": MOCK_SLOT,
+                                "confirmations": null,
+                                "err": null,
+                                "status": { "Ok": null },
+                                "confirmationStatus": "finalized",
+                            })
+                        } else {
+                            Value::Null
+                        }
+                    })
+                    .collect();
+                json!({ "context": context, "value": statuses })
+            }
+            RpcRequest::GetSignaturesForAddress => json!([]),
+            other => return Err(format!("The mock doesn't serve {}", other)),
+        })
+    }
+}
+
+#[async_trait]
+impl RpcSender for MockRpc {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        self.respond(request, &params)
+            .map_err(|error| ClientError::from(ClientErrorKind::Custom(error)))
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        "mock".to_string()
+    }
+}
+
+fn parse_pubkey(value: &Value) -> Result<Pubkey, String> {
+    value
+        .as_str()
+        .and_then(|address| Pubkey::from_str(address).ok())
+        .ok_or_else(|| format!("Expected an address, got {}", value))
+}
+
+fn encode(address: &Pubkey, account: &Account) -> UiAccount {
+    UiAccount::encode(address, account, UiAccountEncoding::Base64, None, None)
+}
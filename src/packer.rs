@@ -0,0 +1,275 @@
+use solana_sdk::{
+    instruction::Instruction, message::Message, packet::PACKET_DATA_SIZE, pubkey::Pubkey,
+    signature::Signature,
+};
+use std::{error::Error, mem::size_of};
+
+// Size of a signed transaction of the instructions: the signature count (compact-u16, 1 byte below 128)
+// and signatures, followed by the message
+pub fn transaction_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    1 + message.header.num_required_signatures as usize * size_of::<Signature>()
+        + message.serialize().len()
+}
+
+// Instructions that must land in the same transaction, labeled for the journal
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionGroup {
+    pub label: String,
+    pub instructions: Vec<Instruction>,
+}
+
+// A transaction of one or more consecutive groups
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedTransaction {
+    pub labels: Vec<String>,
+    pub instructions: Vec<Instruction>,
+    // The prefix had no room in front of the instructions and was left out
+    pub prefix_dropped: bool,
+}
+
+impl PackedTransaction {
+    pub fn label(&self) -> String {
+        self.labels.join(" + ")
+    }
+}
+
+// Packs groups of instructions into as few transactions as fit in a packet, keeping their order, so an instruction
+// only runs after the instructions pushed before it. Signatures count towards the size, so a group needing
+// an extra signer, like a new account, takes more room.
+//
+// Filling each transaction before starting the next gives the fewest transactions for groups in a fixed order.
+#[derive(Debug, Clone)]
+pub struct TransactionPacker {
+    payer: Pubkey,
+    // Put in front of every transaction with room for it, e.g. a heap frame request
+    prefix: Vec<Instruction>,
+    // Appended to every transaction when it is sent, e.g. the tip of a send strategy, only counted here
+    reserved: Vec<Instruction>,
+    groups: Vec<InstructionGroup>,
+}
+
+impl TransactionPacker {
+    pub fn new(payer: Pubkey) -> Self {
+        Self {
+            payer,
+            prefix: Vec::new(),
+            reserved: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: Vec<Instruction>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    pub fn with_reserved(mut self, reserved: Vec<Instruction>) -> Self {
+        self.reserved = reserved;
+        self
+    }
+
+    pub fn push(&mut self, label: &str, instructions: Vec<Instruction>) {
+        self.groups.push(InstructionGroup {
+            label: label.to_string(),
+            instructions,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    // Split the groups into transactions. A group too large to share a transaction with the prefix is sent alone
+    // without it, marked `prefix_dropped`, a group too large even alone fails the packing.
+    pub fn pack(&self) -> Result<Vec<PackedTransaction>, Box<dyn Error>> {
+        let mut transactions = Vec::new();
+        let mut current: Vec<&InstructionGroup> = Vec::new();
+        for group in &self.groups {
+            let mut candidate = current.clone();
+            candidate.push(group);
+            if self.fits(&candidate, true) {
+                current = candidate;
+                continue;
+            }
+            if !current.is_empty() {
+                transactions.push(self.transaction(&current, true));
+            }
+            current = vec![group];
+            if self.fits(&current, true) {
+                continue;
+            }
+            if !self.fits(&current, false) {
+                return Err(format!(
+                    "{} takes {} bytes, more than a transaction can hold ({} bytes)",
+                    group.label,
+                    self.size(&current, false),
+                    PACKET_DATA_SIZE
+                )
+                .into());
+            }
+            transactions.push(self.transaction(&current, false));
+            current = Vec::new();
+        }
+        if !current.is_empty() {
+            transactions.push(self.transaction(&current, true));
+        }
+        Ok(transactions)
+    }
+
+    fn instructions(&self, groups: &[&InstructionGroup], prefix: bool) -> Vec<Instruction> {
+        let prefix = if prefix { &self.prefix[..] } else { &[] };
+        prefix
+            .iter()
+            .chain(groups.iter().flat_map(|group| &group.instructions))
+            .cloned()
+            .collect()
+    }
+
+    fn size(&self, groups: &[&InstructionGroup], prefix: bool) -> usize {
+        let mut instructions = self.instructions(groups, prefix);
+        instructions.extend(self.reserved.iter().cloned());
+        transaction_size(&instructions, &self.payer)
+    }
+
+    fn fits(&self, groups: &[&InstructionGroup], prefix: bool) -> bool {
+        self.size(groups, prefix) <= PACKET_DATA_SIZE
+    }
+
+    fn transaction(&self, groups: &[&InstructionGroup], prefix: bool) -> PackedTransaction {
+        PackedTransaction {
+            labels: groups.iter().map(|group| group.label.clone()).collect(),
+            instructions: self.instructions(groups, prefix),
+            prefix_dropped: !prefix && !self.prefix.is_empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+
+    // An instruction with `len` bytes of data and no accounts
+    fn instruction(len: usize, tag: u8) -> Instruction {
+        Instruction::new_with_bytes(Pubkey::new_from_array([9; 32]), &vec![tag; len], vec![])
+    }
+
+    fn packer() -> TransactionPacker {
+        TransactionPacker::new(Pubkey::new_unique())
+    }
+
+    fn push(packer: &mut TransactionPacker, len: usize, count: u8) {
+        for tag in 0..count {
+            packer.push(&format!("group {}", tag), vec![instruction(len, tag)]);
+        }
+    }
+
+    fn tags(transactions: &[PackedTransaction]) -> Vec<u8> {
+        transactions
+            .iter()
+            .flat_map(|transaction| &transaction.instructions)
+            .filter(|instruction| instruction.program_id == Pubkey::new_from_array([9; 32]))
+            .map(|instruction| instruction.data[0])
+            .collect()
+    }
+
+    #[test]
+    fn small_groups_share_one_transaction() {
+        let mut packer = packer();
+        push(&mut packer, 10, 3);
+        let transactions = packer.pack().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].label(), "group 0 + group 1 + group 2");
+        assert_eq!(tags(&transactions), vec![0, 1, 2]);
+        assert!(!transactions[0].prefix_dropped);
+    }
+
+    #[test]
+    fn groups_are_split_at_the_packet_size_in_order() {
+        let mut packer = packer();
+        // Two of these fit in a transaction, three don't
+        push(&mut packer, 500, 5);
+        let transactions = packer.pack().unwrap();
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|transaction| transaction.labels.len())
+                .collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(tags(&transactions), vec![0, 1, 2, 3, 4]);
+        for transaction in &transactions {
+            assert!(transaction_size(&transaction.instructions, &packer.payer) <= PACKET_DATA_SIZE);
+        }
+    }
+
+    #[test]
+    fn reserved_instructions_take_room_without_being_packed() {
+        let mut packer = packer().with_reserved(vec![instruction(100, 0xff)]);
+        push(&mut packer, 500, 2);
+        let transactions = packer.pack().unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(tags(&transactions), vec![0, 1]);
+        assert!(transactions
+            .iter()
+            .all(|transaction| transaction.instructions.len() == 1));
+    }
+
+    #[test]
+    fn prefix_is_put_in_front_of_each_transaction() {
+        let prefix = ComputeBudgetInstruction::request_heap_frame(256 * 1024);
+        let mut packer = packer().with_prefix(vec![prefix.clone()]);
+        push(&mut packer, 500, 3);
+        let transactions = packer.pack().unwrap();
+        assert_eq!(transactions.len(), 2);
+        for transaction in &transactions {
+            assert_eq!(transaction.instructions[0], prefix);
+            assert!(!transaction.prefix_dropped);
+        }
+        assert_eq!(tags(&transactions), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn oversized_group_is_sent_alone_without_the_prefix() {
+        let payer = Pubkey::new_unique();
+        // The largest instruction fitting in a transaction on its own
+        let len = (0..PACKET_DATA_SIZE)
+            .rev()
+            .find(|len| transaction_size(&[instruction(*len, 1)], &payer) <= PACKET_DATA_SIZE)
+            .unwrap();
+
+        let prefix = ComputeBudgetInstruction::request_heap_frame(256 * 1024);
+        let mut packer = TransactionPacker::new(payer).with_prefix(vec![prefix.clone()]);
+        packer.push("small", vec![instruction(10, 0)]);
+        packer.push("oversized", vec![instruction(len, 1)]);
+        packer.push("small", vec![instruction(10, 2)]);
+        let transactions = packer.pack().unwrap();
+
+        // Also when it comes first, or right after another oversized group
+        let mut first = TransactionPacker::new(payer).with_prefix(vec![prefix.clone()]);
+        first.push("oversized", vec![instruction(len, 0)]);
+        first.push("oversized", vec![instruction(len, 1)]);
+        let first = first.pack().unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(tags(&first), vec![0, 1]);
+        assert!(first.iter().all(|transaction| transaction.prefix_dropped));
+
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(tags(&transactions), vec![0, 1, 2]);
+        assert_eq!(transactions[1].instructions, vec![instruction(len, 1)]);
+        assert!(transactions[1].prefix_dropped);
+        for transaction in [&transactions[0], &transactions[2]] {
+            assert_eq!(transaction.instructions[0], prefix);
+            assert!(!transaction.prefix_dropped);
+        }
+    }
+
+    #[test]
+    fn group_too_large_for_any_transaction_fails() {
+        let mut packer = packer();
+        packer.push("too large", vec![instruction(PACKET_DATA_SIZE, 0)]);
+        let error = packer.pack().unwrap_err().to_string();
+        assert!(error.contains("too large"), "{}", error);
+    }
+}
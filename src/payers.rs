@@ -0,0 +1,56 @@
+use crate::read_keypair;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use std::error::Error;
+
+// Who pays for a flow besides the token owner, who always signs for its own tokens.
+//
+// fee payer   - pays the fee of every transaction
+// rent funder - funds the rent of new proof context state accounts and token accounts,
+//               and gets the rent of proof accounts back when they are closed
+//
+// Each role falls back to the owner when unset, which is how the flows pay out of the box.
+#[derive(Debug, Default)]
+pub struct Payers {
+    pub fee_payer: Option<Keypair>,
+    pub rent_funder: Option<Keypair>,
+}
+
+impl Payers {
+    // Payers read from the .env keypairs of the given names
+    pub fn load(
+        fee_payer: Option<&str>,
+        rent_funder: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let read = |name: Option<&str>| -> Result<Option<Keypair>, Box<dyn Error>> {
+            name.map(|name| {
+                read_keypair(name)?
+                    .ok_or_else(|| format!("No {} keypair in the .env file", name).into())
+            })
+            .transpose()
+        };
+        Ok(Self {
+            fee_payer: read(fee_payer)?,
+            rent_funder: read(rent_funder)?,
+        })
+    }
+
+    pub fn fee_payer(&self, owner: &Pubkey) -> Pubkey {
+        self.fee_payer
+            .as_ref()
+            .map_or(*owner, |fee_payer| fee_payer.pubkey())
+    }
+
+    pub fn rent_funder(&self, owner: &Pubkey) -> Pubkey {
+        self.rent_funder
+            .as_ref()
+            .map_or(*owner, |rent_funder| rent_funder.pubkey())
+    }
+
+    // Keypairs of the configured roles, to sign the transactions they pay for
+    pub fn keypairs(&self) -> impl Iterator<Item = &Keypair> {
+        self.fee_payer.iter().chain(self.rent_funder.iter())
+    }
+}
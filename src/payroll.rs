@@ -0,0 +1,209 @@
+use crate::{contacts::Recipient, journal::TxStatus, ui_amount::UiAmount};
+use serde_json::{json, Value};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{error::Error, fmt};
+
+// Header of a payroll CSV, optional
+pub const CSV_HEADER: &str = "recipient,amount";
+
+// A row of the payroll: a contact name or wallet address, and the amount to pay it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayrollLine {
+    // Line number in the CSV, for reporting
+    pub line: usize,
+    pub recipient: Recipient,
+    // Base units
+    pub amount: u64,
+}
+
+// Read a payroll CSV of `recipient,amount` rows. Blank lines and `#` comments are skipped,
+// and every row is checked before any is paid, so a typo doesn't stop the payroll half way.
+pub fn parse_payroll(csv: &str, ui_amount: &UiAmount) -> Result<Vec<PayrollLine>, Box<dyn Error>> {
+    let mut lines = Vec::new();
+    for (index, row) in csv.lines().enumerate() {
+        let line = index + 1;
+        let row = row.trim();
+        if row.is_empty() || row.starts_with('#') || (line == 1 && row == CSV_HEADER) {
+            continue;
+        }
+        let (recipient, amount) = row.split_once(',').ok_or(format!(
+            "Line {}: expected {}, got {:?}",
+            line, CSV_HEADER, row
+        ))?;
+        let recipient: Recipient = recipient.trim().parse()?;
+        let amount = ui_amount
+            .parse(amount)
+            .map_err(|error| format!("Line {}: {}", line, error))?;
+        if amount == 0 {
+            return Err(format!("Line {}: nothing to pay", line).into());
+        }
+        lines.push(PayrollLine {
+            line,
+            recipient,
+            amount,
+        });
+    }
+    if lines.is_empty() {
+        return Err("The payroll has no rows".into());
+    }
+    Ok(lines)
+}
+
+// What happened to one row of the payroll
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentOutcome {
+    Paid(Signature),
+    Failed(String),
+    // Not attempted, after Ctrl-C
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payment {
+    pub line: usize,
+    pub recipient: String,
+    pub token_account: Pubkey,
+    pub amount: u64,
+    pub outcome: PaymentOutcome,
+    // Status of the transfer in the journal, once reconciled
+    pub journal_status: Option<TxStatus>,
+}
+
+impl Payment {
+    pub fn to_json(&self, ui_amount: &UiAmount) -> Value {
+        let (status, signature, error) = match &self.outcome {
+            PaymentOutcome::Paid(signature) => ("paid", Some(signature.to_string()), None),
+            PaymentOutcome::Failed(error) => ("failed", None, Some(error.clone())),
+            PaymentOutcome::Skipped => ("skipped", None, None),
+        };
+        json!({
+            "line": self.line,
+            "recipient": self.recipient,
+            "token_account": self.token_account.to_string(),
+            "amount": ui_amount.format(self.amount),
+            "status": status,
+            "signature": signature,
+            "error": error,
+            "journal_status": self.journal_status.map(|status| status.as_str()),
+        })
+    }
+}
+
+// Reconciliation of a payroll run: what each row did, and whether the sender's available balance dropped by
+// exactly the amounts paid
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayrollReport {
+    pub ui_amount: UiAmount,
+    pub payments: Vec<Payment>,
+    pub available_before: u64,
+    // `None` if the balance could not be decrypted after the run
+    pub available_after: Option<u64>,
+}
+
+impl PayrollReport {
+    pub fn total(&self) -> u64 {
+        self.payments.iter().map(|payment| payment.amount).sum()
+    }
+
+    pub fn paid(&self) -> u64 {
+        self.payments
+            .iter()
+            .filter(|payment| matches!(payment.outcome, PaymentOutcome::Paid(_)))
+            .map(|payment| payment.amount)
+            .sum()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.payments
+            .iter()
+            .filter(|payment| !matches!(payment.outcome, PaymentOutcome::Paid(_)))
+            .count()
+    }
+
+    // Difference between the available balance after the run and the one expected from the payments,
+    // non zero if e.g. a failed transfer landed after all, or another transfer ran at the same time
+    pub fn drift(&self) -> Option<i128> {
+        self.available_after
+            .map(|after| after as i128 - (self.available_before as i128 - self.paid() as i128))
+    }
+
+    pub fn is_reconciled(&self) -> bool {
+        self.drift() == Some(0)
+            && self.payments.iter().all(|payment| match payment.outcome {
+                PaymentOutcome::Paid(_) => matches!(
+                    payment.journal_status,
+                    Some(TxStatus::Confirmed | TxStatus::Finalized)
+                ),
+                _ => true,
+            })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "payments": self.payments.iter().map(|payment| payment.to_json(&self.ui_amount)).collect::<Vec<_>>(),
+            "total": self.ui_amount.format(self.total()),
+            "paid": self.ui_amount.format(self.paid()),
+            "failed": self.failed(),
+            "available_before": self.ui_amount.format(self.available_before),
+            "available_after": self.available_after.map(|after| self.ui_amount.format(after)),
+            "drift": self.drift().map(|drift| drift.to_string()),
+            "reconciled": self.is_reconciled(),
+        })
+    }
+}
+
+impl fmt::Display for PayrollReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for payment in &self.payments {
+            let status = match &payment.outcome {
+                PaymentOutcome::Paid(signature) => format!("paid {}", signature),
+                PaymentOutcome::Failed(error) => format!("failed: {}", error),
+                PaymentOutcome::Skipped => "skipped".to_string(),
+            };
+            writeln!(
+                f,
+                "Line {:>4}  {:<44}  {:>16}  {}",
+                payment.line,
+                payment.recipient,
+                self.ui_amount.format(payment.amount),
+                status
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Paid:              {} of {}",
+            self.ui_amount.format(self.paid()),
+            self.ui_amount.format(self.total())
+        )?;
+        writeln!(
+            f,
+            "Failed or skipped: {} of {} rows",
+            self.failed(),
+            self.payments.len()
+        )?;
+        writeln!(
+            f,
+            "Available before:  {}",
+            self.ui_amount.format(self.available_before)
+        )?;
+        writeln!(
+            f,
+            "Available after:   {}",
+            self.ui_amount.format_decrypted(self.available_after)
+        )?;
+        match self.drift() {
+            Some(0) if self.is_reconciled() => write!(f, "Reconciled"),
+            Some(0) => write!(
+                f,
+                "Not reconciled: some transfers aren't confirmed in the journal"
+            ),
+            Some(drift) => write!(
+                f,
+                "Not reconciled: the available balance is off by {} base units",
+                drift
+            ),
+            None => write!(f, "Not reconciled: could not decrypt the available balance"),
+        }
+    }
+}
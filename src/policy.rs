@@ -0,0 +1,319 @@
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use std::{error::Error, fmt, fs};
+
+// Window of the daily cap, a rolling 24 hours rather than a calendar day
+pub const DAILY_CAP_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+// Limits evaluated before a transfer or withdraw is signed, loaded from the JSON file named by POLICY:
+//
+// {
+//   "max_amount": 100000,
+//   "allowed_recipients": ["<token account or owner>", ...],
+//   "daily_cap": 500000
+// }
+//
+// Every field is optional. Amounts are in base units of the mint being spent,
+// and the daily cap sums the transfers and withdraws of that mint recorded in the journal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    // Largest amount of a single transfer or withdraw
+    pub max_amount: Option<u64>,
+    // Token accounts, or owners of token accounts, that transfers may be sent to
+    pub allowed_recipients: Option<Vec<Pubkey>>,
+    // Largest amount transferred and withdrawn over the last `DAILY_CAP_WINDOW_SECS`
+    pub daily_cap: Option<u64>,
+}
+
+// What is about to be signed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyAction {
+    Transfer {
+        recipient: Pubkey,
+        recipient_owner: Pubkey,
+    },
+    Withdraw,
+}
+
+impl Policy {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read the policy file {}: {}", path, error))?;
+        Self::from_json(&serde_json::from_str(&contents)?)
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let amount = |field: &str| -> Result<Option<u64>, Box<dyn Error>> {
+            match value.get(field) {
+                None | Some(Value::Null) => Ok(None),
+                Some(amount) => Ok(Some(amount.as_u64().ok_or(format!(
+                    "Policy field {} must be an amount in base units",
+                    field
+                ))?)),
+            }
+        };
+
+        let allowed_recipients = match value.get("allowed_recipients") {
+            None | Some(Value::Null) => None,
+            Some(Value::Array(recipients)) => Some(
+                recipients
+                    .iter()
+                    .map(|recipient| {
+                        recipient
+                            .as_str()
+                            .ok_or("Policy allowed_recipients must be addresses")?
+                            .parse::<Pubkey>()
+                            .map_err(|error| error.into())
+                    })
+                    .collect::<Result<_, Box<dyn Error>>>()?,
+            ),
+            Some(_) => return Err("Policy allowed_recipients must be a list of addresses".into()),
+        };
+
+        Ok(Self {
+            max_amount: amount("max_amount")?,
+            allowed_recipients,
+            daily_cap: amount("daily_cap")?,
+        })
+    }
+
+    // Check an action of `amount` base units, with `spent` base units of the same mint already spent in the window
+    pub fn check(&self, action: &PolicyAction, amount: u64, spent: u64) -> Result<(), PolicyError> {
+        if let Some(max_amount) = self.max_amount {
+            if amount > max_amount {
+                return Err(PolicyError::AmountExceeded { amount, max_amount });
+            }
+        }
+        if let (
+            Some(allowed_recipients),
+            PolicyAction::Transfer {
+                recipient,
+                recipient_owner,
+            },
+        ) = (&self.allowed_recipients, action)
+        {
+            if !allowed_recipients.contains(recipient)
+                && !allowed_recipients.contains(recipient_owner)
+            {
+                return Err(PolicyError::RecipientNotAllowed {
+                    recipient: *recipient,
+                });
+            }
+        }
+        if let Some(daily_cap) = self.daily_cap {
+            if spent.saturating_add(amount) > daily_cap {
+                return Err(PolicyError::DailyCapExceeded {
+                    amount,
+                    spent,
+                    daily_cap,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// A transfer or withdraw refused by the policy, before anything was signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    AmountExceeded {
+        amount: u64,
+        max_amount: u64,
+    },
+    RecipientNotAllowed {
+        recipient: Pubkey,
+    },
+    DailyCapExceeded {
+        amount: u64,
+        spent: u64,
+        daily_cap: u64,
+    },
+    // The daily cap sums past spends from the journal, so it can't be enforced without one
+    NoSpendHistory,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::AmountExceeded { amount, max_amount } => write!(
+                f,
+                "Policy violation: amount {} exceeds the maximum of {} per transaction",
+                amount, max_amount
+            ),
+            PolicyError::RecipientNotAllowed { recipient } => write!(
+                f,
+                "Policy violation: recipient {} is not in the allowed recipients",
+                recipient
+            ),
+            PolicyError::DailyCapExceeded {
+                amount,
+                spent,
+                daily_cap,
+            } => write!(
+                f,
+                "Policy violation: amount {} on top of {} spent in the last 24 hours exceeds the daily cap of {}",
+                amount, spent, daily_cap
+            ),
+            PolicyError::NoSpendHistory => write!(
+                f,
+                "Policy violation: the daily cap can't be enforced without a transaction journal"
+            ),
+        }
+    }
+}
+
+impl Error for PolicyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{flows::FlowContext, journal::Journal};
+    use serde_json::json;
+    use solana_client::rpc_client::RpcClient;
+    use solana_sdk::signature::Signature;
+
+    fn transfer_to(recipient: Pubkey) -> PolicyAction {
+        PolicyAction::Transfer {
+            recipient,
+            recipient_owner: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn max_amount_allows_the_limit_and_refuses_above() {
+        let policy = Policy {
+            max_amount: Some(100),
+            ..Policy::default()
+        };
+        assert_eq!(policy.check(&PolicyAction::Withdraw, 100, 0), Ok(()));
+        assert_eq!(
+            policy.check(&PolicyAction::Withdraw, 101, 0),
+            Err(PolicyError::AmountExceeded {
+                amount: 101,
+                max_amount: 100
+            })
+        );
+    }
+
+    #[test]
+    fn recipients_must_be_allowed_by_token_account_or_owner() {
+        let allowed = Pubkey::new_unique();
+        let policy = Policy {
+            allowed_recipients: Some(vec![allowed]),
+            ..Policy::default()
+        };
+        assert_eq!(policy.check(&transfer_to(allowed), 1, 0), Ok(()));
+        let by_owner = PolicyAction::Transfer {
+            recipient: Pubkey::new_unique(),
+            recipient_owner: allowed,
+        };
+        assert_eq!(policy.check(&by_owner, 1, 0), Ok(()));
+
+        let other = Pubkey::new_unique();
+        assert_eq!(
+            policy.check(&transfer_to(other), 1, 0),
+            Err(PolicyError::RecipientNotAllowed { recipient: other })
+        );
+        // Withdraws have no recipient to check
+        assert_eq!(policy.check(&PolicyAction::Withdraw, 1, 0), Ok(()));
+    }
+
+    #[test]
+    fn daily_cap_counts_earlier_spends() {
+        let policy = Policy {
+            daily_cap: Some(1_000),
+            ..Policy::default()
+        };
+        assert_eq!(policy.check(&PolicyAction::Withdraw, 400, 600), Ok(()));
+        assert_eq!(
+            policy.check(&PolicyAction::Withdraw, 401, 600),
+            Err(PolicyError::DailyCapExceeded {
+                amount: 401,
+                spent: 600,
+                daily_cap: 1_000
+            })
+        );
+        assert!(policy.check(&PolicyAction::Withdraw, 1, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn daily_cap_sums_the_spends_of_the_mint_in_the_journal() {
+        let client = RpcClient::new_mock("succeeds".to_string());
+        let mut ctx = FlowContext::new(&client, 0);
+        ctx.flow = "transfer";
+        ctx.policy = Some(Policy {
+            daily_cap: Some(1_000),
+            ..Policy::default()
+        });
+        let mint = Pubkey::new_unique();
+
+        // Without a journal there are no spends to sum
+        let error = ctx
+            .check_policy(&mint, PolicyAction::Withdraw, 1)
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<PolicyError>(),
+            Some(&PolicyError::NoSpendHistory)
+        );
+
+        ctx.journal = Some(Journal::open(":memory:").unwrap());
+        ctx.record_spend(&Signature::default(), &mint, 300);
+        ctx.record_spend(&Signature::default(), &mint, 500);
+        // Spends of other mints don't count
+        ctx.record_spend(&Signature::default(), &Pubkey::new_unique(), 900);
+
+        ctx.check_policy(&mint, PolicyAction::Withdraw, 200)
+            .unwrap();
+        let error = ctx
+            .check_policy(&mint, PolicyAction::Withdraw, 201)
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<PolicyError>(),
+            Some(&PolicyError::DailyCapExceeded {
+                amount: 201,
+                spent: 800,
+                daily_cap: 1_000
+            })
+        );
+    }
+
+    #[test]
+    fn from_json_reads_every_field() {
+        let recipient = Pubkey::new_unique();
+        let policy = Policy::from_json(&json!({
+            "max_amount": 100,
+            "allowed_recipients": [recipient.to_string()],
+            "daily_cap": 500,
+        }))
+        .unwrap();
+        assert_eq!(
+            policy,
+            Policy {
+                max_amount: Some(100),
+                allowed_recipients: Some(vec![recipient]),
+                daily_cap: Some(500),
+            }
+        );
+        assert_eq!(Policy::from_json(&json!({})).unwrap(), Policy::default());
+    }
+
+    #[test]
+    fn malformed_policies_are_refused() {
+        for value in [
+            json!({ "max_amount": -1 }),
+            json!({ "max_amount": "100" }),
+            json!({ "daily_cap": 1.5 }),
+            json!({ "allowed_recipients": "not a list" }),
+            json!({ "allowed_recipients": [42] }),
+            json!({ "allowed_recipients": ["not an address"] }),
+        ] {
+            assert!(Policy::from_json(&value).is_err(), "{} was accepted", value);
+        }
+
+        let path = std::env::temp_dir().join(format!("policy-{}.json", Pubkey::new_unique()));
+        fs::write(&path, "{ not json").unwrap();
+        assert!(Policy::load(path.to_str().unwrap()).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(Policy::load(path.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    account_state::ConfidentialAccountState, audit::BalanceAudit, key_derivation::KeyDerivation,
+    snapshot::EncryptionKeys, ui_amount::UiAmount,
+};
+use serde_json::{json, Value};
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_request::{RpcRequest, MAX_MULTIPLE_ACCOUNTS},
+    rpc_response::{Response, RpcKeyedAccount},
+};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use spl_token_2022::{
+    extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensionsOwned},
+    state::Account,
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// A Token-2022 account of the wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortfolioAccount {
+    pub address: Pubkey,
+    pub mint: Pubkey,
+    pub public_balance: u64,
+    pub extensions: Vec<ExtensionType>,
+    // Decimals and multiplier of the mint, to display the balances
+    pub ui_amount: UiAmount,
+    // `None` if the account isn't configured for confidential transfers
+    pub confidential: Option<ConfidentialAccountState>,
+    // Decrypted confidential balances, `None` without the keys configured on the account
+    pub audit: Option<BalanceAudit>,
+}
+
+impl PortfolioAccount {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "address": self.address.to_string(),
+            "mint": self.mint.to_string(),
+            "extensions": self.extensions.iter().map(|extension| format!("{:?}", extension)).collect::<Vec<_>>(),
+            "public_balance": self.public_balance,
+            "public_balance_ui": self.ui_amount.format(self.public_balance),
+            "confidential": self.confidential.is_some(),
+            "pending_balance_credit_counter": self.confidential.as_ref().map(|state| state.pending_balance_credit_counter),
+            "audit": self.audit.as_ref().map(BalanceAudit::to_json),
+        })
+    }
+}
+
+// Every Token-2022 account a wallet owns, whatever the mint, ordered by mint
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portfolio {
+    pub owner: Pubkey,
+    pub accounts: Vec<PortfolioAccount>,
+}
+
+impl Portfolio {
+    // `getTokenAccountsByOwner` returns every account in one response, so it is asked for addresses only,
+    // with an empty data slice. The accounts and their mints are then fetched in batches of `getMultipleAccounts`.
+    pub fn fetch(client: &RpcClient, owner: &Pubkey) -> Result<Self, Box<dyn Error>> {
+        let listed: Response<Vec<RpcKeyedAccount>> = client.send(
+            RpcRequest::GetTokenAccountsByOwner,
+            json!([
+                owner.to_string(),
+                { "programId": spl_token_2022::id().to_string() },
+                {
+                    "encoding": "base64",
+                    "dataSlice": { "offset": 0, "length": 0 },
+                    "commitment": client.commitment().commitment,
+                },
+            ]),
+        )?;
+        let addresses = listed
+            .value
+            .iter()
+            .map(|keyed| keyed.pubkey.parse())
+            .collect::<Result<Vec<Pubkey>, _>>()?;
+
+        let mut token_accounts = Vec::new();
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            for (address, account) in chunk.iter().zip(client.get_multiple_accounts(chunk)?) {
+                // Closed between the listing and the fetch
+                let Some(account) = account else { continue };
+                token_accounts.push((*address, account.data));
+            }
+        }
+
+        let mut mints: Vec<Pubkey> = Vec::new();
+        for (_, data) in &token_accounts {
+            let mint = StateWithExtensionsOwned::<Account>::unpack(data.clone())?
+                .base
+                .mint;
+            if !mints.contains(&mint) {
+                mints.push(mint);
+            }
+        }
+        // The program uses the cluster clock, local time is close enough for display
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let mut ui_amounts = HashMap::new();
+        for chunk in mints.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            for (mint, account) in chunk.iter().zip(client.get_multiple_accounts(chunk)?) {
+                let account = account.ok_or(format!("Mint {} not found", mint))?;
+                ui_amounts.insert(*mint, UiAmount::from_mint_data(&account.data, now)?);
+            }
+        }
+
+        let mut accounts = Vec::new();
+        for (address, data) in token_accounts {
+            let state = StateWithExtensionsOwned::<Account>::unpack(data.clone())?;
+            accounts.push(PortfolioAccount {
+                address,
+                mint: state.base.mint,
+                public_balance: state.base.amount,
+                extensions: state.get_extension_types()?,
+                ui_amount: ui_amounts[&state.base.mint],
+                confidential: ConfidentialAccountState::unpack(&address, data)?,
+                audit: None,
+            });
+        }
+        accounts.sort_by_key(|account| (account.mint.to_string(), account.address.to_string()));
+
+        Ok(Self {
+            owner: *owner,
+            accounts,
+        })
+    }
+
+    // Decrypt the confidential balances of the accounts configured with keys derived from `owner`, under any of
+    // the candidates of `derivation`. Accounts configured with other keys, e.g. by another client, are left encrypted.
+    pub fn decrypt(
+        &mut self,
+        owner: &Keypair,
+        derivation: &KeyDerivation,
+    ) -> Result<(), Box<dyn Error>> {
+        for account in &mut self.accounts {
+            let Some(state) = &account.confidential else {
+                continue;
+            };
+            let Ok(derivation) = derivation.resolve(owner, &account.address, &state.elgamal_pubkey)
+            else {
+                continue;
+            };
+            let keys = EncryptionKeys::derive(owner, &account.address, &derivation)?;
+            account.audit =
+                Some(state.audit(&keys.elgamal_keypair, &keys.aes_key, account.ui_amount)?);
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "owner": self.owner.to_string(),
+            "accounts": self.accounts.iter().map(PortfolioAccount::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
@@ -0,0 +1,50 @@
+use serde_json::Value;
+use std::error::Error;
+
+// Where to get the SOL price used for fiat estimates of fees
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriceSource {
+    // A fixed price, e.g. passed on the command line
+    Fixed(f64),
+    // A JSON HTTP endpoint, with a JSON pointer to the price inside the response
+    // e.g. https://api.coingecko.com/api/v3/simple/price?ids=solana&vs_currencies=usd and "/solana/usd"
+    Url { url: String, pointer: String },
+}
+
+impl PriceSource {
+    // Price of 1 SOL in the configured currency
+    pub fn fetch(&self) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        match self {
+            PriceSource::Fixed(price) => Ok(*price),
+            PriceSource::Url { url, pointer } => {
+                let (url, pointer) = (url.clone(), pointer.clone());
+                // The blocking reqwest client can't be used from inside the tokio runtime of the async bins,
+                // so the request is made from a plain thread
+                std::thread::spawn(move || fetch_price(&url, &pointer))
+                    .join()
+                    .map_err(|_| "Price fetch thread panicked")?
+            }
+        }
+    }
+}
+
+fn fetch_price(url: &str, pointer: &str) -> Result<f64, Box<dyn Error + Send + Sync>> {
+    let response: Value = reqwest::blocking::get(url)?.error_for_status()?.json()?;
+    response
+        .pointer(pointer)
+        .and_then(Value::as_f64)
+        .ok_or_else(|| format!("No price at {} in response from {}", pointer, url).into())
+}
+
+// SOL price in a named fiat currency
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiatPrice {
+    pub price: f64,
+    pub currency: String,
+}
+
+impl FiatPrice {
+    pub fn estimate(&self, sol: f64) -> f64 {
+        sol * self.price
+    }
+}
@@ -0,0 +1,115 @@
+use crate::events::FlowEvent;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Spinner shown while a step is running, so slow steps like proof generation don't look hung
+const RUNNING_TEMPLATE: &str = "{prefix:.bold.dim} {spinner:.green} {msg} {elapsed:.dim}";
+const FINISHED_TEMPLATE: &str = "{prefix:.bold.dim} {msg}";
+
+// Step by step progress display for a multi-transaction flow, written to stderr.
+// Driven by the `StepStarted` and `StepFinished` events of a flow.
+pub struct FlowProgress {
+    current: Mutex<Option<Step>>,
+    completed_steps: Mutex<usize>,
+    started: Instant,
+}
+
+impl Default for FlowProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlowProgress {
+    pub fn new() -> Self {
+        Self {
+            current: Mutex::new(None),
+            completed_steps: Mutex::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn handle(&self, event: &FlowEvent) {
+        match event {
+            FlowEvent::StepStarted {
+                step,
+                total_steps,
+                name,
+            } => {
+                *self.current.lock().unwrap() = Some(Step::start(*step, *total_steps, name));
+            }
+            FlowEvent::StepFinished { elapsed, .. } => {
+                if let Some(step) = self.current.lock().unwrap().take() {
+                    step.finish(*elapsed);
+                    *self.completed_steps.lock().unwrap() += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Print a line to stdout without garbling the spinner of the running step
+    pub fn println(&self, line: &str) {
+        self.suspend(|| println!("{}", line))
+    }
+
+    // Run `f` with the spinner of the running step hidden, e.g. to prompt the user
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        match &*self.current.lock().unwrap() {
+            Some(step) => step.bar.suspend(f),
+            None => f(),
+        }
+    }
+
+    // Total time spent on the flow so far
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn finish(&self) {
+        eprintln!(
+            "\nCompleted {} steps in {:.2?}",
+            self.completed_steps.lock().unwrap(),
+            self.elapsed()
+        );
+    }
+}
+
+// A running step of a flow
+struct Step {
+    bar: ProgressBar,
+    name: &'static str,
+}
+
+impl Step {
+    fn start(step: usize, total_steps: usize, name: &'static str) -> Self {
+        let bar = ProgressBar::new_spinner();
+        bar.set_style(ProgressStyle::with_template(RUNNING_TEMPLATE).unwrap());
+        bar.set_prefix(format!("[{}/{}]", step, total_steps));
+        bar.set_message(name);
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Self { bar, name }
+    }
+
+    // Replace the spinner with a check mark and the time the step took
+    fn finish(self, elapsed: Duration) {
+        self.bar
+            .set_style(ProgressStyle::with_template(FINISHED_TEMPLATE).unwrap());
+        self.bar
+            .finish_with_message(format!("✔ {} ({:.2?})", self.name, elapsed));
+    }
+}
+
+impl Drop for Step {
+    // A step dropped without being finished failed part way, e.g. an early return through `?`
+    fn drop(&mut self) {
+        if !self.bar.is_finished() {
+            self.bar
+                .set_style(ProgressStyle::with_template(FINISHED_TEMPLATE).unwrap());
+            self.bar.abandon_with_message(format!("✘ {}", self.name));
+        }
+    }
+}
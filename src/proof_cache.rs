@@ -0,0 +1,384 @@
+use crate::{account_state::ConfidentialAccountState, journal::now};
+use aes_gcm_siv::{
+    aead::{Aead, NewAead, Payload},
+    Aes256GcmSiv, Key, Nonce,
+};
+use bytemuck::Pod;
+use rand::{rngs::OsRng, RngCore};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token_2022::{
+    extension::confidential_transfer::ciphertext_extraction::SourceDecryptHandles,
+    solana_zk_token_sdk::instruction::{
+        BatchedGroupedCiphertext2HandlesValidityProofData, BatchedRangeProofU128Data,
+        BatchedRangeProofU256Data, CiphertextCommitmentEqualityProofData, FeeSigmaProofData,
+        WithdrawData,
+    },
+};
+use std::{error::Error, mem::size_of, path::Path};
+
+const NONCE_LEN: usize = 12;
+
+// Entries older than this are dropped by `prune`, the state they were generated against is long gone
+pub const MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+
+// Proof data generated by a flow, kept in SQLite next to the journal until the flow completes, so a flow retried
+// after a transient send failure reuses it instead of generating the proofs again (the range proof takes seconds).
+//
+// Entries are keyed by a hash of everything the proof was generated against (`ProofKey`), so proof data is only
+// reused while the token account still holds the same ciphertexts. They are encrypted with a key derived from
+// a signature of the token account owner, like the AES key of the token account.
+pub struct ProofCache {
+    connection: Connection,
+}
+
+impl ProofCache {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS proof_cache (
+                key        TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                nonce      BLOB NOT NULL,
+                ciphertext BLOB NOT NULL
+            );",
+        )?;
+        let proof_cache = Self { connection };
+        proof_cache.prune()?;
+        Ok(proof_cache)
+    }
+
+    // Drop the entries older than `MAX_AGE_SECS`, returning how many were dropped
+    pub fn prune(&self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.connection.execute(
+            "DELETE FROM proof_cache WHERE created_at < ?1",
+            params![now().saturating_sub(MAX_AGE_SECS)],
+        )?)
+    }
+
+    // Cached proof data for `key`, `None` if there is none or it doesn't decrypt with the owner's key
+    pub fn get<P: CachedProof>(
+        &self,
+        owner: &Keypair,
+        key: &ProofKey,
+    ) -> Result<Option<P>, Box<dyn Error>> {
+        let row: Option<(Vec<u8>, Vec<u8>)> = self
+            .connection
+            .query_row(
+                "SELECT nonce, ciphertext FROM proof_cache WHERE key = ?1",
+                params![key.0],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((nonce, ciphertext)) = row else {
+            return Ok(None);
+        };
+        if nonce.len() != NONCE_LEN {
+            return Ok(None);
+        }
+        let Ok(bytes) = cipher(owner)?.decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: key.0.as_bytes(),
+            },
+        ) else {
+            return Ok(None);
+        };
+        Ok(P::from_bytes(&bytes))
+    }
+
+    pub fn put<P: CachedProof>(
+        &self,
+        owner: &Keypair,
+        key: &ProofKey,
+        proof: &P,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = cipher(owner)?
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &proof.to_bytes(),
+                    aad: key.0.as_bytes(),
+                },
+            )
+            .map_err(|_| "Could not encrypt the proof data")?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO proof_cache (key, created_at, nonce, ciphertext)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![key.0, now(), nonce.to_vec(), ciphertext],
+        )?;
+        Ok(())
+    }
+
+    // Drop the proof data of a completed flow, its proofs can't be used again
+    pub fn remove(&self, key: &ProofKey) -> Result<(), Box<dyn Error>> {
+        self.connection
+            .execute("DELETE FROM proof_cache WHERE key = ?1", params![key.0])?;
+        Ok(())
+    }
+}
+
+// Hash of what a proof was generated against: the kind of proof, the token account and its encrypted balances,
+// the amount, and any other input such as the ElGamal pubkeys a transfer is encrypted under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofKey(String);
+
+impl ProofKey {
+    pub fn new(
+        kind: &str,
+        state: &ConfidentialAccountState,
+        amount: u64,
+        inputs: &[&[u8]],
+    ) -> Self {
+        let extension = state.extension();
+        let mut hasher = Sha256::new();
+        hasher.update(kind.as_bytes());
+        hasher.update(state.token_account.as_ref());
+        hasher.update(extension.available_balance.0);
+        hasher.update(extension.decryptable_available_balance.0);
+        hasher.update(amount.to_le_bytes());
+        for input in inputs {
+            hasher.update((input.len() as u64).to_le_bytes());
+            hasher.update(input);
+        }
+        Self(
+            hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// Proof data that can be written to and read back from the cache
+pub trait CachedProof: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl CachedProof for WithdrawData {
+    fn to_bytes(&self) -> Vec<u8> {
+        bytemuck::bytes_of(self).to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytemuck::try_pod_read_unaligned(bytes).ok()
+    }
+}
+
+// The proof data and decrypt handles returned by `TransferAccountInfo::generate_split_transfer_proof_data`
+pub type TransferProofData = (
+    CiphertextCommitmentEqualityProofData,
+    BatchedGroupedCiphertext2HandlesValidityProofData,
+    BatchedRangeProofU128Data,
+    SourceDecryptHandles,
+);
+
+impl CachedProof for TransferProofData {
+    fn to_bytes(&self) -> Vec<u8> {
+        let (equality, ciphertext_validity, range, source_decrypt_handles) = self;
+        [
+            bytemuck::bytes_of(equality),
+            bytemuck::bytes_of(ciphertext_validity),
+            bytemuck::bytes_of(range),
+            bytemuck::bytes_of(source_decrypt_handles),
+        ]
+        .concat()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut rest = bytes;
+        let proof = (
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+        );
+        rest.is_empty().then_some(proof)
+    }
+}
+
+// The proof data and decrypt handles returned by `proof_generation::transfer_with_fee_split_proof_data`:
+// equality, transfer amount ciphertext validity, fee sigma, fee ciphertext validity and range proofs
+pub type TransferWithFeeProofData = (
+    CiphertextCommitmentEqualityProofData,
+    BatchedGroupedCiphertext2HandlesValidityProofData,
+    FeeSigmaProofData,
+    BatchedGroupedCiphertext2HandlesValidityProofData,
+    BatchedRangeProofU256Data,
+    SourceDecryptHandles,
+);
+
+impl CachedProof for TransferWithFeeProofData {
+    fn to_bytes(&self) -> Vec<u8> {
+        let (equality, ciphertext_validity, fee_sigma, fee_ciphertext_validity, range, handles) =
+            self;
+        [
+            bytemuck::bytes_of(equality),
+            bytemuck::bytes_of(ciphertext_validity),
+            bytemuck::bytes_of(fee_sigma),
+            bytemuck::bytes_of(fee_ciphertext_validity),
+            bytemuck::bytes_of(range),
+            bytemuck::bytes_of(handles),
+        ]
+        .concat()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut rest = bytes;
+        let proof = (
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+            read_pod(&mut rest)?,
+        );
+        rest.is_empty().then_some(proof)
+    }
+}
+
+// Read a Pod value off the front of `bytes`, advancing past it
+fn read_pod<P: Pod>(bytes: &mut &[u8]) -> Option<P> {
+    let value = bytemuck::try_pod_read_unaligned(bytes.get(..size_of::<P>())?).ok()?;
+    *bytes = &bytes[size_of::<P>()..];
+    Some(value)
+}
+
+// Only the owner can produce this signature, and ed25519 signatures are deterministic, so the key is stable
+fn cipher(owner: &Keypair) -> Result<Aes256GcmSiv, Box<dyn Error>> {
+    let signature = owner
+        .try_sign_message(b"proof-cache")
+        .map_err(|error| format!("Could not derive the proof cache key: {}", error))?;
+    let key = Sha256::digest(signature.as_ref());
+    Ok(Aes256GcmSiv::new(Key::from_slice(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+    use solana_sdk::{pubkey::Pubkey, signer::keypair::keypair_from_seed};
+    use spl_token_2022::{
+        extension::confidential_transfer::ConfidentialTransferAccount,
+        solana_zk_token_sdk::encryption::elgamal::ElGamalKeypair,
+    };
+
+    // Pod value with distinct bytes, so a field read from the wrong offset shows up
+    fn patterned<P: Pod>(seed: u8) -> P {
+        let mut value = P::zeroed();
+        for (index, byte) in bytemuck::bytes_of_mut(&mut value).iter_mut().enumerate() {
+            *byte = seed.wrapping_add(index as u8);
+        }
+        value
+    }
+
+    fn state(available: u64) -> ConfidentialAccountState {
+        let elgamal_keypair = ElGamalKeypair::new_rand();
+        let mut extension = ConfidentialTransferAccount::zeroed();
+        extension.elgamal_pubkey = (*elgamal_keypair.pubkey()).into();
+        extension.available_balance = elgamal_keypair.pubkey().encrypt(available).into();
+        ConfidentialAccountState::new(Pubkey::new_from_array([3; 32]), 0, extension)
+    }
+
+    fn assert_round_trip<P: CachedProof>(proof: &P) {
+        let bytes = proof.to_bytes();
+        let read = P::from_bytes(&bytes).expect("the bytes read back");
+        assert_eq!(read.to_bytes(), bytes);
+        assert!(P::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        assert!(P::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_none());
+    }
+
+    #[test]
+    fn proof_data_round_trips_through_bytes() {
+        assert_round_trip::<WithdrawData>(&patterned(1));
+        assert_round_trip::<TransferProofData>(&(
+            patterned(1),
+            patterned(2),
+            patterned(3),
+            patterned(4),
+        ));
+        assert_round_trip::<TransferWithFeeProofData>(&(
+            patterned(1),
+            patterned(2),
+            patterned(3),
+            patterned(4),
+            patterned(5),
+            patterned(6),
+        ));
+    }
+
+    #[test]
+    fn proof_data_only_decrypts_with_the_owner_key() {
+        let cache = ProofCache::open(":memory:").unwrap();
+        let owner = keypair_from_seed(&[1; 32]).unwrap();
+        let other = keypair_from_seed(&[2; 32]).unwrap();
+        let key = ProofKey::new("withdraw", &state(100), 10, &[]);
+        let proof: WithdrawData = patterned(7);
+        cache.put(&owner, &key, &proof).unwrap();
+
+        let cached: WithdrawData = cache.get(&owner, &key).unwrap().unwrap();
+        assert_eq!(cached.to_bytes(), proof.to_bytes());
+        assert!(cache.get::<WithdrawData>(&other, &key).unwrap().is_none());
+
+        cache.remove(&key).unwrap();
+        assert!(cache.get::<WithdrawData>(&owner, &key).unwrap().is_none());
+    }
+
+    #[test]
+    fn proof_key_changes_with_the_state_and_amount() {
+        let account = state(100);
+        let key = ProofKey::new("transfer", &account, 10, &[b"recipient"]);
+        assert_eq!(
+            key,
+            ProofKey::new("transfer", &account, 10, &[b"recipient"])
+        );
+        assert_ne!(
+            key,
+            ProofKey::new("transfer", &account, 11, &[b"recipient"])
+        );
+        assert_ne!(
+            key,
+            ProofKey::new("transfer", &state(100), 10, &[b"recipient"])
+        );
+        assert_ne!(
+            key,
+            ProofKey::new("withdraw", &account, 10, &[b"recipient"])
+        );
+        assert_ne!(key, ProofKey::new("transfer", &account, 10, &[b"auditor"]));
+    }
+
+    #[test]
+    fn prune_drops_entries_older_than_seven_days() {
+        let cache = ProofCache::open(":memory:").unwrap();
+        let owner = keypair_from_seed(&[1; 32]).unwrap();
+        let fresh = ProofKey::new("withdraw", &state(100), 10, &[]);
+        let stale = ProofKey::new("withdraw", &state(100), 20, &[]);
+        cache
+            .put(&owner, &fresh, &patterned::<WithdrawData>(1))
+            .unwrap();
+        cache
+            .put(&owner, &stale, &patterned::<WithdrawData>(2))
+            .unwrap();
+        cache
+            .connection
+            .execute(
+                "UPDATE proof_cache SET created_at = ?1 WHERE key = ?2",
+                params![now() - MAX_AGE_SECS - 1, stale.as_str()],
+            )
+            .unwrap();
+
+        assert_eq!(cache.prune().unwrap(), 1);
+        assert!(cache.get::<WithdrawData>(&owner, &fresh).unwrap().is_some());
+        assert!(cache.get::<WithdrawData>(&owner, &stale).unwrap().is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    feature,
+    feature_set::{enable_zk_transfer_with_fee, zk_token_sdk_enabled},
+};
+use spl_token_2022::solana_zk_token_sdk::{
+    zk_token_proof_instruction::ProofInstruction, zk_token_proof_program,
+};
+use std::{error::Error, fmt};
+
+// What the ZK Token Proof program of the cluster verifies, queried from its feature accounts.
+//
+// Proofs are generated with the zk-token-sdk of Solana 1.17 and verified by the ZK Token Proof program.
+// Clusters that replaced it with the ZK ElGamal Proof program (Agave 2.x) are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofSupport {
+    // Whether the ZK Token Proof program verifies transfer with fee proofs (`enable_zk_transfer_with_fee`)
+    pub transfer_with_fee: bool,
+}
+
+impl ProofSupport {
+    // Detect the features active on the cluster, failing if the ZK Token Proof program isn't active
+    pub fn detect(client: &RpcClient) -> Result<Self, Box<dyn Error>> {
+        let features = [
+            zk_token_sdk_enabled::id(),
+            enable_zk_transfer_with_fee::id(),
+        ];
+        let active: Vec<bool> = client
+            .get_multiple_accounts(&features)?
+            .iter()
+            .map(|account| {
+                account
+                    .as_ref()
+                    .and_then(feature::from_account)
+                    .is_some_and(|feature| feature.activated_at.is_some())
+            })
+            .collect();
+
+        if !active[0] {
+            return Err(format!(
+                "The ZK Token Proof program ({}) isn't active on the cluster (feature {})",
+                zk_token_proof_program::id(),
+                zk_token_sdk_enabled::id()
+            )
+            .into());
+        }
+        Ok(Self {
+            transfer_with_fee: active[1],
+        })
+    }
+
+    // The instruction to verify the range proof of a split proof transfer.
+    // Transfers without fee prove the new balance and the lo and hi amounts with a batched u128 range proof,
+    // transfers with fee add the fee amounts and need a batched u256 range proof with fee proofs.
+    pub fn transfer_range_proof(
+        &self,
+        transfer_fee: bool,
+    ) -> Result<ProofInstruction, Box<dyn Error>> {
+        if !transfer_fee {
+            return Ok(ProofInstruction::VerifyBatchedRangeProofU128);
+        }
+        if !self.transfer_with_fee {
+            return Err(format!(
+                "The cluster hasn't activated transfer with fee proofs ({}), transfers of mints with a transfer fee can't be verified",
+                enable_zk_transfer_with_fee::id()
+            )
+            .into());
+        }
+        Ok(ProofInstruction::VerifyBatchedRangeProofU256)
+    }
+}
+
+impl fmt::Display for ProofSupport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ZK Token Proof ({}), transfer with fee proofs {}",
+            zk_token_proof_program::id(),
+            if self.transfer_with_fee {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        )
+    }
+}
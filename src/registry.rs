@@ -0,0 +1,112 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    pubkey::Pubkey,
+    system_program,
+};
+use spl_token_2022::solana_zk_token_sdk::zk_token_elgamal::pod::ElGamalPubkey;
+use std::error::Error;
+
+// SPL ElGamal registry program, where wallets publish the ElGamal pubkey others should encrypt to
+pub const REGISTRY_PROGRAM_ID: Pubkey = pubkey!("regVYJW7tcT8zipN5YiBvHsvR5jXW1uLFxaHSbugABg");
+
+// Seed of the registry account address, derived per wallet
+const REGISTRY_ADDRESS_SEED: &[u8] = b"elgamal-registry";
+
+// Registry account data: owner (32 bytes) followed by the ElGamal pubkey (32 bytes)
+pub const REGISTRY_ACCOUNT_LEN: usize = 64;
+
+// Instruction tags of the registry program
+const CREATE_REGISTRY: u8 = 0;
+const UPDATE_REGISTRY: u8 = 1;
+
+// An ElGamal pubkey published by a wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElGamalRegistry {
+    pub owner: Pubkey,
+    pub elgamal_pubkey: ElGamalPubkey,
+}
+
+impl ElGamalRegistry {
+    pub fn unpack(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if data.len() != REGISTRY_ACCOUNT_LEN {
+            return Err(format!(
+                "Invalid ElGamal registry account, expected {} bytes but got {}",
+                REGISTRY_ACCOUNT_LEN,
+                data.len()
+            )
+            .into());
+        }
+        Ok(Self {
+            owner: Pubkey::try_from(&data[..32])?,
+            elgamal_pubkey: ElGamalPubkey(data[32..].try_into()?),
+        })
+    }
+}
+
+pub fn get_registry_address(wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[REGISTRY_ADDRESS_SEED, wallet.as_ref()],
+        &REGISTRY_PROGRAM_ID,
+    )
+    .0
+}
+
+// The ElGamal pubkey published by a wallet, `None` if the wallet has no registry account
+pub fn fetch_registry(
+    client: &RpcClient,
+    wallet: &Pubkey,
+) -> Result<Option<ElGamalRegistry>, Box<dyn Error>> {
+    let registry_address = get_registry_address(wallet);
+    let account = client
+        .get_account_with_commitment(&registry_address, client.commitment())?
+        .value;
+
+    match account {
+        Some(account) if account.owner == REGISTRY_PROGRAM_ID => {
+            let registry = ElGamalRegistry::unpack(&account.data)?;
+            if registry.owner != *wallet {
+                return Err(format!(
+                    "ElGamal registry {} belongs to {}, not {}",
+                    registry_address, registry.owner, wallet
+                )
+                .into());
+            }
+            Ok(Some(registry))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Instruction to publish the owner's ElGamal pubkey.
+//
+// The registry program checks a pubkey validity proof from the zk-elgamal-proof program, which is pre-verified
+// into `proof_context_account`. Proofs generated by the zk-token-sdk used by the flows use a different transcript,
+// so the proof account must come from a zk-elgamal-proof compatible prover.
+pub fn create_registry(owner: &Pubkey, proof_context_account: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: REGISTRY_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(get_registry_address(owner), false),
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*proof_context_account, false),
+        ],
+        // A proof instruction offset of 0 tells the program to read the proof from the context state account
+        data: vec![CREATE_REGISTRY, 0],
+    }
+}
+
+// Instruction to replace the owner's published ElGamal pubkey, with the same proof requirements as `create_registry`
+pub fn update_registry(owner: &Pubkey, proof_context_account: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: REGISTRY_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(get_registry_address(owner), false),
+            AccountMeta::new_readonly(*proof_context_account, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+        data: vec![UPDATE_REGISTRY, 0],
+    }
+}
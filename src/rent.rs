@@ -0,0 +1,92 @@
+use crate::{pausable::PAUSABLE_EXTENSION_LEN, ui_amount::SCALED_UI_AMOUNT_EXTENSION_LEN};
+use bytemuck::Pod;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{account::from_account, program_pack::Pack, rent::Rent, sysvar};
+use spl_token_2022::{
+    extension::ExtensionType,
+    solana_zk_token_sdk::zk_token_proof_state::ProofContextState,
+    state::{Account, Mint},
+};
+use std::{error::Error, mem::size_of};
+
+// Size of an account about to be created, and the lamports it must hold to be rent exempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountRent {
+    pub space: usize,
+    pub lamports: u64,
+}
+
+// Extensions of a mint, including those the token-2022 version used here has no `ExtensionType` for
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MintLayout {
+    pub extensions: Vec<ExtensionType>,
+    pub scaled_ui_amount: bool,
+    pub pausable: bool,
+}
+
+impl MintLayout {
+    // Exact size of the mint account, to create it with
+    pub fn space(&self) -> Result<usize, Box<dyn Error>> {
+        let mut extra = 0;
+        if self.scaled_ui_amount {
+            extra += SCALED_UI_AMOUNT_EXTENSION_LEN;
+        }
+        if self.pausable {
+            extra += PAUSABLE_EXTENSION_LEN;
+        }
+        if extra > 0 && self.extensions.is_empty() {
+            // Extensions start after the mint padded to the size of a token account, and the account type byte
+            return Ok(Account::LEN + 1 + extra);
+        }
+        Ok(ExtensionType::try_calculate_account_len::<Mint>(&self.extensions)? + extra)
+    }
+}
+
+// Exact size of a token account with the extensions
+pub fn token_account_space(extensions: &[ExtensionType]) -> Result<usize, Box<dyn Error>> {
+    Ok(ExtensionType::try_calculate_account_len::<Account>(
+        extensions,
+    )?)
+}
+
+// Size of a proof context state account holding the context `T` of a verified proof
+pub fn proof_account_space<T: Pod>() -> usize {
+    size_of::<ProofContextState<T>>()
+}
+
+// Rent-exempt minimums computed locally from the cluster's Rent sysvar, fetched once,
+// so sizing the accounts of a whole flow takes a single RPC call
+#[derive(Debug, Clone)]
+pub struct RentCalculator {
+    pub rent: Rent,
+}
+
+impl RentCalculator {
+    pub fn fetch(client: &RpcClient) -> Result<Self, Box<dyn Error>> {
+        let account = client.get_account(&sysvar::rent::id())?;
+        let rent = from_account::<Rent, _>(&account).ok_or("Invalid Rent sysvar")?;
+        Ok(Self { rent })
+    }
+
+    pub fn account(&self, space: usize) -> AccountRent {
+        AccountRent {
+            space,
+            lamports: self.rent.minimum_balance(space),
+        }
+    }
+
+    pub fn mint(&self, layout: &MintLayout) -> Result<AccountRent, Box<dyn Error>> {
+        Ok(self.account(layout.space()?))
+    }
+
+    pub fn token_account(
+        &self,
+        extensions: &[ExtensionType],
+    ) -> Result<AccountRent, Box<dyn Error>> {
+        Ok(self.account(token_account_space(extensions)?))
+    }
+
+    pub fn proof_account<T: Pod>(&self) -> AccountRent {
+        self.account(proof_account_space::<T>())
+    }
+}
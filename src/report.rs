@@ -0,0 +1,131 @@
+use crate::price::FiatPrice;
+use serde_json::{json, Value};
+use solana_sdk::{native_token::lamports_to_sol, signature::Signature};
+use std::fmt;
+
+// Fee paid by a single sent transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionFee {
+    pub signature: Signature,
+    pub lamports: u64,
+}
+
+// Lamports spent on rent and transaction fees during a flow, and rent reclaimed from closed accounts
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CostReport {
+    pub rent_paid: u64,
+    pub fees_paid: u64,
+    pub rent_reclaimed: u64,
+    // Every transaction sent during the flow, in order
+    pub transactions: Vec<TransactionFee>,
+}
+
+impl CostReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Rent deposited into a newly created account (mint, token account or proof context state account)
+    pub fn record_rent(&mut self, lamports: u64) {
+        self.rent_paid += lamports;
+    }
+
+    pub fn record_fee(&mut self, signature: Signature, lamports: u64) {
+        self.fees_paid += lamports;
+        self.transactions.push(TransactionFee {
+            signature,
+            lamports,
+        });
+    }
+
+    // Lamports returned when an account is closed
+    pub fn record_reclaimed(&mut self, lamports: u64) {
+        self.rent_reclaimed += lamports;
+    }
+
+    // Add the costs of another report, e.g. of flows run in parallel with their own context
+    pub fn merge(&mut self, other: CostReport) {
+        self.rent_paid += other.rent_paid;
+        self.fees_paid += other.fees_paid;
+        self.rent_reclaimed += other.rent_reclaimed;
+        self.transactions.extend(other.transactions);
+    }
+
+    // Lamports that have left the payers for good: fees plus any rent not yet reclaimed
+    pub fn net_cost(&self) -> i64 {
+        (self.rent_paid + self.fees_paid) as i64 - self.rent_reclaimed as i64
+    }
+
+    pub fn to_json(&self, fiat: Option<&FiatPrice>) -> Value {
+        let transactions: Vec<Value> = self
+            .transactions
+            .iter()
+            .map(|fee| json!({ "signature": fee.signature.to_string(), "fee": fee.lamports }))
+            .collect();
+
+        let mut report = json!({
+            "rent_paid": self.rent_paid,
+            "fees_paid": self.fees_paid,
+            "fees_paid_sol": lamports_to_sol(self.fees_paid),
+            "rent_reclaimed": self.rent_reclaimed,
+            "net_cost": self.net_cost(),
+            "transactions": transactions,
+        });
+        if let Some(fiat) = fiat {
+            report["fees_paid_fiat"] = json!({
+                "amount": fiat.estimate(lamports_to_sol(self.fees_paid)),
+                "currency": fiat.currency,
+                "sol_price": fiat.price,
+            });
+        }
+        report
+    }
+
+    // Print the report at the end of a flow, either human readable or as a JSON object
+    pub fn print(&self, json: bool, fiat: Option<&FiatPrice>) {
+        if json {
+            println!("{}", json!({ "report": self.to_json(fiat) }));
+            return;
+        }
+
+        println!("\n{}", self);
+        if let Some(fiat) = fiat {
+            println!(
+                "  Fees Estimate:  {:.4} {} (at {} {}/SOL)",
+                fiat.estimate(lamports_to_sol(self.fees_paid)),
+                fiat.currency,
+                fiat.price,
+                fiat.currency
+            );
+        }
+    }
+}
+
+impl fmt::Display for CostReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Cost Report")?;
+        writeln!(
+            f,
+            "  Rent Paid:      {}",
+            format_lamports(self.rent_paid as i64)
+        )?;
+        writeln!(
+            f,
+            "  Fees Paid:      {} across {} transactions",
+            format_lamports(self.fees_paid as i64),
+            self.transactions.len()
+        )?;
+        writeln!(
+            f,
+            "  Rent Reclaimed: {}",
+            format_lamports(self.rent_reclaimed as i64)
+        )?;
+        write!(f, "  Net Cost:       {}", format_lamports(self.net_cost()))
+    }
+}
+
+fn format_lamports(lamports: i64) -> String {
+    let sol = lamports_to_sol(lamports.unsigned_abs());
+    let sign = if lamports < 0 { "-" } else { "" };
+    format!("{}{} SOL ({} lamports)", sign, sol, lamports)
+}
@@ -0,0 +1,423 @@
+use crate::journal::now;
+use rusqlite::{params, Connection, Row};
+use serde_json::{json, Value};
+use solana_client::{client_error::ClientError, rpc_client::RpcClient};
+use solana_sdk::{
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+use std::{error::Error, fmt, path::Path, str::FromStr, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryStatus {
+    // Waiting for its next attempt
+    Queued,
+    Landed,
+    // The blockhash expired and the transaction can't be re-signed with the keypairs at hand
+    Expired,
+    // Rejected by the cluster, or still failing after the last attempt
+    GaveUp,
+}
+
+impl RetryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetryStatus::Queued => "queued",
+            RetryStatus::Landed => "landed",
+            RetryStatus::Expired => "expired",
+            RetryStatus::GaveUp => "gave-up",
+        }
+    }
+}
+
+impl FromStr for RetryStatus {
+    type Err = Box<dyn Error>;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "queued" => Ok(RetryStatus::Queued),
+            "landed" => Ok(RetryStatus::Landed),
+            "expired" => Ok(RetryStatus::Expired),
+            "gave-up" => Ok(RetryStatus::GaveUp),
+            other => Err(format!("Unknown retry status {:?}", other).into()),
+        }
+    }
+}
+
+// Delay before each attempt, doubling from `base` up to `max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryBackoff {
+    pub base: Duration,
+    pub max: Duration,
+    // Attempts after which a transaction that keeps failing is given up on
+    pub max_attempts: u32,
+}
+
+impl Default for RetryBackoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_secs(5),
+            max: Duration::from_secs(300),
+            max_attempts: 20,
+        }
+    }
+}
+
+impl RetryBackoff {
+    pub fn delay(&self, attempts: u32) -> Duration {
+        self.base
+            .saturating_mul(2u32.saturating_pow(attempts.min(16)))
+            .min(self.max)
+    }
+}
+
+// A failed transaction kept in the queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedTransaction {
+    pub id: i64,
+    // Seconds since the unix epoch
+    pub queued_at: u64,
+    pub flow: String,
+    pub label: String,
+    // As last signed, or unsigned if the send failed before a blockhash could be fetched
+    pub transaction: Transaction,
+    // Last block height at which the signed transaction can land, 0 if it was never signed
+    pub last_valid_block_height: u64,
+    pub attempts: u32,
+    // Seconds since the unix epoch
+    pub next_attempt_at: u64,
+    pub status: RetryStatus,
+    pub error: Option<String>,
+}
+
+impl QueuedTransaction {
+    // Missing until the transaction has been signed
+    pub fn signature(&self) -> Option<Signature> {
+        self.transaction
+            .signatures
+            .first()
+            .filter(|signature| **signature != Signature::default())
+            .copied()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "queued_at": self.queued_at,
+            "flow": self.flow,
+            "label": self.label,
+            "signature": self.signature().map(|signature| signature.to_string()),
+            "last_valid_block_height": self.last_valid_block_height,
+            "attempts": self.attempts,
+            "next_attempt_at": self.next_attempt_at,
+            "status": self.status.as_str(),
+            "error": self.error,
+        })
+    }
+}
+
+impl fmt::Display for QueuedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "#{} {} [{}] {}: {}",
+            self.id,
+            self.queued_at,
+            self.flow,
+            self.label,
+            self.status.as_str()
+        )?;
+        match self.signature() {
+            Some(signature) => writeln!(f, "  Signature: {}", signature)?,
+            None => writeln!(f, "  Signature: -")?,
+        }
+        write!(f, "  Attempts:  {}", self.attempts)?;
+        if self.status == RetryStatus::Queued {
+            write!(f, ", next at {}", self.next_attempt_at)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, "\n  Error:     {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// What a retry did with a queued transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetryOutcome {
+    Landed(Signature),
+    // Failed again, with the delay before the next attempt
+    Rescheduled(Duration),
+    Expired,
+    GaveUp,
+}
+
+// Transactions that failed to send because the RPC node was unreachable, kept in SQLite next to the journal
+// so a long running process like `watch` can send them again once the node is back, even after a restart.
+//
+// A transaction is resent as signed while its blockhash is valid. Once the blockhash has expired, and the
+// transaction is known not to have landed, it is re-signed with a fresh blockhash if every signer is at hand,
+// otherwise it is marked expired. None of the flows use durable nonces, so the blockhash is the only expiry.
+pub struct RetryQueue {
+    connection: Connection,
+    pub backoff: RetryBackoff,
+}
+
+impl RetryQueue {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS retry_queue (
+                id                      INTEGER PRIMARY KEY AUTOINCREMENT,
+                queued_at               INTEGER NOT NULL,
+                flow                    TEXT NOT NULL,
+                label                   TEXT NOT NULL,
+                transaction_data        BLOB NOT NULL,
+                last_valid_block_height INTEGER NOT NULL,
+                attempts                INTEGER NOT NULL,
+                next_attempt_at         INTEGER NOT NULL,
+                status                  TEXT NOT NULL,
+                error                   TEXT
+            );",
+        )?;
+        Ok(Self {
+            connection,
+            backoff: RetryBackoff::default(),
+        })
+    }
+
+    // Queue a failed transaction for its first retry after `backoff.base`, returning its id
+    pub fn enqueue(
+        &self,
+        flow: &str,
+        label: &str,
+        transaction: &Transaction,
+        last_valid_block_height: u64,
+        error: &str,
+    ) -> Result<i64, Box<dyn Error>> {
+        let queued_at = now();
+        self.connection.execute(
+            "INSERT INTO retry_queue (queued_at, flow, label, transaction_data, last_valid_block_height,
+                                      attempts, next_attempt_at, status, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7, ?8)",
+            params![
+                queued_at,
+                flow,
+                label,
+                bincode::serialize(transaction)?,
+                last_valid_block_height,
+                queued_at + self.backoff.base.as_secs(),
+                RetryStatus::Queued.as_str(),
+                error,
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    // Queued transactions, newest first, optionally only those with a status
+    pub fn entries(
+        &self,
+        status: Option<RetryStatus>,
+    ) -> Result<Vec<QueuedTransaction>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "SELECT * FROM retry_queue WHERE ?1 IS NULL OR status = ?1 ORDER BY id DESC",
+        )?;
+        let rows = statement.query_map(params![status.map(|status| status.as_str())], read_row)?;
+        rows.map(|row| parse_row(row?)).collect()
+    }
+
+    // Delete the entries that landed, expired or were given up on, queued before `before` (seconds since the unix epoch),
+    // returning how many were deleted
+    pub fn purge_finished(&self, before: u64) -> Result<usize, Box<dyn Error>> {
+        Ok(self.connection.execute(
+            "DELETE FROM retry_queue WHERE status != ?1 AND queued_at < ?2",
+            params![RetryStatus::Queued.as_str(), before],
+        )?)
+    }
+
+    // Queued transactions whose next attempt is due, oldest first
+    pub fn due(&self) -> Result<Vec<QueuedTransaction>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "SELECT * FROM retry_queue WHERE status = ?1 AND next_attempt_at <= ?2 ORDER BY id",
+        )?;
+        let rows = statement.query_map(params![RetryStatus::Queued.as_str(), now()], read_row)?;
+        rows.map(|row| parse_row(row?)).collect()
+    }
+
+    // Attempt every due transaction once, re-signing expired ones with `signers` when they are the transaction's signers
+    pub fn retry_due(
+        &self,
+        client: &RpcClient,
+        signers: &[&Keypair],
+    ) -> Result<Vec<(QueuedTransaction, RetryOutcome)>, Box<dyn Error>> {
+        let mut outcomes = Vec::new();
+        for mut queued in self.due()? {
+            let outcome = self.retry(client, &mut queued, signers)?;
+            outcomes.push((queued, outcome));
+        }
+        Ok(outcomes)
+    }
+
+    fn retry(
+        &self,
+        client: &RpcClient,
+        queued: &mut QueuedTransaction,
+        signers: &[&Keypair],
+    ) -> Result<RetryOutcome, Box<dyn Error>> {
+        queued.attempts += 1;
+
+        // The block height is read before the status, so a transaction not found past its last valid height
+        // can no longer land and is safe to re-sign
+        let block_height = client.get_block_height()?;
+        if let Some(signature) = queued.signature() {
+            let status = client
+                .get_signature_statuses_with_history(&[signature])?
+                .value
+                .into_iter()
+                .next()
+                .flatten();
+            match status.map(|status| status.err) {
+                Some(None) => return self.finish(queued, RetryStatus::Landed, None),
+                Some(Some(error)) => {
+                    return self.finish(queued, RetryStatus::GaveUp, Some(error.to_string()))
+                }
+                None => {}
+            }
+        }
+
+        if block_height > queued.last_valid_block_height {
+            let message_signers = queued.transaction.message.signer_keys();
+            let keypairs: Vec<&Keypair> = message_signers
+                .iter()
+                .filter_map(|pubkey| signers.iter().find(|signer| signer.pubkey() == **pubkey))
+                .copied()
+                .collect();
+            if keypairs.len() < message_signers.len() {
+                return self.finish(queued, RetryStatus::Expired, None);
+            }
+
+            let (blockhash, last_valid_block_height) =
+                match client.get_latest_blockhash_with_commitment(client.commitment()) {
+                    Ok(latest) => latest,
+                    Err(error) => return self.reschedule(queued, &error),
+                };
+            queued.transaction.try_sign(&keypairs, blockhash)?;
+            queued.last_valid_block_height = last_valid_block_height;
+        }
+
+        match client.send_and_confirm_transaction(&queued.transaction) {
+            Ok(_) => self.finish(queued, RetryStatus::Landed, None),
+            Err(error) if error.get_transaction_error().is_some() => {
+                self.finish(queued, RetryStatus::GaveUp, Some(error.to_string()))
+            }
+            Err(error) => self.reschedule(queued, &error),
+        }
+    }
+
+    fn reschedule(
+        &self,
+        queued: &mut QueuedTransaction,
+        error: &ClientError,
+    ) -> Result<RetryOutcome, Box<dyn Error>> {
+        if queued.attempts >= self.backoff.max_attempts {
+            return self.finish(queued, RetryStatus::GaveUp, Some(error.to_string()));
+        }
+        let delay = self.backoff.delay(queued.attempts);
+        queued.next_attempt_at = now() + delay.as_secs();
+        queued.error = Some(error.to_string());
+        self.update(queued)?;
+        Ok(RetryOutcome::Rescheduled(delay))
+    }
+
+    fn finish(
+        &self,
+        queued: &mut QueuedTransaction,
+        status: RetryStatus,
+        error: Option<String>,
+    ) -> Result<RetryOutcome, Box<dyn Error>> {
+        queued.status = status;
+        if error.is_some() {
+            queued.error = error;
+        }
+        self.update(queued)?;
+        Ok(match status {
+            RetryStatus::Landed => RetryOutcome::Landed(queued.transaction.signatures[0]),
+            RetryStatus::Expired => RetryOutcome::Expired,
+            RetryStatus::Queued | RetryStatus::GaveUp => RetryOutcome::GaveUp,
+        })
+    }
+
+    fn update(&self, queued: &QueuedTransaction) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "UPDATE retry_queue
+             SET transaction_data = ?2, last_valid_block_height = ?3, attempts = ?4, next_attempt_at = ?5,
+                 status = ?6, error = ?7
+             WHERE id = ?1",
+            params![
+                queued.id,
+                bincode::serialize(&queued.transaction)?,
+                queued.last_valid_block_height,
+                queued.attempts,
+                queued.next_attempt_at,
+                queued.status.as_str(),
+                queued.error,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+type QueueRow = (
+    i64,
+    u64,
+    String,
+    String,
+    Vec<u8>,
+    u64,
+    u32,
+    u64,
+    String,
+    Option<String>,
+);
+
+fn read_row(row: &Row<'_>) -> rusqlite::Result<QueueRow> {
+    Ok((
+        row.get("id")?,
+        row.get("queued_at")?,
+        row.get("flow")?,
+        row.get("label")?,
+        row.get("transaction_data")?,
+        row.get("last_valid_block_height")?,
+        row.get("attempts")?,
+        row.get("next_attempt_at")?,
+        row.get("status")?,
+        row.get("error")?,
+    ))
+}
+
+fn parse_row(row: QueueRow) -> Result<QueuedTransaction, Box<dyn Error>> {
+    let (
+        id,
+        queued_at,
+        flow,
+        label,
+        transaction,
+        last_valid_block_height,
+        attempts,
+        next_attempt_at,
+        status,
+        error,
+    ) = row;
+    Ok(QueuedTransaction {
+        id,
+        queued_at,
+        flow,
+        label,
+        transaction: bincode::deserialize(&transaction)?,
+        last_valid_block_height,
+        attempts,
+        next_attempt_at,
+        status: status.parse()?,
+        error,
+    })
+}
@@ -0,0 +1,458 @@
+use crate::journal::now;
+use chrono::DateTime;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde_json::{json, Value};
+use solana_sdk::{clock::DEFAULT_MS_PER_SLOT, pubkey::Pubkey, signature::Signature};
+use std::{error::Error, fmt, path::Path, str::FromStr, time::Duration};
+
+// When a scheduled request is executed: at a unix timestamp, or once the cluster reaches a slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecuteAt {
+    Timestamp(u64),
+    Slot(u64),
+}
+
+impl ExecuteAt {
+    pub fn is_due(&self, now: u64, slot: u64) -> bool {
+        match self {
+            ExecuteAt::Timestamp(timestamp) => now >= *timestamp,
+            ExecuteAt::Slot(target) => slot >= *target,
+        }
+    }
+
+    // Whether execution is at most `lead` away. Slots are counted at the cluster's target slot time,
+    // real slots are usually a little slower
+    pub fn is_near(&self, now: u64, slot: u64, lead: Duration) -> bool {
+        match self {
+            ExecuteAt::Timestamp(timestamp) => now + lead.as_secs() >= *timestamp,
+            ExecuteAt::Slot(target) => {
+                slot + (lead.as_millis() as u64) / DEFAULT_MS_PER_SLOT >= *target
+            }
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ExecuteAt::Timestamp(_) => "timestamp",
+            ExecuteAt::Slot(_) => "slot",
+        }
+    }
+
+    fn value(&self) -> u64 {
+        match self {
+            ExecuteAt::Timestamp(value) | ExecuteAt::Slot(value) => *value,
+        }
+    }
+
+    fn from_parts(kind: &str, value: u64) -> Result<Self, Box<dyn Error>> {
+        match kind {
+            "timestamp" => Ok(ExecuteAt::Timestamp(value)),
+            "slot" => Ok(ExecuteAt::Slot(value)),
+            other => Err(format!("Unknown execution time kind {:?}", other).into()),
+        }
+    }
+}
+
+// `slot:<slot>`, a unix timestamp, or an RFC 3339 date like 2024-06-01T12:00:00Z
+impl FromStr for ExecuteAt {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(slot) = value.strip_prefix("slot:") {
+            return slot
+                .parse()
+                .map(ExecuteAt::Slot)
+                .map_err(|_| format!("Invalid slot {:?}", slot));
+        }
+        if let Ok(timestamp) = value.parse() {
+            return Ok(ExecuteAt::Timestamp(timestamp));
+        }
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .and_then(|date| u64::try_from(date.timestamp()).ok())
+            .map(ExecuteAt::Timestamp)
+            .ok_or(format!(
+                "Invalid execution time {:?}, expected slot:<slot>, a unix timestamp or an RFC 3339 date",
+                value
+            ))
+    }
+}
+
+impl fmt::Display for ExecuteAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecuteAt::Timestamp(timestamp) => {
+                match i64::try_from(*timestamp)
+                    .ok()
+                    .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+                {
+                    Some(date) => write!(f, "{}", date.to_rfc3339()),
+                    None => write!(f, "{}", timestamp),
+                }
+            }
+            ExecuteAt::Slot(slot) => write!(f, "slot {}", slot),
+        }
+    }
+}
+
+// What a scheduled request does with its amount
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduledAction {
+    // Withdraw from the available balance to the public balance of the wallet's token account
+    Withdraw,
+    // Confidential transfer to a recipient token account
+    Transfer { recipient: Pubkey },
+}
+
+impl ScheduledAction {
+    fn kind(&self) -> &'static str {
+        match self {
+            ScheduledAction::Withdraw => "withdraw",
+            ScheduledAction::Transfer { .. } => "transfer",
+        }
+    }
+}
+
+impl fmt::Display for ScheduledAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduledAction::Withdraw => write!(f, "withdraw"),
+            ScheduledAction::Transfer { recipient } => write!(f, "transfer to {}", recipient),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleStatus {
+    // Waiting for its execution time
+    Scheduled,
+    // Its proofs were generated into the proof cache ahead of its execution time
+    Prepared,
+    // Its flow was started, and hadn't finished when the scheduler last ran
+    Executing,
+    Submitted,
+    Failed,
+    Cancelled,
+}
+
+impl ScheduleStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleStatus::Scheduled => "scheduled",
+            ScheduleStatus::Prepared => "prepared",
+            ScheduleStatus::Executing => "executing",
+            ScheduleStatus::Submitted => "submitted",
+            ScheduleStatus::Failed => "failed",
+            ScheduleStatus::Cancelled => "cancelled",
+        }
+    }
+
+    // Still to be executed
+    pub fn is_pending(&self) -> bool {
+        matches!(self, ScheduleStatus::Scheduled | ScheduleStatus::Prepared)
+    }
+}
+
+impl FromStr for ScheduleStatus {
+    type Err = Box<dyn Error>;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "scheduled" => Ok(ScheduleStatus::Scheduled),
+            "prepared" => Ok(ScheduleStatus::Prepared),
+            "executing" => Ok(ScheduleStatus::Executing),
+            "submitted" => Ok(ScheduleStatus::Submitted),
+            "failed" => Ok(ScheduleStatus::Failed),
+            "cancelled" => Ok(ScheduleStatus::Cancelled),
+            other => Err(format!("Unknown schedule status {:?}", other).into()),
+        }
+    }
+}
+
+// A withdraw or transfer waiting in the schedule, or executed from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledRequest {
+    pub id: i64,
+    // Seconds since the unix epoch
+    pub created_at: u64,
+    // Name of the .env keypair owning the token account
+    pub wallet: String,
+    pub mint: Pubkey,
+    pub action: ScheduledAction,
+    // In base units
+    pub amount: u64,
+    pub execute_at: ExecuteAt,
+    pub status: ScheduleStatus,
+    // Signature of the withdraw or transfer, once submitted
+    pub signature: Option<Signature>,
+    pub error: Option<String>,
+}
+
+impl ScheduledRequest {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "created_at": self.created_at,
+            "wallet": self.wallet,
+            "mint": self.mint.to_string(),
+            "action": self.action.kind(),
+            "recipient": match self.action {
+                ScheduledAction::Transfer { recipient } => Some(recipient.to_string()),
+                ScheduledAction::Withdraw => None,
+            },
+            "amount": self.amount,
+            "execute_at": self.execute_at.value(),
+            "execute_at_kind": self.execute_at.kind(),
+            "status": self.status.as_str(),
+            "signature": self.signature.map(|signature| signature.to_string()),
+            "error": self.error,
+        })
+    }
+}
+
+impl fmt::Display for ScheduledRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "#{} {} {} of {} by {}: {}",
+            self.id,
+            self.execute_at,
+            self.action,
+            self.amount,
+            self.wallet,
+            self.status.as_str()
+        )?;
+        write!(f, "  Mint:      {}", self.mint)?;
+        if let Some(signature) = &self.signature {
+            write!(f, "\n  Signature: {}", signature)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, "\n  Error:     {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// Withdraws and transfers to execute at a later time or slot, kept in SQLite next to the journal so the
+// scheduler (`flows::schedule`) picks them up again after a restart.
+//
+// Requests only name the wallet, never its keypair, which is read from the .env file when the request is executed.
+pub struct Schedule {
+    connection: Connection,
+}
+
+impl Schedule {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS schedule (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at      INTEGER NOT NULL,
+                wallet          TEXT NOT NULL,
+                mint            TEXT NOT NULL,
+                action          TEXT NOT NULL,
+                recipient       TEXT,
+                amount          INTEGER NOT NULL,
+                execute_at_kind TEXT NOT NULL,
+                execute_at      INTEGER NOT NULL,
+                status          TEXT NOT NULL,
+                signature       TEXT,
+                error           TEXT
+            );",
+        )?;
+        Ok(Self { connection })
+    }
+
+    // Schedule a request, returning its id
+    pub fn add(
+        &self,
+        wallet: &str,
+        mint: &Pubkey,
+        action: ScheduledAction,
+        amount: u64,
+        execute_at: ExecuteAt,
+    ) -> Result<i64, Box<dyn Error>> {
+        let recipient = match action {
+            ScheduledAction::Transfer { recipient } => Some(recipient.to_string()),
+            ScheduledAction::Withdraw => None,
+        };
+        self.connection.execute(
+            "INSERT INTO schedule (created_at, wallet, mint, action, recipient, amount, execute_at_kind,
+                                   execute_at, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                now(),
+                wallet,
+                mint.to_string(),
+                action.kind(),
+                recipient,
+                amount,
+                execute_at.kind(),
+                execute_at.value(),
+                ScheduleStatus::Scheduled.as_str(),
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<ScheduledRequest>, Box<dyn Error>> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT * FROM schedule WHERE id = ?1",
+                params![id],
+                read_row,
+            )
+            .optional()?;
+        row.map(parse_row).transpose()
+    }
+
+    // Scheduled requests, newest first, optionally only those with a status
+    pub fn entries(
+        &self,
+        status: Option<ScheduleStatus>,
+    ) -> Result<Vec<ScheduledRequest>, Box<dyn Error>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT * FROM schedule WHERE ?1 IS NULL OR status = ?1 ORDER BY id DESC")?;
+        let rows = statement.query_map(params![status.map(|status| status.as_str())], read_row)?;
+        rows.map(|row| parse_row(row?)).collect()
+    }
+
+    // Requests of the mint still to be executed, in the order they were added
+    pub fn pending(&self, mint: &Pubkey) -> Result<Vec<ScheduledRequest>, Box<dyn Error>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT * FROM schedule WHERE mint = ?1 AND status IN (?2, ?3) ORDER BY id")?;
+        let rows = statement.query_map(
+            params![
+                mint.to_string(),
+                ScheduleStatus::Scheduled.as_str(),
+                ScheduleStatus::Prepared.as_str()
+            ],
+            read_row,
+        )?;
+        rows.map(|row| parse_row(row?)).collect()
+    }
+
+    // Cancel a request that hasn't been executed yet
+    pub fn cancel(&self, id: i64) -> Result<(), Box<dyn Error>> {
+        let request = self
+            .get(id)?
+            .ok_or(format!("No scheduled request #{}", id))?;
+        if !request.status.is_pending() {
+            return Err(format!(
+                "Scheduled request #{} is {}, it can't be cancelled",
+                id,
+                request.status.as_str()
+            )
+            .into());
+        }
+        self.set_status(id, ScheduleStatus::Cancelled, None, None)
+    }
+
+    pub fn mark_prepared(&self, id: i64) -> Result<(), Box<dyn Error>> {
+        self.set_status(id, ScheduleStatus::Prepared, None, None)
+    }
+
+    // Recorded before the flow is started, so a scheduler killed halfway through never executes the request twice
+    pub fn mark_executing(&self, id: i64) -> Result<(), Box<dyn Error>> {
+        self.set_status(id, ScheduleStatus::Executing, None, None)
+    }
+
+    pub fn mark_submitted(&self, id: i64, signature: &Signature) -> Result<(), Box<dyn Error>> {
+        self.set_status(id, ScheduleStatus::Submitted, Some(signature), None)
+    }
+
+    pub fn mark_failed(&self, id: i64, error: &str) -> Result<(), Box<dyn Error>> {
+        self.set_status(id, ScheduleStatus::Failed, None, Some(error))
+    }
+
+    fn set_status(
+        &self,
+        id: i64,
+        status: ScheduleStatus,
+        signature: Option<&Signature>,
+        error: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "UPDATE schedule SET status = ?2, signature = COALESCE(?3, signature), error = COALESCE(?4, error)
+             WHERE id = ?1",
+            params![
+                id,
+                status.as_str(),
+                signature.map(|signature| signature.to_string()),
+                error
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+type ScheduleRow = (
+    i64,
+    u64,
+    String,
+    String,
+    String,
+    Option<String>,
+    u64,
+    String,
+    u64,
+    String,
+    Option<String>,
+    Option<String>,
+);
+
+fn read_row(row: &Row<'_>) -> rusqlite::Result<ScheduleRow> {
+    Ok((
+        row.get("id")?,
+        row.get("created_at")?,
+        row.get("wallet")?,
+        row.get("mint")?,
+        row.get("action")?,
+        row.get("recipient")?,
+        row.get("amount")?,
+        row.get("execute_at_kind")?,
+        row.get("execute_at")?,
+        row.get("status")?,
+        row.get("signature")?,
+        row.get("error")?,
+    ))
+}
+
+fn parse_row(row: ScheduleRow) -> Result<ScheduledRequest, Box<dyn Error>> {
+    let (
+        id,
+        created_at,
+        wallet,
+        mint,
+        action,
+        recipient,
+        amount,
+        execute_at_kind,
+        execute_at,
+        status,
+        signature,
+        error,
+    ) = row;
+    let action = match (action.as_str(), recipient) {
+        ("withdraw", _) => ScheduledAction::Withdraw,
+        ("transfer", Some(recipient)) => ScheduledAction::Transfer {
+            recipient: recipient.parse()?,
+        },
+        (other, _) => return Err(format!("Unknown scheduled action {:?}", other).into()),
+    };
+    Ok(ScheduledRequest {
+        id,
+        created_at,
+        wallet,
+        mint: mint.parse()?,
+        action,
+        amount,
+        execute_at: ExecuteAt::from_parts(&execute_at_kind, execute_at)?,
+        status: status.parse()?,
+        signature: signature.map(|signature| signature.parse()).transpose()?,
+        error,
+    })
+}
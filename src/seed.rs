@@ -0,0 +1,98 @@
+use solana_sdk::{
+    hash::hashv,
+    signer::{
+        keypair::{keypair_from_seed, Keypair},
+        SeedDerivable,
+    },
+};
+use spl_token_2022::solana_zk_token_sdk::encryption::elgamal::ElGamalKeypair;
+use std::{env, error::Error, fmt, str::FromStr};
+
+// Seed of the deterministic mode, read from SEED.
+//
+// Every keypair the client would otherwise generate at random is derived from the seed and a label instead:
+// wallets and mints saved to .env, proof context state accounts and the auditor ElGamal keypair.
+// Two runs with the same seed against a fresh .env and validator then create the same accounts
+// and send the same instructions.
+//
+// The blinding factors of the zero-knowledge proofs are drawn from `OsRng` inside the zk-token-sdk,
+// which has no way to pass another RNG, so proof data and the transactions carrying it still differ between runs.
+// Transactions also differ by their recent blockhash.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Seed([u8; 32]);
+
+impl Seed {
+    // The seed of the SEED variable, `None` to generate keypairs at random
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        dotenv::dotenv().ok();
+
+        match env::var("SEED") {
+            Ok(value) => Ok(Some(value.parse()?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // 32 bytes derived from the seed for a label, e.g. `keypair:wallet_1`
+    pub fn derive(&self, label: &str) -> [u8; 32] {
+        hashv(&[&self.0, label.as_bytes()]).to_bytes()
+    }
+
+    pub fn keypair(&self, label: &str) -> Result<Keypair, Box<dyn Error>> {
+        keypair_from_seed(&self.derive(&format!("keypair:{}", label)))
+    }
+
+    pub fn elgamal_keypair(&self, label: &str) -> Result<ElGamalKeypair, Box<dyn Error>> {
+        ElGamalKeypair::from_seed(&self.derive(&format!("elgamal:{}", label)))
+    }
+}
+
+impl FromStr for Seed {
+    type Err = String;
+
+    // Any non-empty string, hashed so short seeds like `42` can be passed around in bug reports
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.is_empty() {
+            return Err("SEED can't be empty".to_string());
+        }
+        Ok(Seed(
+            hashv(&[b"keypair_utils seed", value.as_bytes()]).to_bytes(),
+        ))
+    }
+}
+
+// The seed is as secret as the keypairs derived from it
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Seed(..)")
+    }
+}
+
+// Source of the keypairs generated during a run: random, or derived from a seed in the order they are requested
+#[derive(Debug, Default)]
+pub struct KeypairSource {
+    seed: Option<Seed>,
+    generated: u64,
+}
+
+impl KeypairSource {
+    pub fn new(seed: Option<Seed>) -> Self {
+        Self { seed, generated: 0 }
+    }
+
+    // A new keypair. Seeded keypairs are numbered, so a flow run twice gets different accounts both times.
+    pub fn keypair(&mut self, label: &str) -> Result<Keypair, Box<dyn Error>> {
+        let Some(seed) = &self.seed else {
+            return Ok(Keypair::new());
+        };
+        self.generated += 1;
+        seed.keypair(&format!("{}#{}", label, self.generated))
+    }
+
+    pub fn elgamal_keypair(&mut self, label: &str) -> Result<ElGamalKeypair, Box<dyn Error>> {
+        let Some(seed) = &self.seed else {
+            return Ok(ElGamalKeypair::new_rand());
+        };
+        self.generated += 1;
+        seed.elgamal_keypair(&format!("{}#{}", label, self.generated))
+    }
+}
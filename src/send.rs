@@ -0,0 +1,478 @@
+use crate::{
+    report::CostReport,
+    send_strategy::{RpcSend, SendStrategy},
+};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    rpc_request::RpcError,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    signature::Signature,
+    signers::Signers,
+    transaction::{Transaction, TransactionError},
+};
+use std::{
+    error::Error,
+    fmt,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+// Number of times a transaction is re-signed after its blockhash went stale before giving up
+const BLOCKHASH_RETRIES: usize = 3;
+
+// Finalization takes 32 slots after confirmation, about 13 seconds, unless the cluster is struggling
+const FINALIZATION_TIMEOUT: Duration = Duration::from_secs(90);
+const FINALIZATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// A processed transaction is confirmed within a few slots, or dropped once its blockhash expires after about a minute
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(90);
+const PROCESSED_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// Reported by `wait_for_commitment` when the confirmation timeout elapsed, turned into `Pending`
+const TIMED_OUT_MESSAGE: &str = "the transaction wasn't confirmed within the confirmation timeout";
+
+// Commitment a sent transaction must reach before the flow proceeds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WaitFor {
+    // Executed by the leader only, so the next transactions of a flow are sent before the cluster voted on it.
+    // Flows confirm these transactions before they finish (`FlowContext::reconcile`).
+    Processed,
+    // Voted on by a supermajority of the cluster, which is how every transaction is sent
+    #[default]
+    Confirmed,
+    // Rooted by a supermajority of the cluster and can no longer be rolled back
+    Finalized,
+}
+
+impl FromStr for WaitFor {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "processed" => Ok(WaitFor::Processed),
+            "confirmed" => Ok(WaitFor::Confirmed),
+            "finalized" => Ok(WaitFor::Finalized),
+            other => Err(format!(
+                "Unknown commitment {:?}, expected processed, confirmed or finalized",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for WaitFor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitFor::Processed => write!(f, "processed"),
+            WaitFor::Confirmed => write!(f, "confirmed"),
+            WaitFor::Finalized => write!(f, "finalized"),
+        }
+    }
+}
+
+// Sign the instructions with a recent blockhash, then send and confirm the transaction, adding its fee to the report.
+// The fee is quoted with `getFeeForMessage` right before signing, so the report holds the exact fee the cluster charges.
+// Proof generation can take long enough for a blockhash to go stale before the transaction lands,
+// so a transaction rejected for an unknown or expired blockhash is re-signed with a fresh one and sent again.
+pub fn send_and_confirm_instructions<T: Signers + ?Sized>(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    signers: &T,
+    report: &mut CostReport,
+) -> Result<Signature, Box<dyn Error>> {
+    send_instructions_with_commitment(
+        client,
+        instructions,
+        payer,
+        signers,
+        report,
+        client.commitment(),
+    )
+}
+
+// Like `send_and_confirm_instructions`, but only wait until the transaction reaches `commitment`,
+// which may be lower than the client's, e.g. processed to build on it right away
+pub fn send_instructions_with_commitment<T: Signers + ?Sized>(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    signers: &T,
+    report: &mut CostReport,
+    commitment: CommitmentConfig,
+) -> Result<Signature, Box<dyn Error>> {
+    send_instructions_with_strategy(
+        client,
+        &RpcSend,
+        instructions,
+        payer,
+        signers,
+        report,
+        commitment,
+        None,
+    )
+    .map(|sent| sent.signature)
+}
+
+// A transaction that reached the awaited commitment, and the block height its blockhash was valid to,
+// which tells whether it can still be dropped, e.g. when it was only processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SentTransaction {
+    pub signature: Signature,
+    pub last_valid_block_height: u64,
+}
+
+// Like `send_instructions_with_commitment`, submitting the transaction through `strategy`.
+// A tip the strategy asks for is appended to the instructions and recorded with the fee.
+// With a `timeout`, a transaction not confirmed in time fails with `Pending` instead of being waited on
+// until its blockhash expires.
+#[allow(clippy::too_many_arguments)]
+pub fn send_instructions_with_strategy<T: Signers + ?Sized>(
+    client: &RpcClient,
+    strategy: &dyn SendStrategy,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    signers: &T,
+    report: &mut CostReport,
+    commitment: CommitmentConfig,
+    timeout: Option<Duration>,
+) -> Result<SentTransaction, Box<dyn Error>> {
+    let mut instructions = instructions.to_vec();
+    let mut tip = 0;
+    if let Some((instruction, lamports)) = strategy.tip(payer) {
+        instructions.push(instruction);
+        tip = lamports;
+    }
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(payer));
+
+    let mut attempt = 0;
+    let mut last_valid_block_height = 0;
+    let (transaction_signature, fee) = loop {
+        // Fee of the message under the new blockhash, quoted before signing
+        let quote = match client.get_latest_blockhash_with_commitment(client.commitment()) {
+            Ok((blockhash, last_valid)) => {
+                let mut message = transaction.message.clone();
+                message.recent_blockhash = blockhash;
+                client
+                    .get_fee_for_message(&message)
+                    .map(|fee| (blockhash, last_valid, fee))
+            }
+            Err(error) => Err(error),
+        };
+        let (blockhash, fee) = match quote {
+            Ok((blockhash, last_valid, fee)) => {
+                last_valid_block_height = last_valid;
+                (blockhash, fee)
+            }
+            Err(error) => {
+                return Err(SendError {
+                    signature: (attempt > 0).then(|| transaction.signatures[0]),
+                    transaction,
+                    last_valid_block_height,
+                    error,
+                }
+                .into())
+            }
+        };
+        transaction.try_sign(signers, blockhash)?;
+
+        match strategy.send(client, &transaction, commitment, timeout) {
+            Ok(signature) => break (signature, fee),
+            Err(error) if is_timed_out(&error) => {
+                return Err(Pending {
+                    signature: transaction.signatures[0],
+                    transaction,
+                    last_valid_block_height,
+                    commitment,
+                }
+                .into())
+            }
+            Err(error) if is_stale_blockhash(&error) && attempt < BLOCKHASH_RETRIES => {
+                attempt += 1;
+                eprintln!(
+                    "\nBlockhash expired before the transaction landed through {}, re-signing (attempt {} of {})",
+                    strategy.name(),
+                    attempt,
+                    BLOCKHASH_RETRIES
+                );
+            }
+            Err(error) => {
+                return Err(SendError {
+                    signature: Some(transaction.signatures[0]),
+                    transaction,
+                    last_valid_block_height,
+                    error: *error,
+                }
+                .into())
+            }
+        }
+    };
+
+    // The tip is paid on top of the fee for the transaction to land, so it is reported as part of it
+    report.record_fee(transaction_signature, fee + tip);
+    Ok(SentTransaction {
+        signature: transaction_signature,
+        last_valid_block_height,
+    })
+}
+
+// Exact fee the cluster charges for a transaction of the instructions, quoted with `getFeeForMessage`
+// without signing or sending anything, e.g. to preview the cost of a run
+pub fn quote_fee(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+) -> Result<u64, Box<dyn Error>> {
+    let blockhash = client.get_latest_blockhash()?;
+    let message = Message::new_with_blockhash(instructions, Some(payer), &blockhash);
+    Ok(client.get_fee_for_message(&message)?)
+}
+
+// Poll the status of a sent transaction until it reaches `commitment`, failing with the transaction error
+// if it was rejected, or once its blockhash expired or `timeout` elapsed
+pub fn wait_for_commitment(
+    client: &RpcClient,
+    transaction: &Transaction,
+    signature: &Signature,
+    commitment: CommitmentConfig,
+    timeout: Option<Duration>,
+) -> Result<Signature, Box<ClientError>> {
+    let started = Instant::now();
+    loop {
+        match client.get_signature_status_with_commitment(signature, commitment)? {
+            Some(Ok(())) => return Ok(*signature),
+            Some(Err(error)) => return Err(Box::new(error.into())),
+            None => {}
+        }
+        // Same message as `send_and_confirm_transaction`, so an expired blockhash is re-signed the same way
+        if !client.is_blockhash_valid(&transaction.message.recent_blockhash, commitment)? {
+            return Err(Box::new(
+                ClientErrorKind::Custom(
+                    "unable to confirm transaction. The blockhash expired before it was processed"
+                        .to_string(),
+                )
+                .into(),
+            ));
+        }
+        if timeout.is_some_and(|timeout| started.elapsed() > timeout) {
+            return Err(Box::new(
+                ClientErrorKind::Custom(TIMED_OUT_MESSAGE.to_string()).into(),
+            ));
+        }
+        thread::sleep(PROCESSED_POLL_INTERVAL);
+    }
+}
+
+// Poll the status of a processed transaction until the cluster confirms it, failing if it was rejected,
+// or dropped with its fork
+pub fn wait_for_confirmed(client: &RpcClient, signature: &Signature) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    loop {
+        let status = client
+            .get_signature_statuses(&[*signature])?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
+        if let Some(status) = status {
+            if let Some(error) = status.err {
+                return Err(format!("Transaction {} failed: {}", signature, error).into());
+            }
+            if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                return Ok(());
+            }
+        }
+        if started.elapsed() > CONFIRMATION_TIMEOUT {
+            return Err(format!(
+                "Transaction {} was processed but not confirmed within {} seconds",
+                signature,
+                CONFIRMATION_TIMEOUT.as_secs()
+            )
+            .into());
+        }
+        thread::sleep(PROCESSED_POLL_INTERVAL);
+    }
+}
+
+// Poll the status of a confirmed transaction until the cluster finalizes it
+pub fn wait_for_finalized(client: &RpcClient, signature: &Signature) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    loop {
+        let status = client
+            .get_signature_statuses(&[*signature])?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
+        if status.is_some_and(|status| status.satisfies_commitment(CommitmentConfig::finalized())) {
+            return Ok(());
+        }
+        if started.elapsed() > FINALIZATION_TIMEOUT {
+            return Err(format!(
+                "Transaction {} was confirmed but not finalized within {} seconds",
+                signature,
+                FINALIZATION_TIMEOUT.as_secs()
+            )
+            .into());
+        }
+        thread::sleep(FINALIZATION_POLL_INTERVAL);
+    }
+}
+
+// A transaction that was rejected or never confirmed, keeping its signature so the failure can be looked up,
+// and the transaction itself so it can be queued and sent again (`retry_queue::RetryQueue`)
+#[derive(Debug)]
+pub struct SendError {
+    // Missing when the transaction failed before it could be signed
+    pub signature: Option<Signature>,
+    // Unsigned if no blockhash could be fetched
+    pub transaction: Transaction,
+    // Last block height at which the signed transaction can land, 0 if it was never signed
+    pub last_valid_block_height: u64,
+    pub error: ClientError,
+}
+
+impl SendError {
+    // Whether the transaction failed because the RPC node couldn't be reached or was unhealthy,
+    // rather than being rejected by the cluster, so sending it again later can succeed
+    pub fn is_transient(&self) -> bool {
+        match self.error.kind() {
+            ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+            ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => true,
+            ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+                *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+            }
+            // Blockhashes kept expiring through every re-sign, the cluster is likely congested
+            _ => is_stale_blockhash(&self.error),
+        }
+    }
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl Error for SendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// A transaction sent but not confirmed within the confirmation timeout. It can still land until the cluster
+// passes `last_valid_block_height`, so the caller can keep polling it with `check` rather than send it again.
+#[derive(Debug)]
+pub struct Pending {
+    pub signature: Signature,
+    pub transaction: Transaction,
+    pub last_valid_block_height: u64,
+    // Commitment the transaction was waited on for
+    pub commitment: CommitmentConfig,
+}
+
+impl Pending {
+    pub fn check(&self, client: &RpcClient) -> Result<LandingStatus, Box<dyn Error>> {
+        landing_status(
+            client,
+            &self.signature,
+            Some(self.last_valid_block_height),
+            self.commitment,
+        )
+    }
+}
+
+// Where a sent transaction stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LandingStatus {
+    // Landed and reached the commitment
+    Confirmed,
+    Rejected(TransactionError),
+    // Not landed yet, and its blockhash is still valid, or its expiry isn't known
+    Pending,
+    // Its blockhash expired without it landing, so it never will and can be signed again
+    Expired,
+}
+
+impl LandingStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LandingStatus::Confirmed => "confirmed",
+            LandingStatus::Rejected(_) => "rejected",
+            LandingStatus::Pending => "pending",
+            LandingStatus::Expired => "expired",
+        }
+    }
+}
+
+// Classify a sent transaction from its status at `commitment` and the cluster's block height.
+// The block height is read before the status: a transaction still missing once the cluster passed
+// `last_valid_block_height` can't land anymore, so `Expired` is final. The status is looked up in
+// the ledger history too, so transactions older than the status cache are still found.
+pub fn landing_status(
+    client: &RpcClient,
+    signature: &Signature,
+    last_valid_block_height: Option<u64>,
+    commitment: CommitmentConfig,
+) -> Result<LandingStatus, Box<dyn Error>> {
+    let block_height = client.get_block_height_with_commitment(commitment)?;
+    let status = client
+        .get_signature_statuses_with_history(&[*signature])?
+        .value
+        .into_iter()
+        .next()
+        .flatten();
+    match status {
+        Some(status) if status.satisfies_commitment(commitment) => {
+            return Ok(match status.err {
+                None => LandingStatus::Confirmed,
+                Some(error) => LandingStatus::Rejected(error),
+            })
+        }
+        // Landed, but not at the commitment yet
+        Some(_) => return Ok(LandingStatus::Pending),
+        None => {}
+    }
+    match last_valid_block_height {
+        Some(last_valid_block_height) if block_height > last_valid_block_height => {
+            Ok(LandingStatus::Expired)
+        }
+        _ => Ok(LandingStatus::Pending),
+    }
+}
+
+impl fmt::Display for Pending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Transaction {} was sent but not confirmed in time, it can still land until block height {}",
+            self.signature, self.last_valid_block_height
+        )
+    }
+}
+
+impl Error for Pending {}
+
+// Whether waiting for the transaction stopped at the confirmation timeout
+fn is_timed_out(error: &ClientError) -> bool {
+    matches!(error.kind(), ClientErrorKind::Custom(message) if message == TIMED_OUT_MESSAGE)
+}
+
+// Whether the transaction failed only because its blockhash was unknown to the cluster or expired
+// before confirmation, in which case it can never land and is safe to re-sign
+fn is_stale_blockhash(error: &ClientError) -> bool {
+    match error.get_transaction_error() {
+        Some(TransactionError::BlockhashNotFound) => true,
+        Some(_) => false,
+        // `send_and_confirm_transaction` gives up with a plain message once the blockhash expires
+        None => error.to_string().contains("unable to confirm transaction"),
+    }
+}
@@ -0,0 +1,313 @@
+use crate::send::wait_for_commitment;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::json;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_config::RpcSendTransactionConfig,
+    rpc_request::RpcRequest,
+    tpu_client::{TpuClient, TpuClientConfig},
+};
+use solana_quic_client::{QuicConfig, QuicConnectionManager, QuicPool};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, instruction::Instruction, pubkey, pubkey::Pubkey,
+    signature::Signature, system_instruction, transaction::Transaction,
+};
+use std::{
+    error::Error,
+    fmt,
+    str::FromStr,
+    sync::{
+        mpsc::{self, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+pub const DEFAULT_JITO_URL: &str = "https://mainnet.block-engine.jito.wtf/api/v1/bundles";
+
+// One of the accounts the Jito block engine accepts tips on, bundles without a tip aren't forwarded
+pub const JITO_TIP_ACCOUNT: Pubkey = pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5");
+
+// Smallest tip the block engine accepts
+pub const DEFAULT_JITO_TIP_LAMPORTS: u64 = 1_000;
+
+// How a signed transaction reaches the cluster. `send::send_instructions_with_strategy` quotes the fee, signs
+// and re-signs on stale blockhashes, the strategy only submits the transaction and waits until it reaches
+// `commitment`, returning the transaction error if it was rejected. With a `timeout`, the wait must give up
+// once it elapsed, through `send::wait_for_commitment`.
+//
+// Implement it to add a submission backend, e.g. a private relay, and set it on `FlowContext::send_strategy`.
+pub trait SendStrategy {
+    fn name(&self) -> &str;
+
+    // Instruction paying for the submission, appended to every transaction, and the lamports it pays
+    fn tip(&self, _payer: &Pubkey) -> Option<(Instruction, u64)> {
+        None
+    }
+
+    fn send(
+        &self,
+        client: &RpcClient,
+        transaction: &Transaction,
+        commitment: CommitmentConfig,
+        timeout: Option<Duration>,
+    ) -> Result<Signature, Box<ClientError>>;
+}
+
+// Send to the configured RPC node, with a preflight simulation at the awaited commitment
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RpcSend;
+
+impl SendStrategy for RpcSend {
+    fn name(&self) -> &str {
+        "rpc"
+    }
+
+    fn send(
+        &self,
+        client: &RpcClient,
+        transaction: &Transaction,
+        commitment: CommitmentConfig,
+        timeout: Option<Duration>,
+    ) -> Result<Signature, Box<ClientError>> {
+        if commitment == client.commitment() && timeout.is_none() {
+            return client
+                .send_and_confirm_transaction(transaction)
+                .map_err(Box::new);
+        }
+        let signature = send_with_preflight(client, transaction, commitment)?;
+        wait_for_commitment(client, transaction, &signature, commitment, timeout)
+    }
+}
+
+// Send to the configured RPC node, then to every other endpoint without preflight, so the transaction reaches
+// the leader even when one node's forwarding is congested. Only the configured node's rejection fails the send.
+pub struct BroadcastSend {
+    endpoints: Vec<RpcClient>,
+}
+
+impl BroadcastSend {
+    pub fn new(urls: &[String]) -> Self {
+        Self {
+            endpoints: urls.iter().map(|url| RpcClient::new(url.clone())).collect(),
+        }
+    }
+}
+
+impl SendStrategy for BroadcastSend {
+    fn name(&self) -> &str {
+        "broadcast"
+    }
+
+    fn send(
+        &self,
+        client: &RpcClient,
+        transaction: &Transaction,
+        commitment: CommitmentConfig,
+        timeout: Option<Duration>,
+    ) -> Result<Signature, Box<ClientError>> {
+        let signature = send_with_preflight(client, transaction, commitment)?;
+        for endpoint in &self.endpoints {
+            let sent = endpoint.send_transaction_with_config(
+                transaction,
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..RpcSendTransactionConfig::default()
+                },
+            );
+            if let Err(error) = sent {
+                eprintln!("\nCould not broadcast to {}: {}", endpoint.url(), error);
+            }
+        }
+        wait_for_commitment(client, transaction, &signature, commitment, timeout)
+    }
+}
+
+// Submit each transaction as a bundle of one through a Jito block engine, tipping `tip_lamports`.
+// Bundles skip the public mempool and land whole or not at all, the status is then polled through RPC.
+pub struct JitoBundleSend {
+    block_engine: RpcClient,
+    tip_lamports: u64,
+}
+
+impl JitoBundleSend {
+    pub fn new(url: &str, tip_lamports: u64) -> Self {
+        Self {
+            block_engine: RpcClient::new(url.to_string()),
+            tip_lamports,
+        }
+    }
+}
+
+impl SendStrategy for JitoBundleSend {
+    fn name(&self) -> &str {
+        "jito"
+    }
+
+    fn tip(&self, payer: &Pubkey) -> Option<(Instruction, u64)> {
+        Some((
+            system_instruction::transfer(payer, &JITO_TIP_ACCOUNT, self.tip_lamports),
+            self.tip_lamports,
+        ))
+    }
+
+    fn send(
+        &self,
+        client: &RpcClient,
+        transaction: &Transaction,
+        commitment: CommitmentConfig,
+        timeout: Option<Duration>,
+    ) -> Result<Signature, Box<ClientError>> {
+        let wire_transaction = bincode::serialize(transaction)
+            .map_err(|error| Box::new(ClientErrorKind::Custom(error.to_string()).into()))?;
+        let _bundle_id: String = self.block_engine.send(
+            RpcRequest::Custom {
+                method: "sendBundle",
+            },
+            json!([
+                [BASE64_STANDARD.encode(wire_transaction)],
+                { "encoding": "base64" }
+            ]),
+        )?;
+        wait_for_commitment(
+            client,
+            transaction,
+            &transaction.signatures[0],
+            commitment,
+            timeout,
+        )
+    }
+}
+
+type QuicTpuClient = TpuClient<QuicPool, QuicConnectionManager, QuicConfig>;
+
+// A transaction for the TPU thread, and where to reply whether it was sent
+type TpuRequest = (Transaction, Sender<Result<(), String>>);
+
+// Send straight to the QUIC ports of the current and upcoming leaders, skipping the RPC node's forwarding.
+// There is no preflight simulation, a transaction that fails is only noticed once its status is polled.
+//
+// The TPU client runs its own async runtime, so it lives on a thread of its own, where it can be created
+// and dropped from the async binaries too.
+pub struct TpuSend {
+    transactions: Sender<TpuRequest>,
+}
+
+impl TpuSend {
+    pub fn new(rpc_url: &str, ws_url: &str) -> Result<Self, Box<dyn Error>> {
+        let (transactions, received) = mpsc::channel::<TpuRequest>();
+        let (ready, connected) = mpsc::channel();
+        let rpc_url = rpc_url.to_string();
+        let ws_url = ws_url.to_string();
+        thread::spawn(move || {
+            let rpc_client = Arc::new(RpcClient::new(rpc_url));
+            let tpu_client =
+                match QuicTpuClient::new(rpc_client, &ws_url, TpuClientConfig::default()) {
+                    Ok(tpu_client) => {
+                        let _ = ready.send(Ok(()));
+                        tpu_client
+                    }
+                    Err(error) => {
+                        let _ = ready.send(Err(error.to_string()));
+                        return;
+                    }
+                };
+            for (transaction, reply) in received {
+                let sent = tpu_client
+                    .try_send_transaction(&transaction)
+                    .map_err(|error| error.to_string());
+                let _ = reply.send(sent);
+            }
+        });
+        connected
+            .recv()?
+            .map_err(|error| format!("Could not connect to the leaders' TPU: {}", error))?;
+        Ok(Self { transactions })
+    }
+}
+
+impl SendStrategy for TpuSend {
+    fn name(&self) -> &str {
+        "tpu"
+    }
+
+    fn send(
+        &self,
+        client: &RpcClient,
+        transaction: &Transaction,
+        commitment: CommitmentConfig,
+        timeout: Option<Duration>,
+    ) -> Result<Signature, Box<ClientError>> {
+        let (reply, sent) = mpsc::channel();
+        let custom = |message: String| Box::new(ClientErrorKind::Custom(message).into());
+        self.transactions
+            .send((transaction.clone(), reply))
+            .map_err(|_| custom("The TPU client stopped".to_string()))?;
+        sent.recv()
+            .map_err(|_| custom("The TPU client stopped".to_string()))?
+            .map_err(custom)?;
+        wait_for_commitment(
+            client,
+            transaction,
+            &transaction.signatures[0],
+            commitment,
+            timeout,
+        )
+    }
+}
+
+// Send with a preflight simulation at `commitment`, so the transaction can use accounts created by transactions
+// that aren't confirmed yet
+fn send_with_preflight(
+    client: &RpcClient,
+    transaction: &Transaction,
+    commitment: CommitmentConfig,
+) -> Result<Signature, Box<ClientError>> {
+    Ok(client.send_transaction_with_config(
+        transaction,
+        RpcSendTransactionConfig {
+            preflight_commitment: Some(commitment.commitment),
+            ..RpcSendTransactionConfig::default()
+        },
+    )?)
+}
+
+// Send strategy selected on the command line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SendStrategyKind {
+    #[default]
+    Rpc,
+    Broadcast,
+    Jito,
+    Tpu,
+}
+
+impl FromStr for SendStrategyKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "rpc" => Ok(SendStrategyKind::Rpc),
+            "broadcast" => Ok(SendStrategyKind::Broadcast),
+            "jito" => Ok(SendStrategyKind::Jito),
+            "tpu" => Ok(SendStrategyKind::Tpu),
+            other => Err(format!(
+                "Unknown send strategy {:?}, expected rpc, broadcast, jito or tpu",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SendStrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendStrategyKind::Rpc => write!(f, "rpc"),
+            SendStrategyKind::Broadcast => write!(f, "broadcast"),
+            SendStrategyKind::Jito => write!(f, "jito"),
+            SendStrategyKind::Tpu => write!(f, "tpu"),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    error::Error,
+    fmt, process,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Exit code of a process killed by SIGINT
+pub const INTERRUPTED_EXIT_CODE: u8 = 130;
+
+// Catch Ctrl-C so flows can stop at the next safe point and close the proof accounts they created,
+// instead of the process dying with their rent locked up. A second Ctrl-C exits right away.
+pub fn install() -> Result<(), Box<dyn Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    thread::spawn(move || {
+        runtime.block_on(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                    process::exit(INTERRUPTED_EXIT_CODE.into());
+                }
+                eprintln!(
+                    "\nInterrupted, stopping after the current transaction. Press Ctrl-C again to exit now."
+                );
+            }
+        })
+    });
+    Ok(())
+}
+
+// Whether Ctrl-C was pressed since `install`
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Returned by a flow stopped by Ctrl-C
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interrupted")
+    }
+}
+
+impl Error for Interrupted {}
@@ -0,0 +1,603 @@
+use crate::{
+    account_state::ConfidentialAccountState,
+    journal::{self, Journal, JournalEntry, JournalQuery},
+    key_derivation::KeyDerivation,
+    mint::DEFAULT_MINT,
+    read_keypair, write_keypair,
+};
+use aes_gcm_siv::{
+    aead::{Aead, NewAead},
+    Aes256GcmSiv, Key, Nonce,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use hmac::Hmac;
+use rand::{rngs::OsRng, RngCore};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use solana_client::{rpc_client::RpcClient, rpc_request::MAX_MULTIPLE_ACCOUNTS};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    signer::EncodableKey,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::solana_zk_token_sdk::encryption::{
+    auth_encryption::AeKey, elgamal::ElGamalKeypair,
+};
+use std::{env, error::Error, fs, path::Path};
+
+// Version of the snapshot format, snapshots of other versions are refused on import
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+// PBKDF2 rounds deriving the encryption key from the passphrase
+const PBKDF2_ROUNDS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+// A keypair of the .env file, by the name the binaries use (`mint:usdc`, stored as `mint.usdc`)
+#[derive(Debug)]
+pub struct NamedKeypair {
+    pub name: String,
+    pub keypair: Keypair,
+}
+
+// Keys of a token account, derived from its owner's keypair under a `KeyDerivation`
+#[derive(Debug)]
+pub struct EncryptionKeys {
+    pub token_account: Pubkey,
+    pub elgamal_keypair: ElGamalKeypair,
+    pub aes_key: AeKey,
+}
+
+impl EncryptionKeys {
+    pub fn derive(
+        owner: &Keypair,
+        token_account: &Pubkey,
+        derivation: &KeyDerivation,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            token_account: *token_account,
+            elgamal_keypair: derivation.elgamal_keypair(owner, token_account)?,
+            aes_key: derivation.aes_key(owner, token_account)?,
+        })
+    }
+
+    // Keys of a token account under the derivation it was configured with, see `KeyDerivation::account_keys`
+    pub fn fetch(
+        client: &RpcClient,
+        owner: &Keypair,
+        token_account: &Pubkey,
+        derivation: &KeyDerivation,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (elgamal_keypair, aes_key) = derivation.account_keys(client, owner, token_account)?;
+        Ok(Self {
+            token_account: *token_account,
+            elgamal_keypair,
+            aes_key,
+        })
+    }
+
+    fn aes_key_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        let json = self.aes_key.write(&mut bytes)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub(crate) fn to_json(&self) -> Result<Value, Box<dyn Error>> {
+        Ok(json!({
+            "token_account": self.token_account.to_string(),
+            "elgamal_keypair": self.elgamal_keypair.to_bytes().to_vec(),
+            "aes_key": self.aes_key_bytes()?,
+        }))
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let elgamal_keypair: Vec<u8> = serde_json::from_value(value["elgamal_keypair"].clone())?;
+        Ok(Self {
+            token_account: value["token_account"]
+                .as_str()
+                .ok_or("Encryption keys without a token account")?
+                .parse()?,
+            elgamal_keypair: ElGamalKeypair::from_bytes(&elgamal_keypair)
+                .ok_or("Invalid ElGamal keypair")?,
+            aes_key: AeKey::read(&mut value["aes_key"].to_string().as_bytes())?,
+        })
+    }
+
+    // Whether both keys are the same as another's
+    pub fn matches(&self, other: &EncryptionKeys) -> Result<bool, Box<dyn Error>> {
+        Ok(
+            self.elgamal_keypair.to_bytes() == other.elgamal_keypair.to_bytes()
+                && self.aes_key_bytes()? == other.aes_key_bytes()?,
+        )
+    }
+}
+
+// The secret part of a snapshot, only stored encrypted
+#[derive(Debug, Default)]
+pub struct SnapshotSecrets {
+    pub keypairs: Vec<NamedKeypair>,
+    pub encryption_keys: Vec<EncryptionKeys>,
+}
+
+impl SnapshotSecrets {
+    // Encrypt with AES-256-GCM-SIV under a key derived from the passphrase with PBKDF2-HMAC-SHA256
+    pub fn encrypt(&self, passphrase: &str) -> Result<Value, Box<dyn Error>> {
+        let keypairs: Vec<Value> = self
+            .keypairs
+            .iter()
+            .map(
+                |named| json!({ "name": named.name, "keypair": named.keypair.to_bytes().to_vec() }),
+            )
+            .collect();
+        let encryption_keys = self
+            .encryption_keys
+            .iter()
+            .map(EncryptionKeys::to_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        encrypt_json(
+            &json!({ "keypairs": keypairs, "encryption_keys": encryption_keys }),
+            passphrase,
+        )
+    }
+
+    pub fn decrypt(encrypted: &Value, passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        let plaintext = decrypt_json(encrypted, passphrase)?;
+
+        let keypairs = plaintext["keypairs"]
+            .as_array()
+            .ok_or("Snapshot secrets without keypairs")?
+            .iter()
+            .map(|named| -> Result<NamedKeypair, Box<dyn Error>> {
+                let bytes: Vec<u8> = serde_json::from_value(named["keypair"].clone())?;
+                Ok(NamedKeypair {
+                    name: named["name"]
+                        .as_str()
+                        .ok_or("Snapshot keypair without a name")?
+                        .to_string(),
+                    keypair: Keypair::from_bytes(&bytes)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let encryption_keys = plaintext["encryption_keys"]
+            .as_array()
+            .ok_or("Snapshot secrets without encryption keys")?
+            .iter()
+            .map(EncryptionKeys::from_json)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            keypairs,
+            encryption_keys,
+        })
+    }
+}
+
+// Encrypt JSON with AES-256-GCM-SIV under a key derived from the passphrase with PBKDF2-HMAC-SHA256
+pub(crate) fn encrypt_json(plaintext: &Value, passphrase: &str) -> Result<Value, Box<dyn Error>> {
+    encrypt_json_with_rounds(plaintext, passphrase, PBKDF2_ROUNDS)
+}
+
+fn encrypt_json_with_rounds(
+    plaintext: &Value,
+    passphrase: &str,
+    rounds: u32,
+) -> Result<Value, Box<dyn Error>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher(passphrase, &salt, rounds)
+        .encrypt(Nonce::from_slice(&nonce), plaintext.to_string().as_bytes())
+        .map_err(|_| "Could not encrypt the snapshot")?;
+
+    Ok(json!({
+        "kdf": "pbkdf2-hmac-sha256",
+        "rounds": rounds,
+        "salt": BASE64_STANDARD.encode(salt),
+        "cipher": "aes-256-gcm-siv",
+        "nonce": BASE64_STANDARD.encode(nonce),
+        "ciphertext": BASE64_STANDARD.encode(ciphertext),
+    }))
+}
+
+pub(crate) fn decrypt_json(encrypted: &Value, passphrase: &str) -> Result<Value, Box<dyn Error>> {
+    let field = |name: &str| -> Result<Vec<u8>, Box<dyn Error>> {
+        let value = encrypted[name]
+            .as_str()
+            .ok_or(format!("Snapshot secrets without {}", name))?;
+        Ok(BASE64_STANDARD.decode(value)?)
+    };
+    let rounds = encrypted["rounds"]
+        .as_u64()
+        .ok_or("Snapshot secrets without rounds")?;
+    let nonce = field("nonce")?;
+    if nonce.len() != NONCE_LEN {
+        return Err("Invalid snapshot nonce".into());
+    }
+    let plaintext = cipher(passphrase, &field("salt")?, rounds.try_into()?)
+        .decrypt(Nonce::from_slice(&nonce), field("ciphertext")?.as_ref())
+        .map_err(|_| "Wrong passphrase, or the snapshot was modified")?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn cipher(passphrase: &str, salt: &[u8], rounds: u32) -> Aes256GcmSiv {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, &mut key);
+    Aes256GcmSiv::new(Key::from_slice(&key))
+}
+
+// A token account of a keypair for one of the mints
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotTokenAccount {
+    // Name of the owner's keypair
+    pub owner: String,
+    pub mint: Pubkey,
+    pub address: Pubkey,
+}
+
+// A working setup, to move between machines or share with a teammate: the mints and accounts of the .env keypairs,
+// their keypairs and the encryption keys of their token accounts (encrypted with a passphrase), and the journal.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub created_at: u64,
+    pub rpc_url: String,
+    // Names and addresses of the mint keypairs, and of all other keypairs
+    pub mints: Vec<(String, Pubkey)>,
+    pub accounts: Vec<(String, Pubkey)>,
+    // Token-2022 associated token accounts of the keypairs that exist on the cluster
+    pub token_accounts: Vec<SnapshotTokenAccount>,
+    // Output of `SnapshotSecrets::encrypt`
+    pub secrets: Value,
+    // Journal entries, oldest first
+    pub journal: Vec<JournalEntry>,
+}
+
+// What an import changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreOutcome {
+    pub keypairs_added: usize,
+    // Keypairs already in the .env file with the same key
+    pub keypairs_present: usize,
+    pub journal_entries_added: usize,
+}
+
+impl Snapshot {
+    // Snapshot the keypairs of the .env file, their token accounts on the cluster and the journal
+    pub fn capture(
+        client: &RpcClient,
+        rpc_url: &str,
+        journal: &Journal,
+        passphrase: &str,
+        derivation: &KeyDerivation,
+    ) -> Result<Self, Box<dyn Error>> {
+        let keypairs = env_keypairs(".env")?;
+        let (mints, owners): (Vec<&NamedKeypair>, Vec<&NamedKeypair>) =
+            keypairs.iter().partition(|named| is_mint_name(&named.name));
+
+        // Every keypair could hold tokens of every mint, keep the token accounts that exist
+        let candidates: Vec<(&NamedKeypair, Pubkey, Pubkey)> = owners
+            .iter()
+            .flat_map(|owner| {
+                mints.iter().map(|mint| {
+                    let address = get_associated_token_address_with_program_id(
+                        &owner.keypair.pubkey(), // Token account owner
+                        &mint.keypair.pubkey(),  // Mint
+                        &spl_token_2022::id(),
+                    );
+                    (*owner, mint.keypair.pubkey(), address)
+                })
+            })
+            .collect();
+        let mut token_accounts = Vec::new();
+        let mut secrets = SnapshotSecrets::default();
+        for batch in candidates.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let addresses: Vec<Pubkey> = batch.iter().map(|(_, _, address)| *address).collect();
+            let accounts = client.get_multiple_accounts(&addresses)?;
+            for ((owner, mint, address), account) in batch.iter().zip(accounts) {
+                let Some(account) = account else {
+                    continue;
+                };
+                // Keys under the derivation the account was configured with, if it is
+                let derivation = match ConfidentialAccountState::unpack(address, account.data)? {
+                    Some(state) => {
+                        derivation.resolve(&owner.keypair, address, &state.elgamal_pubkey)?
+                    }
+                    None => derivation.clone(),
+                };
+                secrets.encryption_keys.push(EncryptionKeys::derive(
+                    &owner.keypair,
+                    address,
+                    &derivation,
+                )?);
+                token_accounts.push(SnapshotTokenAccount {
+                    owner: owner.name.clone(),
+                    mint: *mint,
+                    address: *address,
+                });
+            }
+        }
+
+        let named = |keypairs: &[&NamedKeypair]| -> Vec<(String, Pubkey)> {
+            keypairs
+                .iter()
+                .map(|named| (named.name.clone(), named.keypair.pubkey()))
+                .collect()
+        };
+        let mints = named(&mints);
+        let accounts = named(&owners);
+
+        let mut journal = journal.entries(&JournalQuery::default())?;
+        journal.reverse();
+
+        secrets.keypairs = keypairs;
+        Ok(Self {
+            created_at: journal::now(),
+            rpc_url: rpc_url.to_string(),
+            mints,
+            accounts,
+            token_accounts,
+            secrets: secrets.encrypt(passphrase)?,
+            journal,
+        })
+    }
+
+    // Add the snapshot's keypairs to the .env file and its entries to the journal. Nothing is written if a
+    // keypair of the same name with another key is already in the .env file.
+    pub fn restore(
+        &self,
+        secrets: &SnapshotSecrets,
+        journal: &Journal,
+        derivation: &KeyDerivation,
+    ) -> Result<RestoreOutcome, Box<dyn Error>> {
+        // The encryption keys must still derive from the keypairs, or the token accounts couldn't be used
+        for keys in &secrets.encryption_keys {
+            let owner = self
+                .token_accounts
+                .iter()
+                .find(|token_account| token_account.address == keys.token_account)
+                .and_then(|token_account| {
+                    secrets
+                        .keypairs
+                        .iter()
+                        .find(|named| named.name == token_account.owner)
+                })
+                .ok_or(format!(
+                    "No keypair in the snapshot owns token account {}",
+                    keys.token_account
+                ))?;
+            let mut derived = false;
+            for candidate in derivation.candidates() {
+                if EncryptionKeys::derive(&owner.keypair, &keys.token_account, &candidate)?
+                    .matches(keys)?
+                {
+                    derived = true;
+                    break;
+                }
+            }
+            if !derived {
+                return Err(format!(
+                    "Encryption keys of token account {} don't match its owner {}",
+                    keys.token_account, owner.name
+                )
+                .into());
+            }
+        }
+
+        let mut outcome = RestoreOutcome::default();
+        let mut missing = Vec::new();
+        let mut conflicts = Vec::new();
+        for named in &secrets.keypairs {
+            match read_keypair(&named.name)? {
+                Some(existing) if existing.to_bytes() == named.keypair.to_bytes() => {
+                    outcome.keypairs_present += 1
+                }
+                Some(_) => conflicts.push(named.name.as_str()),
+                None => missing.push(named),
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(format!(
+                "The .env file has other keypairs named {}, move it aside to import the snapshot",
+                conflicts.join(", ")
+            )
+            .into());
+        }
+        for named in missing {
+            write_keypair(&named.name, &named.keypair)?;
+            outcome.keypairs_added += 1;
+        }
+
+        // Entries already in the journal, e.g. from importing the same snapshot before, are skipped
+        let existing = journal.entries(&JournalQuery::default())?;
+        for entry in &self.journal {
+            if !existing.contains(entry) {
+                journal.record(entry)?;
+                outcome.journal_entries_added += 1;
+            }
+        }
+        Ok(outcome)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let named = |named: &[(String, Pubkey)]| -> Vec<Value> {
+            named
+                .iter()
+                .map(|(name, address)| json!({ "name": name, "address": address.to_string() }))
+                .collect()
+        };
+        json!({
+            "version": SNAPSHOT_VERSION,
+            "created_at": self.created_at,
+            "rpc_url": self.rpc_url,
+            "mints": named(&self.mints),
+            "accounts": named(&self.accounts),
+            "token_accounts": self
+                .token_accounts
+                .iter()
+                .map(|token_account| json!({
+                    "owner": token_account.owner,
+                    "mint": token_account.mint.to_string(),
+                    "address": token_account.address.to_string(),
+                }))
+                .collect::<Vec<_>>(),
+            "secrets": self.secrets,
+            "journal": self.journal.iter().map(JournalEntry::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        if value["version"].as_u64() != Some(SNAPSHOT_VERSION) {
+            return Err(format!(
+                "Unsupported snapshot version {}, expected {}",
+                value["version"], SNAPSHOT_VERSION
+            )
+            .into());
+        }
+        let list = |field: &str| -> Result<&Vec<Value>, Box<dyn Error>> {
+            value[field]
+                .as_array()
+                .ok_or_else(|| format!("Snapshot without {}", field).into())
+        };
+        let address = |value: &Value, field: &str| -> Result<Pubkey, Box<dyn Error>> {
+            Ok(value[field]
+                .as_str()
+                .ok_or(format!("Snapshot entry without {}", field))?
+                .parse()?)
+        };
+        let named = |field: &str| -> Result<Vec<(String, Pubkey)>, Box<dyn Error>> {
+            list(field)?
+                .iter()
+                .map(|named| {
+                    Ok((
+                        named["name"]
+                            .as_str()
+                            .ok_or("Snapshot entry without a name")?
+                            .to_string(),
+                        address(named, "address")?,
+                    ))
+                })
+                .collect()
+        };
+
+        Ok(Self {
+            created_at: value["created_at"].as_u64().unwrap_or_default(),
+            rpc_url: value["rpc_url"].as_str().unwrap_or_default().to_string(),
+            mints: named("mints")?,
+            accounts: named("accounts")?,
+            token_accounts: list("token_accounts")?
+                .iter()
+                .map(|token_account| -> Result<_, Box<dyn Error>> {
+                    Ok(SnapshotTokenAccount {
+                        owner: token_account["owner"]
+                            .as_str()
+                            .ok_or("Token account without an owner")?
+                            .to_string(),
+                        mint: address(token_account, "mint")?,
+                        address: address(token_account, "address")?,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            secrets: value["secrets"].clone(),
+            journal: list("journal")?
+                .iter()
+                .map(JournalEntry::from_json)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+// The passphrase of a snapshot or key cache, from a --passphrase flag or the SNAPSHOT_PASSPHRASE variable
+pub fn read_passphrase(flag: Option<String>) -> Result<String, Box<dyn Error>> {
+    let passphrase = flag
+        .or_else(|| env::var("SNAPSHOT_PASSPHRASE").ok())
+        .ok_or("Pass --passphrase or set SNAPSHOT_PASSPHRASE")?;
+    if passphrase.is_empty() {
+        return Err("The passphrase can't be empty".into());
+    }
+    Ok(passphrase)
+}
+
+// Mints are stored as `mint` and `mint:<label>` keypairs
+fn is_mint_name(name: &str) -> bool {
+    name == DEFAULT_MINT || name.starts_with(&format!("{}:", DEFAULT_MINT))
+}
+
+// The keypairs of an .env file, skipping its other settings
+pub fn env_keypairs<P: AsRef<Path>>(path: P) -> Result<Vec<NamedKeypair>, Box<dyn Error>> {
+    if !path.as_ref().exists() {
+        return Ok(Vec::new());
+    }
+    let mut keypairs = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        // Keypairs are written as `<name>=<JSON array of the 64 secret key bytes>`
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let Ok(bytes) = serde_json::from_str::<Vec<u8>>(value.trim()) else {
+            continue;
+        };
+        let Ok(keypair) = Keypair::from_bytes(&bytes) else {
+            continue;
+        };
+        // Undo `keypair_variable_name`
+        let name = key.trim().replace('.', ":");
+        keypairs.push(NamedKeypair { name, keypair });
+    }
+    Ok(keypairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Few rounds keep the tests fast, the rounds are read back from the encrypted JSON
+    const TEST_ROUNDS: u32 = 1_000;
+
+    fn secrets() -> Value {
+        json!({ "keypairs": [{ "name": "wallet_1", "keypair": [1, 2, 3] }] })
+    }
+
+    // Flip one bit of a base64 field of the encrypted JSON
+    fn tamper(encrypted: &mut Value, field: &str) {
+        let mut bytes = BASE64_STANDARD
+            .decode(encrypted[field].as_str().unwrap())
+            .unwrap();
+        bytes[0] ^= 1;
+        encrypted[field] = Value::String(BASE64_STANDARD.encode(bytes));
+    }
+
+    #[test]
+    fn encrypted_json_decrypts_with_the_passphrase() {
+        let encrypted = encrypt_json_with_rounds(&secrets(), "passphrase", TEST_ROUNDS).unwrap();
+        assert!(!encrypted.to_string().contains("wallet_1"));
+        assert_eq!(decrypt_json(&encrypted, "passphrase").unwrap(), secrets());
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let encrypted = encrypt_json_with_rounds(&secrets(), "passphrase", TEST_ROUNDS).unwrap();
+        assert!(decrypt_json(&encrypted, "other passphrase").is_err());
+        assert!(decrypt_json(&encrypted, "").is_err());
+    }
+
+    #[test]
+    fn tampered_secrets_are_rejected() {
+        let encrypted = encrypt_json_with_rounds(&secrets(), "passphrase", TEST_ROUNDS).unwrap();
+        for field in ["ciphertext", "nonce", "salt"] {
+            let mut tampered = encrypted.clone();
+            tamper(&mut tampered, field);
+            assert!(
+                decrypt_json(&tampered, "passphrase").is_err(),
+                "tampered {} was accepted",
+                field
+            );
+        }
+
+        let mut truncated = encrypted.clone();
+        truncated["nonce"] = Value::String(BASE64_STANDARD.encode([0u8; NONCE_LEN - 1]));
+        assert!(decrypt_json(&truncated, "passphrase").is_err());
+        let mut missing = encrypted;
+        missing["ciphertext"] = Value::Null;
+        assert!(decrypt_json(&missing, "passphrase").is_err());
+    }
+}
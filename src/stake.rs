@@ -0,0 +1,143 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    native_token::lamports_to_sol,
+    pubkey::Pubkey,
+    stake::{
+        self, instruction as stake_instruction,
+        state::{Authorized, Lockup, StakeStateV2},
+    },
+    vote,
+};
+use std::{error::Error, fmt};
+
+// A stake account of the wallet, as read back from the cluster
+#[derive(Debug, Clone, PartialEq)]
+pub struct StakeAccount {
+    pub address: Pubkey,
+    pub lamports: u64,
+    pub state: StakeStateV2,
+}
+
+impl StakeAccount {
+    // Vote account the stake is delegated to, `None` until delegated
+    pub fn voter(&self) -> Option<Pubkey> {
+        self.state
+            .delegation()
+            .map(|delegation| delegation.voter_pubkey)
+    }
+
+    // Epoch the stake was deactivated in, `None` while it is (or is becoming) active
+    pub fn deactivation_epoch(&self) -> Option<u64> {
+        self.state
+            .delegation()
+            .map(|delegation| delegation.deactivation_epoch)
+            .filter(|epoch| *epoch != u64::MAX)
+    }
+}
+
+impl fmt::Display for StakeAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Stake Account: {}", self.address)?;
+        write!(f, "  Balance:     {} SOL", lamports_to_sol(self.lamports))?;
+        match &self.state {
+            StakeStateV2::Uninitialized => write!(f, "\n  State:       uninitialized"),
+            StakeStateV2::RewardsPool => write!(f, "\n  State:       rewards pool"),
+            StakeStateV2::Initialized(meta) => write!(
+                f,
+                "\n  State:       initialized, not delegated\n  Staker:      {}\n  Withdrawer:  {}",
+                meta.authorized.staker, meta.authorized.withdrawer
+            ),
+            StakeStateV2::Stake(meta, stake, _) => {
+                write!(
+                    f,
+                    "\n  State:       delegated\n  Staker:      {}\n  Withdrawer:  {}\n  Vote Account: {}\n  Delegated:   {} SOL since epoch {}",
+                    meta.authorized.staker,
+                    meta.authorized.withdrawer,
+                    stake.delegation.voter_pubkey,
+                    lamports_to_sol(stake.delegation.stake),
+                    stake.delegation.activation_epoch
+                )?;
+                if let Some(epoch) = self.deactivation_epoch() {
+                    write!(f, "\n  Deactivated: epoch {}", epoch)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Instructions creating a stake account at `stake_account` funded with `lamports` from `funder`,
+// with `authority` as both staker and withdrawer and no lockup
+pub fn create_stake_account(
+    funder: &Pubkey,
+    stake_account: &Pubkey,
+    authority: &Pubkey,
+    lamports: u64,
+) -> Vec<Instruction> {
+    stake_instruction::create_account(
+        funder,
+        stake_account,
+        &Authorized::auto(authority),
+        &Lockup::default(),
+        lamports,
+    )
+}
+
+pub fn delegate_stake(
+    stake_account: &Pubkey,
+    staker: &Pubkey,
+    vote_account: &Pubkey,
+) -> Instruction {
+    stake_instruction::delegate_stake(stake_account, staker, vote_account)
+}
+
+// Stake stops earning rewards and becomes withdrawable once the cooldown ends at an epoch boundary
+pub fn deactivate_stake(stake_account: &Pubkey, staker: &Pubkey) -> Instruction {
+    stake_instruction::deactivate_stake(stake_account, staker)
+}
+
+pub fn withdraw_stake(
+    stake_account: &Pubkey,
+    withdrawer: &Pubkey,
+    recipient: &Pubkey,
+    lamports: u64,
+) -> Instruction {
+    stake_instruction::withdraw(stake_account, withdrawer, recipient, lamports, None)
+}
+
+// Least lamports a new stake account needs: its rent plus the cluster's minimum delegation
+pub fn minimum_stake_lamports(client: &RpcClient) -> Result<u64, Box<dyn Error>> {
+    let rent = client.get_minimum_balance_for_rent_exemption(StakeStateV2::size_of())?;
+    Ok(rent + client.get_stake_minimum_delegation()?)
+}
+
+// The stake account at an address, `None` if it doesn't exist
+pub fn fetch_stake_account(
+    client: &RpcClient,
+    address: &Pubkey,
+) -> Result<Option<StakeAccount>, Box<dyn Error>> {
+    let Some(account) = client
+        .get_account_with_commitment(address, client.commitment())?
+        .value
+    else {
+        return Ok(None);
+    };
+    if account.owner != stake::program::id() {
+        return Err(format!("{} is not a stake account", address).into());
+    }
+    Ok(Some(StakeAccount {
+        address: *address,
+        lamports: account.lamports,
+        state: bincode::deserialize(&account.data)?,
+    }))
+}
+
+// Stake can only be delegated to a vote account, so check before sending a delegation the program would reject
+pub fn check_vote_account(client: &RpcClient, address: &Pubkey) -> Result<(), Box<dyn Error>> {
+    let account = client.get_account(address)?;
+    if account.owner != vote::program::id() {
+        return Err(format!("{} is not a vote account", address).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+// Anonymized measurements of the flows, for integrators to feed their own analytics. Samples carry flow and step
+// names, timings, sizes and counts, never addresses, signatures or amounts.
+//
+// Telemetry is only compiled with the `telemetry` feature, and only recorded once a sink is set on
+// `FlowContext::telemetry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TelemetrySample<'a> {
+    StepFinished {
+        flow: &'a str,
+        step: &'a str,
+        elapsed: Duration,
+    },
+    // Proof data used by a flow: its serialized size, and how long it took to generate, `None` if it was taken
+    // from the proof cache
+    Proof {
+        flow: &'a str,
+        bytes: usize,
+        generation: Option<Duration>,
+    },
+    // A transaction sent and confirmed, or failed, including the wait for its confirmation
+    TransactionSent {
+        flow: &'a str,
+        elapsed: Duration,
+        succeeded: bool,
+    },
+    // A transaction of the retry queue was attempted again, `attempts` counting this one
+    TransactionRetried {
+        flow: &'a str,
+        attempts: u32,
+        landed: bool,
+    },
+}
+
+// Receives the samples of the flows. Sinks are called on the flow's thread, so they should hand samples off
+// rather than block on I/O.
+pub trait TelemetrySink: Send + Sync {
+    fn record(&self, sample: &TelemetrySample<'_>);
+}
+
+impl<F> TelemetrySink for F
+where
+    F: Fn(&TelemetrySample<'_>) + Send + Sync,
+{
+    fn record(&self, sample: &TelemetrySample<'_>) {
+        self(sample)
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{events::FlowEvent, explorer::Cluster, flows::FlowContext};
+use solana_sdk::{
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+};
+use std::{error::Error, thread, time::Duration};
+
+// Airdrops are confirmed by polling, as they aren't sent through `FlowContext::send`
+const AIRDROP_CONFIRM_ATTEMPTS: usize = 30;
+const AIRDROP_CONFIRM_INTERVAL: Duration = Duration::from_secs(1);
+
+// Where the SOL to top up the fee payer comes from
+pub enum TopUpSource {
+    // A funded keypair transferring SOL to the fee payer
+    Treasury(Box<Keypair>),
+    // Airdrops from the faucet, not available on mainnet
+    Airdrop,
+}
+
+// Keeps the fee payer of a long-running process funded for fees and rent
+pub struct TopUp {
+    pub source: TopUpSource,
+    // Top up once the fee payer's balance drops below this many lamports
+    pub threshold: u64,
+    // Lamports added by each top up
+    pub amount: u64,
+}
+
+impl TopUp {
+    pub fn new(source: TopUpSource, threshold_sol: f64, amount_sol: f64) -> Self {
+        Self {
+            source,
+            threshold: sol_to_lamports(threshold_sol),
+            amount: sol_to_lamports(amount_sol),
+        }
+    }
+
+    // Fail early for a source that can't work on the cluster
+    pub fn check_cluster(&self, cluster: &Cluster) -> Result<(), Box<dyn Error>> {
+        if matches!(self.source, TopUpSource::Airdrop) && *cluster == Cluster::Mainnet {
+            return Err(
+                "Airdrops are not available on mainnet, top up from a treasury instead".into(),
+            );
+        }
+        Ok(())
+    }
+
+    // Top up the fee payer if its balance is below the threshold, returning the top up's signature
+    pub fn ensure_funded(
+        &self,
+        ctx: &mut FlowContext<'_>,
+        payer: &Pubkey,
+    ) -> Result<Option<Signature>, Box<dyn Error>> {
+        let balance = ctx.client.get_balance(payer)?;
+        if balance >= self.threshold {
+            return Ok(None);
+        }
+
+        let signature = match &self.source {
+            TopUpSource::Treasury(treasury) => {
+                let treasury_balance = ctx.client.get_balance(&treasury.pubkey())?;
+                if treasury_balance < self.amount {
+                    return Err(format!(
+                        "Treasury {} holds {} SOL, not enough to top up {} with {} SOL",
+                        treasury.pubkey(),
+                        lamports_to_sol(treasury_balance),
+                        payer,
+                        lamports_to_sol(self.amount)
+                    )
+                    .into());
+                }
+                ctx.send(
+                    "Top Up Fee Payer",
+                    &[system_instruction::transfer(
+                        &treasury.pubkey(),
+                        payer,
+                        self.amount,
+                    )],
+                    &treasury.pubkey(),
+                    &[treasury],
+                )?
+            }
+            TopUpSource::Airdrop => {
+                let signature = ctx.client.request_airdrop(payer, self.amount)?;
+                confirm_airdrop(ctx, &signature)?;
+                signature
+            }
+        };
+
+        ctx.emit(FlowEvent::FeePayerToppedUp {
+            payer: *payer,
+            lamports: self.amount,
+            balance,
+        });
+        Ok(Some(signature))
+    }
+}
+
+fn confirm_airdrop(ctx: &FlowContext<'_>, signature: &Signature) -> Result<(), Box<dyn Error>> {
+    for _ in 0..AIRDROP_CONFIRM_ATTEMPTS {
+        if ctx.client.confirm_transaction(signature)? {
+            return Ok(());
+        }
+        thread::sleep(AIRDROP_CONFIRM_INTERVAL);
+    }
+    Err(format!("Airdrop {} was not confirmed", signature).into())
+}
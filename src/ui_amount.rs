@@ -0,0 +1,175 @@
+use crate::mint::get_mint_extension_data;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token_2022::state::Mint;
+use std::{
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// The ScaledUiAmount extension is newer than the token-2022 version this crate builds against,
+// so its account data and instruction are encoded here by hand, see `mint::get_mint_extension_data`.
+//
+// Extension type of `ScaledUiAmountConfig` in the mint TLV data
+pub const SCALED_UI_AMOUNT_EXTENSION_TYPE: u16 = 25;
+
+// authority (32 bytes), multiplier (8), new_multiplier_effective_timestamp (8), new_multiplier (8)
+pub const SCALED_UI_AMOUNT_CONFIG_LEN: usize = 56;
+
+// Extra mint account space for the extension: type (2 bytes) and length (2) followed by the config
+pub const SCALED_UI_AMOUNT_EXTENSION_LEN: usize = 4 + SCALED_UI_AMOUNT_CONFIG_LEN;
+
+// Token instruction tag of the ScaledUiAmount extension, and its `Initialize` sub-instruction
+const SCALED_UI_AMOUNT_EXTENSION_INSTRUCTION: u8 = 43;
+const INITIALIZE: u8 = 0;
+
+// Multiplier applied to token amounts when they are shown to users, the raw amounts are unchanged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaledUiAmountConfig {
+    pub authority: Option<Pubkey>,
+    pub multiplier: f64,
+    pub new_multiplier_effective_timestamp: i64,
+    pub new_multiplier: f64,
+}
+
+impl ScaledUiAmountConfig {
+    pub fn unpack(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if data.len() != SCALED_UI_AMOUNT_CONFIG_LEN {
+            return Err(format!(
+                "Invalid ScaledUiAmount extension, expected {} bytes but got {}",
+                SCALED_UI_AMOUNT_CONFIG_LEN,
+                data.len()
+            )
+            .into());
+        }
+        // An all zero authority means no authority
+        let authority = Pubkey::try_from(&data[..32])?;
+        Ok(Self {
+            authority: (authority != Pubkey::default()).then_some(authority),
+            multiplier: f64::from_le_bytes(data[32..40].try_into()?),
+            new_multiplier_effective_timestamp: i64::from_le_bytes(data[40..48].try_into()?),
+            new_multiplier: f64::from_le_bytes(data[48..56].try_into()?),
+        })
+    }
+
+    // The multiplier in effect at `unix_timestamp`, the authority can schedule a new one ahead of time
+    pub fn multiplier_at(&self, unix_timestamp: i64) -> f64 {
+        if unix_timestamp >= self.new_multiplier_effective_timestamp {
+            self.new_multiplier
+        } else {
+            self.multiplier
+        }
+    }
+}
+
+// The ScaledUiAmount extension of a mint, `None` if the mint doesn't have it
+pub fn get_scaled_ui_amount_config(
+    mint_data: &[u8],
+) -> Result<Option<ScaledUiAmountConfig>, Box<dyn Error>> {
+    get_mint_extension_data(mint_data, SCALED_UI_AMOUNT_EXTENSION_TYPE)?
+        .map(ScaledUiAmountConfig::unpack)
+        .transpose()
+}
+
+// Instruction to initialize the ScaledUiAmount extension, sent before `initialize_mint` like the other mint extensions.
+// Requires a token-2022 program with the extension deployed on the cluster.
+pub fn initialize_scaled_ui_amount(
+    mint: &Pubkey,
+    authority: Option<&Pubkey>,
+    multiplier: f64,
+) -> Instruction {
+    let mut data = vec![SCALED_UI_AMOUNT_EXTENSION_INSTRUCTION, INITIALIZE];
+    data.extend_from_slice(authority.copied().unwrap_or_default().as_ref());
+    data.extend_from_slice(&multiplier.to_le_bytes());
+
+    Instruction {
+        program_id: spl_token_2022::id(),
+        accounts: vec![AccountMeta::new(*mint, false)],
+        data,
+    }
+}
+
+// Renders raw token amounts the way wallets show them: divided by the mint decimals and scaled by the
+// ScaledUiAmount multiplier, if the mint has one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiAmount {
+    pub decimals: u8,
+    pub multiplier: f64,
+}
+
+impl UiAmount {
+    pub fn new(decimals: u8, multiplier: Option<f64>) -> Self {
+        Self {
+            decimals,
+            multiplier: multiplier.unwrap_or(1.0),
+        }
+    }
+
+    // Read the decimals and current multiplier of a mint
+    pub fn fetch(client: &RpcClient, mint: &Pubkey) -> Result<Self, Box<dyn Error>> {
+        let mint_data = client.get_account_data(mint)?;
+        // The program uses the cluster clock, local time is close enough for display
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        Self::from_mint_data(&mint_data, now)
+    }
+
+    // The decimals and multiplier at `unix_timestamp` of a mint account's data
+    pub fn from_mint_data(mint_data: &[u8], unix_timestamp: i64) -> Result<Self, Box<dyn Error>> {
+        let base = mint_data
+            .get(..Mint::LEN)
+            .ok_or("Invalid mint account, too short")?;
+        let decimals = Mint::unpack(base)?.decimals;
+        let multiplier = get_scaled_ui_amount_config(mint_data)?
+            .map(|config| config.multiplier_at(unix_timestamp));
+
+        Ok(Self::new(decimals, multiplier))
+    }
+
+    pub fn format(&self, amount: u64) -> String {
+        // Scaled amounts are truncated to whole base units, like the program's `AmountToUiAmount`
+        let scaled_amount = (amount as f64 * self.multiplier).trunc();
+        let decimals = self.decimals as usize;
+        format!(
+            "{:.decimals$}",
+            scaled_amount / 10f64.powi(self.decimals as i32)
+        )
+    }
+
+    // Format a decrypted balance, which is `None` when it could not be decrypted
+    pub fn format_decrypted(&self, amount: Option<u64>) -> String {
+        match amount {
+            Some(amount) => self.format(amount),
+            None => "could not decrypt".to_string(),
+        }
+    }
+
+    // Base units of an amount written the way `format` displays it, e.g. "12.50".
+    // The decimal part is read exactly, then unscaled by the multiplier, rounding down like the program.
+    pub fn parse(&self, value: &str) -> Result<u64, Box<dyn Error>> {
+        let value = value.trim();
+        let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+        let decimals = self.decimals as usize;
+        if whole.is_empty() && fraction.is_empty()
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(format!("{:?} is not an amount", value).into());
+        }
+        if fraction.len() > decimals {
+            return Err(format!("{} has more than {} decimals", value, decimals).into());
+        }
+        let scaled = format!("{}{:0<decimals$}", whole, fraction)
+            .parse::<u64>()
+            .map_err(|_| format!("{} is too large", value))?;
+        if self.multiplier == 1.0 {
+            return Ok(scaled);
+        }
+        Ok((scaled as f64 / self.multiplier).floor() as u64)
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{account_state::ConfidentialAccountState, key_derivation::KeyDerivation};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use std::{error::Error, fmt};
+
+// Public and decrypted confidential balances of a token account at one point of a flow
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceSnapshot {
+    pub token_account: Pubkey,
+    pub public: u64,
+    // Decrypted from the ElGamal ciphertexts, `None` if they could not be decrypted
+    pub pending: Option<u64>,
+    pub available: Option<u64>,
+}
+
+impl BalanceSnapshot {
+    // Fetch the token account and decrypt its balances with the keys of the owner, under the derivation
+    // the account was configured with
+    pub fn fetch(
+        client: &RpcClient,
+        token_account: &Pubkey,
+        owner: &Keypair,
+        derivation: &KeyDerivation,
+    ) -> Result<Self, Box<dyn Error>> {
+        let state = ConfidentialAccountState::fetch(client, token_account)?;
+
+        let elgamal_keypair = derivation
+            .resolve(owner, token_account, &state.elgamal_pubkey)?
+            .elgamal_keypair(owner, token_account)?;
+
+        Ok(Self {
+            token_account: *token_account,
+            public: state.public_balance,
+            pending: state.decrypt_pending_balance(&elgamal_keypair)?,
+            available: state.decrypt_available_balance(&elgamal_keypair)?,
+        })
+    }
+
+    // Check that the balances changed from `before` by exactly `expected`
+    pub fn verify_change(
+        &self,
+        before: &BalanceSnapshot,
+        expected: BalanceChange,
+    ) -> Result<(), BalanceMismatch> {
+        let checks = [
+            (
+                Balance::Public,
+                Some(before.public),
+                Some(self.public),
+                expected.public,
+            ),
+            (
+                Balance::Pending,
+                before.pending,
+                self.pending,
+                expected.pending,
+            ),
+            (
+                Balance::Available,
+                before.available,
+                self.available,
+                expected.available,
+            ),
+        ];
+        for (balance, before, after, expected) in checks {
+            let actual = before
+                .zip(after)
+                .map(|(before, after)| after as i128 - before as i128);
+            if actual != Some(expected) {
+                return Err(BalanceMismatch {
+                    token_account: self.token_account,
+                    balance,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// Expected change of each balance over a flow, in base units
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub public: i128,
+    pub pending: i128,
+    pub available: i128,
+}
+
+impl BalanceChange {
+    // Applying the pending balance moves all of it to the available balance.
+    // An undecryptable pending balance fails the verification anyway, so it counts as 0 here.
+    pub fn apply_pending(before: &BalanceSnapshot) -> Self {
+        let pending = before.pending.unwrap_or_default() as i128;
+        Self {
+            pending: -pending,
+            available: pending,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balance {
+    Public,
+    Pending,
+    Available,
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Balance::Public => write!(f, "public"),
+            Balance::Pending => write!(f, "pending"),
+            Balance::Available => write!(f, "available"),
+        }
+    }
+}
+
+// A balance that didn't change by the expected amount after a flow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceMismatch {
+    pub token_account: Pubkey,
+    pub balance: Balance,
+    pub expected: i128,
+    // `None` if the balance could not be decrypted before or after the flow
+    pub actual: Option<i128>,
+}
+
+impl fmt::Display for BalanceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.actual {
+            Some(actual) => write!(
+                f,
+                "The {} balance of {} changed by {} instead of {}",
+                self.balance, self.token_account, actual, self.expected
+            ),
+            None => write!(
+                f,
+                "The {} balance of {} could not be decrypted to verify a change of {}",
+                self.balance, self.token_account, self.expected
+            ),
+        }
+    }
+}
+
+impl Error for BalanceMismatch {}
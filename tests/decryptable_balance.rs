@@ -0,0 +1,409 @@
+// Property tests of the decryptable (AES) available balance against the ElGamal available balance.
+//
+// A token account's `ConfidentialTransferAccount` extension is modelled in memory and updated with the same
+// ciphertext arithmetic the token program uses for each instruction, while the new decryptable available balance
+// is computed the way the flows compute it. For random sequences of deposits, transfers and withdraws,
+// both balances must decrypt to the same amount whenever the program saw every credit the client decrypted.
+//
+// Cases are generated from the seeds 0..PROPERTY_CASES, so every run checks the same cases. A failing case is
+// shrunk, dropping operations and halving amounts while it still fails, and reported with its seed.
+//
+// cargo test --test decryptable_balance
+// PROPERTY_CASES=1000 to run more cases, PROPERTY_SEED=<seed> to replay the case a failure reports
+use curve25519_dalek::scalar::Scalar;
+use keypair_utils::{
+    audit::BalanceAudit, flows::apply_pending::balances_to_apply, ui_amount::UiAmount,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::{
+    extension::confidential_transfer::{
+        account_info::{TransferAccountInfo, WithdrawAccountInfo},
+        processor::verify_and_split_deposit_amount,
+        ConfidentialTransferAccount, EncryptedBalance,
+    },
+    solana_zk_token_sdk::{
+        encryption::{
+            auth_encryption::{AeCiphertext, AeKey},
+            elgamal::{ElGamalCiphertext, ElGamalKeypair},
+        },
+        zk_token_elgamal::ops,
+    },
+};
+use std::{env, fmt};
+
+const DEFAULT_CASES: u64 = 16;
+const MAX_OPERATIONS: usize = 16;
+// Amounts span both the 16 bit low part and the high part of pending balances,
+// while keeping the balances within the 32 bits `BalanceAudit` decrypts
+const MAX_AMOUNT: u64 = 200_000;
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Deposit(u64),
+    TransferIn(u64),
+    TransferOut(u64),
+    Withdraw(u64),
+    ApplyPendingBalance,
+    // A credit lands between the client decrypting the pending balance and the apply executing
+    RacedApplyPendingBalance(u64),
+}
+
+impl Operation {
+    fn random(rng: &mut StdRng) -> Self {
+        let amount = rng.gen_range(0..=MAX_AMOUNT);
+        match rng.gen_range(0..6) {
+            0 => Operation::Deposit(amount),
+            1 => Operation::TransferIn(amount),
+            2 => Operation::TransferOut(amount),
+            3 => Operation::Withdraw(amount),
+            4 => Operation::ApplyPendingBalance,
+            _ => Operation::RacedApplyPendingBalance(amount),
+        }
+    }
+
+    // The same operation with half the amount, `None` without an amount left to halve
+    fn halved(self) -> Option<Self> {
+        match self {
+            Operation::Deposit(amount) if amount > 0 => Some(Operation::Deposit(amount / 2)),
+            Operation::TransferIn(amount) if amount > 0 => Some(Operation::TransferIn(amount / 2)),
+            Operation::TransferOut(amount) if amount > 0 => {
+                Some(Operation::TransferOut(amount / 2))
+            }
+            Operation::Withdraw(amount) if amount > 0 => Some(Operation::Withdraw(amount / 2)),
+            Operation::RacedApplyPendingBalance(amount) if amount > 0 => {
+                Some(Operation::RacedApplyPendingBalance(amount / 2))
+            }
+            _ => None,
+        }
+    }
+}
+
+// The extension of one token account, with its keys and the balances it should hold
+struct Account {
+    token_account: Pubkey,
+    elgamal_keypair: ElGamalKeypair,
+    aes_key: AeKey,
+    extension: ConfidentialTransferAccount,
+    // Tokens in the available balance and the two parts of the pending balance, tracked in the clear
+    available: u64,
+    pending_lo: u64,
+    pending_hi: u64,
+}
+
+impl Account {
+    // A freshly configured account, as left by `ConfigureAccount`
+    fn new() -> Self {
+        let elgamal_keypair = ElGamalKeypair::new_rand();
+        let aes_key = AeKey::new_rand();
+        let extension = ConfidentialTransferAccount {
+            elgamal_pubkey: (*elgamal_keypair.pubkey()).into(),
+            decryptable_available_balance: aes_key.encrypt(0).into(),
+            ..ConfidentialTransferAccount::default()
+        };
+        Self {
+            token_account: Pubkey::new_unique(),
+            elgamal_keypair,
+            aes_key,
+            extension,
+            available: 0,
+            pending_lo: 0,
+            pending_hi: 0,
+        }
+    }
+
+    fn audit(&self) -> BalanceAudit {
+        BalanceAudit::new(
+            self.token_account,
+            &self.extension,
+            &self.elgamal_keypair,
+            &self.aes_key,
+            UiAmount::new(0, None),
+        )
+        .unwrap()
+    }
+
+    fn credit_counter(&self) -> u64 {
+        self.extension.pending_balance_credit_counter.into()
+    }
+
+    // The program saw every credit decrypted by the last apply
+    fn is_current(&self) -> bool {
+        self.extension.expected_pending_balance_credit_counter
+            == self.extension.actual_pending_balance_credit_counter
+    }
+
+    fn deposit(&mut self, amount: u64) {
+        let (amount_lo, amount_hi) = verify_and_split_deposit_amount(amount).unwrap();
+        let credit_counter = self.credit_counter() + 1;
+        let extension = &mut self.extension;
+        extension.pending_balance_lo =
+            ops::add_to(&extension.pending_balance_lo, amount_lo).unwrap();
+        extension.pending_balance_hi =
+            ops::add_to(&extension.pending_balance_hi, amount_hi).unwrap();
+        extension.pending_balance_credit_counter = credit_counter.into();
+        self.pending_lo += amount_lo;
+        self.pending_hi += amount_hi;
+    }
+
+    // Incoming transfers are encrypted under the destination's ElGamal pubkey by the sender
+    fn transfer_in(&mut self, amount: u64) {
+        let (amount_lo, amount_hi) = verify_and_split_deposit_amount(amount).unwrap();
+        let pubkey = self.elgamal_keypair.pubkey();
+        let credit_counter = self.credit_counter() + 1;
+        let extension = &mut self.extension;
+        extension.pending_balance_lo = ops::add(
+            &extension.pending_balance_lo,
+            &pubkey.encrypt(amount_lo).into(),
+        )
+        .unwrap();
+        extension.pending_balance_hi = ops::add(
+            &extension.pending_balance_hi,
+            &pubkey.encrypt(amount_hi).into(),
+        )
+        .unwrap();
+        extension.pending_balance_credit_counter = credit_counter.into();
+        self.pending_lo += amount_lo;
+        self.pending_hi += amount_hi;
+    }
+
+    // `None` if the client refuses the transfer for lack of funds
+    fn transfer_out(&mut self, amount: u64) -> Option<()> {
+        let new_decryptable_available_balance = TransferAccountInfo::new(&self.extension)
+            .new_decryptable_available_balance(amount, &self.aes_key)
+            .ok()?;
+        let (amount_lo, amount_hi) = verify_and_split_deposit_amount(amount).unwrap();
+        let pubkey = self.elgamal_keypair.pubkey();
+        let extension = &mut self.extension;
+        extension.available_balance = ops::subtract_with_lo_hi(
+            &extension.available_balance,
+            &pubkey.encrypt(amount_lo).into(),
+            &pubkey.encrypt(amount_hi).into(),
+        )
+        .unwrap();
+        extension.decryptable_available_balance = new_decryptable_available_balance.into();
+        self.available -= amount;
+        Some(())
+    }
+
+    // `None` if the client refuses the withdraw for lack of funds
+    fn withdraw(&mut self, amount: u64) -> Option<()> {
+        let new_decryptable_available_balance = WithdrawAccountInfo::new(&self.extension)
+            .new_decryptable_available_balance(amount, &self.aes_key)
+            .ok()?;
+        let extension = &mut self.extension;
+        extension.available_balance =
+            ops::subtract_from(&extension.available_balance, amount).unwrap();
+        extension.decryptable_available_balance = new_decryptable_available_balance.into();
+        self.available -= amount;
+        Some(())
+    }
+
+    // Apply the pending balance as `flows::apply_pending` does, with `raced` credited in between
+    fn apply_pending_balance(&mut self, raced: Option<u64>) {
+        let credit_counter = self.credit_counter();
+        let (available, pending) = balances_to_apply(
+            &self.token_account,
+            &self.extension,
+            &self.elgamal_keypair,
+            &self.aes_key,
+        )
+        .unwrap();
+        let new_decryptable_available_balance = self.aes_key.encrypt(available + pending);
+
+        if let Some(amount) = raced {
+            self.deposit(amount);
+        }
+
+        let extension = &mut self.extension;
+        extension.available_balance = ops::add_with_lo_hi(
+            &extension.available_balance,
+            &extension.pending_balance_lo,
+            &extension.pending_balance_hi,
+        )
+        .unwrap();
+        extension.actual_pending_balance_credit_counter = extension.pending_balance_credit_counter;
+        extension.expected_pending_balance_credit_counter = credit_counter.into();
+        extension.decryptable_available_balance = new_decryptable_available_balance.into();
+        extension.pending_balance_credit_counter = 0.into();
+        extension.pending_balance_lo = EncryptedBalance::default();
+        extension.pending_balance_hi = EncryptedBalance::default();
+        self.available += self.pending_lo + (self.pending_hi << 16);
+        self.pending_lo = 0;
+        self.pending_hi = 0;
+    }
+}
+
+// Operations applied so far, printed with a failure
+struct Trace(Vec<Operation>);
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for operation in &self.0 {
+            writeln!(f, "  {:?}", operation)?;
+        }
+        Ok(())
+    }
+}
+
+// Whether a ciphertext encrypts `amount`, checked without solving the discrete log of a decryption
+fn encrypts(account: &Account, ciphertext: &EncryptedBalance, amount: u64) -> bool {
+    let discrete_log = ElGamalCiphertext::try_from(*ciphertext)
+        .unwrap()
+        .decrypt(account.elgamal_keypair.secret());
+    discrete_log.target == discrete_log.generator * Scalar::from(amount)
+}
+
+fn check_invariants(account: &Account, trace: &Trace) -> Result<(), String> {
+    let extension = &account.extension;
+    if !encrypts(account, &extension.available_balance, account.available) {
+        return Err(format!(
+            "ElGamal available balance isn't {} after:\n{}",
+            account.available, trace
+        ));
+    }
+    if !(encrypts(account, &extension.pending_balance_lo, account.pending_lo)
+        && encrypts(account, &extension.pending_balance_hi, account.pending_hi))
+    {
+        return Err(format!(
+            "pending balance isn't {} + {} << 16 after:\n{}",
+            account.pending_lo, account.pending_hi, trace
+        ));
+    }
+    if account.is_current() {
+        let decryptable_available_balance =
+            AeCiphertext::try_from(extension.decryptable_available_balance)
+                .unwrap()
+                .decrypt(&account.aes_key);
+        if decryptable_available_balance != Some(account.available) {
+            return Err(format!(
+                "decryptable available balance drifted, {:?} instead of {} after:\n{}",
+                decryptable_available_balance, account.available, trace
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn operations(seed: u64) -> Vec<Operation> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..rng.gen_range(1..=MAX_OPERATIONS))
+        .map(|_| Operation::random(&mut rng))
+        .collect()
+}
+
+// Apply the operations to a new account, checking the invariants after each one the client accepts
+fn run(operations: &[Operation]) -> Result<(), String> {
+    let mut account = Account::new();
+    let mut trace = Trace(Vec::new());
+
+    for &operation in operations {
+        let applied = match operation {
+            Operation::Deposit(amount) => {
+                account.deposit(amount);
+                true
+            }
+            Operation::TransferIn(amount) => {
+                account.transfer_in(amount);
+                true
+            }
+            Operation::TransferOut(amount) => account.transfer_out(amount).is_some(),
+            Operation::Withdraw(amount) => account.withdraw(amount).is_some(),
+            Operation::ApplyPendingBalance => {
+                account.apply_pending_balance(None);
+                true
+            }
+            Operation::RacedApplyPendingBalance(amount) => {
+                account.apply_pending_balance(Some(amount));
+                true
+            }
+        };
+        if applied {
+            trace.0.push(operation);
+            check_invariants(&account, &trace)?;
+        }
+    }
+
+    // A final apply catches up on any raced credits, leaving both balances equal to everything credited
+    trace.0.push(Operation::ApplyPendingBalance);
+    account.apply_pending_balance(None);
+    check_invariants(&account, &trace)?;
+    if !account.is_current() || !account.audit().is_consistent() {
+        return Err(format!(
+            "balances are inconsistent after a final apply, after:\n{}",
+            trace
+        ));
+    }
+    Ok(())
+}
+
+// Shrink a failing case until no smaller case fails: drop one operation, or halve one amount, at a time
+fn shrink(mut operations: Vec<Operation>, mut error: String) -> (Vec<Operation>, String) {
+    'shrinking: loop {
+        let dropped = (0..operations.len()).map(|index| {
+            let mut smaller = operations.clone();
+            smaller.remove(index);
+            smaller
+        });
+        let halved = (0..operations.len()).filter_map(|index| {
+            let mut smaller = operations.clone();
+            smaller[index] = smaller[index].halved()?;
+            Some(smaller)
+        });
+        for smaller in dropped.chain(halved).collect::<Vec<_>>() {
+            if let Err(smaller_error) = run(&smaller) {
+                operations = smaller;
+                error = smaller_error;
+                continue 'shrinking;
+            }
+        }
+        return (operations, error);
+    }
+}
+
+fn run_case(seed: u64) {
+    let operations = operations(seed);
+    if let Err(error) = run(&operations) {
+        let (operations, error) = shrink(operations, error);
+        panic!(
+            "{}(PROPERTY_SEED={}, shrunk to {} operations)",
+            error,
+            seed,
+            operations.len()
+        );
+    }
+}
+
+#[test]
+fn decryptable_balance_matches_elgamal_balance() {
+    if let Ok(seed) = env::var("PROPERTY_SEED") {
+        run_case(seed.parse().expect("PROPERTY_SEED must be a number"));
+        return;
+    }
+
+    let cases = env::var("PROPERTY_CASES")
+        .map(|cases| cases.parse().expect("PROPERTY_CASES must be a number"))
+        .unwrap_or(DEFAULT_CASES);
+    for seed in 0..cases {
+        run_case(seed);
+    }
+}
+
+// The decryptable balance of an apply raced by a credit misses that credit until the next apply,
+// and the counters flag it in the meantime
+#[test]
+fn raced_apply_is_caught_up_by_the_next_apply() {
+    let mut account = Account::new();
+    account.deposit(1_000);
+    account.apply_pending_balance(Some(70_000));
+
+    let audit = account.audit();
+    assert!(!account.is_current());
+    assert_eq!(audit.available_balance, Some(71_000));
+    assert_eq!(audit.decryptable_available_balance, Some(1_000));
+
+    account.apply_pending_balance(None);
+    let audit = account.audit();
+    assert!(account.is_current());
+    assert_eq!(audit.decryptable_available_balance, Some(71_000));
+}
@@ -0,0 +1,258 @@
+// Flows run against `mock::MockRpc` instead of a validator: canned accounts in, recorded transactions out.
+//
+// cargo test --test mock_flows
+use keypair_utils::{
+    flows::{apply_pending, configure_account, FlowContext},
+    history::{decode_history_instruction, HistoryInstruction},
+    mock::MockRpc,
+    verify::{Balance, BalanceChange, BalanceMismatch},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account as SolanaAccount,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::{
+        confidential_transfer::{ConfidentialTransferAccount, DecryptableBalance},
+        ExtensionType, StateWithExtensionsMut,
+    },
+    solana_zk_token_sdk::encryption::{
+        auth_encryption::{AeCiphertext, AeKey},
+        elgamal::ElGamalKeypair,
+    },
+    state::{Account, AccountState},
+};
+use std::time::Duration;
+
+// A confidential token account of `owner` with `available` tokens applied and `pending` credited in two parts
+fn confidential_token_account(
+    token_account: &Pubkey,
+    owner: &Keypair,
+    mint: &Pubkey,
+    available: u64,
+    pending: (u64, u64),
+) -> SolanaAccount {
+    let elgamal_keypair =
+        ElGamalKeypair::new_from_signer(owner, &token_account.to_bytes()).unwrap();
+    let aes_key = AeKey::new_from_signer(owner, &token_account.to_bytes()).unwrap();
+
+    let space = ExtensionType::try_calculate_account_len::<Account>(&[
+        ExtensionType::ConfidentialTransferAccount,
+    ])
+    .unwrap();
+    let mut data = vec![0; space];
+    let mut state = StateWithExtensionsMut::<Account>::unpack_uninitialized(&mut data).unwrap();
+    state.base = Account {
+        mint: *mint,
+        owner: owner.pubkey(),
+        state: AccountState::Initialized,
+        ..Account::default()
+    };
+    state.pack_base();
+    state.init_account_type().unwrap();
+
+    let extension = state
+        .init_extension::<ConfidentialTransferAccount>(true)
+        .unwrap();
+    extension.approved = true.into();
+    extension.elgamal_pubkey = (*elgamal_keypair.pubkey()).into();
+    extension.available_balance = elgamal_keypair.pubkey().encrypt(available).into();
+    extension.decryptable_available_balance = aes_key.encrypt(available).into();
+    extension.pending_balance_lo = elgamal_keypair.pubkey().encrypt(pending.0).into();
+    extension.pending_balance_hi = elgamal_keypair.pubkey().encrypt(pending.1).into();
+    extension.pending_balance_credit_counter = 2.into();
+    extension.allow_confidential_credits = true.into();
+
+    SolanaAccount {
+        lamports: Rent::default().minimum_balance(space),
+        data,
+        owner: spl_token_2022::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// Flow context with balance verification always on, as every flow under test should pass it
+fn flow_context(client: &RpcClient, steps: usize) -> FlowContext<'_> {
+    let mut ctx = FlowContext::new(client, steps);
+    ctx.verify = true;
+    ctx
+}
+
+fn decrypt(aes_key: &AeKey, balance: DecryptableBalance) -> Option<u64> {
+    AeCiphertext::try_from(balance).ok()?.decrypt(aes_key)
+}
+
+#[test]
+fn apply_pending_balance_sends_the_new_decryptable_balance() {
+    let mock = MockRpc::new();
+    let owner = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let token_account =
+        get_associated_token_address_with_program_id(&owner.pubkey(), &mint, &spl_token_2022::id());
+    // 100 + (3 << 16) pending on top of 250 available
+    mock.set_account(
+        token_account,
+        confidential_token_account(&token_account, &owner, &mint, 250, (100, 3)),
+    );
+
+    let client = mock.rpc_client();
+    let mut ctx = flow_context(&client, apply_pending::STEPS);
+    let before = ctx.snapshot_balances(&token_account, &owner).unwrap();
+    let expected = BalanceChange::apply_pending(before.as_ref().unwrap());
+    let outcome = apply_pending::apply_pending_balance(
+        &mut ctx,
+        &token_account,
+        &owner,
+        apply_pending::DEFAULT_MAX_ROUNDS,
+        Duration::ZERO,
+    )
+    .unwrap();
+
+    let pending = 100 + (3 << 16);
+    assert_eq!(outcome.applied, pending);
+    // The mock doesn't execute transactions, so land the applied balance by hand
+    mock.set_account(
+        token_account,
+        confidential_token_account(&token_account, &owner, &mint, 250 + pending, (0, 0)),
+    );
+    ctx.verify_balances(before, &owner, expected).unwrap();
+    let transactions = mock.transactions();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(outcome.signatures, vec![transactions[0].signatures[0]]);
+
+    let instructions = transactions[0].message.instructions();
+    let Some(HistoryInstruction::ApplyPendingBalance {
+        new_decryptable_available_balance,
+    }) = decode_history_instruction(&instructions[0].data).unwrap()
+    else {
+        panic!("Expected an ApplyPendingBalance instruction");
+    };
+    let aes_key = AeKey::new_from_signer(&owner, &token_account.to_bytes()).unwrap();
+    assert_eq!(
+        decrypt(&aes_key, new_decryptable_available_balance),
+        Some(250 + pending)
+    );
+}
+
+#[test]
+fn apply_pending_balance_skips_an_account_without_credits() {
+    let mock = MockRpc::new();
+    let owner = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let token_account =
+        get_associated_token_address_with_program_id(&owner.pubkey(), &mint, &spl_token_2022::id());
+    let mut account = confidential_token_account(&token_account, &owner, &mint, 250, (0, 0));
+    let mut state = StateWithExtensionsMut::<Account>::unpack(&mut account.data).unwrap();
+    state
+        .get_extension_mut::<ConfidentialTransferAccount>()
+        .unwrap()
+        .pending_balance_credit_counter = 0.into();
+    mock.set_account(token_account, account);
+
+    let client = mock.rpc_client();
+    let mut ctx = flow_context(&client, apply_pending::STEPS);
+    let outcome = apply_pending::apply_pending_balance(
+        &mut ctx,
+        &token_account,
+        &owner,
+        apply_pending::DEFAULT_MAX_ROUNDS,
+        Duration::ZERO,
+    )
+    .unwrap();
+
+    assert!(outcome.signatures.is_empty());
+    assert!(mock.transactions().is_empty());
+}
+
+#[test]
+fn create_confidential_account_configures_a_new_account_once() {
+    let mock = MockRpc::new();
+    let owner = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let token_account =
+        get_associated_token_address_with_program_id(&owner.pubkey(), &mint, &spl_token_2022::id());
+
+    let client = mock.rpc_client();
+    let mut ctx = flow_context(&client, configure_account::STEPS * 2);
+    let signature = configure_account::create_confidential_account(&mut ctx, &owner, &mint)
+        .unwrap()
+        .expect("a new account is configured");
+
+    let transactions = mock.transactions();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].signatures[0], signature);
+    // Create the associated token account, reallocate, ConfigureAccount and its pubkey validity proof
+    let message = &transactions[0].message;
+    let program_ids: Vec<Pubkey> = message
+        .instructions()
+        .iter()
+        .map(|instruction| *instruction.program_id(message.static_account_keys()))
+        .collect();
+    assert_eq!(
+        program_ids,
+        vec![
+            spl_associated_token_account::id(),
+            spl_token_2022::id(),
+            spl_token_2022::id(),
+            spl_token_2022::solana_zk_token_sdk::zk_token_proof_program::id(),
+        ]
+    );
+    let Some(HistoryInstruction::ConfigureAccount {
+        decryptable_zero_balance,
+    }) = decode_history_instruction(&message.instructions()[2].data).unwrap()
+    else {
+        panic!("Expected a ConfigureAccount instruction");
+    };
+    let aes_key = AeKey::new_from_signer(&owner, &token_account.to_bytes()).unwrap();
+    assert_eq!(decrypt(&aes_key, decryptable_zero_balance), Some(0));
+
+    // Once the account exists with the extension there is nothing left to send
+    mock.set_account(
+        token_account,
+        confidential_token_account(&token_account, &owner, &mint, 0, (0, 0)),
+    );
+    assert_eq!(
+        configure_account::create_confidential_account(&mut ctx, &owner, &mint).unwrap(),
+        None
+    );
+    assert_eq!(mock.transactions().len(), 1);
+}
+
+#[test]
+fn verify_balances_reports_a_balance_that_moved_by_the_wrong_amount() {
+    let mock = MockRpc::new();
+    let owner = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let token_account =
+        get_associated_token_address_with_program_id(&owner.pubkey(), &mint, &spl_token_2022::id());
+    mock.set_account(
+        token_account,
+        confidential_token_account(&token_account, &owner, &mint, 250, (100, 0)),
+    );
+
+    let client = mock.rpc_client();
+    let ctx = flow_context(&client, apply_pending::STEPS);
+    let before = ctx.snapshot_balances(&token_account, &owner).unwrap();
+    let expected = BalanceChange::apply_pending(before.as_ref().unwrap());
+    // The pending balance is gone, but only 99 of the 100 tokens reached the available balance
+    mock.set_account(
+        token_account,
+        confidential_token_account(&token_account, &owner, &mint, 349, (0, 0)),
+    );
+
+    let error = ctx.verify_balances(before, &owner, expected).unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<BalanceMismatch>(),
+        Some(&BalanceMismatch {
+            token_account,
+            balance: Balance::Available,
+            expected: 100,
+            actual: Some(99),
+        })
+    );
+}